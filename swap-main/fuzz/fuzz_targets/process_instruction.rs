@@ -0,0 +1,8 @@
+#![no_main]
+
+use cargo_swap_fuzz::{run, FuzzInput};
+use libfuzzer_sys::fuzz_target;
+
+fuzz_target!(|input: FuzzInput| {
+    run(&input);
+});
@@ -0,0 +1,2152 @@
+// In-memory execution harness for fuzzing `cargo_swap::process_instruction`.
+//
+// Every account the program can see lives in a `World` table. Instructions are
+// run natively against `AccountInfo`s over buffers laid out like the runtime's,
+// so accounts can be created and reallocated, and the CPIs the processors make
+// are served by `StandInStubs`, a small emulation of the system program, the
+// GorbChain SPL Token program, its ATA program and a flash loan receiver
+// installed through `solana_program::program_stubs`. Failed instructions are
+// rolled back like the runtime would; successful ones are checked against the
+// pool invariants.
+
+use arbitrary::Arbitrary;
+use borsh::{BorshDeserialize, BorshSerialize};
+use cargo_swap::{
+    concentrated::{self, ConcentratedPool, Position},
+    curve::stable,
+    flash_loan::FlashReceiverInstruction,
+    multi_pool::{self, MultiPool},
+    oracle::{ObservationRing, PriceObservation},
+    CurveParams, Pool, SplitRoute, TestProjectInstruction, LEGACY_POOL_LEN,
+    NATIVE_MINT,
+};
+use solana_program::{
+    account_info::AccountInfo,
+    clock::Clock,
+    entrypoint::{ProgramResult, MAX_PERMITTED_DATA_INCREASE},
+    instruction::Instruction,
+    program_error::ProgramError,
+    program_option::COption,
+    program_pack::{IsInitialized, Pack},
+    program_stubs::{set_syscall_stubs, SyscallStubs},
+    pubkey::Pubkey,
+    rent::Rent,
+    system_program, sysvar,
+};
+use spl_token::{
+    error::TokenError,
+    state::{Account as TokenAccount, AccountState as TokenAccountState, Mint},
+};
+use std::sync::{
+    atomic::{AtomicI64, Ordering},
+    Mutex, Once, OnceLock,
+};
+
+pub const TOKEN_PROGRAM_ID: Pubkey =
+    solana_program::pubkey!("G22oYgZ6LnVcy7v8eSNi2xpNk1NcZiPD8CVKSTut7oZ6");
+pub const ATA_PROGRAM_ID: Pubkey =
+    solana_program::pubkey!("GoATGVNeSXerFerPqTJ8hcED1msPWHHLxao2vwBYqowm");
+pub const FLASH_RECEIVER_ID: Pubkey =
+    solana_program::pubkey!("F1ashRecv1111111111111111111111111111111111");
+
+const MINT_COUNT: usize = 3;
+// Pools between the three base mints: P/Q, Q/R and R/P.
+const POOL_PAIRS: [(usize, usize); 3] = [(0, 1), (1, 2), (2, 0)];
+// The base pools, then a P/wrapped GOR pool only reachable through WithNative.
+const POOL_COUNT: usize = 4;
+// Ranges of the concentrated P/Q pool's positions; multiples of 60 so they
+// suit most tick spacings.
+const POSITION_RANGES: [(i32, i32); 4] = [(-600, 600), (-6_000, -60), (60, 6_000), (-443_400, 443_400)];
+const TICK_SPACINGS: [u16; 3] = [1, 10, 60];
+// Balance of every base-mint token account the harness creates.
+const INITIAL_BALANCE: u64 = 1 << 48;
+const USER_LAMPORTS: u64 = 1_000_000_000_000_000;
+const MAX_STEPS: usize = 64;
+const MAX_HOPS: usize = 8;
+// One past cargo_swap::MAX_SPLIT_ROUTES, so its bound is exercised too.
+const MAX_ROUTES: usize = 4;
+const GENESIS_UNIX_TIMESTAMP: i64 = 1_700_000_000;
+
+// ---------------------------------------------------------------------------
+// Fuzz input
+// ---------------------------------------------------------------------------
+
+#[derive(Arbitrary, Debug)]
+pub struct FuzzInput {
+    // Initial liquidity and curve for each base pool; a pool whose InitPool
+    // fails stays empty, as do the pools below.
+    pub pools: [(u64, u64, FuzzCurve); 3],
+    // Initial P and wrapped GOR liquidity of the native pool.
+    pub native_pool: (u64, u64),
+    // Initial liquidity of the P/Q/R multi-asset pool.
+    pub multi_pool: [u64; MINT_COUNT],
+    // Tick spacing choice and initial tick of the concentrated P/Q pool.
+    pub concentrated: (u8, i32),
+    pub steps: Vec<FuzzStep>,
+}
+
+#[derive(Arbitrary, Debug)]
+pub enum FuzzStep {
+    // Arbitrary bytes against an arbitrary selection of accounts.
+    Raw { data: Vec<u8>, accounts: Vec<u8> },
+    // A well-formed instruction with its canonical accounts, optionally with
+    // some account slots swapped for other accounts from the table.
+    Instruction {
+        instruction: FuzzInstruction,
+        substitutions: Vec<(u8, u8)>,
+    },
+    // Moves the clock forward, e.g. across an amplification ramp.
+    Warp { seconds: u32 },
+    // Rewrites a pool in the layout from before versioning, as MigratePool
+    // finds pools created by earlier releases.
+    Downgrade { pool: u8 },
+}
+
+#[derive(Arbitrary, Debug)]
+pub enum FuzzInstruction {
+    InitPool { pool: u8, amount_a: u64, amount_b: u64 },
+    AddLiquidity { pool: u8, amount_a: u64, amount_b: u64 },
+    RemoveLiquidity { pool: u8, lp_amount: u64 },
+    Swap { pool: u8, amount_in: u64, direction_a_to_b: bool },
+    MultihopSwap { route: Route, amount_in: u64, minimum_amount_out: u64 },
+    MultihopSwapWithPath {
+        route: Route,
+        amount_in: u64,
+        minimum_amount_out: u64,
+        // Replaces the path implied by the route when present.
+        token_path: Option<Vec<u8>>,
+    },
+    GetPoolInfo { pool: u8 },
+    GetTotalPools,
+    FindPoolsByToken { token: u8 },
+    GetSwapQuote { pool: u8, amount_in: u64, token_in: u8 },
+    GetMultihopQuote { route: Route, amount_in: u64 },
+    InitPoolWithCurve { pool: u8, amount_a: u64, amount_b: u64, curve: FuzzCurve },
+    RampAmp { pool: u8, target_amp: u64, ramp_stop_ts: i64 },
+    StopRampAmp { pool: u8 },
+    // Multi-asset instructions against the P/Q/R pool; tokens index the base mints.
+    InitMultiPool { amounts: Vec<u64> },
+    MultiPoolSwap { token_in: u8, token_out: u8, amount_in: u64, minimum_amount_out: u64 },
+    AddMultiLiquidity { amounts: Vec<u64> },
+    RemoveMultiLiquidity { lp_amount: u64 },
+    AddMultiLiquiditySingle { token: u8, amount_in: u64, minimum_lp_out: u64 },
+    RemoveMultiLiquiditySingle { token: u8, lp_amount: u64, minimum_amount_out: u64 },
+    // Concentrated instructions against the P/Q pool; positions index POSITION_RANGES.
+    InitConcentratedPool { tick_spacing: u16, initial_sqrt_price: u128 },
+    OpenPosition { position: u8 },
+    IncreaseLiquidity { position: u8, liquidity: u64, maximum_amount_a: u64, maximum_amount_b: u64 },
+    DecreaseLiquidity { position: u8, liquidity: u64, minimum_amount_a: u64, minimum_amount_b: u64 },
+    CollectFees { position: u8 },
+    ConcentratedSwap { amount_in: u64, minimum_amount_out: u64, direction_a_to_b: bool },
+    // Pools past the two-token ones lend from the multi-asset pool; `token`
+    // picks the vault.
+    FlashLoan { pool: u8, token: u8, amount: u64, repayment: FlashRepayment },
+    FlashSwap { pool: u8, amount_a_out: u64, amount_b_out: u64, repayment: FlashRepayment },
+    // The user's wrapped GOR account in the inner instruction is the wrap account.
+    WithNative { wrap_amount: u64, instruction: Box<FuzzInstruction> },
+    MigratePool { pool: u8 },
+    CompactMultihopSwap {
+        route: Route,
+        amount_in: u64,
+        minimum_amount_out: u64,
+        minimum_hop_amounts_out: Vec<u64>,
+    },
+    // Every route starts from the same side of the first route's first pool.
+    SplitRouteSwap {
+        start_with_b: bool,
+        routes: Vec<(Vec<u8>, u64)>,
+        minimum_amount_out: u64,
+    },
+    // Without `since`, averages from the pool's stored accumulators.
+    GetTwap { pool: u8, since: Option<(u32, u128, u128)> },
+    InitOracle { pool: u8, capacity: u16 },
+    GrowOracle { pool: u8, capacity: u16 },
+    Observe { pool: u8, seconds_agos: Vec<u32> },
+    MultihopSwapWithHopMinimums {
+        route: Route,
+        amount_in: u64,
+        minimum_amount_out: u64,
+        minimum_hop_amounts_out: Vec<u64>,
+    },
+    MultihopSwapWithPathAndHopMinimums {
+        route: Route,
+        amount_in: u64,
+        minimum_amount_out: u64,
+        token_path: Option<Vec<u8>>,
+        minimum_hop_amounts_out: Vec<u64>,
+    },
+}
+
+// What the flash receiver stand-in pays back from the user's token accounts.
+#[derive(Arbitrary, Clone, Copy, Debug)]
+pub enum FlashRepayment {
+    // The loan and its fee, or swap output repaid in the same token, off by
+    // `adjustment` in each token owed.
+    Owed { adjustment: i16 },
+    // Fixed amounts of token A, or the borrowed token, and token B.
+    Amounts { amount_a: u64, amount_b: u64 },
+}
+
+#[derive(Arbitrary, Debug)]
+pub enum FuzzCurve {
+    ConstantProduct,
+    Stable { amp: u64 },
+    ConstantPrice { token_b_price: u64 },
+    Offset { token_a_offset: u64, token_b_offset: u64 },
+    Weighted { weight_a: u64, weight_b: u64 },
+}
+
+impl From<&FuzzCurve> for CurveParams {
+    fn from(curve: &FuzzCurve) -> Self {
+        match *curve {
+            FuzzCurve::ConstantProduct => CurveParams::ConstantProduct,
+            FuzzCurve::Stable { amp } => CurveParams::Stable { amp },
+            FuzzCurve::ConstantPrice { token_b_price } => CurveParams::ConstantPrice { token_b_price },
+            FuzzCurve::Offset { token_a_offset, token_b_offset } => CurveParams::Offset {
+                token_a_offset,
+                token_b_offset,
+            },
+            FuzzCurve::Weighted { weight_a, weight_b } => CurveParams::Weighted { weight_a, weight_b },
+        }
+    }
+}
+
+#[derive(Arbitrary, Debug)]
+pub struct Route {
+    pub start_with_b: bool,
+    pub pools: Vec<u8>,
+}
+
+// ---------------------------------------------------------------------------
+// Account table
+// ---------------------------------------------------------------------------
+
+#[derive(Clone, Debug)]
+struct AccountState {
+    key: Pubkey,
+    lamports: u64,
+    data: Vec<u8>,
+    owner: Pubkey,
+    is_signer: bool,
+    is_writable: bool,
+    executable: bool,
+}
+
+impl AccountState {
+    fn new(key: Pubkey, owner: Pubkey, lamports: u64, data: Vec<u8>) -> Self {
+        Self {
+            key,
+            lamports,
+            data,
+            owner,
+            is_signer: false,
+            is_writable: true,
+            executable: false,
+        }
+    }
+
+    fn program(key: Pubkey) -> Self {
+        Self {
+            is_writable: false,
+            executable: true,
+            ..Self::new(key, solana_program::bpf_loader::id(), 1, Vec::new())
+        }
+    }
+}
+
+// Table indices of everything belonging to one pool.
+#[derive(Clone, Copy, Debug)]
+struct PoolSlot {
+    token_a: usize,
+    token_b: usize,
+    pool: usize,
+    vault_a: usize,
+    vault_b: usize,
+    lp_mint: usize,
+    user_lp: usize,
+    oracle: usize,
+}
+
+// Table indices of the multi-asset pool over the base mints; vaults follow
+// the mints.
+#[derive(Clone, Copy, Debug)]
+struct MultiPoolSlot {
+    pool: usize,
+    lp_mint: usize,
+    user_lp: usize,
+    vaults: [usize; MINT_COUNT],
+}
+
+// Table indices of the concentrated P/Q pool and the user's positions in it,
+// one per entry of POSITION_RANGES.
+#[derive(Clone, Copy, Debug)]
+struct ConcentratedSlot {
+    pool: usize,
+    vault_a: usize,
+    vault_b: usize,
+    positions: [usize; POSITION_RANGES.len()],
+}
+
+// Pool state observed before an instruction, for the post-instruction checks.
+#[derive(Clone, Copy, Debug)]
+struct PoolObservation {
+    reserve_a: u64,
+    reserve_b: u64,
+    total_lp_supply: u64,
+}
+
+// Everything the checks compare against the state before an instruction.
+#[derive(Clone, Copy, Debug)]
+struct WorldObservation {
+    pools: [Option<PoolObservation>; POOL_COUNT],
+    lamports: u128,
+}
+
+#[derive(Clone, Debug)]
+pub struct World {
+    accounts: Vec<AccountState>,
+    unix_timestamp: i64,
+    user: usize,
+    token_program: usize,
+    system_program: usize,
+    rent: usize,
+    ata_program: usize,
+    flash_receiver: usize,
+    mints: [usize; MINT_COUNT],
+    user_tokens: [usize; MINT_COUNT],
+    native_mint: usize,
+    // The user's wrapped GOR account, which only exists during WithNative.
+    wrap: usize,
+    pools: [PoolSlot; POOL_COUNT],
+    multi_pool: MultiPoolSlot,
+    concentrated: ConcentratedSlot,
+}
+
+fn key(seed: u8) -> Pubkey {
+    Pubkey::new_from_array([seed; 32])
+}
+
+fn packed<T: Pack>(state: T) -> Vec<u8> {
+    let mut data = vec![0; T::LEN];
+    T::pack(state, &mut data).unwrap();
+    data
+}
+
+fn rent_sysvar_data() -> Vec<u8> {
+    let rent = Rent::default();
+    let mut data = Vec::new();
+    data.extend_from_slice(&rent.lamports_per_byte_year.to_le_bytes());
+    data.extend_from_slice(&rent.exemption_threshold.to_le_bytes());
+    data.push(rent.burn_percent);
+    data
+}
+
+fn find_address(seeds: &[&[u8]], program_id: &Pubkey) -> Pubkey {
+    Pubkey::find_program_address(seeds, program_id).0
+}
+
+fn user_ata(mint: &Pubkey) -> Pubkey {
+    find_address(
+        &[key(1).as_ref(), TOKEN_PROGRAM_ID.as_ref(), mint.as_ref()],
+        &ATA_PROGRAM_ID,
+    )
+}
+
+// The original data length as the runtime serializes it, just before the key.
+#[repr(C)]
+struct KeyCell {
+    original_data_len: u32,
+    key: Pubkey,
+}
+
+// One account serialized like the runtime's program input, which
+// `AccountInfo::realloc` relies on: the original data length sits just
+// before the key, the current length in the word before the data, and the
+// data is followed by room to grow by MAX_PERMITTED_DATA_INCREASE.
+struct SerializedAccount {
+    key: Box<KeyCell>,
+    lamports: u64,
+    owner: Pubkey,
+    words: Vec<u64>,
+}
+
+impl SerializedAccount {
+    fn new(account: &AccountState) -> Self {
+        let len = account.data.len();
+        let mut words = vec![0; 1 + (len + MAX_PERMITTED_DATA_INCREASE).div_ceil(8)];
+        words[0] = len as u64;
+        Self::bytes(&mut words, len).copy_from_slice(&account.data);
+        Self {
+            key: Box::new(KeyCell {
+                original_data_len: len as u32,
+                key: account.key,
+            }),
+            lamports: account.lamports,
+            owner: account.owner,
+            words,
+        }
+    }
+
+    fn bytes(words: &mut [u64], len: usize) -> &mut [u8] {
+        assert!(len <= (words.len() - 1) * 8);
+        // Safety: the words after the length hold at least `len` bytes
+        unsafe { std::slice::from_raw_parts_mut(words.as_mut_ptr().add(1).cast::<u8>(), len) }
+    }
+
+    fn info(&mut self, account: &AccountState) -> AccountInfo<'_> {
+        let len = self.words[0] as usize;
+        AccountInfo::new(
+            &self.key.key,
+            account.is_signer,
+            account.is_writable,
+            &mut self.lamports,
+            Self::bytes(&mut self.words, len),
+            &self.owner,
+            account.executable,
+            0,
+        )
+    }
+
+    // Copies the account back after a successful instruction, at the length
+    // the program left it.
+    fn store(mut self, account: &mut AccountState) {
+        let len = self.words[0] as usize;
+        account.lamports = self.lamports;
+        account.owner = self.owner;
+        account.data = Self::bytes(&mut self.words, len).to_vec();
+    }
+}
+
+impl World {
+    // The template world is the same for every input, so build it once.
+    pub fn new() -> Self {
+        static TEMPLATE: OnceLock<World> = OnceLock::new();
+        TEMPLATE.get_or_init(World::build).clone()
+    }
+
+    fn add(&mut self, account: AccountState) -> usize {
+        self.accounts.push(account);
+        self.accounts.len() - 1
+    }
+
+    // Accounts the program creates start out as empty system accounts.
+    fn add_empty(&mut self, key: Pubkey) -> usize {
+        self.add(AccountState::new(key, system_program::id(), 0, Vec::new()))
+    }
+
+    fn build() -> Self {
+        let empty_pool = PoolSlot {
+            token_a: 0,
+            token_b: 0,
+            pool: 0,
+            vault_a: 0,
+            vault_b: 0,
+            lp_mint: 0,
+            user_lp: 0,
+            oracle: 0,
+        };
+        let mut world = World {
+            accounts: Vec::new(),
+            unix_timestamp: GENESIS_UNIX_TIMESTAMP,
+            user: 0,
+            token_program: 0,
+            system_program: 0,
+            rent: 0,
+            ata_program: 0,
+            flash_receiver: 0,
+            mints: [0; MINT_COUNT],
+            user_tokens: [0; MINT_COUNT],
+            native_mint: 0,
+            wrap: 0,
+            pools: [empty_pool; POOL_COUNT],
+            multi_pool: MultiPoolSlot {
+                pool: 0,
+                lp_mint: 0,
+                user_lp: 0,
+                vaults: [0; MINT_COUNT],
+            },
+            concentrated: ConcentratedSlot {
+                pool: 0,
+                vault_a: 0,
+                vault_b: 0,
+                positions: [0; POSITION_RANGES.len()],
+            },
+        };
+
+        let user_key = key(1);
+        let attacker_key = key(2);
+        let mint_authority = key(3);
+        let program_id = cargo_swap::id();
+
+        world.user = world.add(AccountState {
+            is_signer: true,
+            ..AccountState::new(user_key, system_program::id(), USER_LAMPORTS, Vec::new())
+        });
+        world.add(AccountState::new(attacker_key, system_program::id(), 1, Vec::new()));
+        world.token_program = world.add(AccountState::program(TOKEN_PROGRAM_ID));
+        world.system_program = world.add(AccountState::program(system_program::id()));
+        world.ata_program = world.add(AccountState::program(ATA_PROGRAM_ID));
+        world.flash_receiver = world.add(AccountState::program(FLASH_RECEIVER_ID));
+        world.add(AccountState::program(program_id));
+        world.rent = world.add(AccountState {
+            is_writable: false,
+            ..AccountState::new(sysvar::rent::id(), sysvar::id(), 1, rent_sysvar_data())
+        });
+
+        for index in 0..MINT_COUNT {
+            let mint = Mint {
+                mint_authority: COption::Some(mint_authority),
+                supply: INITIAL_BALANCE * 2,
+                decimals: 6,
+                is_initialized: true,
+                freeze_authority: COption::None,
+            };
+            world.mints[index] = world.add(AccountState::new(
+                key(10 + index as u8),
+                TOKEN_PROGRAM_ID,
+                1,
+                packed(mint),
+            ));
+        }
+        // Wrapped GOR has no supply; its accounts hold lamports instead
+        let native_mint = Mint {
+            decimals: 9,
+            is_initialized: true,
+            ..Mint::default()
+        };
+        world.native_mint = world.add(AccountState::new(NATIVE_MINT, TOKEN_PROGRAM_ID, 1, packed(native_mint)));
+        world.wrap = world.add_empty(find_address(&[b"native", user_key.as_ref()], &program_id));
+
+        // Each base mint has one account owned by the signing user and one owned
+        // by a bystander, so substituted accounts can point at someone else's funds.
+        for index in 0..MINT_COUNT {
+            for (owner, seed) in [(user_key, 20), (attacker_key, 30)] {
+                let account = TokenAccount {
+                    mint: key(10 + index as u8),
+                    owner,
+                    amount: INITIAL_BALANCE,
+                    state: TokenAccountState::Initialized,
+                    ..TokenAccount::default()
+                };
+                let slot = world.add(AccountState::new(
+                    key(seed + index as u8),
+                    TOKEN_PROGRAM_ID,
+                    1,
+                    packed(account),
+                ));
+                if owner == user_key {
+                    world.user_tokens[index] = slot;
+                }
+            }
+        }
+
+        let pairs = POOL_PAIRS
+            .map(|(a, b)| (world.mints[a], world.mints[b]))
+            .into_iter()
+            .chain([(world.mints[0], world.native_mint)]);
+        for (index, (token_a, token_b)) in pairs.enumerate() {
+            let mint_a = world.key(token_a);
+            let mint_b = world.key(token_b);
+            let pool = find_address(&[b"pool", mint_a.as_ref(), mint_b.as_ref()], &program_id);
+            let lp_mint = find_address(&[b"mint", pool.as_ref()], &program_id);
+            world.pools[index] = PoolSlot {
+                token_a,
+                token_b,
+                pool: world.add_empty(pool),
+                vault_a: world.add_empty(find_address(&[b"vault", pool.as_ref(), mint_a.as_ref()], &program_id)),
+                vault_b: world.add_empty(find_address(&[b"vault", pool.as_ref(), mint_b.as_ref()], &program_id)),
+                lp_mint: world.add_empty(lp_mint),
+                user_lp: world.add_empty(user_ata(&lp_mint)),
+                oracle: world.add_empty(find_address(&[b"oracle", pool.as_ref()], &program_id)),
+            };
+        }
+
+        let tokens = world.mints.map(|mint| world.key(mint));
+        let pool = find_address(&multi_pool::pool_seeds(&tokens), &program_id);
+        let lp_mint = find_address(&[b"mint", pool.as_ref()], &program_id);
+        world.multi_pool = MultiPoolSlot {
+            pool: world.add_empty(pool),
+            lp_mint: world.add_empty(lp_mint),
+            user_lp: world.add_empty(user_ata(&lp_mint)),
+            vaults: tokens.map(|mint| world.add_empty(find_address(&[b"vault", pool.as_ref(), mint.as_ref()], &program_id))),
+        };
+
+        let (mint_a, mint_b) = (tokens[0], tokens[1]);
+        let pool = find_address(&[b"cl_pool", mint_a.as_ref(), mint_b.as_ref()], &program_id);
+        world.concentrated = ConcentratedSlot {
+            pool: world.add_empty(pool),
+            vault_a: world.add_empty(find_address(&[b"vault", pool.as_ref(), mint_a.as_ref()], &program_id)),
+            vault_b: world.add_empty(find_address(&[b"vault", pool.as_ref(), mint_b.as_ref()], &program_id)),
+            positions: POSITION_RANGES.map(|(lower, upper)| {
+                world.add_empty(find_address(
+                    &[
+                        b"position",
+                        pool.as_ref(),
+                        user_key.as_ref(),
+                        &lower.to_le_bytes(),
+                        &upper.to_le_bytes(),
+                    ],
+                    &program_id,
+                ))
+            }),
+        };
+
+        world
+    }
+
+    // Runs one instruction; on error every account is left as it was, like the
+    // runtime would.
+    pub fn process(&mut self, accounts: &[usize], data: &[u8]) -> ProgramResult {
+        install_stubs();
+        UNIX_TIMESTAMP.store(self.unix_timestamp, Ordering::Relaxed);
+        set_return_data(cargo_swap::id(), &[]);
+        let mut buffers: Vec<SerializedAccount> = self.accounts.iter().map(SerializedAccount::new).collect();
+        let result = {
+            let infos: Vec<AccountInfo> = buffers
+                .iter_mut()
+                .zip(&self.accounts)
+                .map(|(buffer, account)| buffer.info(account))
+                .collect();
+            let selected: Vec<AccountInfo> = accounts.iter().map(|&i| infos[i].clone()).collect();
+            cargo_swap::process_instruction(&cargo_swap::id(), &selected, data)
+        };
+        if result.is_ok() {
+            for (buffer, account) in buffers.into_iter().zip(&mut self.accounts) {
+                buffer.store(account);
+            }
+        }
+        result
+    }
+
+    // Rewrites a pool like the tests' downgrade_pool: the legacy layout is a
+    // prefix of the fields without the version byte.
+    fn downgrade(&mut self, pool: u8) {
+        let slot = self.pool_slot(pool);
+        let Some(state) = self.pool_state(&slot) else {
+            return;
+        };
+        let account = &mut self.accounts[slot.pool];
+        account.data = state.try_to_vec().unwrap()[..LEGACY_POOL_LEN].to_vec();
+        account.lamports = Rent::default().minimum_balance(LEGACY_POOL_LEN);
+    }
+
+    fn key(&self, index: usize) -> Pubkey {
+        self.accounts[index].key
+    }
+
+    fn token_account(&self, index: usize) -> Option<TokenAccount> {
+        let account = &self.accounts[index];
+        if account.owner != TOKEN_PROGRAM_ID {
+            return None;
+        }
+        TokenAccount::unpack(&account.data).ok()
+    }
+
+    fn mint(&self, index: usize) -> Option<Mint> {
+        let account = &self.accounts[index];
+        if account.owner != TOKEN_PROGRAM_ID {
+            return None;
+        }
+        Mint::unpack(&account.data).ok()
+    }
+
+    fn pool_state(&self, slot: &PoolSlot) -> Option<Pool> {
+        let account = &self.accounts[slot.pool];
+        if account.owner != cargo_swap::id() {
+            return None;
+        }
+        Pool::unpack(&account.data).ok()
+    }
+
+    fn has_oracle(&self, slot: &PoolSlot) -> bool {
+        self.pool_state(slot).is_some_and(|pool| pool.has_oracle)
+    }
+
+    // State of a program-owned account other than a two-token pool.
+    fn program_state<T: Pack + IsInitialized>(&self, index: usize) -> Option<T> {
+        let account = &self.accounts[index];
+        if account.owner != cargo_swap::id() {
+            return None;
+        }
+        T::unpack(&account.data).ok()
+    }
+
+    fn observe(&self) -> WorldObservation {
+        WorldObservation {
+            pools: self.pools.map(|slot| {
+                self.pool_state(&slot).map(|pool| PoolObservation {
+                    reserve_a: pool.reserve_a,
+                    reserve_b: pool.reserve_b,
+                    total_lp_supply: pool.total_lp_supply,
+                })
+            }),
+            lamports: self.accounts.iter().map(|account| account.lamports as u128).sum(),
+        }
+    }
+
+    // Invariants that must hold after every successful instruction.
+    fn check_invariants(&self, before: &WorldObservation, step: &FuzzStep) {
+        let lamports: u128 = self.accounts.iter().map(|account| account.lamports as u128).sum();
+        assert_eq!(lamports, before.lamports, "lamports created or destroyed by {step:?}");
+        let wrap = &self.accounts[self.wrap];
+        assert!(
+            wrap.owner == system_program::id() && wrap.lamports == 0,
+            "wrap account left open after {step:?}"
+        );
+
+        // Conservation: the program can move base tokens but never create or
+        // destroy them, and LP balances always add up to the LP mint supply.
+        // Wrapped GOR is backed by lamports instead, checked above.
+        let mint_keys: Vec<Pubkey> = self
+            .accounts
+            .iter()
+            .enumerate()
+            .filter(|(index, account)| account.key != NATIVE_MINT && self.mint(*index).is_some())
+            .map(|(_, account)| account.key)
+            .collect();
+        for mint_key in mint_keys {
+            let mint_index = self.accounts.iter().position(|a| a.key == mint_key).unwrap();
+            let supply = self.mint(mint_index).unwrap().supply as u128;
+            let held: u128 = (0..self.accounts.len())
+                .filter_map(|index| self.token_account(index))
+                .filter(|account| account.mint == mint_key)
+                .map(|account| account.amount as u128)
+                .sum();
+            assert_eq!(held, supply, "token conservation violated for mint {mint_key} after {step:?}");
+            if self.mints.contains(&mint_index) {
+                assert_eq!(supply, INITIAL_BALANCE as u128 * 2, "base mint supply changed after {step:?}");
+            }
+        }
+
+        for (slot, before) in self.pools.iter().zip(&before.pools) {
+            let Some(pool) = self.pool_state(slot) else {
+                continue;
+            };
+            // Reserves are an accounting of what sits in the vaults; recording
+            // more than the vault holds means a later withdrawal will underflow.
+            let vault_a = self.token_account(slot.vault_a).map_or(0, |v| v.amount);
+            let vault_b = self.token_account(slot.vault_b).map_or(0, |v| v.amount);
+            assert!(
+                pool.reserve_a <= vault_a && pool.reserve_b <= vault_b,
+                "reserves {}/{} exceed vault balances {}/{} after {step:?}",
+                pool.reserve_a,
+                pool.reserve_b,
+                vault_a,
+                vault_b
+            );
+            let lp_supply = self.mint(slot.lp_mint).map_or(0, |m| m.supply);
+            assert_eq!(
+                pool.total_lp_supply, lp_supply,
+                "pool LP supply out of sync with LP mint after {step:?}"
+            );
+
+            // A pool flagged for an oracle always has its own, never written
+            // ahead of the clock
+            if pool.has_oracle {
+                let oracle = &self.accounts[slot.oracle];
+                let ring = ObservationRing::from_bytes(&oracle.data).expect("pool oracle");
+                assert_eq!(ring.header.pool, self.key(slot.pool), "oracle of another pool");
+                let newest = ring.newest().expect("oracle observation");
+                assert!(
+                    newest.timestamp <= self.unix_timestamp,
+                    "oracle observation in the future after {step:?}"
+                );
+            }
+
+            // With the LP supply unchanged only swaps happened, which must never
+            // shrink the pool's invariant.
+            if let Some(before) = before {
+                if before.total_lp_supply == pool.total_lp_supply {
+                    // Both sides at the current amplification so ramps do not count
+                    let curve = pool.curve().expect("pool curve");
+                    if let (Ok(invariant_before), Ok(invariant_after)) = (
+                        curve.invariant(before.reserve_a, before.reserve_b),
+                        curve.invariant(pool.reserve_a, pool.reserve_b),
+                    ) {
+                        assert!(
+                            invariant_after >= invariant_before,
+                            "{:?} invariant decreased after {step:?}",
+                            pool.curve_type
+                        );
+                    }
+                }
+            }
+        }
+
+        if let Some(pool) = self.program_state::<MultiPool>(self.multi_pool.pool) {
+            for (reserve, &vault) in pool.reserves().iter().zip(&self.multi_pool.vaults) {
+                let balance = self.token_account(vault).map_or(0, |v| v.amount);
+                assert!(
+                    *reserve <= balance,
+                    "multi-asset reserve {reserve} exceeds vault balance {balance} after {step:?}"
+                );
+            }
+            let lp_supply = self.mint(self.multi_pool.lp_mint).map_or(0, |m| m.supply);
+            assert_eq!(
+                pool.total_lp_supply, lp_supply,
+                "multi-asset LP supply out of sync with LP mint after {step:?}"
+            );
+        }
+
+        // All positions belong to the user, so the active liquidity is the sum
+        // of the positions whose range holds the current tick
+        if let Some(pool) = self.program_state::<ConcentratedPool>(self.concentrated.pool) {
+            let active: u128 = self
+                .concentrated
+                .positions
+                .iter()
+                .filter_map(|&index| self.program_state::<Position>(index))
+                .filter(|position| (position.tick_lower..position.tick_upper).contains(&pool.current_tick))
+                .map(|position| position.liquidity)
+                .sum();
+            assert_eq!(
+                pool.liquidity, active,
+                "concentrated liquidity out of sync with its positions after {step:?}"
+            );
+        }
+    }
+
+    // ---------------------------------------------------------------------
+    // Instruction builders
+    // ---------------------------------------------------------------------
+
+    fn pool_slot(&self, pool: u8) -> PoolSlot {
+        self.pools[pool as usize % self.pools.len()]
+    }
+
+    // The user's account for `mint`; for wrapped GOR the wrap account, which
+    // only WithNative creates.
+    fn user_token_for(&self, mint: usize) -> usize {
+        if mint == self.native_mint {
+            return self.wrap;
+        }
+        let position = self.mints.iter().position(|&m| m == mint).unwrap();
+        self.user_tokens[position]
+    }
+
+    // Table index of a token mint chosen by the fuzzer: base mints, wrapped
+    // GOR, LP mints or an account that is not a mint at all.
+    fn token_choice(&self, token: u8) -> usize {
+        let choices: Vec<usize> = self
+            .mints
+            .iter()
+            .copied()
+            .chain([self.native_mint])
+            .chain(self.pools.iter().map(|slot| slot.lp_mint))
+            .chain([self.multi_pool.lp_mint, self.user])
+            .collect();
+        choices[token as usize % choices.len()]
+    }
+
+    fn token_path(&self, token_path: &Option<Vec<u8>>, path: &[usize]) -> Vec<Pubkey> {
+        match token_path {
+            Some(tokens) => tokens
+                .iter()
+                .take(MAX_HOPS + 1)
+                .map(|&token| self.key(self.token_choice(token)))
+                .collect(),
+            None => path.iter().map(|&mint| self.key(mint)).collect(),
+        }
+    }
+
+    // Walks a route from one side of its first pool, returning the pools used
+    // and the mint sequence they imply.
+    fn walk(&self, route: &Route) -> Option<(Vec<PoolSlot>, Vec<usize>)> {
+        let first = self.pool_slot(*route.pools.first()?);
+        let start = if route.start_with_b { first.token_b } else { first.token_a };
+        Some(self.walk_from(start, &route.pools))
+    }
+
+    fn walk_from(&self, start: usize, pools: &[u8]) -> (Vec<PoolSlot>, Vec<usize>) {
+        let pools: Vec<PoolSlot> = pools.iter().take(MAX_HOPS).map(|&pool| self.pool_slot(pool)).collect();
+        let mut path = vec![start];
+        for slot in &pools {
+            let current = *path.last().unwrap();
+            path.push(if current == slot.token_a { slot.token_b } else { slot.token_a });
+        }
+        (pools, path)
+    }
+
+    // Oracles route instructions pass after the route, one per pool with one.
+    fn route_oracles(&self, pools: &[PoolSlot]) -> Vec<usize> {
+        pools
+            .iter()
+            .filter(|slot| self.has_oracle(slot))
+            .map(|slot| slot.oracle)
+            .collect()
+    }
+
+    // The pool's oracle, for the instructions that pass it only when it has one.
+    fn pool_oracle(&self, slot: &PoolSlot) -> Option<usize> {
+        self.has_oracle(slot).then_some(slot.oracle)
+    }
+
+    fn multihop_accounts(&self, pools: &[PoolSlot], path: &[usize]) -> Vec<usize> {
+        let mut accounts = vec![self.user, self.token_program, self.user_token_for(path[0])];
+        for (slot, output_mint) in pools.iter().zip(&path[1..]) {
+            let output = self.user_token_for(*output_mint);
+            accounts.extend([
+                slot.pool,
+                slot.token_a,
+                slot.token_b,
+                slot.vault_a,
+                slot.vault_b,
+                output,
+                output,
+            ]);
+        }
+        accounts.extend(self.route_oracles(pools));
+        accounts
+    }
+
+    // `[pool, vault_in, vault_out]` per hop, then the route's mints.
+    fn compact_route_accounts(&self, pools: &[PoolSlot], path: &[usize]) -> Vec<usize> {
+        let mut accounts = Vec::new();
+        for (slot, mints) in pools.iter().zip(path.windows(2)) {
+            let vault = |mint| if mint == slot.token_a { slot.vault_a } else { slot.vault_b };
+            accounts.extend([slot.pool, vault(mints[0]), vault(mints[1])]);
+        }
+        accounts.extend(path);
+        accounts
+    }
+
+    fn init_pool_accounts(&self, pool: u8) -> Vec<usize> {
+        let slot = self.pool_slot(pool);
+        vec![
+            slot.pool,
+            slot.token_a,
+            slot.token_b,
+            slot.vault_a,
+            slot.vault_b,
+            slot.lp_mint,
+            self.user,
+            self.user_token_for(slot.token_a),
+            self.user_token_for(slot.token_b),
+            slot.user_lp,
+            self.token_program,
+            self.system_program,
+            self.rent,
+            self.ata_program,
+        ]
+    }
+
+    // `[mint, vault, user_token]` per token of the multi-asset pool.
+    fn multi_pool_token_accounts(&self) -> Vec<usize> {
+        self.mints
+            .iter()
+            .zip(&self.multi_pool.vaults)
+            .flat_map(|(&mint, &vault)| [mint, vault, self.user_token_for(mint)])
+            .collect()
+    }
+
+    fn multi_liquidity_accounts(&self) -> Vec<usize> {
+        let slot = self.multi_pool;
+        let mut accounts = vec![slot.pool, slot.lp_mint, slot.user_lp, self.user, self.token_program];
+        accounts.extend(self.multi_pool_token_accounts());
+        accounts
+    }
+
+    fn position_accounts(&self, position: u8) -> Vec<usize> {
+        let slot = self.concentrated;
+        let (token_a, token_b) = (self.mints[0], self.mints[1]);
+        vec![
+            slot.pool,
+            slot.positions[position as usize % slot.positions.len()],
+            token_a,
+            token_b,
+            slot.vault_a,
+            slot.vault_b,
+            self.user_token_for(token_a),
+            self.user_token_for(token_b),
+            self.user,
+            self.token_program,
+        ]
+    }
+
+    fn instruction(&self, instruction: &FuzzInstruction) -> Option<(TestProjectInstruction, Vec<usize>)> {
+        Some(match instruction {
+            FuzzInstruction::InitPool { pool, amount_a, amount_b } => (
+                TestProjectInstruction::InitPool {
+                    amount_a: *amount_a,
+                    amount_b: *amount_b,
+                },
+                self.init_pool_accounts(*pool),
+            ),
+            FuzzInstruction::InitPoolWithCurve {
+                pool,
+                amount_a,
+                amount_b,
+                curve,
+            } => (
+                TestProjectInstruction::InitPoolWithCurve {
+                    amount_a: *amount_a,
+                    amount_b: *amount_b,
+                    curve: curve.into(),
+                },
+                self.init_pool_accounts(*pool),
+            ),
+            FuzzInstruction::RampAmp {
+                pool,
+                target_amp,
+                ramp_stop_ts,
+            } => (
+                TestProjectInstruction::RampAmp {
+                    target_amp: *target_amp,
+                    // Relative to the current clock so ramps are reachable
+                    ramp_stop_ts: self.unix_timestamp.saturating_add(*ramp_stop_ts),
+                },
+                vec![self.pool_slot(*pool).pool, self.user],
+            ),
+            FuzzInstruction::StopRampAmp { pool } => (
+                TestProjectInstruction::StopRampAmp,
+                vec![self.pool_slot(*pool).pool, self.user],
+            ),
+            FuzzInstruction::AddLiquidity { pool, amount_a, amount_b } => {
+                let slot = self.pool_slot(*pool);
+                let mut accounts = vec![
+                    slot.pool,
+                    slot.token_a,
+                    slot.token_b,
+                    slot.vault_a,
+                    slot.vault_b,
+                    slot.lp_mint,
+                    self.user_token_for(slot.token_a),
+                    self.user_token_for(slot.token_b),
+                    slot.user_lp,
+                    self.user,
+                    self.token_program,
+                ];
+                accounts.extend(self.pool_oracle(&slot));
+                (
+                    TestProjectInstruction::AddLiquidity {
+                        amount_a: *amount_a,
+                        amount_b: *amount_b,
+                    },
+                    accounts,
+                )
+            }
+            FuzzInstruction::RemoveLiquidity { pool, lp_amount } => {
+                let slot = self.pool_slot(*pool);
+                let mut accounts = vec![
+                    slot.pool,
+                    slot.token_a,
+                    slot.token_b,
+                    slot.vault_a,
+                    slot.vault_b,
+                    slot.lp_mint,
+                    slot.user_lp,
+                    self.user_token_for(slot.token_a),
+                    self.user_token_for(slot.token_b),
+                    self.user,
+                    self.token_program,
+                ];
+                accounts.extend(self.pool_oracle(&slot));
+                (TestProjectInstruction::RemoveLiquidity { lp_amount: *lp_amount }, accounts)
+            }
+            FuzzInstruction::Swap { pool, amount_in, direction_a_to_b } => {
+                let slot = self.pool_slot(*pool);
+                let (mint_in, mint_out) = if *direction_a_to_b {
+                    (slot.token_a, slot.token_b)
+                } else {
+                    (slot.token_b, slot.token_a)
+                };
+                let mut accounts = vec![
+                    slot.pool,
+                    slot.token_a,
+                    slot.token_b,
+                    slot.vault_a,
+                    slot.vault_b,
+                    self.user_token_for(mint_in),
+                    self.user_token_for(mint_out),
+                    self.user,
+                    self.token_program,
+                ];
+                accounts.extend(self.pool_oracle(&slot));
+                (
+                    TestProjectInstruction::Swap {
+                        amount_in: *amount_in,
+                        direction_a_to_b: *direction_a_to_b,
+                    },
+                    accounts,
+                )
+            }
+            FuzzInstruction::MultihopSwap { route, amount_in, minimum_amount_out } => {
+                let (pools, path) = self.walk(route)?;
+                (
+                    TestProjectInstruction::MultihopSwap {
+                        amount_in: *amount_in,
+                        minimum_amount_out: *minimum_amount_out,
+                    },
+                    self.multihop_accounts(&pools, &path),
+                )
+            }
+            FuzzInstruction::MultihopSwapWithPath {
+                route,
+                amount_in,
+                minimum_amount_out,
+                token_path,
+            } => {
+                let (pools, path) = self.walk(route)?;
+                (
+                    TestProjectInstruction::MultihopSwapWithPath {
+                        amount_in: *amount_in,
+                        minimum_amount_out: *minimum_amount_out,
+                        token_path: self.token_path(token_path, &path),
+                    },
+                    self.multihop_accounts(&pools, &path),
+                )
+            }
+            FuzzInstruction::MultihopSwapWithHopMinimums {
+                route,
+                amount_in,
+                minimum_amount_out,
+                minimum_hop_amounts_out,
+            } => {
+                let (pools, path) = self.walk(route)?;
+                (
+                    TestProjectInstruction::MultihopSwapWithHopMinimums {
+                        amount_in: *amount_in,
+                        minimum_amount_out: *minimum_amount_out,
+                        minimum_hop_amounts_out: minimum_hop_amounts_out.clone(),
+                    },
+                    self.multihop_accounts(&pools, &path),
+                )
+            }
+            FuzzInstruction::MultihopSwapWithPathAndHopMinimums {
+                route,
+                amount_in,
+                minimum_amount_out,
+                token_path,
+                minimum_hop_amounts_out,
+            } => {
+                let (pools, path) = self.walk(route)?;
+                (
+                    TestProjectInstruction::MultihopSwapWithPathAndHopMinimums {
+                        amount_in: *amount_in,
+                        minimum_amount_out: *minimum_amount_out,
+                        token_path: self.token_path(token_path, &path),
+                        minimum_hop_amounts_out: minimum_hop_amounts_out.clone(),
+                    },
+                    self.multihop_accounts(&pools, &path),
+                )
+            }
+            FuzzInstruction::CompactMultihopSwap {
+                route,
+                amount_in,
+                minimum_amount_out,
+                minimum_hop_amounts_out,
+            } => {
+                let (pools, path) = self.walk(route)?;
+                let mut accounts = vec![self.user, self.token_program];
+                accounts.extend(self.compact_route_accounts(&pools, &path));
+                accounts.push(self.user_token_for(path[0]));
+                accounts.push(self.user_token_for(*path.last().unwrap()));
+                accounts.extend(self.route_oracles(&pools));
+                (
+                    TestProjectInstruction::CompactMultihopSwap {
+                        amount_in: *amount_in,
+                        minimum_amount_out: *minimum_amount_out,
+                        minimum_hop_amounts_out: minimum_hop_amounts_out.clone(),
+                    },
+                    accounts,
+                )
+            }
+            FuzzInstruction::SplitRouteSwap {
+                start_with_b,
+                routes,
+                minimum_amount_out,
+            } => {
+                let first = self.pool_slot(*routes.first()?.0.first()?);
+                let start = if *start_with_b { first.token_b } else { first.token_a };
+                let walks: Vec<_> = routes
+                    .iter()
+                    .take(MAX_ROUTES)
+                    .map(|(pools, amount_in)| (self.walk_from(start, pools), *amount_in))
+                    .collect();
+                let end = *walks[0].0 .1.last().unwrap();
+                let mut accounts = vec![
+                    self.user,
+                    self.token_program,
+                    self.user_token_for(start),
+                    self.user_token_for(end),
+                ];
+                let mut all_pools = Vec::new();
+                for ((pools, path), _) in &walks {
+                    accounts.extend(self.compact_route_accounts(pools, path));
+                    all_pools.extend(pools);
+                }
+                accounts.extend(self.route_oracles(&all_pools));
+                (
+                    TestProjectInstruction::SplitRouteSwap {
+                        routes: walks
+                            .iter()
+                            .map(|((pools, _), amount_in)| SplitRoute {
+                                amount_in: *amount_in,
+                                num_hops: pools.len() as u8,
+                            })
+                            .collect(),
+                        minimum_amount_out: *minimum_amount_out,
+                    },
+                    accounts,
+                )
+            }
+            FuzzInstruction::GetPoolInfo { pool } => (
+                TestProjectInstruction::GetPoolInfo,
+                vec![self.pool_slot(*pool).pool],
+            ),
+            FuzzInstruction::GetTotalPools => (TestProjectInstruction::GetTotalPools, Vec::new()),
+            FuzzInstruction::FindPoolsByToken { token } => (
+                TestProjectInstruction::FindPoolsByToken {
+                    token_address: self.key(self.token_choice(*token)),
+                },
+                Vec::new(),
+            ),
+            FuzzInstruction::GetSwapQuote { pool, amount_in, token_in } => (
+                TestProjectInstruction::GetSwapQuote {
+                    amount_in: *amount_in,
+                    token_in: self.key(self.token_choice(*token_in)),
+                },
+                vec![self.pool_slot(*pool).pool],
+            ),
+            FuzzInstruction::GetMultihopQuote { route, amount_in } => {
+                let (pools, path) = self.walk(route)?;
+                (
+                    TestProjectInstruction::GetMultihopQuote {
+                        amount_in: *amount_in,
+                        token_path: path.iter().map(|&mint| self.key(mint)).collect(),
+                    },
+                    pools.iter().map(|slot| slot.pool).collect(),
+                )
+            }
+            FuzzInstruction::InitMultiPool { amounts } => {
+                let slot = self.multi_pool;
+                let mut accounts = vec![
+                    slot.pool,
+                    slot.lp_mint,
+                    self.user,
+                    slot.user_lp,
+                    self.token_program,
+                    self.system_program,
+                    self.rent,
+                    self.ata_program,
+                ];
+                accounts.extend(self.multi_pool_token_accounts());
+                (TestProjectInstruction::InitMultiPool { amounts: amounts.clone() }, accounts)
+            }
+            FuzzInstruction::MultiPoolSwap {
+                token_in,
+                token_out,
+                amount_in,
+                minimum_amount_out,
+            } => {
+                let token_in = *token_in as usize % MINT_COUNT;
+                let token_out = *token_out as usize % MINT_COUNT;
+                (
+                    TestProjectInstruction::MultiPoolSwap {
+                        amount_in: *amount_in,
+                        minimum_amount_out: *minimum_amount_out,
+                    },
+                    vec![
+                        self.multi_pool.pool,
+                        self.mints[token_in],
+                        self.mints[token_out],
+                        self.multi_pool.vaults[token_in],
+                        self.multi_pool.vaults[token_out],
+                        self.user_tokens[token_in],
+                        self.user_tokens[token_out],
+                        self.user,
+                        self.token_program,
+                    ],
+                )
+            }
+            FuzzInstruction::AddMultiLiquidity { amounts } => (
+                TestProjectInstruction::AddMultiLiquidity { amounts: amounts.clone() },
+                self.multi_liquidity_accounts(),
+            ),
+            FuzzInstruction::RemoveMultiLiquidity { lp_amount } => (
+                TestProjectInstruction::RemoveMultiLiquidity { lp_amount: *lp_amount },
+                self.multi_liquidity_accounts(),
+            ),
+            FuzzInstruction::AddMultiLiquiditySingle {
+                token,
+                amount_in,
+                minimum_lp_out,
+            } => {
+                let token = *token as usize % MINT_COUNT;
+                (
+                    TestProjectInstruction::AddMultiLiquiditySingle {
+                        amount_in: *amount_in,
+                        minimum_lp_out: *minimum_lp_out,
+                    },
+                    vec![
+                        self.multi_pool.pool,
+                        self.mints[token],
+                        self.multi_pool.vaults[token],
+                        self.multi_pool.lp_mint,
+                        self.user_tokens[token],
+                        self.multi_pool.user_lp,
+                        self.user,
+                        self.token_program,
+                    ],
+                )
+            }
+            FuzzInstruction::RemoveMultiLiquiditySingle {
+                token,
+                lp_amount,
+                minimum_amount_out,
+            } => {
+                let token = *token as usize % MINT_COUNT;
+                (
+                    TestProjectInstruction::RemoveMultiLiquiditySingle {
+                        lp_amount: *lp_amount,
+                        minimum_amount_out: *minimum_amount_out,
+                    },
+                    vec![
+                        self.multi_pool.pool,
+                        self.mints[token],
+                        self.multi_pool.vaults[token],
+                        self.multi_pool.lp_mint,
+                        self.multi_pool.user_lp,
+                        self.user_tokens[token],
+                        self.user,
+                        self.token_program,
+                    ],
+                )
+            }
+            FuzzInstruction::InitConcentratedPool {
+                tick_spacing,
+                initial_sqrt_price,
+            } => {
+                let slot = self.concentrated;
+                (
+                    TestProjectInstruction::InitConcentratedPool {
+                        tick_spacing: *tick_spacing,
+                        initial_sqrt_price: *initial_sqrt_price,
+                    },
+                    vec![
+                        slot.pool,
+                        self.mints[0],
+                        self.mints[1],
+                        slot.vault_a,
+                        slot.vault_b,
+                        self.user,
+                        self.token_program,
+                        self.system_program,
+                        self.rent,
+                    ],
+                )
+            }
+            FuzzInstruction::OpenPosition { position } => {
+                let index = *position as usize % POSITION_RANGES.len();
+                let (tick_lower, tick_upper) = POSITION_RANGES[index];
+                (
+                    TestProjectInstruction::OpenPosition { tick_lower, tick_upper },
+                    vec![
+                        self.concentrated.pool,
+                        self.concentrated.positions[index],
+                        self.user,
+                        self.system_program,
+                    ],
+                )
+            }
+            FuzzInstruction::IncreaseLiquidity {
+                position,
+                liquidity,
+                maximum_amount_a,
+                maximum_amount_b,
+            } => (
+                TestProjectInstruction::IncreaseLiquidity {
+                    liquidity: *liquidity as u128,
+                    maximum_amount_a: *maximum_amount_a,
+                    maximum_amount_b: *maximum_amount_b,
+                },
+                self.position_accounts(*position),
+            ),
+            FuzzInstruction::DecreaseLiquidity {
+                position,
+                liquidity,
+                minimum_amount_a,
+                minimum_amount_b,
+            } => (
+                TestProjectInstruction::DecreaseLiquidity {
+                    liquidity: *liquidity as u128,
+                    minimum_amount_a: *minimum_amount_a,
+                    minimum_amount_b: *minimum_amount_b,
+                },
+                self.position_accounts(*position),
+            ),
+            FuzzInstruction::CollectFees { position } => {
+                (TestProjectInstruction::CollectFees, self.position_accounts(*position))
+            }
+            FuzzInstruction::ConcentratedSwap {
+                amount_in,
+                minimum_amount_out,
+                direction_a_to_b,
+            } => {
+                let slot = self.concentrated;
+                let (token_in, token_out) = if *direction_a_to_b { (0, 1) } else { (1, 0) };
+                (
+                    TestProjectInstruction::ConcentratedSwap {
+                        amount_in: *amount_in,
+                        minimum_amount_out: *minimum_amount_out,
+                        direction_a_to_b: *direction_a_to_b,
+                    },
+                    vec![
+                        slot.pool,
+                        self.mints[0],
+                        self.mints[1],
+                        slot.vault_a,
+                        slot.vault_b,
+                        self.user_tokens[token_in],
+                        self.user_tokens[token_out],
+                        self.user,
+                        self.token_program,
+                    ],
+                )
+            }
+            FuzzInstruction::FlashLoan { pool, token, amount, .. } => {
+                let pool = *pool as usize % (POOL_COUNT + 1);
+                let (pool, mint, vault, oracle) = match self.pools.get(pool) {
+                    Some(slot) if token % 2 == 0 => (slot.pool, slot.token_a, slot.vault_a, self.pool_oracle(slot)),
+                    Some(slot) => (slot.pool, slot.token_b, slot.vault_b, self.pool_oracle(slot)),
+                    None => {
+                        let token = *token as usize % MINT_COUNT;
+                        (self.multi_pool.pool, self.mints[token], self.multi_pool.vaults[token], None)
+                    }
+                };
+                let mut accounts = vec![
+                    pool,
+                    mint,
+                    vault,
+                    self.user_token_for(mint),
+                    self.flash_receiver,
+                    self.token_program,
+                ];
+                accounts.extend(oracle);
+                // The receiver repays as the user
+                accounts.push(self.user);
+                (TestProjectInstruction::FlashLoan { amount: *amount }, accounts)
+            }
+            FuzzInstruction::FlashSwap {
+                pool,
+                amount_a_out,
+                amount_b_out,
+                ..
+            } => {
+                let slot = self.pool_slot(*pool);
+                let user_a = self.user_token_for(slot.token_a);
+                let user_b = self.user_token_for(slot.token_b);
+                let mut accounts = vec![
+                    slot.pool,
+                    slot.token_a,
+                    slot.token_b,
+                    slot.vault_a,
+                    slot.vault_b,
+                    user_a,
+                    user_b,
+                    self.flash_receiver,
+                    self.token_program,
+                    self.token_program,
+                ];
+                accounts.extend(self.pool_oracle(&slot));
+                // The receiver pays from the user's accounts as the user
+                accounts.extend([user_a, user_b, self.user]);
+                (
+                    TestProjectInstruction::FlashSwap {
+                        amount_a_out: *amount_a_out,
+                        amount_b_out: *amount_b_out,
+                    },
+                    accounts,
+                )
+            }
+            FuzzInstruction::WithNative { wrap_amount, instruction } => {
+                let (instruction, inner_accounts) = self.instruction(instruction)?;
+                let mut accounts = vec![
+                    self.user,
+                    self.wrap,
+                    self.native_mint,
+                    self.system_program,
+                    self.token_program,
+                ];
+                accounts.extend(inner_accounts);
+                (
+                    TestProjectInstruction::WithNative {
+                        wrap_amount: *wrap_amount,
+                        instruction_data: instruction.try_to_vec().unwrap(),
+                    },
+                    accounts,
+                )
+            }
+            FuzzInstruction::MigratePool { pool } => (
+                TestProjectInstruction::MigratePool,
+                vec![self.pool_slot(*pool).pool, self.user, self.system_program],
+            ),
+            FuzzInstruction::GetTwap { pool, since } => {
+                let slot = self.pool_slot(*pool);
+                let since = match *since {
+                    Some((seconds_ago, price_a_cumulative, price_b_cumulative)) => PriceObservation {
+                        timestamp: self.unix_timestamp - seconds_ago as i64,
+                        price_a_cumulative,
+                        price_b_cumulative,
+                    },
+                    None => {
+                        let pool = self.pool_state(&slot)?;
+                        PriceObservation {
+                            timestamp: pool.last_update_timestamp,
+                            price_a_cumulative: pool.price_a_cumulative,
+                            price_b_cumulative: pool.price_b_cumulative,
+                        }
+                    }
+                };
+                (TestProjectInstruction::GetTwap { since }, vec![slot.pool])
+            }
+            FuzzInstruction::InitOracle { pool, capacity } => {
+                let slot = self.pool_slot(*pool);
+                (
+                    TestProjectInstruction::InitOracle { capacity: *capacity },
+                    vec![slot.pool, slot.oracle, self.user, self.system_program],
+                )
+            }
+            FuzzInstruction::GrowOracle { pool, capacity } => (
+                TestProjectInstruction::GrowOracle { capacity: *capacity },
+                vec![self.pool_slot(*pool).oracle, self.user, self.system_program],
+            ),
+            FuzzInstruction::Observe { pool, seconds_agos } => {
+                let slot = self.pool_slot(*pool);
+                (
+                    TestProjectInstruction::Observe {
+                        seconds_agos: seconds_agos.clone(),
+                    },
+                    vec![slot.pool, slot.oracle],
+                )
+            }
+        })
+    }
+
+    // Turns a fuzz step into instruction data and table indices.
+    fn step(&self, step: &FuzzStep) -> Option<(Vec<u8>, Vec<usize>)> {
+        let table_len = self.accounts.len();
+        match step {
+            FuzzStep::Raw { data, accounts } => Some((
+                data.clone(),
+                accounts.iter().map(|&index| index as usize % table_len).collect(),
+            )),
+            FuzzStep::Instruction { instruction, substitutions } => {
+                let (instruction, mut accounts) = self.instruction(instruction)?;
+                for &(position, replacement) in substitutions {
+                    if accounts.is_empty() {
+                        break;
+                    }
+                    let position = position as usize % accounts.len();
+                    accounts[position] = replacement as usize % table_len;
+                }
+                Some((instruction.try_to_vec().unwrap(), accounts))
+            }
+            FuzzStep::Warp { .. } | FuzzStep::Downgrade { .. } => None,
+        }
+    }
+}
+
+impl Default for World {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+// Entry point shared by the fuzz targets.
+pub fn run(input: &FuzzInput) {
+    let mut world = World::new();
+
+    for (pool, (amount_a, amount_b, curve)) in input.pools.iter().enumerate() {
+        // Keep parameters in a range where InitPool usually succeeds
+        let curve = match *curve {
+            FuzzCurve::Stable { amp } => FuzzCurve::Stable {
+                amp: amp % (stable::MAX_AMP + 1),
+            },
+            FuzzCurve::ConstantPrice { token_b_price } => FuzzCurve::ConstantPrice {
+                token_b_price: token_b_price % 1_000,
+            },
+            FuzzCurve::Offset { token_a_offset, token_b_offset } => FuzzCurve::Offset {
+                token_a_offset: token_a_offset % INITIAL_BALANCE,
+                token_b_offset: token_b_offset % INITIAL_BALANCE,
+            },
+            FuzzCurve::Weighted { weight_a, .. } => {
+                let weight_a = 100 + weight_a % 9_801;
+                FuzzCurve::Weighted {
+                    weight_a,
+                    weight_b: 10_000 - weight_a,
+                }
+            }
+            FuzzCurve::ConstantProduct => FuzzCurve::ConstantProduct,
+        };
+        let init = FuzzStep::Instruction {
+            instruction: FuzzInstruction::InitPoolWithCurve {
+                pool: pool as u8,
+                amount_a: amount_a % INITIAL_BALANCE,
+                amount_b: amount_b % INITIAL_BALANCE,
+                curve,
+            },
+            substitutions: Vec::new(),
+        };
+        run_step(&mut world, &init);
+    }
+
+    let (amount_a, amount_b) = input.native_pool;
+    let (tick_spacing, tick) = input.concentrated;
+    let tick_spacing = TICK_SPACINGS[tick_spacing as usize % TICK_SPACINGS.len()];
+    let initial_sqrt_price = concentrated::sqrt_price_at_tick(tick % 10_000).unwrap();
+    let setup = [
+        FuzzInstruction::WithNative {
+            wrap_amount: amount_b % INITIAL_BALANCE,
+            instruction: Box::new(FuzzInstruction::InitPool {
+                pool: POOL_PAIRS.len() as u8,
+                amount_a: amount_a % INITIAL_BALANCE,
+                amount_b: amount_b % INITIAL_BALANCE,
+            }),
+        },
+        FuzzInstruction::InitMultiPool {
+            amounts: input.multi_pool.map(|amount| amount % INITIAL_BALANCE).to_vec(),
+        },
+        FuzzInstruction::InitConcentratedPool {
+            tick_spacing,
+            initial_sqrt_price,
+        },
+    ];
+    for instruction in setup {
+        let init = FuzzStep::Instruction {
+            instruction,
+            substitutions: Vec::new(),
+        };
+        run_step(&mut world, &init);
+    }
+
+    for step in input.steps.iter().take(MAX_STEPS) {
+        run_step(&mut world, step);
+    }
+}
+
+fn run_step(world: &mut World, step: &FuzzStep) {
+    match *step {
+        FuzzStep::Warp { seconds } => {
+            world.unix_timestamp += seconds as i64;
+            return;
+        }
+        FuzzStep::Downgrade { pool } => {
+            world.downgrade(pool);
+            return;
+        }
+        _ => {}
+    }
+    let Some((data, accounts)) = world.step(step) else {
+        return;
+    };
+    *FLASH_REPAYMENT.lock().unwrap() = match step {
+        FuzzStep::Instruction {
+            instruction:
+                FuzzInstruction::FlashLoan { repayment, .. } | FuzzInstruction::FlashSwap { repayment, .. },
+            ..
+        } => Some(*repayment),
+        _ => None,
+    };
+    let before = world.observe();
+    if world.process(&accounts, &data).is_ok() {
+        world.check_invariants(&before, step);
+    }
+}
+
+// ---------------------------------------------------------------------------
+// CPI stand-ins
+// ---------------------------------------------------------------------------
+
+// Clock the stubs report; set from the world before each instruction.
+static UNIX_TIMESTAMP: AtomicI64 = AtomicI64::new(GENESIS_UNIX_TIMESTAMP);
+// Return data of the last program to set any; cleared on every invocation
+// like the runtime does.
+static RETURN_DATA: Mutex<Option<(Pubkey, Vec<u8>)>> = Mutex::new(None);
+// How the flash receiver repays the current instruction, if at all.
+static FLASH_REPAYMENT: Mutex<Option<FlashRepayment>> = Mutex::new(None);
+
+fn set_return_data(program_id: Pubkey, data: &[u8]) {
+    *RETURN_DATA.lock().unwrap() = Some((program_id, data.to_vec()));
+}
+
+fn install_stubs() {
+    static INSTALL: Once = Once::new();
+    INSTALL.call_once(|| {
+        set_syscall_stubs(Box::new(StandInStubs));
+    });
+}
+
+struct StandInStubs;
+
+impl SyscallStubs for StandInStubs {
+    fn sol_log(&self, _message: &str) {}
+
+    fn sol_log_compute_units(&self) {}
+
+    fn sol_get_clock_sysvar(&self, var_addr: *mut u8) -> u64 {
+        let clock = Clock {
+            unix_timestamp: UNIX_TIMESTAMP.load(Ordering::Relaxed),
+            ..Clock::default()
+        };
+        // Safety: the runtime hands us space for exactly one Clock
+        unsafe { *(var_addr as *mut Clock) = clock };
+        solana_program::entrypoint::SUCCESS
+    }
+
+    fn sol_get_rent_sysvar(&self, var_addr: *mut u8) -> u64 {
+        // Safety: as for the clock
+        unsafe { *(var_addr as *mut Rent) = Rent::default() };
+        solana_program::entrypoint::SUCCESS
+    }
+
+    fn sol_get_return_data(&self) -> Option<(Pubkey, Vec<u8>)> {
+        RETURN_DATA.lock().unwrap().clone().filter(|(_, data)| !data.is_empty())
+    }
+
+    // Only the program under test calls this natively
+    fn sol_set_return_data(&self, data: &[u8]) {
+        set_return_data(cargo_swap::id(), data);
+    }
+
+    fn sol_invoke_signed(
+        &self,
+        instruction: &Instruction,
+        account_infos: &[AccountInfo],
+        signers_seeds: &[&[&[u8]]],
+    ) -> ProgramResult {
+        execute_cpi(instruction, account_infos, signers_seeds)
+    }
+}
+
+// Accounts of one CPI after the runtime's privilege checks.
+struct CpiAccounts<'a, 'b> {
+    infos: Vec<&'b AccountInfo<'a>>,
+    signed: Vec<bool>,
+}
+
+impl<'a, 'b> CpiAccounts<'a, 'b> {
+    fn get(&self, index: usize) -> Result<&'b AccountInfo<'a>, ProgramError> {
+        self.infos
+            .get(index)
+            .copied()
+            .ok_or(ProgramError::NotEnoughAccountKeys)
+    }
+
+    fn require_signer(&self, index: usize) -> ProgramResult {
+        if self.signed.get(index).copied().unwrap_or(false) {
+            Ok(())
+        } else {
+            Err(ProgramError::MissingRequiredSignature)
+        }
+    }
+}
+
+fn execute_cpi(
+    instruction: &Instruction,
+    account_infos: &[AccountInfo],
+    signers_seeds: &[&[&[u8]]],
+) -> ProgramResult {
+    let pda_signers = signers_seeds
+        .iter()
+        .map(|seeds| Pubkey::create_program_address(seeds, &cargo_swap::id()))
+        .collect::<Result<Vec<_>, _>>()
+        .map_err(|_| ProgramError::InvalidSeeds)?;
+
+    let mut accounts = CpiAccounts {
+        infos: Vec::new(),
+        signed: Vec::new(),
+    };
+    for meta in &instruction.accounts {
+        let info = account_infos
+            .iter()
+            .find(|info| *info.key == meta.pubkey)
+            .ok_or(ProgramError::NotEnoughAccountKeys)?;
+        if meta.is_writable && !info.is_writable {
+            return Err(ProgramError::InvalidArgument);
+        }
+        let signed = info.is_signer || pda_signers.contains(info.key);
+        if meta.is_signer && !signed {
+            return Err(ProgramError::MissingRequiredSignature);
+        }
+        accounts.infos.push(info);
+        accounts.signed.push(meta.is_signer && signed);
+    }
+    set_return_data(instruction.program_id, &[]);
+
+    if instruction.program_id == system_program::id() {
+        system_instruction(&accounts, &instruction.data)
+    } else if instruction.program_id == TOKEN_PROGRAM_ID {
+        token_instruction(&accounts, &instruction.data)
+    } else if instruction.program_id == ATA_PROGRAM_ID {
+        ata_instruction(&accounts, &instruction.data)
+    } else if instruction.program_id == FLASH_RECEIVER_ID {
+        flash_receiver_instruction(&accounts, &instruction.data)
+    } else {
+        Err(ProgramError::IncorrectProgramId)
+    }
+}
+
+fn read_u64(data: &[u8], offset: usize) -> Result<u64, ProgramError> {
+    data.get(offset..offset + 8)
+        .map(|bytes| u64::from_le_bytes(bytes.try_into().unwrap()))
+        .ok_or(ProgramError::InvalidInstructionData)
+}
+
+fn read_pubkey(data: &[u8], offset: usize) -> Result<Pubkey, ProgramError> {
+    data.get(offset..offset + 32)
+        .map(|bytes| Pubkey::new_from_array(bytes.try_into().unwrap()))
+        .ok_or(ProgramError::InvalidInstructionData)
+}
+
+// CreateAccount, Assign, Transfer and Allocate. New data is allocated by
+// reallocating the account, which the harness's buffers leave room for.
+fn system_instruction(accounts: &CpiAccounts, data: &[u8]) -> ProgramResult {
+    let Some(kind) = data.get(0..4) else {
+        return Err(ProgramError::InvalidInstructionData);
+    };
+    match u32::from_le_bytes(kind.try_into().unwrap()) {
+        0 => {
+            let lamports = read_u64(data, 4)?;
+            let space = read_u64(data, 12)?;
+            let owner = read_pubkey(data, 20)?;
+            system_create_account(accounts.get(0)?, accounts.get(1)?, lamports, space, &owner)?;
+            accounts.require_signer(0)?;
+            accounts.require_signer(1)
+        }
+        1 => {
+            let account = system_account(accounts, 0)?;
+            account.assign(&read_pubkey(data, 4)?);
+            Ok(())
+        }
+        2 => {
+            let from = system_account(accounts, 0)?;
+            let to = accounts.get(1)?;
+            let lamports = read_u64(data, 4)?;
+            if !from.data_is_empty() {
+                return Err(ProgramError::InvalidArgument);
+            }
+            let remaining = from
+                .lamports()
+                .checked_sub(lamports)
+                .ok_or(ProgramError::InsufficientFunds)?;
+            **from.lamports.borrow_mut() = remaining;
+            **to.lamports.borrow_mut() += lamports;
+            Ok(())
+        }
+        8 => {
+            let account = system_account(accounts, 0)?;
+            if !account.data_is_empty() {
+                return Err(ProgramError::AccountAlreadyInitialized);
+            }
+            account.realloc(read_u64(data, 4)? as usize, true)
+        }
+        _ => Err(ProgramError::InvalidInstructionData),
+    }
+}
+
+// A signing account the system program owns.
+fn system_account<'a, 'b>(accounts: &CpiAccounts<'a, 'b>, index: usize) -> Result<&'b AccountInfo<'a>, ProgramError> {
+    let account = accounts.get(index)?;
+    accounts.require_signer(index)?;
+    if *account.owner != system_program::id() {
+        return Err(ProgramError::IncorrectProgramId);
+    }
+    Ok(account)
+}
+
+fn system_create_account(
+    from: &AccountInfo,
+    to: &AccountInfo,
+    lamports: u64,
+    space: u64,
+    owner: &Pubkey,
+) -> ProgramResult {
+    if *to.owner != system_program::id() || to.lamports() != 0 || !to.data_is_empty() {
+        return Err(ProgramError::AccountAlreadyInitialized);
+    }
+    if from.key == to.key {
+        return Err(ProgramError::InvalidArgument);
+    }
+    let remaining = from
+        .lamports()
+        .checked_sub(lamports)
+        .ok_or(ProgramError::InsufficientFunds)?;
+    to.realloc(space as usize, true)?;
+    **from.lamports.borrow_mut() = remaining;
+    **to.lamports.borrow_mut() = lamports;
+    to.assign(owner);
+    Ok(())
+}
+
+fn token_account(info: &AccountInfo) -> Result<TokenAccount, ProgramError> {
+    if *info.owner != TOKEN_PROGRAM_ID {
+        return Err(ProgramError::IncorrectProgramId);
+    }
+    TokenAccount::unpack(&info.data.borrow())
+}
+
+fn mint(info: &AccountInfo) -> Result<Mint, ProgramError> {
+    if *info.owner != TOKEN_PROGRAM_ID {
+        return Err(ProgramError::IncorrectProgramId);
+    }
+    Mint::unpack(&info.data.borrow())
+}
+
+fn initialize_token_account(account: &AccountInfo, mint_info: &AccountInfo, owner: &Pubkey) -> ProgramResult {
+    if *account.owner != TOKEN_PROGRAM_ID {
+        return Err(ProgramError::IncorrectProgramId);
+    }
+    let existing = TokenAccount::unpack_unchecked(&account.data.borrow())?;
+    if existing.state != TokenAccountState::Uninitialized {
+        return Err(TokenError::AlreadyInUse.into());
+    }
+    mint(mint_info)?;
+    let mut state = TokenAccount {
+        mint: *mint_info.key,
+        owner: *owner,
+        state: TokenAccountState::Initialized,
+        ..TokenAccount::default()
+    };
+    // Wrapped SOL: everything above the rent-exempt reserve is the balance
+    if *mint_info.key == cargo_swap::NATIVE_MINT {
+        let reserve = Rent::default().minimum_balance(TokenAccount::LEN);
+        state.is_native = COption::Some(reserve);
+        state.amount = account
+            .lamports()
+            .checked_sub(reserve)
+            .ok_or(TokenError::NotRentExempt)?;
+    }
+    TokenAccount::pack(state, &mut account.data.borrow_mut())
+}
+
+// Transfer and TransferChecked once the checked variant has verified the mint.
+// Wrapped SOL moves its lamports along with the balance.
+fn transfer(
+    accounts: &CpiAccounts,
+    source_index: usize,
+    destination_index: usize,
+    authority_index: usize,
+    amount: u64,
+) -> ProgramResult {
+    let source_info = accounts.get(source_index)?;
+    let destination_info = accounts.get(destination_index)?;
+    let mut source = token_account(source_info)?;
+    let mut destination = token_account(destination_info)?;
+    if source.owner != *accounts.get(authority_index)?.key {
+        return Err(TokenError::OwnerMismatch.into());
+    }
+    accounts.require_signer(authority_index)?;
+    if source.mint != destination.mint {
+        return Err(TokenError::MintMismatch.into());
+    }
+    if source.amount < amount {
+        return Err(TokenError::InsufficientFunds.into());
+    }
+    if source_info.key == destination_info.key {
+        return Ok(());
+    }
+    source.amount -= amount;
+    destination.amount = destination
+        .amount
+        .checked_add(amount)
+        .ok_or(TokenError::Overflow)?;
+    if source.is_native() {
+        **source_info.lamports.borrow_mut() -= amount;
+        **destination_info.lamports.borrow_mut() += amount;
+    }
+    TokenAccount::pack(source, &mut source_info.data.borrow_mut())?;
+    TokenAccount::pack(destination, &mut destination_info.data.borrow_mut())
+}
+
+fn token_instruction(accounts: &CpiAccounts, data: &[u8]) -> ProgramResult {
+    match data.first() {
+        // InitializeMint
+        Some(0) => {
+            let mint_info = accounts.get(0)?;
+            if *mint_info.owner != TOKEN_PROGRAM_ID {
+                return Err(ProgramError::IncorrectProgramId);
+            }
+            let existing = Mint::unpack_unchecked(&mint_info.data.borrow())?;
+            if existing.is_initialized {
+                return Err(TokenError::AlreadyInUse.into());
+            }
+            let decimals = *data.get(1).ok_or(ProgramError::InvalidInstructionData)?;
+            let authority = read_pubkey(data, 2)?;
+            let freeze_authority = match data.get(34) {
+                Some(1) => COption::Some(read_pubkey(data, 35)?),
+                Some(0) => COption::None,
+                _ => return Err(ProgramError::InvalidInstructionData),
+            };
+            let state = Mint {
+                mint_authority: COption::Some(authority),
+                supply: 0,
+                decimals,
+                is_initialized: true,
+                freeze_authority,
+            };
+            Mint::pack(state, &mut mint_info.data.borrow_mut())
+        }
+        // InitializeAccount
+        Some(1) => initialize_token_account(accounts.get(0)?, accounts.get(1)?, accounts.get(2)?.key),
+        // InitializeAccount3
+        Some(18) => initialize_token_account(accounts.get(0)?, accounts.get(1)?, &read_pubkey(data, 1)?),
+        // GetAccountDataSize; no extensions, so every account is the base size
+        Some(21) => {
+            mint(accounts.get(0)?)?;
+            set_return_data(TOKEN_PROGRAM_ID, &(TokenAccount::LEN as u64).to_le_bytes());
+            Ok(())
+        }
+        // Transfer
+        Some(3) => transfer(accounts, 0, 1, 2, read_u64(data, 1)?),
+        // TransferChecked
+        Some(12) => {
+            let amount = read_u64(data, 1)?;
+            let decimals = *data.get(9).ok_or(ProgramError::InvalidInstructionData)?;
+            let mint_info = accounts.get(1)?;
+            if token_account(accounts.get(0)?)?.mint != *mint_info.key {
+                return Err(TokenError::MintMismatch.into());
+            }
+            if mint(mint_info)?.decimals != decimals {
+                return Err(TokenError::MintDecimalsMismatch.into());
+            }
+            transfer(accounts, 0, 2, 3, amount)
+        }
+        // MintTo
+        Some(7) => {
+            let amount = read_u64(data, 1)?;
+            let mint_info = accounts.get(0)?;
+            let destination_info = accounts.get(1)?;
+            let mut mint_state = mint(mint_info)?;
+            let mut destination = token_account(destination_info)?;
+            if mint_state.mint_authority != COption::Some(*accounts.get(2)?.key) {
+                return Err(TokenError::OwnerMismatch.into());
+            }
+            accounts.require_signer(2)?;
+            if destination.mint != *mint_info.key {
+                return Err(TokenError::MintMismatch.into());
+            }
+            mint_state.supply = mint_state.supply.checked_add(amount).ok_or(TokenError::Overflow)?;
+            destination.amount = destination.amount.checked_add(amount).ok_or(TokenError::Overflow)?;
+            Mint::pack(mint_state, &mut mint_info.data.borrow_mut())?;
+            TokenAccount::pack(destination, &mut destination_info.data.borrow_mut())
+        }
+        // Burn
+        Some(8) => {
+            let amount = read_u64(data, 1)?;
+            let account_info = accounts.get(0)?;
+            let mint_info = accounts.get(1)?;
+            let mut account = token_account(account_info)?;
+            let mut mint_state = mint(mint_info)?;
+            if account.owner != *accounts.get(2)?.key {
+                return Err(TokenError::OwnerMismatch.into());
+            }
+            accounts.require_signer(2)?;
+            if account.mint != *mint_info.key {
+                return Err(TokenError::MintMismatch.into());
+            }
+            if account.amount < amount {
+                return Err(TokenError::InsufficientFunds.into());
+            }
+            account.amount -= amount;
+            mint_state.supply -= amount;
+            TokenAccount::pack(account, &mut account_info.data.borrow_mut())?;
+            Mint::pack(mint_state, &mut mint_info.data.borrow_mut())
+        }
+        // CloseAccount; the account goes back to the system program so it can be
+        // created again, as the runtime would after garbage-collecting it
+        Some(9) => {
+            let account_info = accounts.get(0)?;
+            let destination_info = accounts.get(1)?;
+            let account = token_account(account_info)?;
+            if account.owner != *accounts.get(2)?.key {
+                return Err(TokenError::OwnerMismatch.into());
+            }
+            accounts.require_signer(2)?;
+            if !account.is_native() && account.amount != 0 {
+                return Err(TokenError::NonNativeHasBalance.into());
+            }
+            if account_info.key == destination_info.key {
+                return Err(ProgramError::InvalidAccountData);
+            }
+            let lamports = destination_info
+                .lamports()
+                .checked_add(account_info.lamports())
+                .ok_or(TokenError::Overflow)?;
+            **destination_info.lamports.borrow_mut() = lamports;
+            **account_info.lamports.borrow_mut() = 0;
+            account_info.realloc(0, false)?;
+            account_info.assign(&system_program::id());
+            Ok(())
+        }
+        _ => Err(ProgramError::InvalidInstructionData),
+    }
+}
+
+// CreateAssociatedTokenAccount: [payer, ata, owner, mint, rent, token_program, system_program]
+fn ata_instruction(accounts: &CpiAccounts, data: &[u8]) -> ProgramResult {
+    if !data.is_empty() && data != [0] {
+        return Err(ProgramError::InvalidInstructionData);
+    }
+    let payer = accounts.get(0)?;
+    let ata = accounts.get(1)?;
+    let owner = accounts.get(2)?;
+    let mint_info = accounts.get(3)?;
+    let token_program = accounts.get(5)?;
+    accounts.require_signer(0)?;
+    let expected = find_address(
+        &[owner.key.as_ref(), token_program.key.as_ref(), mint_info.key.as_ref()],
+        &ATA_PROGRAM_ID,
+    );
+    if expected != *ata.key || *token_program.key != TOKEN_PROGRAM_ID {
+        return Err(ProgramError::InvalidSeeds);
+    }
+    system_create_account(payer, ata, 1, TokenAccount::LEN as u64, &TOKEN_PROGRAM_ID)?;
+    initialize_token_account(ata, mint_info, owner.key)
+}
+
+// Repays a flash loan or flash swap as FLASH_REPAYMENT says, from the user's
+// token accounts passed as extra accounts and signed for by the user (see
+// FuzzInstruction::FlashLoan and FlashSwap for their order).
+fn flash_receiver_instruction(accounts: &CpiAccounts, data: &[u8]) -> ProgramResult {
+    let instruction =
+        FlashReceiverInstruction::try_from_slice(data).map_err(|_| ProgramError::InvalidInstructionData)?;
+    let Some(repayment) = *FLASH_REPAYMENT.lock().unwrap() else {
+        return Ok(());
+    };
+    match instruction {
+        FlashReceiverInstruction::ReceiveFlashLoan { amount, fee } => {
+            let repay = match repayment {
+                FlashRepayment::Owed { adjustment } => {
+                    amount.saturating_add(fee).saturating_add_signed(adjustment as i64)
+                }
+                FlashRepayment::Amounts { amount_a, .. } => amount_a,
+            };
+            // [receiver token, mint, vault, token program, user]
+            transfer(accounts, 0, 2, 4, repay)
+        }
+        FlashReceiverInstruction::ReceiveFlashSwap { amount_a_out, amount_b_out } => {
+            // What the docs quote for repaying output in the same token
+            let owed = |amount_out: u64, adjustment: i16| {
+                if amount_out == 0 {
+                    return 0;
+                }
+                let owed = (amount_out as u128 * 1000).div_ceil(997);
+                u64::try_from(owed).unwrap_or(u64::MAX).saturating_add_signed(adjustment as i64)
+            };
+            let (repay_a, repay_b) = match repayment {
+                FlashRepayment::Owed { adjustment } => (owed(amount_a_out, adjustment), owed(amount_b_out, adjustment)),
+                FlashRepayment::Amounts { amount_a, amount_b } => (amount_a, amount_b),
+            };
+            // [pool, token A, token B, vault A, vault B, program A, program B,
+            // user token A, user token B, user]
+            if repay_a > 0 {
+                transfer(accounts, 7, 3, 9, repay_a)?;
+            }
+            if repay_b > 0 {
+                transfer(accounts, 8, 4, 9, repay_b)?;
+            }
+            Ok(())
+        }
+    }
+}
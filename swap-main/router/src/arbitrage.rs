@@ -0,0 +1,179 @@
+// Profitable cycles among constant product pools.
+//
+// A cycle is a route that ends in the token it starts from. Constant product
+// hops compose into one curve of the same shape, f(x) = γ·x·Eb / (Ea + γ·x)
+// with γ = 997/1000, so the virtual reserves (Ea, Eb) of a whole cycle follow
+// hop by hop and the input that maximizes f(x) - x is found in closed form.
+// The chosen input is then priced with the program's integer math, and only
+// cycles that still pay back more than they take are reported.
+//
+// An `Opportunity` builds the MultihopSwapWithPath that trades its cycle from
+// and back into the user's associated token account.
+
+use crate::{Route, Router, Step};
+use borsh::BorshSerialize;
+use cargo_swap::{
+    curve::{FEE_DENOMINATOR, FEE_NUMERATOR},
+    CurveType, TestProjectInstruction, GORBCHAIN_ATA_PROGRAM, GORBCHAIN_SPL_TOKEN_PROGRAM,
+};
+use solana_program::{
+    instruction::{AccountMeta, Instruction},
+    pubkey::Pubkey,
+};
+use std::cmp::Reverse;
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Opportunity {
+    // Token the cycle starts and ends in
+    pub token: Pubkey,
+    pub route: Route,
+}
+
+impl Opportunity {
+    pub fn profit(&self) -> u64 {
+        self.route.amount_out - self.route.amount_in
+    }
+
+    // MultihopSwapWithPath from the user's associated token account of `token`
+    // back into it, reverting unless it returns at least `minimum_profit` more
+    // than it takes. Every hop pays into the user's associated account of its
+    // output token, which must exist. The oracles of pools that have one
+    // follow the hops; pools of Token-2022 mints also need that program
+    // appended after them.
+    pub fn instruction(
+        &self,
+        program_id: &Pubkey,
+        user: &Pubkey,
+        minimum_profit: u64,
+    ) -> Instruction {
+        let user_token = associated_token_address(user, &self.token);
+        let mut accounts = vec![
+            AccountMeta::new_readonly(*user, true),
+            AccountMeta::new_readonly(GORBCHAIN_SPL_TOKEN_PROGRAM, false),
+            AccountMeta::new(user_token, false),
+        ];
+        for hop in &self.route.hops {
+            let (token_a, token_b) = if hop.direction_a_to_b {
+                (hop.token_in, hop.token_out)
+            } else {
+                (hop.token_out, hop.token_in)
+            };
+            let vault_a = vault_address(program_id, &hop.pool, &token_a);
+            let vault_b = vault_address(program_id, &hop.pool, &token_b);
+            let user_out = associated_token_address(user, &hop.token_out);
+            accounts.extend([
+                AccountMeta::new(hop.pool, false),
+                AccountMeta::new_readonly(token_a, false),
+                AccountMeta::new_readonly(token_b, false),
+                AccountMeta::new(vault_a, false),
+                AccountMeta::new(vault_b, false),
+                AccountMeta::new(user_out, false),
+                AccountMeta::new(user_out, false),
+            ]);
+        }
+        accounts.extend(
+            self.route
+                .hops
+                .iter()
+                .filter(|hop| hop.has_oracle)
+                .map(|hop| AccountMeta::new(oracle_address(program_id, &hop.pool), false)),
+        );
+        let data = TestProjectInstruction::MultihopSwapWithPath {
+            amount_in: self.route.amount_in,
+            minimum_amount_out: self.route.amount_in.saturating_add(minimum_profit),
+            token_path: self.route.token_path(),
+        };
+        Instruction {
+            program_id: *program_id,
+            accounts,
+            data: data.try_to_vec().unwrap(),
+        }
+    }
+}
+
+impl Router {
+    // Profitable cycles of 2 to `max_hops` constant product hops starting from
+    // each of `tokens`, most profitable first. Each takes the input that
+    // maximizes its profit, capped at `max_amount_in`. A cycle is reported
+    // once per listed token it passes through.
+    pub fn arbitrage_opportunities(
+        &self,
+        tokens: &[Pubkey],
+        max_hops: usize,
+        max_amount_in: u64,
+    ) -> Vec<Opportunity> {
+        let mut opportunities: Vec<Opportunity> = tokens
+            .iter()
+            .flat_map(|token| {
+                self.walks(token, token, max_hops)
+                    .into_iter()
+                    .filter_map(|path| self.opportunity(token, &path, max_amount_in))
+            })
+            .collect();
+        opportunities.sort_by_key(|opportunity| Reverse(opportunity.profit()));
+        opportunities
+    }
+
+    fn opportunity(
+        &self,
+        token: &Pubkey,
+        path: &[Step],
+        max_amount_in: u64,
+    ) -> Option<Opportunity> {
+        let amount_in = self.optimal_amount_in(path)?.min(max_amount_in);
+        if amount_in == 0 {
+            return None;
+        }
+        let route = self.quote_exact_in(path, amount_in)?;
+        if route.amount_out <= route.amount_in {
+            return None;
+        }
+        Some(Opportunity { token: *token, route })
+    }
+
+    // Input maximizing f(x) - x over the cycle, or None when the cycle has a
+    // pool of another curve or even its first unit loses
+    fn optimal_amount_in(&self, path: &[Step]) -> Option<u64> {
+        let gamma = (FEE_DENOMINATOR - FEE_NUMERATOR) as f64 / FEE_DENOMINATOR as f64;
+        let mut virtual_reserves: Option<(f64, f64)> = None;
+        for step in path {
+            let pool = &self.pools[step.pool].pool;
+            if pool.curve_type != CurveType::ConstantProduct {
+                return None;
+            }
+            let (reserve_in, reserve_out) = if step.direction_a_to_b {
+                (pool.reserve_a as f64, pool.reserve_b as f64)
+            } else {
+                (pool.reserve_b as f64, pool.reserve_a as f64)
+            };
+            virtual_reserves = Some(match virtual_reserves {
+                None => (reserve_in, reserve_out),
+                Some((ea, eb)) => {
+                    let denominator = reserve_in + gamma * eb;
+                    (ea * reserve_in / denominator, gamma * eb * reserve_out / denominator)
+                }
+            });
+        }
+        let (ea, eb) = virtual_reserves?;
+        if gamma * eb <= ea {
+            return None;
+        }
+        Some((((gamma * ea * eb).sqrt() - ea) / gamma) as u64)
+    }
+}
+
+fn vault_address(program_id: &Pubkey, pool: &Pubkey, mint: &Pubkey) -> Pubkey {
+    Pubkey::find_program_address(&[b"vault", pool.as_ref(), mint.as_ref()], program_id).0
+}
+
+fn oracle_address(program_id: &Pubkey, pool: &Pubkey) -> Pubkey {
+    Pubkey::find_program_address(&[b"oracle", pool.as_ref()], program_id).0
+}
+
+fn associated_token_address(owner: &Pubkey, mint: &Pubkey) -> Pubkey {
+    Pubkey::find_program_address(
+        &[owner.as_ref(), GORBCHAIN_SPL_TOKEN_PROGRAM.as_ref(), mint.as_ref()],
+        &GORBCHAIN_ATA_PROGRAM,
+    )
+    .0
+}
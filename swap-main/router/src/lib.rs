@@ -0,0 +1,361 @@
+// Off-chain route finding over snapshots of two-token pools.
+//
+// The router indexes pools by token, enumerates every route between two tokens
+// that visits no token (and so no pool) twice, and prices each hop on the
+// pool's own curve exactly as the swap processors do: constant product pools
+// with the 997/1000 math of `calculate_swap_output`, the other curves through
+// their `SwapCurve`. Hops of a route are priced on the snapshot reserves, which
+// is what the program sees since a route never reuses a pool.
+//
+// A `Route` maps onto CompactMultihopSwap (`token_path` and `pools`) and a
+// `SplitQuote` onto SplitRouteSwap (`split_routes`).
+
+use cargo_swap::{
+    curve::{StableCurve, SwapCurve},
+    CurveType, Pool, SplitRoute, MAX_HOPS, MAX_SPLIT_ROUTES,
+};
+use solana_program::{program_error::ProgramError, pubkey::Pubkey};
+use std::collections::HashMap;
+
+pub mod arbitrage;
+
+// A pool account as read from the chain
+pub struct PoolSnapshot {
+    pub address: Pubkey,
+    pub pool: Pool,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct RouteHop {
+    pub pool: Pubkey,
+    pub token_in: Pubkey,
+    pub token_out: Pubkey,
+    // Whether token_in is the pool's token_a
+    pub direction_a_to_b: bool,
+    pub amount_in: u64,
+    pub amount_out: u64,
+    // Whether the pool has an oracle, which swaps through it must pass
+    pub has_oracle: bool,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Route {
+    pub amount_in: u64,
+    pub amount_out: u64,
+    pub hops: Vec<RouteHop>,
+}
+
+impl Route {
+    // Mints along the route, input first: the mints of a CompactMultihopSwap
+    pub fn token_path(&self) -> Vec<Pubkey> {
+        let mut path = vec![self.hops[0].token_in];
+        path.extend(self.hops.iter().map(|hop| hop.token_out));
+        path
+    }
+
+    pub fn pools(&self) -> Vec<Pubkey> {
+        self.hops.iter().map(|hop| hop.pool).collect()
+    }
+}
+
+// An exact-in order spread over pool-disjoint routes
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct SplitQuote {
+    pub amount_in: u64,
+    pub amount_out: u64,
+    pub routes: Vec<Route>,
+}
+
+impl SplitQuote {
+    // `routes` argument of SplitRouteSwap, in the order of `self.routes`
+    pub fn split_routes(&self) -> Vec<SplitRoute> {
+        self.routes
+            .iter()
+            .map(|route| SplitRoute {
+                amount_in: route.amount_in,
+                num_hops: route.hops.len() as u8,
+            })
+            .collect()
+    }
+}
+
+// One hop of a candidate route: an index into the router's pools and the
+// direction it is crossed in
+#[derive(Clone, Copy, Debug)]
+struct Step {
+    pool: usize,
+    direction_a_to_b: bool,
+}
+
+pub struct Router {
+    pools: Vec<PoolSnapshot>,
+    // Pools trading each token, by index into `pools`
+    pools_by_token: HashMap<Pubkey, Vec<usize>>,
+    // Stable pools are priced at their amplification at this time
+    unix_timestamp: i64,
+}
+
+impl Router {
+    pub fn new(pools: Vec<PoolSnapshot>, unix_timestamp: i64) -> Self {
+        let mut pools_by_token: HashMap<Pubkey, Vec<usize>> = HashMap::new();
+        for (index, snapshot) in pools.iter().enumerate() {
+            pools_by_token.entry(snapshot.pool.token_a).or_default().push(index);
+            pools_by_token.entry(snapshot.pool.token_b).or_default().push(index);
+        }
+        Self {
+            pools,
+            pools_by_token,
+            unix_timestamp,
+        }
+    }
+
+    // Route with the most output for exactly `amount_in`, of at most
+    // `max_hops` hops (capped at MAX_HOPS)
+    pub fn best_exact_in(
+        &self,
+        token_in: &Pubkey,
+        token_out: &Pubkey,
+        amount_in: u64,
+        max_hops: usize,
+    ) -> Option<Route> {
+        self.paths(token_in, token_out, max_hops)
+            .iter()
+            .filter_map(|path| self.quote_exact_in(path, amount_in))
+            .max_by_key(|route| route.amount_out)
+    }
+
+    // Route needing the least input for exactly `amount_out`
+    pub fn best_exact_out(
+        &self,
+        token_in: &Pubkey,
+        token_out: &Pubkey,
+        amount_out: u64,
+        max_hops: usize,
+    ) -> Option<Route> {
+        self.paths(token_in, token_out, max_hops)
+            .iter()
+            .filter_map(|path| self.quote_exact_out(path, amount_out))
+            .min_by_key(|route| route.amount_in)
+    }
+
+    // Exact-in order split over up to `max_routes` routes (capped at
+    // MAX_SPLIT_ROUTES) that share no pool, as SplitRouteSwap requires. The
+    // input is handed out in `parts` equal slices, each to the route it adds
+    // the most output on; the best single route is returned instead when the
+    // split does not beat it.
+    pub fn best_split_exact_in(
+        &self,
+        token_in: &Pubkey,
+        token_out: &Pubkey,
+        amount_in: u64,
+        max_hops: usize,
+        max_routes: usize,
+        parts: u64,
+    ) -> Option<SplitQuote> {
+        let single = self.best_exact_in(token_in, token_out, amount_in, max_hops)?;
+        let single = SplitQuote {
+            amount_in,
+            amount_out: single.amount_out,
+            routes: vec![single],
+        };
+        let max_routes = max_routes.clamp(1, MAX_SPLIT_ROUTES);
+        let parts = parts.clamp(1, amount_in);
+        let slice = amount_in / parts;
+
+        let paths = self.paths(token_in, token_out, max_hops);
+        let mut allocated = vec![0u64; paths.len()];
+        let mut outputs = vec![0u64; paths.len()];
+        let mut used: Vec<usize> = Vec::new();
+        for part in 0..parts {
+            let amount = if part + 1 == parts {
+                amount_in - slice * (parts - 1)
+            } else {
+                slice
+            };
+            let mut best: Option<(usize, u64)> = None;
+            for (index, path) in paths.iter().enumerate() {
+                let new_route = !used.contains(&index);
+                if new_route
+                    && (used.len() == max_routes
+                        || used.iter().any(|&other| shares_pool(&paths[other], path)))
+                {
+                    continue;
+                }
+                let Some(route) = self.quote_exact_in(path, allocated[index] + amount) else {
+                    continue;
+                };
+                let gain = route.amount_out.saturating_sub(outputs[index]);
+                let better = match best {
+                    Some((other, output)) => gain > output.saturating_sub(outputs[other]),
+                    None => true,
+                };
+                if better {
+                    best = Some((index, route.amount_out));
+                }
+            }
+            let Some((index, output)) = best else {
+                return Some(single);
+            };
+            if !used.contains(&index) {
+                used.push(index);
+            }
+            allocated[index] += amount;
+            outputs[index] = output;
+        }
+
+        let routes: Vec<Route> = used
+            .iter()
+            .filter_map(|&index| self.quote_exact_in(&paths[index], allocated[index]))
+            .collect();
+        let amount_out = routes.iter().map(|route| route.amount_out).sum::<u64>();
+        if routes.len() != used.len() || amount_out <= single.amount_out {
+            return Some(single);
+        }
+        Some(SplitQuote {
+            amount_in,
+            amount_out,
+            routes,
+        })
+    }
+
+    // Every route from token_in to token_out of 1 to `max_hops` hops that
+    // visits each token once
+    fn paths(&self, token_in: &Pubkey, token_out: &Pubkey, max_hops: usize) -> Vec<Vec<Step>> {
+        if token_in == token_out {
+            return Vec::new();
+        }
+        self.walks(token_in, token_out, max_hops)
+    }
+
+    // Routes from token_in to token_out, which are cycles when the two are the
+    // same token: then only the last hop comes back to it
+    fn walks(&self, token_in: &Pubkey, token_out: &Pubkey, max_hops: usize) -> Vec<Vec<Step>> {
+        let mut paths = Vec::new();
+        let mut visited = vec![*token_in];
+        let mut path = Vec::new();
+        let max_hops = max_hops.min(MAX_HOPS);
+        self.extend_paths(token_out, max_hops, &mut visited, &mut path, &mut paths);
+        paths
+    }
+
+    fn extend_paths(
+        &self,
+        token_out: &Pubkey,
+        max_hops: usize,
+        visited: &mut Vec<Pubkey>,
+        path: &mut Vec<Step>,
+        paths: &mut Vec<Vec<Step>>,
+    ) {
+        if path.len() == max_hops {
+            return;
+        }
+        let token = *visited.last().unwrap();
+        let Some(pools) = self.pools_by_token.get(&token) else {
+            return;
+        };
+        for &index in pools {
+            let pool = &self.pools[index].pool;
+            let direction_a_to_b = pool.token_a == token;
+            let next = if direction_a_to_b { pool.token_b } else { pool.token_a };
+            let arrives = next == *token_out;
+            if (!arrives && visited.contains(&next)) || path.iter().any(|step| step.pool == index) {
+                continue;
+            }
+            path.push(Step {
+                pool: index,
+                direction_a_to_b,
+            });
+            if arrives {
+                paths.push(path.clone());
+            } else {
+                visited.push(next);
+                self.extend_paths(token_out, max_hops, visited, path, paths);
+                visited.pop();
+            }
+            path.pop();
+        }
+    }
+
+    // The pool's curve as the program prices it, with stable pools at the
+    // router's time instead of the on-chain clock
+    fn curve(&self, pool: &Pool) -> Result<Box<dyn SwapCurve>, ProgramError> {
+        match pool.curve_type {
+            CurveType::Stable => Ok(Box::new(StableCurve {
+                amp: pool.stable_params()?.current_amp(self.unix_timestamp),
+            })),
+            curve_type => curve_type.curve(&pool.curve_params),
+        }
+    }
+
+    fn hop(&self, step: Step, amount_in: u64, amount_out: u64) -> RouteHop {
+        let snapshot = &self.pools[step.pool];
+        let (token_in, token_out) = if step.direction_a_to_b {
+            (snapshot.pool.token_a, snapshot.pool.token_b)
+        } else {
+            (snapshot.pool.token_b, snapshot.pool.token_a)
+        };
+        RouteHop {
+            pool: snapshot.address,
+            token_in,
+            token_out,
+            direction_a_to_b: step.direction_a_to_b,
+            amount_in,
+            amount_out,
+            has_oracle: snapshot.pool.has_oracle,
+        }
+    }
+
+    // None when a hop fails or pays out nothing
+    fn quote_exact_in(&self, path: &[Step], amount_in: u64) -> Option<Route> {
+        let mut hops = Vec::with_capacity(path.len());
+        let mut amount = amount_in;
+        for &step in path {
+            let pool = &self.pools[step.pool].pool;
+            let amount_out = self
+                .curve(pool)
+                .and_then(|curve| {
+                    let direction_a_to_b = step.direction_a_to_b;
+                    curve.swap_exact_in(amount, pool.reserve_a, pool.reserve_b, direction_a_to_b)
+                })
+                .ok()
+                .filter(|&amount_out| amount_out > 0)?;
+            hops.push(self.hop(step, amount, amount_out));
+            amount = amount_out;
+        }
+        Some(Route {
+            amount_in,
+            amount_out: amount,
+            hops,
+        })
+    }
+
+    // Priced from the last hop back; None when a pool cannot pay out what the
+    // next hop needs
+    fn quote_exact_out(&self, path: &[Step], amount_out: u64) -> Option<Route> {
+        let mut hops = Vec::with_capacity(path.len());
+        let mut amount = amount_out;
+        for &step in path.iter().rev() {
+            let pool = &self.pools[step.pool].pool;
+            let amount_in = self
+                .curve(pool)
+                .and_then(|curve| {
+                    let direction_a_to_b = step.direction_a_to_b;
+                    curve.swap_exact_out(amount, pool.reserve_a, pool.reserve_b, direction_a_to_b)
+                })
+                .ok()?;
+            hops.push(self.hop(step, amount_in, amount));
+            amount = amount_in;
+        }
+        hops.reverse();
+        Some(Route {
+            amount_in: amount,
+            amount_out,
+            hops,
+        })
+    }
+}
+
+fn shares_pool(path: &[Step], other: &[Step]) -> bool {
+    path.iter()
+        .any(|step| other.iter().any(|other_step| other_step.pool == step.pool))
+}
@@ -0,0 +1,180 @@
+use borsh::BorshDeserialize;
+use cargo_swap::{
+    calculate_swap_output, curve::CURVE_PARAMS_LEN, CurveType, Pool, TestProjectInstruction,
+};
+use cargo_swap_router::{PoolSnapshot, Router};
+use solana_program::pubkey::Pubkey;
+
+fn snapshot(token_a: Pubkey, token_b: Pubkey, reserve_a: u64, reserve_b: u64) -> PoolSnapshot {
+    PoolSnapshot {
+        address: Pubkey::new_unique(),
+        pool: Pool {
+            token_a,
+            token_b,
+            bump: 255,
+            reserve_a,
+            reserve_b,
+            total_lp_supply: 0,
+            curve_type: CurveType::ConstantProduct,
+            admin: Pubkey::default(),
+            curve_params: [0; CURVE_PARAMS_LEN],
+            vault_a_bump: 255,
+            vault_b_bump: 255,
+            lp_mint_bump: 255,
+            price_a_cumulative: 0,
+            price_b_cumulative: 0,
+            last_update_timestamp: 0,
+            has_oracle: false,
+        },
+    }
+}
+
+// P/Q and Q/R trade at par, while R/P holds `rp_reserve_p` P against 1_000_000 R
+struct Triangle {
+    router: Router,
+    p: Pubkey,
+    q: Pubkey,
+    r: Pubkey,
+}
+
+fn triangle(rp_reserve_p: u64) -> Triangle {
+    let (p, q, r) = (Pubkey::new_unique(), Pubkey::new_unique(), Pubkey::new_unique());
+    let snapshots = vec![
+        snapshot(p, q, 1_000_000, 1_000_000),
+        snapshot(q, r, 1_000_000, 1_000_000),
+        snapshot(r, p, 1_000_000, rp_reserve_p),
+    ];
+    Triangle {
+        router: Router::new(snapshots, 0),
+        p,
+        q,
+        r,
+    }
+}
+
+// Profit of trading `amount_in` P around P -> Q -> R -> P, if every hop pays out
+fn cycle_profit(amount_in: u64) -> Option<i64> {
+    let q = calculate_swap_output(amount_in, 1_000_000, 1_000_000).ok()?;
+    let r = calculate_swap_output(q, 1_000_000, 1_000_000).ok()?;
+    let p = calculate_swap_output(r, 1_000_000, 1_200_000).ok()?;
+    Some(p as i64 - amount_in as i64)
+}
+
+#[test]
+fn finds_the_cycle_at_its_most_profitable_size() {
+    let Triangle { router, p, q, r } = triangle(1_200_000);
+
+    let opportunities = router.arbitrage_opportunities(&[p], 3, u64::MAX);
+
+    // Only the direction buying P cheaply through R pays
+    assert_eq!(opportunities.len(), 1);
+    let opportunity = &opportunities[0];
+    assert_eq!(opportunity.token, p);
+    assert_eq!(opportunity.route.token_path(), vec![p, q, r, p]);
+    let amount_in = opportunity.route.amount_in;
+    assert_eq!(Some(opportunity.profit() as i64), cycle_profit(amount_in));
+    // The closed form lands within rounding of the best integer input; the
+    // smallest inputs round to nothing at the first hop and cannot be traded
+    assert_eq!(cycle_profit(1), None);
+    let best = (1..2 * amount_in)
+        .step_by(7)
+        .filter_map(cycle_profit)
+        .max()
+        .unwrap();
+    assert!(opportunity.profit() as i64 + 2 >= best);
+    for size in [amount_in * 9 / 10, amount_in * 11 / 10] {
+        assert!(cycle_profit(size).unwrap() < opportunity.profit() as i64);
+    }
+}
+
+#[test]
+fn input_is_capped_by_the_budget() {
+    let Triangle { router, p, .. } = triangle(1_200_000);
+
+    let opportunities = router.arbitrage_opportunities(&[p], 3, 10_000);
+
+    assert_eq!(opportunities[0].route.amount_in, 10_000);
+    assert_eq!(Some(opportunities[0].profit() as i64), cycle_profit(10_000));
+}
+
+#[test]
+fn every_listed_token_starts_its_own_cycle() {
+    let Triangle { router, p, q, r } = triangle(1_200_000);
+
+    let opportunities = router.arbitrage_opportunities(&[p, q, r], 3, u64::MAX);
+
+    let mut tokens: Vec<Pubkey> =
+        opportunities.iter().map(|opportunity| opportunity.token).collect();
+    tokens.sort();
+    let mut expected = vec![p, q, r];
+    expected.sort();
+    assert_eq!(tokens, expected);
+    for pair in opportunities.windows(2) {
+        assert!(pair[0].profit() >= pair[1].profit());
+    }
+    // Cycles of fewer hops than the triangle do not exist
+    assert!(router.arbitrage_opportunities(&[p, q, r], 2, u64::MAX).is_empty());
+}
+
+#[test]
+fn fees_absorb_small_mispricings() {
+    let Triangle { router, p, q, r } = triangle(1_005_000);
+
+    assert!(router.arbitrage_opportunities(&[p, q, r], 3, u64::MAX).is_empty());
+}
+
+#[test]
+fn instruction_trades_the_cycle_back_into_the_input_account() {
+    let Triangle { router, p, q, r } = triangle(1_200_000);
+    let opportunity = &router.arbitrage_opportunities(&[p], 3, u64::MAX)[0];
+    let user = Pubkey::new_unique();
+
+    let instruction = opportunity.instruction(&cargo_swap::id(), &user, 100);
+
+    assert_eq!(instruction.program_id, cargo_swap::id());
+    let TestProjectInstruction::MultihopSwapWithPath {
+        amount_in,
+        minimum_amount_out,
+        token_path,
+    } = TestProjectInstruction::try_from_slice(&instruction.data).unwrap()
+    else {
+        panic!("expected MultihopSwapWithPath");
+    };
+    assert_eq!(amount_in, opportunity.route.amount_in);
+    assert_eq!(minimum_amount_out, opportunity.route.amount_in + 100);
+    assert_eq!(token_path, vec![p, q, r, p]);
+
+    let accounts = &instruction.accounts;
+    assert_eq!(accounts.len(), 3 + 7 * 3);
+    assert_eq!(accounts[0].pubkey, user);
+    assert!(accounts[0].is_signer);
+    for (hop, route_hop) in opportunity.route.hops.iter().enumerate() {
+        assert_eq!(accounts[3 + hop * 7].pubkey, route_hop.pool);
+    }
+    // The last hop pays into the account the first one spends from
+    assert_eq!(accounts[3 + 2 * 7 + 6].pubkey, accounts[2].pubkey);
+    assert_ne!(accounts[3 + 6].pubkey, accounts[2].pubkey);
+}
+
+#[test]
+fn instruction_passes_the_oracles_after_the_hops() {
+    let (p, q, r) = (Pubkey::new_unique(), Pubkey::new_unique(), Pubkey::new_unique());
+    let mut with_oracle = snapshot(q, r, 1_000_000, 1_000_000);
+    with_oracle.pool.has_oracle = true;
+    let oracle_pool = with_oracle.address;
+    let snapshots = vec![
+        snapshot(p, q, 1_000_000, 1_000_000),
+        with_oracle,
+        snapshot(r, p, 1_000_000, 1_200_000),
+    ];
+    let router = Router::new(snapshots, 0);
+    let opportunity = &router.arbitrage_opportunities(&[p], 3, u64::MAX)[0];
+
+    let instruction = opportunity.instruction(&cargo_swap::id(), &Pubkey::new_unique(), 0);
+
+    let accounts = &instruction.accounts;
+    assert_eq!(accounts.len(), 3 + 7 * 3 + 1);
+    let oracle = Pubkey::find_program_address(&[b"oracle", oracle_pool.as_ref()], &cargo_swap::id()).0;
+    assert_eq!(accounts[3 + 7 * 3].pubkey, oracle);
+    assert!(accounts[3 + 7 * 3].is_writable);
+}
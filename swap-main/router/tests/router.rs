@@ -0,0 +1,193 @@
+use cargo_swap::{
+    calculate_swap_output,
+    curve::{pack_curve_params, StableCurve, StableParams, SwapCurve, CURVE_PARAMS_LEN},
+    CurveType, Pool,
+};
+use cargo_swap_router::{PoolSnapshot, Router};
+use solana_program::pubkey::Pubkey;
+
+fn snapshot(token_a: Pubkey, token_b: Pubkey, reserve_a: u64, reserve_b: u64) -> PoolSnapshot {
+    PoolSnapshot {
+        address: Pubkey::new_unique(),
+        pool: Pool {
+            token_a,
+            token_b,
+            bump: 255,
+            reserve_a,
+            reserve_b,
+            total_lp_supply: 0,
+            curve_type: CurveType::ConstantProduct,
+            admin: Pubkey::default(),
+            curve_params: [0; CURVE_PARAMS_LEN],
+            vault_a_bump: 255,
+            vault_b_bump: 255,
+            lp_mint_bump: 255,
+            price_a_cumulative: 0,
+            price_b_cumulative: 0,
+            last_update_timestamp: 0,
+            has_oracle: false,
+        },
+    }
+}
+
+// P trades for R directly in a shallow pool, and through Q or S in deep ones
+struct Market {
+    router: Router,
+    p: Pubkey,
+    q: Pubkey,
+    r: Pubkey,
+    pools: [Pubkey; 5],
+}
+
+fn market() -> Market {
+    let (p, q, r, s) = (
+        Pubkey::new_unique(),
+        Pubkey::new_unique(),
+        Pubkey::new_unique(),
+        Pubkey::new_unique(),
+    );
+    let snapshots = vec![
+        snapshot(p, r, 100_000, 100_000),
+        snapshot(p, q, 10_000_000, 10_000_000),
+        snapshot(q, r, 10_000_000, 10_000_000),
+        snapshot(s, p, 8_000_000, 8_000_000),
+        snapshot(r, s, 8_000_000, 8_000_000),
+    ];
+    let pools = [
+        snapshots[0].address,
+        snapshots[1].address,
+        snapshots[2].address,
+        snapshots[3].address,
+        snapshots[4].address,
+    ];
+    Market {
+        router: Router::new(snapshots, 0),
+        p,
+        q,
+        r,
+        pools,
+    }
+}
+
+#[test]
+fn exact_in_takes_the_route_paying_the_most() {
+    let Market { router, p, q, r, pools } = market();
+
+    let route = router.best_exact_in(&p, &r, 50_000, 3).unwrap();
+
+    let q_out = calculate_swap_output(50_000, 10_000_000, 10_000_000).unwrap();
+    let r_out = calculate_swap_output(q_out, 10_000_000, 10_000_000).unwrap();
+    assert_eq!(route.token_path(), vec![p, q, r]);
+    assert_eq!(route.pools(), vec![pools[1], pools[2]]);
+    assert_eq!(route.amount_in, 50_000);
+    assert_eq!(route.amount_out, r_out);
+    assert_eq!(route.hops[0].amount_out, q_out);
+    assert_eq!(route.hops[1].amount_in, q_out);
+    assert!(r_out > calculate_swap_output(50_000, 100_000, 100_000).unwrap());
+}
+
+#[test]
+fn routes_are_bounded_by_max_hops() {
+    let Market { router, p, r, pools, .. } = market();
+
+    let route = router.best_exact_in(&p, &r, 50_000, 1).unwrap();
+
+    assert_eq!(route.pools(), vec![pools[0]]);
+    assert_eq!(
+        route.amount_out,
+        calculate_swap_output(50_000, 100_000, 100_000).unwrap()
+    );
+}
+
+#[test]
+fn exact_out_needs_the_least_input() {
+    let Market { router, p, r, .. } = market();
+
+    let route = router.best_exact_out(&p, &r, 30_000, 3).unwrap();
+
+    assert_eq!(route.amount_out, 30_000);
+    assert_eq!(route.hops.len(), 2);
+    // Swapping the quoted input forward delivers at least the amount asked for
+    let mut amount = route.amount_in;
+    for hop in &route.hops {
+        assert_eq!(hop.amount_in, amount);
+        amount = router.best_exact_in(&hop.token_in, &hop.token_out, amount, 1).unwrap().amount_out;
+        assert!(amount >= hop.amount_out);
+    }
+    // The shallow direct pool would need more
+    let direct = router.best_exact_out(&p, &r, 30_000, 1).unwrap();
+    assert!(direct.amount_in > route.amount_in);
+    // and cannot pay out its whole reserve at any price
+    assert!(router.best_exact_out(&p, &r, 100_000, 1).is_none());
+}
+
+#[test]
+fn large_orders_split_over_pool_disjoint_routes() {
+    let Market { router, p, r, .. } = market();
+    let single = router.best_exact_in(&p, &r, 2_000_000, 3).unwrap();
+
+    let quote = router.best_split_exact_in(&p, &r, 2_000_000, 3, 3, 20).unwrap();
+
+    assert!(quote.routes.len() > 1);
+    assert!(quote.amount_out > single.amount_out);
+    assert_eq!(quote.routes.iter().map(|route| route.amount_in).sum::<u64>(), 2_000_000);
+    assert_eq!(
+        quote.routes.iter().map(|route| route.amount_out).sum::<u64>(),
+        quote.amount_out
+    );
+    let mut pools: Vec<Pubkey> = quote.routes.iter().flat_map(|route| route.pools()).collect();
+    let count = pools.len();
+    pools.sort();
+    pools.dedup();
+    assert_eq!(pools.len(), count);
+    let split_routes = quote.split_routes();
+    assert_eq!(split_routes.len(), quote.routes.len());
+    for (split_route, route) in split_routes.iter().zip(&quote.routes) {
+        assert_eq!(split_route.amount_in, route.amount_in);
+        assert_eq!(split_route.num_hops as usize, route.hops.len());
+    }
+}
+
+#[test]
+fn a_single_route_split_is_the_best_route() {
+    let Market { router, p, r, .. } = market();
+    let single = router.best_exact_in(&p, &r, 2_000_000, 3).unwrap();
+
+    let quote = router.best_split_exact_in(&p, &r, 2_000_000, 3, 1, 20).unwrap();
+
+    assert_eq!(quote.routes, vec![single.clone()]);
+    assert_eq!(quote.amount_out, single.amount_out);
+}
+
+#[test]
+fn unconnected_tokens_have_no_route() {
+    let Market { router, p, .. } = market();
+    let stranger = Pubkey::new_unique();
+
+    assert!(router.best_exact_in(&p, &stranger, 1_000, 6).is_none());
+    assert!(router.best_exact_out(&p, &stranger, 1_000, 6).is_none());
+    assert!(router.best_split_exact_in(&p, &stranger, 1_000, 6, 3, 4).is_none());
+    assert!(router.best_exact_in(&p, &p, 1_000, 6).is_none());
+}
+
+#[test]
+fn stable_pools_are_priced_at_the_router_time() {
+    let (x, y) = (Pubkey::new_unique(), Pubkey::new_unique());
+    let params = StableParams {
+        initial_amp: 10,
+        target_amp: 100,
+        ramp_start_ts: 1_000,
+        ramp_stop_ts: 2_000,
+    };
+    let mut stable = snapshot(x, y, 1_000_000, 1_000_000);
+    stable.pool.curve_type = CurveType::Stable;
+    stable.pool.curve_params = pack_curve_params(&params).unwrap();
+    let router = Router::new(vec![stable], 1_500);
+
+    let route = router.best_exact_in(&x, &y, 100_000, 1).unwrap();
+
+    let expected = StableCurve { amp: params.current_amp(1_500) }
+        .swap_exact_in(100_000, 1_000_000, 1_000_000, true)
+        .unwrap();
+    assert_eq!(route.amount_out, expected);
+}
@@ -0,0 +1,525 @@
+// Concentrated liquidity pools: liquidity is provided over tick ranges.
+//
+// Tick i prices token A at 1.0001^i token B. The pool tracks sqrt(price) in
+// the 18-decimal fixed point of curve::fixed_point together with the
+// liquidity active at that price. Every initialized tick records the
+// liquidity that starts or stops there and the fee growth on its far side,
+// so the fees earned inside a range follow from three numbers instead of a
+// walk over positions. Initialized ticks live in the pool account itself,
+// at most MAX_TICKS of them.
+
+use crate::curve::{
+    fixed_point::{exp, ln, mul_div, ONE},
+    FEE_DENOMINATOR, FEE_NUMERATOR,
+};
+use borsh::{BorshDeserialize, BorshSerialize};
+use shank::ShankAccount;
+use solana_program::{
+    program_error::ProgramError,
+    program_pack::{IsInitialized, Pack, Sealed},
+    pubkey::Pubkey,
+};
+
+// Sqrt prices span 2^-32 ..= 2^32
+pub const MIN_TICK: i32 = -443_636;
+pub const MAX_TICK: i32 = 443_636;
+
+pub const MAX_TICK_SPACING: u16 = 1_000;
+
+// Initialized ticks a pool account has room for
+pub const MAX_TICKS: usize = 64;
+
+// Fee growth is fees per unit of liquidity, scaled by 2^64
+pub const FEE_GROWTH_ONE: u128 = 1 << 64;
+
+// ln(1.0001) scaled by ONE
+const LN_TICK_BASE: i128 = 99_995_000_333_308;
+
+#[derive(BorshSerialize, BorshDeserialize, Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct Tick {
+    pub index: i32,
+    // Change in active liquidity when the price crosses this tick upwards
+    pub liquidity_net: i128,
+    // Liquidity of all positions bounded by this tick; zero uninitializes it
+    pub liquidity_gross: u128,
+    // Fee growth on the side of this tick away from the current price
+    pub fee_growth_outside_a: u128,
+    pub fee_growth_outside_b: u128,
+}
+
+const TICK_LEN: usize = 4 + 16 + 16 + 16 + 16;
+
+// Concentrated liquidity pool state
+#[derive(BorshSerialize, BorshDeserialize, Debug, ShankAccount)]
+pub struct ConcentratedPool {
+    pub token_a: Pubkey,
+    pub token_b: Pubkey,
+    pub bump: u8,
+    // Vault PDA bumps, so their addresses are checked with create_program_address
+    pub vault_a_bump: u8,
+    pub vault_b_bump: u8,
+    pub tick_spacing: u16,
+    pub sqrt_price: u128,
+    // Tick at or below the current price
+    pub current_tick: i32,
+    // Liquidity of the positions whose range holds the current price
+    pub liquidity: u128,
+    pub fee_growth_global_a: u128,
+    pub fee_growth_global_b: u128,
+    // Initialized ticks in ascending order
+    pub ticks: Vec<Tick>,
+}
+
+impl Sealed for ConcentratedPool {}
+
+impl IsInitialized for ConcentratedPool {
+    fn is_initialized(&self) -> bool {
+        self.token_a != Pubkey::default()
+    }
+}
+
+impl Pack for ConcentratedPool {
+    const LEN: usize = 32 + 32 + 1 + 2 + 2 + 16 + 4 + 16 + 16 + 16 + 4 + TICK_LEN * MAX_TICKS; // 4493 bytes
+
+    // The tick table only fills part of the account, so read just what it holds
+    fn unpack_from_slice(src: &[u8]) -> Result<Self, ProgramError> {
+        let pool = ConcentratedPool::deserialize(&mut &src[..])
+            .map_err(|_| ProgramError::InvalidAccountData)?;
+        if pool.ticks.len() > MAX_TICKS {
+            return Err(ProgramError::InvalidAccountData);
+        }
+        Ok(pool)
+    }
+
+    fn pack_into_slice(&self, dst: &mut [u8]) {
+        let data = self.try_to_vec().unwrap();
+        dst[..data.len()].copy_from_slice(&data);
+    }
+}
+
+// One LP's liquidity over [tick_lower, tick_upper)
+#[derive(BorshSerialize, BorshDeserialize, Debug, ShankAccount)]
+pub struct Position {
+    pub pool: Pubkey,
+    pub owner: Pubkey,
+    pub tick_lower: i32,
+    pub tick_upper: i32,
+    pub liquidity: u128,
+    // Fee growth inside the range when fees were last credited
+    pub fee_growth_inside_last_a: u128,
+    pub fee_growth_inside_last_b: u128,
+    // Fees credited and not yet collected
+    pub tokens_owed_a: u64,
+    pub tokens_owed_b: u64,
+    pub bump: u8,
+}
+
+impl Sealed for Position {}
+
+impl IsInitialized for Position {
+    fn is_initialized(&self) -> bool {
+        self.pool != Pubkey::default()
+    }
+}
+
+impl Pack for Position {
+    const LEN: usize = 32 + 32 + 4 + 4 + 16 + 16 + 16 + 8 + 8 + 1; // 137 bytes
+
+    fn unpack_from_slice(src: &[u8]) -> Result<Self, ProgramError> {
+        let position = Position::try_from_slice(src)
+            .map_err(|_| ProgramError::InvalidAccountData)?;
+        Ok(position)
+    }
+
+    fn pack_into_slice(&self, dst: &mut [u8]) {
+        let data = self.try_to_vec().unwrap();
+        dst[..data.len()].copy_from_slice(&data);
+    }
+}
+
+// sqrt(1.0001^tick), scaled by ONE
+pub fn sqrt_price_at_tick(tick: i32) -> Result<u128, ProgramError> {
+    if !(MIN_TICK..=MAX_TICK).contains(&tick) {
+        return Err(ProgramError::InvalidArgument);
+    }
+    exp(tick as i128 * LN_TICK_BASE / 2)
+}
+
+// Largest tick whose sqrt price does not exceed `sqrt_price`
+pub fn tick_at_sqrt_price(sqrt_price: u128) -> Result<i32, ProgramError> {
+    if sqrt_price < sqrt_price_at_tick(MIN_TICK)? || sqrt_price > sqrt_price_at_tick(MAX_TICK)? {
+        return Err(ProgramError::InvalidArgument);
+    }
+    // The logarithm lands within a tick; step onto the exact boundary
+    let estimate = (2 * ln(sqrt_price)?).div_euclid(LN_TICK_BASE);
+    let mut tick = estimate.clamp(MIN_TICK as i128, MAX_TICK as i128) as i32;
+    while tick > MIN_TICK && sqrt_price_at_tick(tick)? > sqrt_price {
+        tick -= 1;
+    }
+    while tick < MAX_TICK && sqrt_price_at_tick(tick + 1)? <= sqrt_price {
+        tick += 1;
+    }
+    Ok(tick)
+}
+
+// Token A between two sqrt prices: liquidity * (1 / sqrt_lower - 1 / sqrt_upper)
+pub fn amount_a_delta(
+    sqrt_lower: u128,
+    sqrt_upper: u128,
+    liquidity: u128,
+    round_up: bool,
+) -> Result<u128, ProgramError> {
+    let scaled = mul_div(liquidity, sqrt_upper - sqrt_lower, sqrt_upper, round_up)?;
+    mul_div(scaled, ONE, sqrt_lower, round_up)
+}
+
+// Token B between two sqrt prices: liquidity * (sqrt_upper - sqrt_lower)
+pub fn amount_b_delta(
+    sqrt_lower: u128,
+    sqrt_upper: u128,
+    liquidity: u128,
+    round_up: bool,
+) -> Result<u128, ProgramError> {
+    mul_div(liquidity, sqrt_upper - sqrt_lower, ONE, round_up)
+}
+
+fn to_u64(amount: u128) -> Result<u64, ProgramError> {
+    u64::try_from(amount).map_err(|_| ProgramError::InvalidArgument)
+}
+
+// Tokens backing `liquidity` over [sqrt_lower, sqrt_upper) at the current price.
+// Deposits round up, withdrawals round down.
+pub fn amounts_for_liquidity(
+    sqrt_price: u128,
+    sqrt_lower: u128,
+    sqrt_upper: u128,
+    liquidity: u128,
+    round_up: bool,
+) -> Result<(u64, u64), ProgramError> {
+    let (amount_a, amount_b) = if sqrt_price <= sqrt_lower {
+        (amount_a_delta(sqrt_lower, sqrt_upper, liquidity, round_up)?, 0)
+    } else if sqrt_price >= sqrt_upper {
+        (0, amount_b_delta(sqrt_lower, sqrt_upper, liquidity, round_up)?)
+    } else {
+        (
+            amount_a_delta(sqrt_price, sqrt_upper, liquidity, round_up)?,
+            amount_b_delta(sqrt_lower, sqrt_price, liquidity, round_up)?,
+        )
+    };
+    Ok((to_u64(amount_a)?, to_u64(amount_b)?))
+}
+
+// Most liquidity that `amount_a` and `amount_b` can back over the range
+pub fn liquidity_for_amounts(
+    sqrt_price: u128,
+    sqrt_lower: u128,
+    sqrt_upper: u128,
+    amount_a: u64,
+    amount_b: u64,
+) -> Result<u128, ProgramError> {
+    if sqrt_lower >= sqrt_upper {
+        return Err(ProgramError::InvalidArgument);
+    }
+    let from_a = |sqrt_from: u128| -> Result<u128, ProgramError> {
+        let scaled = mul_div(amount_a as u128, sqrt_from, sqrt_upper - sqrt_from, false)?;
+        mul_div(scaled, sqrt_upper, ONE, false)
+    };
+    let from_b = |sqrt_to: u128| mul_div(amount_b as u128, ONE, sqrt_to - sqrt_lower, false);
+    if sqrt_price <= sqrt_lower {
+        from_a(sqrt_lower)
+    } else if sqrt_price >= sqrt_upper {
+        from_b(sqrt_upper)
+    } else {
+        Ok(from_a(sqrt_price)?.min(from_b(sqrt_price)?))
+    }
+}
+
+// One step of a swap within a single liquidity segment
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct SwapStep {
+    pub sqrt_price_next: u128,
+    pub amount_in: u64,
+    pub amount_out: u64,
+    pub fee: u64,
+}
+
+// Moves the price towards `sqrt_price_target` with at most `amount_remaining`
+// of input, fee included. The fee is 0.3% of the input actually used.
+pub fn compute_swap_step(
+    sqrt_price: u128,
+    sqrt_price_target: u128,
+    liquidity: u128,
+    amount_remaining: u64,
+    direction_a_to_b: bool,
+) -> Result<SwapStep, ProgramError> {
+    let remaining_less_fee = amount_remaining as u128 * (FEE_DENOMINATOR - FEE_NUMERATOR) as u128
+        / FEE_DENOMINATOR as u128;
+    let in_to_target = if direction_a_to_b {
+        amount_a_delta(sqrt_price_target, sqrt_price, liquidity, true)?
+    } else {
+        amount_b_delta(sqrt_price, sqrt_price_target, liquidity, true)?
+    };
+
+    let (sqrt_price_next, amount_in) = if remaining_less_fee >= in_to_target {
+        (sqrt_price_target, in_to_target)
+    } else if direction_a_to_b {
+        // 1 / sqrt' = 1 / sqrt + in / L, rounded so the price moves less
+        let denominator = liquidity
+            .checked_add(mul_div(remaining_less_fee, sqrt_price, ONE, false)?)
+            .ok_or(ProgramError::InvalidArgument)?;
+        (mul_div(liquidity, sqrt_price, denominator, true)?, remaining_less_fee)
+    } else {
+        // sqrt' = sqrt + in / L, rounded so the price moves less
+        (sqrt_price + mul_div(remaining_less_fee, ONE, liquidity, false)?, remaining_less_fee)
+    };
+
+    let amount_out = if direction_a_to_b {
+        amount_b_delta(sqrt_price_next, sqrt_price, liquidity, false)?
+    } else {
+        amount_a_delta(sqrt_price, sqrt_price_next, liquidity, false)?
+    };
+    let amount_in = to_u64(amount_in)?;
+    let fee = if sqrt_price_next != sqrt_price_target {
+        // The whole remainder is spent; what the price move did not use is fee
+        amount_remaining - amount_in
+    } else {
+        to_u64(mul_div(
+            amount_in as u128,
+            FEE_NUMERATOR as u128,
+            (FEE_DENOMINATOR - FEE_NUMERATOR) as u128,
+            true,
+        )?)?
+    };
+
+    Ok(SwapStep {
+        sqrt_price_next,
+        amount_in,
+        amount_out: to_u64(amount_out)?,
+        fee,
+    })
+}
+
+fn add_delta(liquidity: u128, delta: i128) -> Result<u128, ProgramError> {
+    if delta >= 0 {
+        liquidity.checked_add(delta as u128)
+    } else {
+        liquidity.checked_sub(delta.unsigned_abs())
+    }
+    .ok_or(ProgramError::InvalidArgument)
+}
+
+impl ConcentratedPool {
+    // Ticks must be ordered, on the spacing grid and within range
+    pub fn validate_range(&self, tick_lower: i32, tick_upper: i32) -> Result<(), ProgramError> {
+        let spacing = self.tick_spacing as i32;
+        if tick_lower >= tick_upper
+            || tick_lower < MIN_TICK
+            || tick_upper > MAX_TICK
+            || tick_lower % spacing != 0
+            || tick_upper % spacing != 0
+        {
+            return Err(ProgramError::InvalidArgument);
+        }
+        Ok(())
+    }
+
+    pub fn tick(&self, index: i32) -> Option<&Tick> {
+        self.ticks
+            .binary_search_by_key(&index, |tick| tick.index)
+            .ok()
+            .map(|position| &self.ticks[position])
+    }
+
+    // Adds `liquidity_delta` to a range bound, initializing the tick if needed
+    fn update_tick(&mut self, index: i32, liquidity_delta: i128, upper: bool) -> Result<(), ProgramError> {
+        let position = match self.ticks.binary_search_by_key(&index, |tick| tick.index) {
+            Ok(position) => position,
+            Err(position) => {
+                if liquidity_delta <= 0 {
+                    return Err(ProgramError::InvalidArgument);
+                }
+                if self.ticks.len() >= MAX_TICKS {
+                    return Err(ProgramError::AccountDataTooSmall);
+                }
+                // By convention all growth so far happened below the current price
+                let (outside_a, outside_b) = if index <= self.current_tick {
+                    (self.fee_growth_global_a, self.fee_growth_global_b)
+                } else {
+                    (0, 0)
+                };
+                self.ticks.insert(
+                    position,
+                    Tick {
+                        index,
+                        fee_growth_outside_a: outside_a,
+                        fee_growth_outside_b: outside_b,
+                        ..Tick::default()
+                    },
+                );
+                position
+            }
+        };
+
+        let tick = &mut self.ticks[position];
+        tick.liquidity_gross = add_delta(tick.liquidity_gross, liquidity_delta)?;
+        let net_delta = if upper { liquidity_delta.checked_neg() } else { Some(liquidity_delta) };
+        tick.liquidity_net = net_delta
+            .and_then(|delta| tick.liquidity_net.checked_add(delta))
+            .ok_or(ProgramError::InvalidArgument)?;
+        Ok(())
+    }
+
+    // Fee growth per unit of liquidity earned inside [tick_lower, tick_upper)
+    pub fn fee_growth_inside(&self, tick_lower: i32, tick_upper: i32) -> (u128, u128) {
+        let lower = self.tick(tick_lower).copied().unwrap_or_default();
+        let upper = self.tick(tick_upper).copied().unwrap_or_default();
+        let side = |global: u128, outside: u128, current_is_outside: bool| {
+            if current_is_outside {
+                global.wrapping_sub(outside)
+            } else {
+                outside
+            }
+        };
+        let inside = |global: u128, lower_outside: u128, upper_outside: u128| {
+            let below = side(global, lower_outside, self.current_tick < tick_lower);
+            let above = side(global, upper_outside, self.current_tick >= tick_upper);
+            global.wrapping_sub(below).wrapping_sub(above)
+        };
+        (
+            inside(self.fee_growth_global_a, lower.fee_growth_outside_a, upper.fee_growth_outside_a),
+            inside(self.fee_growth_global_b, lower.fee_growth_outside_b, upper.fee_growth_outside_b),
+        )
+    }
+
+    // Credits the fees a position earned since it was last touched
+    pub fn update_fees(&self, position: &mut Position) -> Result<(), ProgramError> {
+        let (inside_a, inside_b) = self.fee_growth_inside(position.tick_lower, position.tick_upper);
+        let earned = |inside: u128, last: u128| -> Result<u64, ProgramError> {
+            to_u64(mul_div(inside.wrapping_sub(last), position.liquidity, FEE_GROWTH_ONE, false)?)
+        };
+        let earned_a = earned(inside_a, position.fee_growth_inside_last_a)?;
+        let earned_b = earned(inside_b, position.fee_growth_inside_last_b)?;
+        position.tokens_owed_a = position
+            .tokens_owed_a
+            .checked_add(earned_a)
+            .ok_or(ProgramError::InvalidArgument)?;
+        position.tokens_owed_b = position
+            .tokens_owed_b
+            .checked_add(earned_b)
+            .ok_or(ProgramError::InvalidArgument)?;
+        position.fee_growth_inside_last_a = inside_a;
+        position.fee_growth_inside_last_b = inside_b;
+        Ok(())
+    }
+
+    // Adds (positive) or removes (negative) liquidity from a position. Returns
+    // the tokens the pool takes in or pays out.
+    pub fn modify_position(
+        &mut self,
+        position: &mut Position,
+        liquidity_delta: i128,
+    ) -> Result<(u64, u64), ProgramError> {
+        if liquidity_delta == 0 {
+            return Err(ProgramError::InvalidArgument);
+        }
+        let (tick_lower, tick_upper) = (position.tick_lower, position.tick_upper);
+        self.validate_range(tick_lower, tick_upper)?;
+
+        self.update_tick(tick_lower, liquidity_delta, false)?;
+        self.update_tick(tick_upper, liquidity_delta, true)?;
+        // Fees so far accrue to the liquidity held before this change
+        self.update_fees(position)?;
+        position.liquidity = add_delta(position.liquidity, liquidity_delta)?;
+        self.ticks.retain(|tick| tick.liquidity_gross > 0);
+
+        if (tick_lower..tick_upper).contains(&self.current_tick) {
+            self.liquidity = add_delta(self.liquidity, liquidity_delta)?;
+        }
+
+        amounts_for_liquidity(
+            self.sqrt_price,
+            sqrt_price_at_tick(tick_lower)?,
+            sqrt_price_at_tick(tick_upper)?,
+            liquidity_delta.unsigned_abs(),
+            liquidity_delta > 0,
+        )
+    }
+
+    // Crosses an initialized tick, flipping its outside fee growth
+    fn cross_tick(&mut self, position: usize, direction_a_to_b: bool) -> Result<(), ProgramError> {
+        let (global_a, global_b) = (self.fee_growth_global_a, self.fee_growth_global_b);
+        let tick = &mut self.ticks[position];
+        tick.fee_growth_outside_a = global_a.wrapping_sub(tick.fee_growth_outside_a);
+        tick.fee_growth_outside_b = global_b.wrapping_sub(tick.fee_growth_outside_b);
+        let liquidity_net = if direction_a_to_b {
+            tick.liquidity_net.checked_neg().ok_or(ProgramError::InvalidArgument)?
+        } else {
+            tick.liquidity_net
+        };
+        self.liquidity = add_delta(self.liquidity, liquidity_net)?;
+        Ok(())
+    }
+
+    // Swaps up to `amount_in`, crossing initialized ticks as the price moves.
+    // Returns the input actually used, fee included, the output and the fee.
+    pub fn swap(&mut self, amount_in: u64, direction_a_to_b: bool) -> Result<(u64, u64, u64), ProgramError> {
+        if amount_in == 0 {
+            return Err(ProgramError::InvalidArgument);
+        }
+        let mut remaining = amount_in;
+        let mut amount_out: u64 = 0;
+        let mut fee: u64 = 0;
+
+        while remaining > 0 {
+            // Next initialized tick in the direction of travel, or the end of the range
+            let next = if direction_a_to_b {
+                self.ticks.iter().rposition(|tick| tick.index <= self.current_tick)
+            } else {
+                self.ticks.iter().position(|tick| tick.index > self.current_tick)
+            };
+            let target_tick = match next {
+                Some(position) => self.ticks[position].index,
+                None if direction_a_to_b => MIN_TICK,
+                None => MAX_TICK,
+            };
+            let sqrt_price_target = sqrt_price_at_tick(target_tick)?;
+            if next.is_none() && sqrt_price_target == self.sqrt_price {
+                break;
+            }
+
+            let step = compute_swap_step(
+                self.sqrt_price,
+                sqrt_price_target,
+                self.liquidity,
+                remaining,
+                direction_a_to_b,
+            )?;
+            remaining -= step.amount_in + step.fee;
+            fee += step.fee;
+            amount_out = amount_out
+                .checked_add(step.amount_out)
+                .ok_or(ProgramError::InvalidArgument)?;
+            if self.liquidity > 0 {
+                let growth = mul_div(step.fee as u128, FEE_GROWTH_ONE, self.liquidity, false)?;
+                if direction_a_to_b {
+                    self.fee_growth_global_a = self.fee_growth_global_a.wrapping_add(growth);
+                } else {
+                    self.fee_growth_global_b = self.fee_growth_global_b.wrapping_add(growth);
+                }
+            }
+            self.sqrt_price = step.sqrt_price_next;
+
+            if step.sqrt_price_next != sqrt_price_target {
+                self.current_tick = tick_at_sqrt_price(self.sqrt_price)?;
+            } else if let Some(position) = next {
+                self.cross_tick(position, direction_a_to_b)?;
+                // Moving down, the price now sits just below the crossed tick
+                self.current_tick = if direction_a_to_b { target_tick - 1 } else { target_tick };
+            } else {
+                self.current_tick = target_tick;
+            }
+        }
+
+        Ok((amount_in - remaining, amount_out, fee))
+    }
+}
@@ -0,0 +1,431 @@
+// Cross-program invocation of the swap from other programs.
+//
+// Callers depend on this crate with the `no-entrypoint` feature, fill in the
+// account struct of an instruction with the AccountInfos they were given, and
+// call its function here. The plain functions are for a user that signed the
+// outer transaction; the `_signed` ones pass `signer_seeds` to invoke_signed
+// so a PDA of the calling program can be the user. Swaps decode the
+// SwapResult / MultihopSwapResult the program sets as return data, get_twap
+// the TwapResult of GetTwap and observe the ObserveResult of Observe.
+
+use crate::{
+    oracle::{ObserveResult, PriceObservation, TwapResult},
+    MultihopSwapResult, SwapResult, TestProjectInstruction,
+};
+use borsh::{BorshDeserialize, BorshSerialize};
+use solana_program::{
+    account_info::AccountInfo,
+    entrypoint::ProgramResult,
+    instruction::{AccountMeta, Instruction},
+    program::{get_return_data, invoke, invoke_signed},
+    program_error::ProgramError,
+};
+
+// Accounts of Swap. `swap_program` is the swap program itself, whose address
+// the instruction is sent to.
+pub struct SwapAccounts<'a> {
+    pub swap_program: AccountInfo<'a>,
+    pub pool: AccountInfo<'a>,
+    pub token_a: AccountInfo<'a>,
+    pub token_b: AccountInfo<'a>,
+    pub vault_a: AccountInfo<'a>,
+    pub vault_b: AccountInfo<'a>,
+    pub user_in: AccountInfo<'a>,
+    pub user_out: AccountInfo<'a>,
+    pub user: AccountInfo<'a>,
+    pub token_program: AccountInfo<'a>,
+    // Needed when the pool has an oracle
+    pub oracle: Option<AccountInfo<'a>>,
+    // Needed when either mint belongs to Token-2022
+    pub token_2022_program: Option<AccountInfo<'a>>,
+}
+
+// Accounts of AddLiquidity and RemoveLiquidity
+pub struct LiquidityAccounts<'a> {
+    pub swap_program: AccountInfo<'a>,
+    pub pool: AccountInfo<'a>,
+    pub token_a: AccountInfo<'a>,
+    pub token_b: AccountInfo<'a>,
+    pub vault_a: AccountInfo<'a>,
+    pub vault_b: AccountInfo<'a>,
+    pub lp_mint: AccountInfo<'a>,
+    pub user_token_a: AccountInfo<'a>,
+    pub user_token_b: AccountInfo<'a>,
+    pub user_lp: AccountInfo<'a>,
+    pub user: AccountInfo<'a>,
+    pub token_program: AccountInfo<'a>,
+    pub oracle: Option<AccountInfo<'a>>,
+    pub token_2022_program: Option<AccountInfo<'a>>,
+}
+
+// One hop of a CompactMultihopSwap: the pool and its vaults of the hop's
+// input and output mints, and the pool's oracle if it has one
+pub struct HopAccounts<'a> {
+    pub pool: AccountInfo<'a>,
+    pub vault_in: AccountInfo<'a>,
+    pub vault_out: AccountInfo<'a>,
+    pub oracle: Option<AccountInfo<'a>>,
+}
+
+// Accounts of CompactMultihopSwap. Hop `i` swaps `mints[i]` for
+// `mints[i + 1]`, so there is one more mint than hops.
+pub struct MultihopSwapAccounts<'a> {
+    pub swap_program: AccountInfo<'a>,
+    pub user: AccountInfo<'a>,
+    pub token_program: AccountInfo<'a>,
+    pub hops: Vec<HopAccounts<'a>>,
+    pub mints: Vec<AccountInfo<'a>>,
+    pub user_in: AccountInfo<'a>,
+    pub user_out: AccountInfo<'a>,
+    pub token_2022_program: Option<AccountInfo<'a>>,
+}
+
+impl<'a> SwapAccounts<'a> {
+    fn to_account_metas(&self) -> Vec<AccountMeta> {
+        let mut accounts = vec![
+            AccountMeta::new(*self.pool.key, false),
+            AccountMeta::new_readonly(*self.token_a.key, false),
+            AccountMeta::new_readonly(*self.token_b.key, false),
+            AccountMeta::new(*self.vault_a.key, false),
+            AccountMeta::new(*self.vault_b.key, false),
+            AccountMeta::new(*self.user_in.key, false),
+            AccountMeta::new(*self.user_out.key, false),
+            AccountMeta::new(*self.user.key, true),
+            AccountMeta::new_readonly(*self.token_program.key, false),
+        ];
+        accounts.extend(oracle_meta(&self.oracle));
+        accounts.extend(token_2022_meta(&self.token_2022_program));
+        accounts
+    }
+
+    fn to_account_infos(&self) -> Vec<AccountInfo<'a>> {
+        let mut accounts = vec![
+            self.pool.clone(),
+            self.token_a.clone(),
+            self.token_b.clone(),
+            self.vault_a.clone(),
+            self.vault_b.clone(),
+            self.user_in.clone(),
+            self.user_out.clone(),
+            self.user.clone(),
+            self.token_program.clone(),
+        ];
+        accounts.extend(self.oracle.clone());
+        accounts.extend(self.token_2022_program.clone());
+        accounts.push(self.swap_program.clone());
+        accounts
+    }
+}
+
+impl<'a> LiquidityAccounts<'a> {
+    // AddLiquidity takes the user's tokens before the LP account and
+    // RemoveLiquidity after it
+    fn to_account_metas(&self, lp_first: bool) -> Vec<AccountMeta> {
+        let user_tokens = [
+            AccountMeta::new(*self.user_token_a.key, false),
+            AccountMeta::new(*self.user_token_b.key, false),
+        ];
+        let user_lp = AccountMeta::new(*self.user_lp.key, false);
+        let mut accounts = vec![
+            AccountMeta::new(*self.pool.key, false),
+            AccountMeta::new_readonly(*self.token_a.key, false),
+            AccountMeta::new_readonly(*self.token_b.key, false),
+            AccountMeta::new(*self.vault_a.key, false),
+            AccountMeta::new(*self.vault_b.key, false),
+            AccountMeta::new(*self.lp_mint.key, false),
+        ];
+        if lp_first {
+            accounts.push(user_lp);
+            accounts.extend(user_tokens);
+        } else {
+            accounts.extend(user_tokens);
+            accounts.push(user_lp);
+        }
+        accounts.extend([
+            AccountMeta::new(*self.user.key, true),
+            AccountMeta::new_readonly(*self.token_program.key, false),
+        ]);
+        accounts.extend(oracle_meta(&self.oracle));
+        accounts.extend(token_2022_meta(&self.token_2022_program));
+        accounts
+    }
+
+    fn to_account_infos(&self) -> Vec<AccountInfo<'a>> {
+        let mut accounts = vec![
+            self.pool.clone(),
+            self.token_a.clone(),
+            self.token_b.clone(),
+            self.vault_a.clone(),
+            self.vault_b.clone(),
+            self.lp_mint.clone(),
+            self.user_token_a.clone(),
+            self.user_token_b.clone(),
+            self.user_lp.clone(),
+            self.user.clone(),
+            self.token_program.clone(),
+        ];
+        accounts.extend(self.oracle.clone());
+        accounts.extend(self.token_2022_program.clone());
+        accounts.push(self.swap_program.clone());
+        accounts
+    }
+}
+
+impl<'a> MultihopSwapAccounts<'a> {
+    fn to_account_metas(&self) -> Vec<AccountMeta> {
+        let mut accounts = vec![
+            AccountMeta::new(*self.user.key, true),
+            AccountMeta::new_readonly(*self.token_program.key, false),
+        ];
+        for hop in &self.hops {
+            accounts.extend([
+                AccountMeta::new(*hop.pool.key, false),
+                AccountMeta::new(*hop.vault_in.key, false),
+                AccountMeta::new(*hop.vault_out.key, false),
+            ]);
+        }
+        accounts.extend(self.mints.iter().map(|mint| AccountMeta::new_readonly(*mint.key, false)));
+        accounts.extend([
+            AccountMeta::new(*self.user_in.key, false),
+            AccountMeta::new(*self.user_out.key, false),
+        ]);
+        accounts.extend(self.hops.iter().filter_map(|hop| oracle_meta(&hop.oracle)));
+        accounts.extend(token_2022_meta(&self.token_2022_program));
+        accounts
+    }
+
+    fn to_account_infos(&self) -> Vec<AccountInfo<'a>> {
+        let mut accounts = vec![self.user.clone(), self.token_program.clone()];
+        for hop in &self.hops {
+            accounts.extend([hop.pool.clone(), hop.vault_in.clone(), hop.vault_out.clone()]);
+        }
+        accounts.extend(self.mints.iter().cloned());
+        accounts.extend([self.user_in.clone(), self.user_out.clone()]);
+        accounts.extend(self.hops.iter().filter_map(|hop| hop.oracle.clone()));
+        accounts.extend(self.token_2022_program.clone());
+        accounts.push(self.swap_program.clone());
+        accounts
+    }
+}
+
+fn oracle_meta(oracle: &Option<AccountInfo>) -> Option<AccountMeta> {
+    oracle.as_ref().map(|oracle| AccountMeta::new(*oracle.key, false))
+}
+
+fn token_2022_meta(token_2022_program: &Option<AccountInfo>) -> Option<AccountMeta> {
+    token_2022_program
+        .as_ref()
+        .map(|program| AccountMeta::new_readonly(*program.key, false))
+}
+
+fn instruction(
+    swap_program: &AccountInfo,
+    accounts: Vec<AccountMeta>,
+    data: &TestProjectInstruction,
+) -> Instruction {
+    Instruction {
+        program_id: *swap_program.key,
+        accounts,
+        data: data.try_to_vec().unwrap(),
+    }
+}
+
+// What the swap program returned from the instruction just invoked
+fn swap_return_data<T: BorshDeserialize>(swap_program: &AccountInfo) -> Result<T, ProgramError> {
+    match get_return_data() {
+        Some((program_id, data)) if program_id == *swap_program.key => {
+            T::try_from_slice(&data).map_err(|_| ProgramError::InvalidAccountData)
+        }
+        _ => Err(ProgramError::InvalidAccountData),
+    }
+}
+
+pub fn swap_instruction(
+    accounts: &SwapAccounts,
+    amount_in: u64,
+    direction_a_to_b: bool,
+) -> Instruction {
+    instruction(
+        &accounts.swap_program,
+        accounts.to_account_metas(),
+        &TestProjectInstruction::Swap { amount_in, direction_a_to_b },
+    )
+}
+
+pub fn swap(
+    accounts: &SwapAccounts,
+    amount_in: u64,
+    direction_a_to_b: bool,
+) -> Result<SwapResult, ProgramError> {
+    swap_signed(accounts, amount_in, direction_a_to_b, &[])
+}
+
+pub fn swap_signed(
+    accounts: &SwapAccounts,
+    amount_in: u64,
+    direction_a_to_b: bool,
+    signer_seeds: &[&[&[u8]]],
+) -> Result<SwapResult, ProgramError> {
+    invoke_signed(
+        &swap_instruction(accounts, amount_in, direction_a_to_b),
+        &accounts.to_account_infos(),
+        signer_seeds,
+    )?;
+    swap_return_data(&accounts.swap_program)
+}
+
+// CompactMultihopSwap; `minimum_hop_amounts_out` is empty or has one bound per hop
+pub fn multihop_swap_instruction(
+    accounts: &MultihopSwapAccounts,
+    amount_in: u64,
+    minimum_amount_out: u64,
+    minimum_hop_amounts_out: Vec<u64>,
+) -> Instruction {
+    instruction(
+        &accounts.swap_program,
+        accounts.to_account_metas(),
+        &TestProjectInstruction::CompactMultihopSwap {
+            amount_in,
+            minimum_amount_out,
+            minimum_hop_amounts_out,
+        },
+    )
+}
+
+pub fn multihop_swap(
+    accounts: &MultihopSwapAccounts,
+    amount_in: u64,
+    minimum_amount_out: u64,
+    minimum_hop_amounts_out: Vec<u64>,
+) -> Result<MultihopSwapResult, ProgramError> {
+    multihop_swap_signed(accounts, amount_in, minimum_amount_out, minimum_hop_amounts_out, &[])
+}
+
+pub fn multihop_swap_signed(
+    accounts: &MultihopSwapAccounts,
+    amount_in: u64,
+    minimum_amount_out: u64,
+    minimum_hop_amounts_out: Vec<u64>,
+    signer_seeds: &[&[&[u8]]],
+) -> Result<MultihopSwapResult, ProgramError> {
+    invoke_signed(
+        &multihop_swap_instruction(
+            accounts,
+            amount_in,
+            minimum_amount_out,
+            minimum_hop_amounts_out,
+        ),
+        &accounts.to_account_infos(),
+        signer_seeds,
+    )?;
+    swap_return_data(&accounts.swap_program)
+}
+
+pub fn add_liquidity_instruction(
+    accounts: &LiquidityAccounts,
+    amount_a: u64,
+    amount_b: u64,
+) -> Instruction {
+    instruction(
+        &accounts.swap_program,
+        accounts.to_account_metas(false),
+        &TestProjectInstruction::AddLiquidity { amount_a, amount_b },
+    )
+}
+
+pub fn add_liquidity(accounts: &LiquidityAccounts, amount_a: u64, amount_b: u64) -> ProgramResult {
+    add_liquidity_signed(accounts, amount_a, amount_b, &[])
+}
+
+pub fn add_liquidity_signed(
+    accounts: &LiquidityAccounts,
+    amount_a: u64,
+    amount_b: u64,
+    signer_seeds: &[&[&[u8]]],
+) -> ProgramResult {
+    invoke_signed(
+        &add_liquidity_instruction(accounts, amount_a, amount_b),
+        &accounts.to_account_infos(),
+        signer_seeds,
+    )
+}
+
+pub fn remove_liquidity_instruction(accounts: &LiquidityAccounts, lp_amount: u64) -> Instruction {
+    instruction(
+        &accounts.swap_program,
+        accounts.to_account_metas(true),
+        &TestProjectInstruction::RemoveLiquidity { lp_amount },
+    )
+}
+
+pub fn remove_liquidity(accounts: &LiquidityAccounts, lp_amount: u64) -> ProgramResult {
+    remove_liquidity_signed(accounts, lp_amount, &[])
+}
+
+pub fn remove_liquidity_signed(
+    accounts: &LiquidityAccounts,
+    lp_amount: u64,
+    signer_seeds: &[&[&[u8]]],
+) -> ProgramResult {
+    invoke_signed(
+        &remove_liquidity_instruction(accounts, lp_amount),
+        &accounts.to_account_infos(),
+        signer_seeds,
+    )
+}
+
+pub fn get_twap_instruction(
+    swap_program: &AccountInfo,
+    pool: &AccountInfo,
+    since: PriceObservation,
+) -> Instruction {
+    instruction(
+        swap_program,
+        vec![AccountMeta::new_readonly(*pool.key, false)],
+        &TestProjectInstruction::GetTwap { since },
+    )
+}
+
+// Average prices of a two-token pool since an earlier observation of it
+pub fn get_twap<'a>(
+    swap_program: &AccountInfo<'a>,
+    pool: &AccountInfo<'a>,
+    since: PriceObservation,
+) -> Result<TwapResult, ProgramError> {
+    invoke(
+        &get_twap_instruction(swap_program, pool, since),
+        &[pool.clone(), swap_program.clone()],
+    )?;
+    swap_return_data(swap_program)
+}
+
+pub fn observe_instruction(
+    swap_program: &AccountInfo,
+    pool: &AccountInfo,
+    oracle: &AccountInfo,
+    seconds_agos: Vec<u32>,
+) -> Instruction {
+    instruction(
+        swap_program,
+        vec![
+            AccountMeta::new_readonly(*pool.key, false),
+            AccountMeta::new_readonly(*oracle.key, false),
+        ],
+        &TestProjectInstruction::Observe { seconds_agos },
+    )
+}
+
+// Average prices and liquidity of a two-token pool over windows ending now,
+// from its oracle account
+pub fn observe<'a>(
+    swap_program: &AccountInfo<'a>,
+    pool: &AccountInfo<'a>,
+    oracle: &AccountInfo<'a>,
+    seconds_agos: Vec<u32>,
+) -> Result<ObserveResult, ProgramError> {
+    invoke(
+        &observe_instruction(swap_program, pool, oracle, seconds_agos),
+        &[pool.clone(), oracle.clone(), swap_program.clone()],
+    )?;
+    swap_return_data(swap_program)
+}
@@ -0,0 +1,104 @@
+// Fixed exchange rate curve: one token B always costs `token_b_price` token A,
+// e.g. for pegged wrappers or fixed-price token sales.
+
+use super::{amount_after_fee, amount_before_fee, ceil_div, SwapCurve, PRICE_SCALE, U256};
+use borsh::{BorshDeserialize, BorshSerialize};
+use solana_program::program_error::ProgramError;
+
+#[derive(BorshSerialize, BorshDeserialize, Clone, Copy, Debug, PartialEq, Eq)]
+pub struct ConstantPriceCurve {
+    // Amount of token A exchanged for one token B
+    pub token_b_price: u64,
+}
+
+impl ConstantPriceCurve {
+    // Pool value measured in token A
+    fn value(&self, amount_a: u64, amount_b: u64) -> Result<u128, ProgramError> {
+        (amount_b as u128)
+            .checked_mul(self.token_b_price as u128)
+            .and_then(|value_b| value_b.checked_add(amount_a as u128))
+            .ok_or(ProgramError::InvalidArgument)
+    }
+}
+
+impl SwapCurve for ConstantPriceCurve {
+    fn swap_exact_in(
+        &self,
+        amount_in: u64,
+        reserve_a: u64,
+        reserve_b: u64,
+        direction_a_to_b: bool,
+    ) -> Result<u64, ProgramError> {
+        if amount_in == 0 || self.token_b_price == 0 {
+            return Err(ProgramError::InvalidArgument);
+        }
+        let net_in = amount_after_fee(amount_in);
+        let price = self.token_b_price as u128;
+        let (amount_out, reserve_out) = if direction_a_to_b {
+            (net_in / price, reserve_b as u128)
+        } else {
+            (net_in * price, reserve_a as u128)
+        };
+        // The pool can only sell what it holds
+        if amount_out == 0 || amount_out >= reserve_out {
+            return Err(ProgramError::InvalidArgument);
+        }
+        Ok(amount_out as u64)
+    }
+
+    fn swap_exact_out(
+        &self,
+        amount_out: u64,
+        reserve_a: u64,
+        reserve_b: u64,
+        direction_a_to_b: bool,
+    ) -> Result<u64, ProgramError> {
+        let reserve_out = if direction_a_to_b { reserve_b } else { reserve_a };
+        if amount_out == 0 || self.token_b_price == 0 || amount_out >= reserve_out {
+            return Err(ProgramError::InvalidArgument);
+        }
+        let price = self.token_b_price as u128;
+        let net_in = if direction_a_to_b {
+            amount_out as u128 * price
+        } else {
+            ceil_div(amount_out as u128, price)
+        };
+        amount_before_fee(net_in)
+    }
+
+    fn initial_lp_tokens(&self, amount_a: u64, amount_b: u64) -> Result<u64, ProgramError> {
+        u64::try_from(self.value(amount_a, amount_b)?).map_err(|_| ProgramError::InvalidArgument)
+    }
+
+    // Reserves drift away from any fixed ratio, so deposits are valued at the fixed price
+    fn deposit_lp_tokens(
+        &self,
+        amount_a: u64,
+        amount_b: u64,
+        reserve_a: u64,
+        reserve_b: u64,
+        supply: u64,
+    ) -> Result<u64, ProgramError> {
+        let pool_value = self.value(reserve_a, reserve_b)?;
+        if supply == 0 || pool_value == 0 {
+            return self.initial_lp_tokens(amount_a, amount_b);
+        }
+        let liquidity = self
+            .value(amount_a, amount_b)?
+            .checked_mul(supply as u128)
+            .ok_or(ProgramError::InvalidArgument)?
+            / pool_value;
+        u64::try_from(liquidity).map_err(|_| ProgramError::InvalidArgument)
+    }
+
+    fn spot_price(&self, _reserve_a: u64, _reserve_b: u64) -> Result<u128, ProgramError> {
+        if self.token_b_price == 0 {
+            return Err(ProgramError::InvalidArgument);
+        }
+        Ok(PRICE_SCALE / self.token_b_price as u128)
+    }
+
+    fn invariant(&self, reserve_a: u64, reserve_b: u64) -> Result<U256, ProgramError> {
+        Ok(U256::from(self.value(reserve_a, reserve_b)?))
+    }
+}
@@ -0,0 +1,63 @@
+// Uniswap-style x * y = k curve, the default for every pool.
+
+use super::{SwapCurve, PRICE_SCALE, U256};
+use crate::{calculate_lp_tokens_to_mint, calculate_swap_output};
+use solana_program::program_error::ProgramError;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct ConstantProductCurve;
+
+impl SwapCurve for ConstantProductCurve {
+    fn swap_exact_in(
+        &self,
+        amount_in: u64,
+        reserve_a: u64,
+        reserve_b: u64,
+        direction_a_to_b: bool,
+    ) -> Result<u64, ProgramError> {
+        if direction_a_to_b {
+            calculate_swap_output(amount_in, reserve_a, reserve_b)
+        } else {
+            calculate_swap_output(amount_in, reserve_b, reserve_a)
+        }
+    }
+
+    // Inverse of calculate_swap_output: floor(R_in * out * 1000 / ((R_out - out) * 997)) + 1
+    fn swap_exact_out(
+        &self,
+        amount_out: u64,
+        reserve_a: u64,
+        reserve_b: u64,
+        direction_a_to_b: bool,
+    ) -> Result<u64, ProgramError> {
+        let (reserve_in, reserve_out) = if direction_a_to_b {
+            (reserve_a, reserve_b)
+        } else {
+            (reserve_b, reserve_a)
+        };
+        if amount_out == 0 || reserve_in == 0 || amount_out >= reserve_out {
+            return Err(ProgramError::InvalidArgument);
+        }
+
+        let numerator = (reserve_in as u128)
+            .checked_mul(amount_out as u128).ok_or(ProgramError::InvalidArgument)?
+            .checked_mul(1000).ok_or(ProgramError::InvalidArgument)?;
+        let denominator = ((reserve_out - amount_out) as u128) * 997;
+        u64::try_from(numerator / denominator + 1).map_err(|_| ProgramError::InvalidArgument)
+    }
+
+    fn initial_lp_tokens(&self, amount_a: u64, amount_b: u64) -> Result<u64, ProgramError> {
+        calculate_lp_tokens_to_mint(amount_a, amount_b, 0, 0, 0)
+    }
+
+    fn spot_price(&self, reserve_a: u64, reserve_b: u64) -> Result<u128, ProgramError> {
+        if reserve_a == 0 {
+            return Err(ProgramError::InvalidArgument);
+        }
+        Ok(reserve_b as u128 * PRICE_SCALE / reserve_a as u128)
+    }
+
+    fn invariant(&self, reserve_a: u64, reserve_b: u64) -> Result<U256, ProgramError> {
+        Ok(U256::from(reserve_a) * U256::from(reserve_b))
+    }
+}
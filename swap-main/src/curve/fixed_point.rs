@@ -0,0 +1,130 @@
+// Fixed-point logarithm, exponential and power functions for weighted pools.
+//
+// Values carry 18 decimals (ONE is 1.0). ln uses the atanh series on a
+// mantissa in [1, 2); exp reduces by ln(2) and sums the Taylor series. Both
+// are accurate to a few units in the last place, which pow_up / pow_down
+// cover with MAX_POW_RELATIVE_ERROR so callers can round against the trader.
+
+use super::U256;
+use solana_program::program_error::ProgramError;
+
+pub const ONE: u128 = 1_000_000_000_000_000_000;
+
+// ln(2) scaled by ONE
+const LN_2: i128 = 693_147_180_559_945_309;
+
+// Relative error bound of pow, scaled by ONE (1e-14)
+pub const MAX_POW_RELATIVE_ERROR: u128 = 10_000;
+
+// exp results above 2^66 * ONE could overflow u128
+const MAX_EXP_SHIFT: i128 = 66;
+
+// a * b / denominator in 256 bits, rounded down or up
+pub fn mul_div(a: u128, b: u128, denominator: u128, round_up: bool) -> Result<u128, ProgramError> {
+    if denominator == 0 {
+        return Err(ProgramError::InvalidArgument);
+    }
+    let product = U256::from(a) * U256::from(b);
+    let denominator = U256::from(denominator);
+    let mut quotient = product / denominator;
+    if round_up && quotient * denominator < product {
+        quotient += U256::one();
+    }
+    if quotient > U256::from(u128::MAX) {
+        return Err(ProgramError::InvalidArgument);
+    }
+    Ok(quotient.as_u128())
+}
+
+// Natural logarithm of a positive fixed-point value
+pub fn ln(x: u128) -> Result<i128, ProgramError> {
+    if x == 0 {
+        return Err(ProgramError::InvalidArgument);
+    }
+
+    // x = m * 2^k with m in [ONE, 2 * ONE)
+    let mut k: i128 = 0;
+    let mut m = x;
+    while m >= 2 * ONE {
+        m >>= 1;
+        k += 1;
+    }
+    while m < ONE {
+        m <<= 1;
+        k -= 1;
+    }
+
+    // ln(m) = 2 * atanh(z) = 2 * (z + z^3/3 + z^5/5 + ...), z = (m - 1) / (m + 1) <= 1/3
+    let z = mul_div(m - ONE, ONE, m + ONE, false)?;
+    let z_squared = z * z / ONE;
+    let mut term = z;
+    let mut sum: u128 = 0;
+    let mut denominator = 1;
+    while term != 0 {
+        sum += term / denominator;
+        term = term * z_squared / ONE;
+        denominator += 2;
+    }
+
+    Ok(k * LN_2 + 2 * sum as i128)
+}
+
+// e^y for a fixed-point exponent
+pub fn exp(y: i128) -> Result<u128, ProgramError> {
+    // e^y = 2^k * e^r with r in [0, ln 2)
+    let k = y.div_euclid(LN_2);
+    let r = y.rem_euclid(LN_2) as u128;
+    if k > MAX_EXP_SHIFT {
+        return Err(ProgramError::InvalidArgument);
+    }
+
+    let mut term = ONE;
+    let mut sum = ONE;
+    let mut n = 1;
+    while term != 0 {
+        term = term * r / ONE / n;
+        sum += term;
+        n += 1;
+    }
+
+    Ok(if k >= 0 {
+        sum << k
+    } else if k > -128 {
+        sum >> -k
+    } else {
+        0
+    })
+}
+
+// x^y for fixed-point base and exponent, computed as e^(y * ln x)
+fn pow_raw(x: u128, y: u128) -> Result<u128, ProgramError> {
+    if y == 0 {
+        return Ok(ONE);
+    }
+    if x == 0 {
+        return Ok(0);
+    }
+    let ln_x = ln(x)?;
+    // Split y so neither product leaves i128
+    let y_int = i128::try_from(y / ONE).map_err(|_| ProgramError::InvalidArgument)?;
+    let y_frac = (y % ONE) as i128;
+    let exponent = ln_x
+        .checked_mul(y_int)
+        .and_then(|whole| whole.checked_add(ln_x * y_frac / ONE as i128))
+        .ok_or(ProgramError::InvalidArgument)?;
+    exp(exponent)
+}
+
+// x^y rounded up past the error bound
+pub fn pow_up(x: u128, y: u128) -> Result<u128, ProgramError> {
+    let raw = pow_raw(x, y)?;
+    let error = mul_div(raw, MAX_POW_RELATIVE_ERROR, ONE, true)? + 1;
+    raw.checked_add(error).ok_or(ProgramError::InvalidArgument)
+}
+
+// x^y rounded down past the error bound
+pub fn pow_down(x: u128, y: u128) -> Result<u128, ProgramError> {
+    let raw = pow_raw(x, y)?;
+    let error = mul_div(raw, MAX_POW_RELATIVE_ERROR, ONE, true)? + 1;
+    Ok(raw.saturating_sub(error))
+}
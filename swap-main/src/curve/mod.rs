@@ -0,0 +1,155 @@
+// Pricing curves a pool can be created with.
+//
+// Every curve implements `SwapCurve`, so the processors, quotes and multihop
+// routing price a pool without knowing which curve it uses. A pool stores its
+// curve as a `CurveType` tag plus up to `CURVE_PARAMS_LEN` bytes of
+// Borsh-encoded parameters.
+
+use crate::{calculate_lp_tokens_to_mint, calculate_withdraw_amounts};
+use borsh::{BorshDeserialize, BorshSerialize};
+use solana_program::program_error::ProgramError;
+
+pub mod constant_price;
+pub mod constant_product;
+pub mod fixed_point;
+pub mod offset;
+pub mod stable;
+pub mod weighted;
+
+pub use constant_price::ConstantPriceCurve;
+pub use constant_product::ConstantProductCurve;
+pub use offset::OffsetCurve;
+pub use stable::{StableCurve, StableParams};
+pub use weighted::WeightedCurve;
+
+mod uint_types {
+    // The macro expansion trips a few style lints we cannot fix here
+    #![allow(clippy::assign_op_pattern, clippy::manual_div_ceil)]
+    uint::construct_uint! {
+        pub struct U256(4);
+    }
+}
+pub use uint_types::U256;
+
+// Bytes reserved in `Pool` for curve parameters
+pub const CURVE_PARAMS_LEN: usize = 32;
+
+// Swap fee taken from the input of every trade: 0.3%
+pub const FEE_NUMERATOR: u64 = 3;
+pub const FEE_DENOMINATOR: u64 = 1000;
+
+// Spot prices are fixed point with this many units per 1.0
+pub const PRICE_SCALE: u128 = 1_000_000_000_000;
+
+pub trait SwapCurve {
+    // Output for exactly `amount_in`, fee included
+    fn swap_exact_in(
+        &self,
+        amount_in: u64,
+        reserve_a: u64,
+        reserve_b: u64,
+        direction_a_to_b: bool,
+    ) -> Result<u64, ProgramError>;
+
+    // Input (fee included) needed to receive exactly `amount_out`
+    fn swap_exact_out(
+        &self,
+        amount_out: u64,
+        reserve_a: u64,
+        reserve_b: u64,
+        direction_a_to_b: bool,
+    ) -> Result<u64, ProgramError>;
+
+    // LP tokens for the deposit that creates the pool
+    fn initial_lp_tokens(&self, amount_a: u64, amount_b: u64) -> Result<u64, ProgramError>;
+
+    // Whether liquidity can be added after the pool is created
+    fn allows_deposits(&self) -> bool {
+        true
+    }
+
+    // LP tokens for a ratio-matched deposit. Curves whose invariant scales
+    // linearly with the reserves mint proportionally to the existing supply.
+    fn deposit_lp_tokens(
+        &self,
+        amount_a: u64,
+        amount_b: u64,
+        reserve_a: u64,
+        reserve_b: u64,
+        supply: u64,
+    ) -> Result<u64, ProgramError> {
+        if supply == 0 {
+            return self.initial_lp_tokens(amount_a, amount_b);
+        }
+        calculate_lp_tokens_to_mint(amount_a, amount_b, reserve_a, reserve_b, supply)
+    }
+
+    // Tokens returned for burning `lp_amount`
+    fn withdraw_amounts(
+        &self,
+        lp_amount: u64,
+        reserve_a: u64,
+        reserve_b: u64,
+        supply: u64,
+    ) -> Result<(u64, u64), ProgramError> {
+        calculate_withdraw_amounts(lp_amount, reserve_a, reserve_b, supply)
+    }
+
+    // Fee a swap of `amount_in` keeps in the pool, in the input token
+    fn swap_fee(&self, amount_in: u64) -> u64 {
+        swap_fee(amount_in)
+    }
+
+    // Marginal price of token A in token B before fees, scaled by PRICE_SCALE
+    fn spot_price(&self, reserve_a: u64, reserve_b: u64) -> Result<u128, ProgramError>;
+
+    // Quantity a swap may never decrease
+    fn invariant(&self, reserve_a: u64, reserve_b: u64) -> Result<U256, ProgramError>;
+}
+
+// Input left after the fee, rounded down
+pub fn amount_after_fee(amount_in: u64) -> u128 {
+    amount_in as u128 * (FEE_DENOMINATOR - FEE_NUMERATOR) as u128 / FEE_DENOMINATOR as u128
+}
+
+// Fee kept out of `amount_in`, the rest of it after amount_after_fee
+pub fn swap_fee(amount_in: u64) -> u64 {
+    amount_in - amount_after_fee(amount_in) as u64
+}
+
+// Division rounding up
+pub fn ceil_div(numerator: u128, denominator: u128) -> u128 {
+    let quotient = numerator / denominator;
+    if quotient * denominator < numerator {
+        quotient + 1
+    } else {
+        quotient
+    }
+}
+
+// Smallest input whose post-fee amount is at least `net_amount`
+pub fn amount_before_fee(net_amount: u128) -> Result<u64, ProgramError> {
+    let numerator = net_amount
+        .checked_mul(FEE_DENOMINATOR as u128)
+        .ok_or(ProgramError::InvalidArgument)?;
+    let denominator = (FEE_DENOMINATOR - FEE_NUMERATOR) as u128;
+    u64::try_from(ceil_div(numerator, denominator)).map_err(|_| ProgramError::InvalidArgument)
+}
+
+pub fn pack_curve_params<T: BorshSerialize>(
+    params: &T,
+) -> Result<[u8; CURVE_PARAMS_LEN], ProgramError> {
+    let data = params.try_to_vec().map_err(|_| ProgramError::InvalidArgument)?;
+    if data.len() > CURVE_PARAMS_LEN {
+        return Err(ProgramError::InvalidArgument);
+    }
+    let mut packed = [0u8; CURVE_PARAMS_LEN];
+    packed[..data.len()].copy_from_slice(&data);
+    Ok(packed)
+}
+
+pub fn unpack_curve_params<T: BorshDeserialize>(
+    packed: &[u8; CURVE_PARAMS_LEN],
+) -> Result<T, ProgramError> {
+    T::deserialize(&mut &packed[..]).map_err(|_| ProgramError::InvalidAccountData)
+}
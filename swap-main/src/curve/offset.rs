@@ -0,0 +1,84 @@
+// Constant product over virtual reserves. The offsets let a pool open with a
+// single token (e.g. a token launch quoted against nothing on the other side)
+// while still pricing as if both sides were funded.
+
+use super::{SwapCurve, U256};
+use crate::{calculate_swap_output, IntegerSqrt};
+use borsh::{BorshDeserialize, BorshSerialize};
+use solana_program::program_error::ProgramError;
+
+#[derive(BorshSerialize, BorshDeserialize, Clone, Copy, Debug, PartialEq, Eq)]
+pub struct OffsetCurve {
+    pub token_a_offset: u64,
+    pub token_b_offset: u64,
+}
+
+impl OffsetCurve {
+    fn virtual_reserves(&self, reserve_a: u64, reserve_b: u64) -> Result<(u64, u64), ProgramError> {
+        let virtual_a = reserve_a
+            .checked_add(self.token_a_offset)
+            .ok_or(ProgramError::InvalidArgument)?;
+        let virtual_b = reserve_b
+            .checked_add(self.token_b_offset)
+            .ok_or(ProgramError::InvalidArgument)?;
+        Ok((virtual_a, virtual_b))
+    }
+}
+
+impl SwapCurve for OffsetCurve {
+    fn swap_exact_in(
+        &self,
+        amount_in: u64,
+        reserve_a: u64,
+        reserve_b: u64,
+        direction_a_to_b: bool,
+    ) -> Result<u64, ProgramError> {
+        let (virtual_a, virtual_b) = self.virtual_reserves(reserve_a, reserve_b)?;
+        let (amount_out, reserve_out) = if direction_a_to_b {
+            (calculate_swap_output(amount_in, virtual_a, virtual_b)?, reserve_b)
+        } else {
+            (calculate_swap_output(amount_in, virtual_b, virtual_a)?, reserve_a)
+        };
+        // Virtual liquidity can be priced against but never paid out
+        if amount_out >= reserve_out {
+            return Err(ProgramError::InvalidArgument);
+        }
+        Ok(amount_out)
+    }
+
+    fn swap_exact_out(
+        &self,
+        amount_out: u64,
+        reserve_a: u64,
+        reserve_b: u64,
+        direction_a_to_b: bool,
+    ) -> Result<u64, ProgramError> {
+        let reserve_out = if direction_a_to_b { reserve_b } else { reserve_a };
+        if amount_out >= reserve_out {
+            return Err(ProgramError::InvalidArgument);
+        }
+        let (virtual_a, virtual_b) = self.virtual_reserves(reserve_a, reserve_b)?;
+        super::ConstantProductCurve.swap_exact_out(amount_out, virtual_a, virtual_b, direction_a_to_b)
+    }
+
+    fn initial_lp_tokens(&self, amount_a: u64, amount_b: u64) -> Result<u64, ProgramError> {
+        let (virtual_a, virtual_b) = self.virtual_reserves(amount_a, amount_b)?;
+        let liquidity = (virtual_a as u128 * virtual_b as u128).integer_sqrt();
+        u64::try_from(liquidity).map_err(|_| ProgramError::InvalidArgument)
+    }
+
+    // Proportional deposits would hand new LPs a share of the virtual reserves
+    fn allows_deposits(&self) -> bool {
+        false
+    }
+
+    fn spot_price(&self, reserve_a: u64, reserve_b: u64) -> Result<u128, ProgramError> {
+        let (virtual_a, virtual_b) = self.virtual_reserves(reserve_a, reserve_b)?;
+        super::ConstantProductCurve.spot_price(virtual_a, virtual_b)
+    }
+
+    fn invariant(&self, reserve_a: u64, reserve_b: u64) -> Result<U256, ProgramError> {
+        let (virtual_a, virtual_b) = self.virtual_reserves(reserve_a, reserve_b)?;
+        Ok(U256::from(virtual_a) * U256::from(virtual_b))
+    }
+}
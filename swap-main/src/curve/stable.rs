@@ -0,0 +1,302 @@
+// StableSwap (Curve) invariant for two-token pools of pegged assets.
+//
+// The invariant blends constant sum and constant product:
+//   A * n^n * (x + y) + D = A * D * n^n + D^(n+1) / (n^n * x * y)
+// with n = 2. A large amplification A keeps prices near 1:1 around the
+// balanced point and falls back to constant product as the pool empties.
+
+use super::{amount_after_fee, amount_before_fee, SwapCurve, PRICE_SCALE, U256};
+use borsh::{BorshDeserialize, BorshSerialize};
+use solana_program::program_error::ProgramError;
+
+// Number of tokens in a pool
+const N_COINS: u64 = 2;
+
+// Newton iterations before giving up on convergence
+const MAX_ITERATIONS: usize = 64;
+
+// Bounds on the amplification coefficient
+pub const MIN_AMP: u64 = 1;
+pub const MAX_AMP: u64 = 1_000_000;
+
+// A ramp must last at least this long and may not start sooner than this after the last one
+pub const MIN_RAMP_DURATION: i64 = 86_400;
+
+// A single ramp may move the amplification by at most this factor either way
+pub const MAX_AMP_CHANGE: u64 = 10;
+
+// Amplification in effect at `now`, interpolated linearly over the ramp window
+pub fn current_amp(
+    initial_amp: u64,
+    target_amp: u64,
+    ramp_start_ts: i64,
+    ramp_stop_ts: i64,
+    now: i64,
+) -> u64 {
+    if now >= ramp_stop_ts || ramp_stop_ts <= ramp_start_ts {
+        return target_amp;
+    }
+    let elapsed = now.saturating_sub(ramp_start_ts).max(0) as u128;
+    let duration = (ramp_stop_ts - ramp_start_ts) as u128;
+    // elapsed < duration, so the interpolated value stays between the two endpoints
+    if target_amp >= initial_amp {
+        let delta = (target_amp - initial_amp) as u128 * elapsed / duration;
+        initial_amp + delta as u64
+    } else {
+        let delta = (initial_amp - target_amp) as u128 * elapsed / duration;
+        initial_amp - delta as u64
+    }
+}
+
+// Validates a requested ramp from the current amplification
+pub fn validate_ramp(
+    current_amp: u64,
+    target_amp: u64,
+    last_ramp_start_ts: i64,
+    ramp_stop_ts: i64,
+    now: i64,
+) -> Result<(), ProgramError> {
+    if !(MIN_AMP..=MAX_AMP).contains(&target_amp) {
+        return Err(ProgramError::InvalidArgument);
+    }
+    if now < last_ramp_start_ts.saturating_add(MIN_RAMP_DURATION)
+        || ramp_stop_ts < now.saturating_add(MIN_RAMP_DURATION)
+    {
+        return Err(ProgramError::InvalidArgument);
+    }
+    let too_high = target_amp > current_amp.saturating_mul(MAX_AMP_CHANGE);
+    let too_low = target_amp.saturating_mul(MAX_AMP_CHANGE) < current_amp;
+    if too_high || too_low {
+        return Err(ProgramError::InvalidArgument);
+    }
+    Ok(())
+}
+
+fn leverage(amp: u64) -> Result<U256, ProgramError> {
+    if !(MIN_AMP..=MAX_AMP).contains(&amp) {
+        return Err(ProgramError::InvalidArgument);
+    }
+    Ok(U256::from(amp) * U256::from(N_COINS))
+}
+
+fn abs_diff(a: U256, b: U256) -> U256 {
+    if a > b {
+        a - b
+    } else {
+        b - a
+    }
+}
+
+// Solves the invariant for D given both reserves
+pub fn compute_d(amp: u64, reserve_a: u64, reserve_b: u64) -> Result<U256, ProgramError> {
+    if reserve_a == 0 || reserve_b == 0 {
+        return Err(ProgramError::InvalidArgument);
+    }
+    let ann = leverage(amp)?;
+    let n = U256::from(N_COINS);
+    let (x, y) = (U256::from(reserve_a), U256::from(reserve_b));
+    let sum = x + y;
+
+    let mut d = sum;
+    for _ in 0..MAX_ITERATIONS {
+        // D^(n+1) / (n^n * x * y) in one division; rounding twice can keep Newton oscillating
+        let d_p = d * d * d / (x * y * n * n);
+
+        let previous = d;
+        let numerator = (ann * sum + d_p * n) * d;
+        let denominator = (ann - 1) * d + (n + 1) * d_p;
+        d = numerator / denominator;
+        // Starting from the sum, Newton falls monotonically towards D. In very
+        // unbalanced pools rounding can bounce it around the root instead, so
+        // stop as soon as it no longer falls and keep the lower value.
+        if d >= previous {
+            return Ok(previous);
+        }
+        if previous - d <= U256::one() {
+            return Ok(d);
+        }
+    }
+    Err(ProgramError::InvalidArgument)
+}
+
+// Solves the invariant for the other reserve once one side is set to `x`
+pub fn compute_y(amp: u64, x: U256, d: U256) -> Result<U256, ProgramError> {
+    if x.is_zero() {
+        return Err(ProgramError::InvalidArgument);
+    }
+    let ann = leverage(amp)?;
+    let n = U256::from(N_COINS);
+
+    // y^2 + (b - D) * y = c
+    let c = d * d / (x * n) * d / (ann * n);
+    let b = x + d / ann;
+
+    let mut y = d;
+    for iteration in 0..MAX_ITERATIONS {
+        let previous = y;
+        let denominator = (y * U256::from(2u8) + b)
+            .checked_sub(d)
+            .filter(|denominator| !denominator.is_zero())
+            .ok_or(ProgramError::InvalidArgument)?;
+        y = (y * y + c) / denominator;
+        if abs_diff(y, previous) <= U256::one() {
+            return Ok(y);
+        }
+        // After the first step Newton approaches y from above, so a rise is
+        // rounding noise; the higher value pays out less and is the safe one
+        if iteration > 0 && y > previous {
+            return Ok(y);
+        }
+    }
+    Err(ProgramError::InvalidArgument)
+}
+
+// Output of a stable swap after the same 0.3% input fee as constant product pools.
+// The fee stays in the pool, so D grows with every trade.
+pub fn calculate_swap_output(
+    amount_in: u64,
+    reserve_in: u64,
+    reserve_out: u64,
+    amp: u64,
+) -> Result<u64, ProgramError> {
+    if amount_in == 0 || reserve_in == 0 || reserve_out == 0 {
+        return Err(ProgramError::InvalidArgument);
+    }
+
+    let d = compute_d(amp, reserve_in, reserve_out)?;
+    let new_reserve_in = U256::from(reserve_in) + U256::from(amount_after_fee(amount_in));
+    let new_reserve_out = compute_y(amp, new_reserve_in, d)?;
+
+    // Round against the trader; Newton may land one unit short of the true root
+    let amount_out = U256::from(reserve_out)
+        .saturating_sub(new_reserve_out)
+        .saturating_sub(U256::one());
+    if amount_out >= U256::from(reserve_out) {
+        return Err(ProgramError::InvalidArgument);
+    }
+    Ok(amount_out.as_u64())
+}
+
+// LP tokens for the first deposit: D itself, i.e. the pool's value in pegged units
+pub fn calculate_initial_lp_tokens(
+    amp: u64,
+    amount_a: u64,
+    amount_b: u64,
+) -> Result<u64, ProgramError> {
+    let d = compute_d(amp, amount_a, amount_b)?;
+    if d > U256::from(u64::MAX) {
+        return Err(ProgramError::InvalidArgument);
+    }
+    Ok(d.as_u64())
+}
+
+// Input needed to take exactly `amount_out`, mirroring the rounding of calculate_swap_output
+pub fn calculate_swap_input(
+    amount_out: u64,
+    reserve_in: u64,
+    reserve_out: u64,
+    amp: u64,
+) -> Result<u64, ProgramError> {
+    if amount_out == 0 || reserve_in == 0 || amount_out >= reserve_out {
+        return Err(ProgramError::InvalidArgument);
+    }
+
+    let d = compute_d(amp, reserve_in, reserve_out)?;
+    // One extra unit out covers the unit calculate_swap_output withholds
+    let new_reserve_out = U256::from(reserve_out - amount_out)
+        .checked_sub(U256::one())
+        .filter(|reserve| !reserve.is_zero())
+        .ok_or(ProgramError::InvalidArgument)?;
+    let new_reserve_in = compute_y(amp, new_reserve_out, d)?;
+    let net_in = new_reserve_in
+        .checked_sub(U256::from(reserve_in))
+        .ok_or(ProgramError::InvalidArgument)?
+        + U256::one();
+    if net_in > U256::from(u64::MAX) {
+        return Err(ProgramError::InvalidArgument);
+    }
+    amount_before_fee(net_in.as_u128())
+}
+
+// Amplification schedule stored in the pool's curve parameters
+#[derive(BorshSerialize, BorshDeserialize, Clone, Copy, Debug, PartialEq, Eq)]
+pub struct StableParams {
+    pub initial_amp: u64,
+    pub target_amp: u64,
+    pub ramp_start_ts: i64,
+    pub ramp_stop_ts: i64,
+}
+
+impl StableParams {
+    pub fn current_amp(&self, now: i64) -> u64 {
+        current_amp(
+            self.initial_amp,
+            self.target_amp,
+            self.ramp_start_ts,
+            self.ramp_stop_ts,
+            now,
+        )
+    }
+}
+
+// StableSwap curve at a fixed amplification
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct StableCurve {
+    pub amp: u64,
+}
+
+impl SwapCurve for StableCurve {
+    fn swap_exact_in(
+        &self,
+        amount_in: u64,
+        reserve_a: u64,
+        reserve_b: u64,
+        direction_a_to_b: bool,
+    ) -> Result<u64, ProgramError> {
+        let (reserve_in, reserve_out) = if direction_a_to_b {
+            (reserve_a, reserve_b)
+        } else {
+            (reserve_b, reserve_a)
+        };
+        calculate_swap_output(amount_in, reserve_in, reserve_out, self.amp)
+    }
+
+    fn swap_exact_out(
+        &self,
+        amount_out: u64,
+        reserve_a: u64,
+        reserve_b: u64,
+        direction_a_to_b: bool,
+    ) -> Result<u64, ProgramError> {
+        let (reserve_in, reserve_out) = if direction_a_to_b {
+            (reserve_a, reserve_b)
+        } else {
+            (reserve_b, reserve_a)
+        };
+        calculate_swap_input(amount_out, reserve_in, reserve_out, self.amp)
+    }
+
+    fn initial_lp_tokens(&self, amount_a: u64, amount_b: u64) -> Result<u64, ProgramError> {
+        calculate_initial_lp_tokens(self.amp, amount_a, amount_b)
+    }
+
+    // -dy/dx of the invariant: (4·Ann·x·y + D³/x) / (4·Ann·x·y + D³/y)
+    fn spot_price(&self, reserve_a: u64, reserve_b: u64) -> Result<u128, ProgramError> {
+        let d = compute_d(self.amp, reserve_a, reserve_b)?;
+        let ann = leverage(self.amp)?;
+        let (x, y) = (U256::from(reserve_a), U256::from(reserve_b));
+        let d_cubed = d * d * d;
+        let base = U256::from(4u8) * ann * x * y;
+        let numerator = base + d_cubed / x;
+        let denominator = base + d_cubed / y;
+        let price = numerator * U256::from(PRICE_SCALE) / denominator;
+        if price > U256::from(u128::MAX) {
+            return Err(ProgramError::InvalidArgument);
+        }
+        Ok(price.as_u128())
+    }
+
+    fn invariant(&self, reserve_a: u64, reserve_b: u64) -> Result<U256, ProgramError> {
+        compute_d(self.amp, reserve_a, reserve_b)
+    }
+}
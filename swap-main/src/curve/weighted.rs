@@ -0,0 +1,154 @@
+// Balancer-style weighted product curve: B_a^w_a * B_b^w_b stays constant.
+//
+// Weights are basis points summing to WEIGHT_TOTAL, so an 80/20 pool is
+// weight_a = 8_000, weight_b = 2_000. Equal weights price like
+// constant product; skewed weights let a liquidity provider hold mostly one
+// token. Trades are capped at MAX_RATIO of the reserves, where the fixed-point
+// power functions stay precise.
+
+use super::{
+    amount_after_fee, amount_before_fee,
+    fixed_point::{mul_div, pow_down, pow_up, MAX_POW_RELATIVE_ERROR, ONE},
+    SwapCurve, PRICE_SCALE, U256,
+};
+use borsh::{BorshDeserialize, BorshSerialize};
+use solana_program::program_error::ProgramError;
+
+// Weights are in basis points of the pool
+pub const WEIGHT_TOTAL: u64 = 10_000;
+
+// Each token keeps at least 1% of the pool
+pub const MIN_WEIGHT: u64 = 100;
+
+// A swap may move at most 30% of either reserve
+const MAX_RATIO_NUMERATOR: u128 = 3;
+const MAX_RATIO_DENOMINATOR: u128 = 10;
+
+#[derive(BorshSerialize, BorshDeserialize, Clone, Copy, Debug, PartialEq, Eq)]
+pub struct WeightedCurve {
+    pub weight_a: u64,
+    pub weight_b: u64,
+}
+
+impl WeightedCurve {
+    pub fn validate(&self) -> Result<(), ProgramError> {
+        let total = self.weight_a.checked_add(self.weight_b);
+        if total != Some(WEIGHT_TOTAL) || self.weight_a < MIN_WEIGHT || self.weight_b < MIN_WEIGHT {
+            return Err(ProgramError::InvalidArgument);
+        }
+        Ok(())
+    }
+
+    // (reserve_in, reserve_out, weight_in, weight_out) for a trade direction
+    fn sides(
+        &self,
+        reserve_a: u64,
+        reserve_b: u64,
+        direction_a_to_b: bool,
+    ) -> (u128, u128, u128, u128) {
+        if direction_a_to_b {
+            (reserve_a as u128, reserve_b as u128, self.weight_a as u128, self.weight_b as u128)
+        } else {
+            (reserve_b as u128, reserve_a as u128, self.weight_b as u128, self.weight_a as u128)
+        }
+    }
+}
+
+fn exceeds_max_ratio(amount: u128, reserve: u128) -> bool {
+    amount * MAX_RATIO_DENOMINATOR > reserve * MAX_RATIO_NUMERATOR
+}
+
+impl SwapCurve for WeightedCurve {
+    // out = B_out * (1 - (B_in / (B_in + in))^(w_in / w_out))
+    fn swap_exact_in(
+        &self,
+        amount_in: u64,
+        reserve_a: u64,
+        reserve_b: u64,
+        direction_a_to_b: bool,
+    ) -> Result<u64, ProgramError> {
+        self.validate()?;
+        let (reserve_in, reserve_out, weight_in, weight_out) =
+            self.sides(reserve_a, reserve_b, direction_a_to_b);
+        if amount_in == 0 || reserve_in == 0 || reserve_out == 0 {
+            return Err(ProgramError::InvalidArgument);
+        }
+        let net_in = amount_after_fee(amount_in);
+        if exceeds_max_ratio(net_in, reserve_in) {
+            return Err(ProgramError::InvalidArgument);
+        }
+
+        // Every rounding step shrinks the output
+        let base = mul_div(reserve_in, ONE, reserve_in + net_in, true)?;
+        let exponent = mul_div(weight_in, ONE, weight_out, false)?;
+        let power = pow_up(base, exponent)?;
+        let amount_out = mul_div(reserve_out, ONE.saturating_sub(power), ONE, false)?;
+        Ok(amount_out as u64)
+    }
+
+    // in = B_in * ((B_out / (B_out - out))^(w_out / w_in) - 1)
+    fn swap_exact_out(
+        &self,
+        amount_out: u64,
+        reserve_a: u64,
+        reserve_b: u64,
+        direction_a_to_b: bool,
+    ) -> Result<u64, ProgramError> {
+        self.validate()?;
+        let (reserve_in, reserve_out, weight_in, weight_out) =
+            self.sides(reserve_a, reserve_b, direction_a_to_b);
+        if amount_out == 0 || reserve_in == 0 || exceeds_max_ratio(amount_out as u128, reserve_out) {
+            return Err(ProgramError::InvalidArgument);
+        }
+
+        // Ask for a little more than requested so the power error and the
+        // rounding in swap_exact_in can never leave the trader short
+        let margin = 1 + mul_div(reserve_out, 2 * MAX_POW_RELATIVE_ERROR, ONE, true)?;
+        let target_out = amount_out as u128 + margin;
+        if target_out >= reserve_out {
+            return Err(ProgramError::InvalidArgument);
+        }
+
+        let base = mul_div(reserve_out, ONE, reserve_out - target_out, true)?;
+        let exponent = mul_div(weight_out, ONE, weight_in, true)?;
+        let power = pow_up(base, exponent)?;
+        let net_in = mul_div(reserve_in, power - ONE, ONE, true)?;
+        amount_before_fee(net_in)
+    }
+
+    // The invariant itself, so a 50/50 pool mints about sqrt(a * b) like constant product
+    fn initial_lp_tokens(&self, amount_a: u64, amount_b: u64) -> Result<u64, ProgramError> {
+        let value = self.invariant(amount_a, amount_b)? / U256::from(ONE);
+        if value > U256::from(u64::MAX) {
+            return Err(ProgramError::InvalidArgument);
+        }
+        Ok(value.as_u64())
+    }
+
+    // (B_b / w_b) / (B_a / w_a)
+    fn spot_price(&self, reserve_a: u64, reserve_b: u64) -> Result<u128, ProgramError> {
+        self.validate()?;
+        if reserve_a == 0 {
+            return Err(ProgramError::InvalidArgument);
+        }
+        mul_div(
+            reserve_b as u128 * self.weight_a as u128,
+            PRICE_SCALE,
+            reserve_a as u128 * self.weight_b as u128,
+            false,
+        )
+    }
+
+    // B_a^w_a * B_b^w_b with normalized weights, scaled by ONE
+    fn invariant(&self, reserve_a: u64, reserve_b: u64) -> Result<U256, ProgramError> {
+        self.validate()?;
+        if reserve_a == 0 || reserve_b == 0 {
+            return Ok(U256::zero());
+        }
+        let weight_a = mul_div(self.weight_a as u128, ONE, WEIGHT_TOTAL as u128, false)?;
+        let weight_b = ONE - weight_a;
+        let factor_a = pow_down(reserve_a as u128 * ONE, weight_a)?;
+        let factor_b = pow_down(reserve_b as u128 * ONE, weight_b)?;
+        Ok(U256::from(factor_a) * U256::from(factor_b) / U256::from(ONE))
+    }
+}
@@ -0,0 +1,65 @@
+// Flash loans and flash swaps: pool tokens are paid out before they are paid for.
+//
+// FlashLoan pays the loan out, invokes the receiver program with
+// FlashReceiverInstruction::ReceiveFlashLoan and then requires the vault
+// balance to have grown by at least the fee. FlashSwap pays out swap output,
+// invokes ReceiveFlashSwap and then requires the vault balances to satisfy the
+// pool's curve, with input in either token. Solana rejects reentrancy through
+// another program, so the receiver cannot touch the pool in between.
+
+use crate::curve::fixed_point::mul_div;
+use borsh::{BorshDeserialize, BorshSerialize};
+use solana_program::{
+    account_info::AccountInfo,
+    instruction::{AccountMeta, Instruction},
+    program_error::ProgramError,
+    pubkey::Pubkey,
+};
+
+// 0.09% of the amount borrowed, rounded up
+pub const FLASH_LOAN_FEE_NUMERATOR: u64 = 9;
+pub const FLASH_LOAN_FEE_DENOMINATOR: u64 = 10_000;
+
+// Instruction data the receiver program is invoked with
+#[derive(BorshSerialize, BorshDeserialize, Debug, PartialEq, Eq)]
+pub enum FlashReceiverInstruction {
+    // Repay `amount + fee` into the vault before returning, through the mint's
+    // token program.
+    // Accounts: [receiver_token, mint, vault, token_program, ...extra accounts of FlashLoan]
+    ReceiveFlashLoan { amount: u64, fee: u64 },
+    // Pay enough of either token into the vaults to keep the curve invariant,
+    // after the 0.3% swap fee on what is paid in.
+    // Accounts: [pool, token_a, token_b, vault_a, vault_b, token_a_program,
+    // token_b_program, ...extra accounts of FlashSwap]
+    ReceiveFlashSwap { amount_a_out: u64, amount_b_out: u64 },
+}
+
+pub fn flash_loan_fee(amount: u64) -> Result<u64, ProgramError> {
+    let fee = mul_div(
+        amount as u128,
+        FLASH_LOAN_FEE_NUMERATOR as u128,
+        FLASH_LOAN_FEE_DENOMINATOR as u128,
+        true,
+    )?;
+    u64::try_from(fee).map_err(|_| ProgramError::InvalidArgument)
+}
+
+// Callback into the receiver: `accounts` first, then the extra accounts of the
+// outer instruction with their signer and writable flags
+pub fn receiver_instruction(
+    receiver_program: &Pubkey,
+    data: &FlashReceiverInstruction,
+    mut accounts: Vec<AccountMeta>,
+    extra_accounts: &[AccountInfo],
+) -> Result<Instruction, ProgramError> {
+    accounts.extend(extra_accounts.iter().map(|account| AccountMeta {
+        pubkey: *account.key,
+        is_signer: account.is_signer,
+        is_writable: account.is_writable,
+    }));
+    Ok(Instruction {
+        program_id: *receiver_program,
+        accounts,
+        data: data.try_to_vec().map_err(|_| ProgramError::InvalidArgument)?,
+    })
+}
@@ -1,8 +1,8 @@
 use solana_program::{
     account_info::{next_account_info, AccountInfo},
-    entrypoint,
+    clock::Clock,
     entrypoint::ProgramResult,
-    program::{invoke, invoke_signed},
+    program::{get_return_data, invoke, invoke_signed, set_return_data},
     program_error::ProgramError,
     program_pack::{IsInitialized, Pack, Sealed},
     pubkey::Pubkey,
@@ -11,40 +11,45 @@ use solana_program::{
     sysvar::Sysvar,
 };
 // use spl_token::state::Account as TokenAccount; // Unused import
-use std::str::FromStr;
 use borsh::{BorshDeserialize, BorshSerialize};
 use shank::{ShankInstruction, ShankAccount};
 
+pub mod concentrated;
+pub mod cpi;
+pub mod curve;
+pub mod flash_loan;
+pub mod multi_pool;
+pub mod oracle;
+pub mod pool_state;
+
+use curve::{
+    stable, ConstantPriceCurve, ConstantProductCurve, OffsetCurve, StableCurve, StableParams,
+    SwapCurve, WeightedCurve, CURVE_PARAMS_LEN,
+};
+use concentrated::{ConcentratedPool, Position};
+use flash_loan::FlashReceiverInstruction;
+use multi_pool::MultiPool;
+use oracle::{
+    Observation, ObservationRing, ObserveResult, OracleHeader, PriceObservation, TwapResult,
+};
+use pool_state::{PoolState, PoolStateMut};
+
 // Program ID
 solana_program::declare_id!("aBfrRgukSYDMgdyQ8y1XNEk4w5u7Ugtz5fPHFnkStJX");
 
 // GorbChain SPL Token Program ID
-const GORBCHAIN_SPL_TOKEN_PROGRAM: &str = "G22oYgZ6LnVcy7v8eSNi2xpNk1NcZiPD8CVKSTut7oZ6";
+pub const GORBCHAIN_SPL_TOKEN_PROGRAM: Pubkey = solana_program::pubkey!("G22oYgZ6LnVcy7v8eSNi2xpNk1NcZiPD8CVKSTut7oZ6");
 
-// Manual instruction creation for GorbChain SPL Token program
-fn create_transfer_instruction(
-    source: &Pubkey,
-    destination: &Pubkey,
-    authority: &Pubkey,
-    amount: u64,
-) -> solana_program::instruction::Instruction {
-    let data = {
-        let mut buf = Vec::new();
-        buf.push(3); // Transfer instruction discriminator
-        buf.extend_from_slice(&amount.to_le_bytes());
-        buf
-    };
-    solana_program::instruction::Instruction {
-        program_id: Pubkey::from_str(GORBCHAIN_SPL_TOKEN_PROGRAM).unwrap(),
-        accounts: vec![
-            solana_program::instruction::AccountMeta::new(*source, false),
-            solana_program::instruction::AccountMeta::new(*destination, false),
-            solana_program::instruction::AccountMeta::new_readonly(*authority, true),
-        ],
-        data,
-    }
-}
+// GorbChain Associated Token Account Program ID
+pub const GORBCHAIN_ATA_PROGRAM: Pubkey = solana_program::pubkey!("GoATGVNeSXerFerPqTJ8hcED1msPWHHLxao2vwBYqowm");
 
+// Token-2022 program, for mints with extensions such as transfer fees
+const SPL_TOKEN_2022_PROGRAM: Pubkey = solana_program::pubkey!("TokenzQdBNbLqP5VEhdkAS6EPFLC1PHnBqCXEpPxuEb");
+
+// Mint of wrapped native GOR
+pub const NATIVE_MINT: Pubkey = solana_program::pubkey!("So11111111111111111111111111111111111111112");
+
+// Manual instruction creation for GorbChain SPL Token program
 fn create_mint_to_instruction(
     mint: &Pubkey,
     destination: &Pubkey,
@@ -58,7 +63,7 @@ fn create_mint_to_instruction(
         buf
     };
     solana_program::instruction::Instruction {
-        program_id: Pubkey::from_str(GORBCHAIN_SPL_TOKEN_PROGRAM).unwrap(),
+        program_id: GORBCHAIN_SPL_TOKEN_PROGRAM,
         accounts: vec![
             solana_program::instruction::AccountMeta::new(*mint, false),
             solana_program::instruction::AccountMeta::new(*destination, false),
@@ -81,7 +86,7 @@ fn create_burn_instruction(
         buf
     };
     solana_program::instruction::Instruction {
-        program_id: Pubkey::from_str(GORBCHAIN_SPL_TOKEN_PROGRAM).unwrap(),
+        program_id: GORBCHAIN_SPL_TOKEN_PROGRAM,
         accounts: vec![
             solana_program::instruction::AccountMeta::new(*account, false),
             solana_program::instruction::AccountMeta::new(*mint, false),
@@ -92,17 +97,14 @@ fn create_burn_instruction(
 }
 
 fn create_initialize_account_instruction(
+    token_program: &Pubkey,
     account: &Pubkey,
     mint: &Pubkey,
     authority: &Pubkey,
 ) -> solana_program::instruction::Instruction {
-    let data = {
-        let mut buf = Vec::new();
-        buf.push(1); // InitializeAccount instruction discriminator
-        buf
-    };
+    let data = vec![1]; // InitializeAccount instruction discriminator
     solana_program::instruction::Instruction {
-        program_id: Pubkey::from_str(GORBCHAIN_SPL_TOKEN_PROGRAM).unwrap(),
+        program_id: *token_program,
         accounts: vec![
             solana_program::instruction::AccountMeta::new(*account, false),
             solana_program::instruction::AccountMeta::new_readonly(*mint, false),
@@ -134,7 +136,7 @@ fn create_initialize_mint_instruction(
         buf
     };
     solana_program::instruction::Instruction {
-        program_id: Pubkey::from_str(GORBCHAIN_SPL_TOKEN_PROGRAM).unwrap(),
+        program_id: GORBCHAIN_SPL_TOKEN_PROGRAM,
         accounts: vec![
             solana_program::instruction::AccountMeta::new(*mint, false),
             solana_program::instruction::AccountMeta::new_readonly(solana_program::sysvar::rent::id(), false),
@@ -143,6 +145,46 @@ fn create_initialize_mint_instruction(
     }
 }
 
+// InitializeAccount3 instruction for GorbChain SPL Token program (no rent sysvar)
+fn create_initialize_account3_instruction(
+    account: &Pubkey,
+    mint: &Pubkey,
+    owner: &Pubkey,
+) -> solana_program::instruction::Instruction {
+    let data = {
+        let mut buf = Vec::new();
+        buf.push(18); // InitializeAccount3 instruction discriminator
+        buf.extend_from_slice(owner.as_ref());
+        buf
+    };
+    solana_program::instruction::Instruction {
+        program_id: GORBCHAIN_SPL_TOKEN_PROGRAM,
+        accounts: vec![
+            solana_program::instruction::AccountMeta::new(*account, false),
+            solana_program::instruction::AccountMeta::new_readonly(*mint, false),
+        ],
+        data,
+    }
+}
+
+// CloseAccount instruction for GorbChain SPL Token program
+fn create_close_account_instruction(
+    account: &Pubkey,
+    destination: &Pubkey,
+    authority: &Pubkey,
+) -> solana_program::instruction::Instruction {
+    let data = vec![9]; // CloseAccount instruction discriminator
+    solana_program::instruction::Instruction {
+        program_id: GORBCHAIN_SPL_TOKEN_PROGRAM,
+        accounts: vec![
+            solana_program::instruction::AccountMeta::new(*account, false),
+            solana_program::instruction::AccountMeta::new(*destination, false),
+            solana_program::instruction::AccountMeta::new_readonly(*authority, true),
+        ],
+        data,
+    }
+}
+
 // Create Associated Token Account instruction for GorbChain SPL Token program
 fn create_associated_token_account_instruction(
     payer: &Pubkey,
@@ -150,27 +192,63 @@ fn create_associated_token_account_instruction(
     owner: &Pubkey,
     mint: &Pubkey,
 ) -> solana_program::instruction::Instruction {
-    let data = {
-        let mut buf = Vec::new();
-        buf.push(0); // CreateAssociatedTokenAccount instruction discriminator
-        buf
-    };
+    let data = vec![0]; // CreateAssociatedTokenAccount instruction discriminator
     solana_program::instruction::Instruction {
-        program_id: Pubkey::from_str("GoATGVNeSXerFerPqTJ8hcED1msPWHHLxao2vwBYqowm").unwrap(), // ATA Program ID
+        program_id: GORBCHAIN_ATA_PROGRAM,
         accounts: vec![
             solana_program::instruction::AccountMeta::new(*payer, true),
             solana_program::instruction::AccountMeta::new(*associated_token, false),
             solana_program::instruction::AccountMeta::new_readonly(*owner, false),
             solana_program::instruction::AccountMeta::new_readonly(*mint, false),
             solana_program::instruction::AccountMeta::new_readonly(solana_program::sysvar::rent::id(), false),
-            solana_program::instruction::AccountMeta::new_readonly(Pubkey::from_str(GORBCHAIN_SPL_TOKEN_PROGRAM).unwrap(), false),
+            solana_program::instruction::AccountMeta::new_readonly(GORBCHAIN_SPL_TOKEN_PROGRAM, false),
             solana_program::instruction::AccountMeta::new_readonly(solana_program::system_program::id(), false),
         ],
         data,
     }
 }
 
-// Helper function to derive vault PDAs
+// TransferChecked instruction for either token program. Token-2022 mints with
+// extensions cannot be moved with the plain Transfer.
+fn create_transfer_checked_instruction(
+    token_program: &Pubkey,
+    source: &Pubkey,
+    mint: &Pubkey,
+    destination: &Pubkey,
+    authority: &Pubkey,
+    amount: u64,
+    decimals: u8,
+) -> solana_program::instruction::Instruction {
+    let mut data = vec![12]; // TransferChecked instruction discriminator
+    data.extend_from_slice(&amount.to_le_bytes());
+    data.push(decimals);
+    solana_program::instruction::Instruction {
+        program_id: *token_program,
+        accounts: vec![
+            solana_program::instruction::AccountMeta::new(*source, false),
+            solana_program::instruction::AccountMeta::new_readonly(*mint, false),
+            solana_program::instruction::AccountMeta::new(*destination, false),
+            solana_program::instruction::AccountMeta::new_readonly(*authority, true),
+        ],
+        data,
+    }
+}
+
+// GetAccountDataSize instruction: the token program returns the size of a
+// token account for `mint`, including the extensions the mint requires
+fn create_get_account_data_size_instruction(
+    token_program: &Pubkey,
+    mint: &Pubkey,
+) -> solana_program::instruction::Instruction {
+    solana_program::instruction::Instruction {
+        program_id: *token_program,
+        accounts: vec![solana_program::instruction::AccountMeta::new_readonly(*mint, false)],
+        data: vec![21], // GetAccountDataSize instruction discriminator
+    }
+}
+
+// Vault PDA and its bump, searched for once when a pool is created; later
+// instructions check the bump the pool stored with verify_vault
 fn get_vault_address(pool: &Pubkey, token_mint: &Pubkey, program_id: &Pubkey) -> (Pubkey, u8) {
     Pubkey::find_program_address(
         &[b"vault", pool.as_ref(), token_mint.as_ref()],
@@ -178,8 +256,225 @@ fn get_vault_address(pool: &Pubkey, token_mint: &Pubkey, program_id: &Pubkey) ->
     )
 }
 
-// Entry point
-entrypoint!(process_instruction);
+// Checks a vault account of any pool against the bump stored for it
+fn verify_vault(
+    program_id: &Pubkey,
+    pool_key: &Pubkey,
+    mint: &Pubkey,
+    vault_bump: u8,
+    vault_info: &AccountInfo,
+) -> ProgramResult {
+    let vault_pubkey = Pubkey::create_program_address(
+        &[b"vault", pool_key.as_ref(), mint.as_ref(), &[vault_bump]],
+        program_id,
+    )?;
+    if vault_pubkey != *vault_info.key {
+        return Err(ProgramError::InvalidSeeds);
+    }
+    Ok(())
+}
+
+// Checks a two-token pool's vault accounts against the bumps stored in the pool
+fn verify_vaults(
+    program_id: &Pubkey,
+    pool: &PoolState,
+    pool_key: &Pubkey,
+    vault_a_info: &AccountInfo,
+    vault_b_info: &AccountInfo,
+) -> ProgramResult {
+    verify_vault(program_id, pool_key, &pool.token_a, pool.vault_a_bump, vault_a_info)?;
+    verify_vault(program_id, pool_key, &pool.token_b, pool.vault_b_bump, vault_b_info)
+}
+
+// Longest route a multihop swap accepts
+pub const MAX_HOPS: usize = 6;
+
+// Most routes a SplitRouteSwap accepts, which keeps its return data for
+// routes of MAX_HOPS hops within the 1024-byte limit
+pub const MAX_SPLIT_ROUTES: usize = 3;
+
+// Route checks shared by the multihop swaps: a bounded number of hops, none
+// of them through a pool an earlier hop already used
+fn check_route(pools: &[&Pubkey]) -> ProgramResult {
+    if pools.len() > MAX_HOPS {
+        return Err(ProgramError::InvalidArgument);
+    }
+    check_distinct_pools(pools)
+}
+
+fn check_distinct_pools(pools: &[&Pubkey]) -> ProgramResult {
+    for (hop, pool) in pools.iter().enumerate() {
+        if pools[..hop].contains(pool) {
+            return Err(ProgramError::InvalidArgument);
+        }
+    }
+    Ok(())
+}
+
+// Two-token pool of a multihop hop, owned by this program at the PDA of its
+// own mints
+fn load_hop_pool<'a, 'b>(
+    program_id: &Pubkey,
+    pool_info: &'a AccountInfo<'b>,
+) -> Result<PoolStateMut<'a, 'b>, ProgramError> {
+    if pool_info.owner != program_id {
+        return Err(ProgramError::IncorrectProgramId);
+    }
+    let pool = PoolState::load_mut(program_id, pool_info)?;
+    let pool_pubkey = Pubkey::create_program_address(
+        &[b"pool", pool.token_a.as_ref(), pool.token_b.as_ref(), &[pool.bump]],
+        program_id,
+    )?;
+    if pool_pubkey != *pool_info.key {
+        return Err(ProgramError::InvalidSeeds);
+    }
+    Ok(pool)
+}
+
+// Token program of a pool mint: the supported token program that owns it. The
+// program account must be among the instruction's accounts, so pools with a
+// Token-2022 mint take that program after their listed accounts.
+fn mint_token_program<'a, 'b>(
+    mint_info: &AccountInfo<'a>,
+    accounts: &'b [AccountInfo<'a>],
+) -> Result<&'b AccountInfo<'a>, ProgramError> {
+    if *mint_info.owner != GORBCHAIN_SPL_TOKEN_PROGRAM
+        && *mint_info.owner != SPL_TOKEN_2022_PROGRAM
+    {
+        return Err(ProgramError::IncorrectProgramId);
+    }
+    accounts
+        .iter()
+        .find(|account| account.key == mint_info.owner)
+        .ok_or(ProgramError::NotEnoughAccountKeys)
+}
+
+// Token-2022 accounts and mints start with the SPL Token layout and append
+// their extensions, so only that prefix is unpacked
+fn token_account_amount(account_info: &AccountInfo) -> Result<u64, ProgramError> {
+    let data = account_info.data.borrow();
+    let base = data
+        .get(..spl_token::state::Account::LEN)
+        .ok_or(ProgramError::InvalidAccountData)?;
+    Ok(spl_token::state::Account::unpack(base)?.amount)
+}
+
+// Mint of a token account of either supported token program
+fn token_account_mint(account_info: &AccountInfo) -> Result<Pubkey, ProgramError> {
+    if *account_info.owner != GORBCHAIN_SPL_TOKEN_PROGRAM && *account_info.owner != SPL_TOKEN_2022_PROGRAM {
+        return Err(ProgramError::IncorrectProgramId);
+    }
+    let data = account_info.data.borrow();
+    let base = data
+        .get(..spl_token::state::Account::LEN)
+        .ok_or(ProgramError::InvalidAccountData)?;
+    Ok(spl_token::state::Account::unpack(base)?.mint)
+}
+
+fn mint_decimals(mint_info: &AccountInfo) -> Result<u8, ProgramError> {
+    let data = mint_info.data.borrow();
+    let base = data
+        .get(..spl_token::state::Mint::LEN)
+        .ok_or(ProgramError::InvalidAccountData)?;
+    Ok(spl_token::state::Mint::unpack(base)?.decimals)
+}
+
+// TransferChecked through the mint's token program, signed with `signer_seeds`
+// when the source is a vault. Returns what `destination_info` was credited,
+// which is less than `amount` when the mint withholds a transfer fee.
+fn transfer_checked<'a>(
+    token_program_info: &AccountInfo<'a>,
+    source_info: &AccountInfo<'a>,
+    mint_info: &AccountInfo<'a>,
+    destination_info: &AccountInfo<'a>,
+    authority_info: &AccountInfo<'a>,
+    amount: u64,
+    signer_seeds: &[&[&[u8]]],
+) -> Result<u64, ProgramError> {
+    let balance_before = token_account_amount(destination_info)?;
+    invoke_signed(
+        &create_transfer_checked_instruction(
+            token_program_info.key,
+            source_info.key,
+            mint_info.key,
+            destination_info.key,
+            authority_info.key,
+            amount,
+            mint_decimals(mint_info)?,
+        ),
+        &[
+            source_info.clone(),
+            mint_info.clone(),
+            destination_info.clone(),
+            authority_info.clone(),
+            token_program_info.clone(),
+        ],
+        signer_seeds,
+    )?;
+    token_account_amount(destination_info)?
+        .checked_sub(balance_before)
+        .ok_or(ProgramError::InvalidAccountData)
+}
+
+// Create and initialize a pool vault under the mint's token program,
+// sized by that program for the mint's extensions. The vault is its own authority.
+fn create_vault<'a>(
+    payer_info: &AccountInfo<'a>,
+    vault_info: &AccountInfo<'a>,
+    mint_info: &AccountInfo<'a>,
+    token_program_info: &AccountInfo<'a>,
+    system_program_info: &AccountInfo<'a>,
+    rent_info: &AccountInfo<'a>,
+    vault_signer_seeds: &[&[u8]],
+) -> ProgramResult {
+    invoke(
+        &create_get_account_data_size_instruction(token_program_info.key, mint_info.key),
+        &[mint_info.clone(), token_program_info.clone()],
+    )?;
+    let vault_space = match get_return_data() {
+        Some((program, data)) if program == *token_program_info.key && data.len() == 8 => {
+            u64::from_le_bytes(data.try_into().unwrap())
+        }
+        _ => return Err(ProgramError::InvalidAccountData),
+    };
+    let rent = Rent::from_account_info(rent_info)?;
+
+    invoke_signed(
+        &system_instruction::create_account(
+            payer_info.key,
+            vault_info.key,
+            rent.minimum_balance(vault_space as usize),
+            vault_space,
+            token_program_info.key,
+        ),
+        &[
+            payer_info.clone(),
+            vault_info.clone(),
+            system_program_info.clone(),
+        ],
+        &[vault_signer_seeds],
+    )?;
+    invoke_signed(
+        &create_initialize_account_instruction(
+            token_program_info.key,
+            vault_info.key,
+            mint_info.key,
+            vault_info.key,
+        ),
+        &[
+            vault_info.clone(),
+            mint_info.clone(),
+            vault_info.clone(),
+            rent_info.clone(),
+            token_program_info.clone(),
+        ],
+        &[vault_signer_seeds],
+    )
+}
+
+// Entry point, left out when another program links this crate for `cpi`
+#[cfg(not(feature = "no-entrypoint"))]
+solana_program::entrypoint!(process_instruction);
 
 // Instructions
 #[derive(BorshSerialize, BorshDeserialize, Debug, ShankInstruction)]
@@ -195,9 +490,215 @@ pub enum TestProjectInstruction {
     FindPoolsByToken { token_address: Pubkey },
     GetSwapQuote { amount_in: u64, token_in: Pubkey },
     GetMultihopQuote { amount_in: u64, token_path: Vec<Pubkey> },
+    InitPoolWithCurve { amount_a: u64, amount_b: u64, curve: CurveParams },
+    RampAmp { target_amp: u64, ramp_stop_ts: i64 },
+    StopRampAmp,
+    // Multi-asset pools hold 3 to 8 tokens; amounts are in ascending mint order
+    InitMultiPool { amounts: Vec<u64> },
+    MultiPoolSwap { amount_in: u64, minimum_amount_out: u64 },
+    AddMultiLiquidity { amounts: Vec<u64> },
+    RemoveMultiLiquidity { lp_amount: u64 },
+    AddMultiLiquiditySingle { amount_in: u64, minimum_lp_out: u64 },
+    RemoveMultiLiquiditySingle { lp_amount: u64, minimum_amount_out: u64 },
+    // Concentrated liquidity pools; sqrt prices are scaled by 1e18
+    InitConcentratedPool { tick_spacing: u16, initial_sqrt_price: u128 },
+    OpenPosition { tick_lower: i32, tick_upper: i32 },
+    IncreaseLiquidity { liquidity: u128, maximum_amount_a: u64, maximum_amount_b: u64 },
+    DecreaseLiquidity { liquidity: u128, minimum_amount_a: u64, minimum_amount_b: u64 },
+    CollectFees,
+    ConcentratedSwap { amount_in: u64, minimum_amount_out: u64, direction_a_to_b: bool },
+    // Lends `amount` from one vault to a receiver program, repaid with a fee
+    FlashLoan { amount: u64 },
+    // Pays out swap output first, then calls the receiver to pay for it in either token
+    FlashSwap { amount_a_out: u64, amount_b_out: u64 },
+    // Runs a pool init, liquidity, swap or multihop instruction (Borsh-encoded in
+    // `instruction_data`) with a temporary wrapped GOR account for the user
+    WithNative { wrap_amount: u64, instruction_data: Vec<u8> },
+    // Reallocs a pool created before versioning to the current layout
+    MigratePool,
+    // Multihop swap taking [pool, vault_in, vault_out] per hop and the route's
+    // mints once each; `minimum_hop_amounts_out` is empty or one bound per hop
+    CompactMultihopSwap { amount_in: u64, minimum_amount_out: u64, minimum_hop_amounts_out: Vec<u64> },
+    // Swaps along up to MAX_SPLIT_ROUTES compact routes between the same two
+    // tokens, each with its share of the input, against one aggregate minimum
+    SplitRouteSwap { routes: Vec<SplitRoute>, minimum_amount_out: u64 },
+    // Average prices of a two-token pool since `since`, an earlier observation
+    // of it, returned as a TwapResult
+    GetTwap { since: PriceObservation },
+    // Creates the pool's oracle account with room for `capacity` observations
+    InitOracle { capacity: u16 },
+    // Reallocs a pool's oracle account to hold `capacity` observations
+    GrowOracle { capacity: u16 },
+    // Average prices and liquidity over windows ending now, each starting
+    // `seconds_ago` seconds back, returned as an ObserveResult
+    Observe { seconds_agos: Vec<u32> },
+    // MultihopSwap and MultihopSwapWithPath with `minimum_hop_amounts_out`
+    // bounding what each hop delivers, one bound per hop
+    MultihopSwapWithHopMinimums { amount_in: u64, minimum_amount_out: u64, minimum_hop_amounts_out: Vec<u64> },
+    MultihopSwapWithPathAndHopMinimums {
+        amount_in: u64,
+        minimum_amount_out: u64,
+        token_path: Vec<Pubkey>,
+        minimum_hop_amounts_out: Vec<u64>,
+    },
+}
+
+// One leg of a SplitRouteSwap: its share of the input and its number of hops
+#[derive(BorshSerialize, BorshDeserialize, Clone, Copy, Debug, PartialEq, Eq)]
+pub struct SplitRoute {
+    pub amount_in: u64,
+    pub num_hops: u8,
+}
+
+// Return data of Swap, MultiPoolSwap, ConcentratedSwap and FlashSwap, and of
+// each hop of a multihop swap.
+// Amounts are what actually arrived, net of any transfer fee.
+#[derive(BorshSerialize, BorshDeserialize, Clone, Copy, Debug, PartialEq, Eq)]
+pub struct SwapResult {
+    // Input credited to the pool
+    pub amount_in: u64,
+    // Output credited to the recipient: the user, or the next hop's vault
+    pub amount_out: u64,
+    // Swap fee kept by the pool, in the input token
+    pub fee: u64,
+    // Reserves after the swap
+    pub reserve_in: u64,
+    pub reserve_out: u64,
+}
+
+impl SwapResult {
+    // Result of a two-token pool swap, once its reserves are updated, with
+    // the fee of the pool's curve
+    fn from_pool(
+        pool: &PoolState,
+        amount_in: u64,
+        amount_out: u64,
+        direction_a_to_b: bool,
+    ) -> Result<Self, ProgramError> {
+        let (reserve_in, reserve_out) = if direction_a_to_b {
+            (pool.reserve_a, pool.reserve_b)
+        } else {
+            (pool.reserve_b, pool.reserve_a)
+        };
+        Ok(SwapResult {
+            amount_in,
+            amount_out,
+            fee: pool.swap_fee(amount_in)?,
+            reserve_in,
+            reserve_out,
+        })
+    }
+}
+
+// Return data of MultihopSwap, MultihopSwapWithPath and CompactMultihopSwap
+#[derive(BorshSerialize, BorshDeserialize, Clone, Debug, PartialEq, Eq)]
+pub struct MultihopSwapResult {
+    // Taken from the user's input account
+    pub amount_in: u64,
+    // Credited to the user's output account
+    pub amount_out: u64,
+    pub hops: Vec<SwapResult>,
+}
+
+// Return data of SplitRouteSwap, with one MultihopSwapResult per route
+#[derive(BorshSerialize, BorshDeserialize, Clone, Debug, PartialEq, Eq)]
+pub struct SplitRouteSwapResult {
+    pub amount_in: u64,
+    pub amount_out: u64,
+    pub routes: Vec<MultihopSwapResult>,
+}
+
+// Curve selection passed to InitPoolWithCurve
+#[derive(BorshSerialize, BorshDeserialize, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum CurveParams {
+    ConstantProduct,
+    Stable { amp: u64 },
+    ConstantPrice { token_b_price: u64 },
+    Offset { token_a_offset: u64, token_b_offset: u64 },
+    // Basis points summing to 10_000, e.g. 8_000 / 2_000 for an 80/20 pool
+    Weighted { weight_a: u64, weight_b: u64 },
+}
+
+impl CurveParams {
+    // Curve these parameters describe, at a fixed amplification for stable pools
+    pub fn curve(&self) -> Box<dyn SwapCurve> {
+        match *self {
+            CurveParams::ConstantProduct => Box::new(ConstantProductCurve),
+            CurveParams::Stable { amp } => Box::new(StableCurve { amp }),
+            CurveParams::ConstantPrice { token_b_price } => {
+                Box::new(ConstantPriceCurve { token_b_price })
+            }
+            CurveParams::Offset { token_a_offset, token_b_offset } => {
+                Box::new(OffsetCurve { token_a_offset, token_b_offset })
+            }
+            CurveParams::Weighted { weight_a, weight_b } => {
+                Box::new(WeightedCurve { weight_a, weight_b })
+            }
+        }
+    }
+}
+
+// Pricing curve a pool was created with
+#[derive(BorshSerialize, BorshDeserialize, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum CurveType {
+    ConstantProduct,
+    Stable,
+    ConstantPrice,
+    Offset,
+    Weighted,
+}
+
+impl CurveType {
+    // Pricing curve for `curve_params`; stable pools are priced at the current amplification
+    pub fn curve(self, curve_params: &[u8; CURVE_PARAMS_LEN]) -> Result<Box<dyn SwapCurve>, ProgramError> {
+        Ok(match self {
+            CurveType::ConstantProduct => Box::new(ConstantProductCurve),
+            CurveType::Stable => {
+                let params = curve::unpack_curve_params::<StableParams>(curve_params)?;
+                let amp = params.current_amp(Clock::get()?.unix_timestamp);
+                Box::new(StableCurve { amp })
+            }
+            CurveType::ConstantPrice => {
+                Box::new(curve::unpack_curve_params::<ConstantPriceCurve>(curve_params)?)
+            }
+            CurveType::Offset => {
+                Box::new(curve::unpack_curve_params::<OffsetCurve>(curve_params)?)
+            }
+            CurveType::Weighted => {
+                Box::new(curve::unpack_curve_params::<WeightedCurve>(curve_params)?)
+            }
+        })
+    }
+}
+
+impl TryFrom<u8> for CurveType {
+    type Error = ProgramError;
+
+    fn try_from(value: u8) -> Result<Self, ProgramError> {
+        match value {
+            0 => Ok(CurveType::ConstantProduct),
+            1 => Ok(CurveType::Stable),
+            2 => Ok(CurveType::ConstantPrice),
+            3 => Ok(CurveType::Offset),
+            4 => Ok(CurveType::Weighted),
+            _ => Err(ProgramError::InvalidAccountData),
+        }
+    }
 }
 
-// Pool state
+// Layout version written as the first byte of pool accounts
+pub const POOL_VERSION: u8 = 1;
+// Zeroed bytes at the end of a pool account for fields added by later versions
+pub const POOL_RESERVED_LEN: usize = 84;
+// Unversioned layout of the constant product pools created before MigratePool
+pub const LEGACY_POOL_LEN: usize = 32 + 32 + 1 + 8 + 8 + 8; // 89 bytes
+// Borsh-encoded Pool fields: the legacy ones, curve type, admin and curve
+// params, the PDA bumps, then the price accumulators and the oracle flag,
+// which version 1 pools created before them read as zeros
+pub(crate) const POOL_FIELDS_LEN: usize =
+    LEGACY_POOL_LEN + 1 + 32 + CURVE_PARAMS_LEN + 3 + 16 + 16 + 8 + 1;
+
+// Pool state, stored as [version, fields, reserved]
 #[derive(BorshSerialize, BorshDeserialize, Debug, ShankAccount)]
 pub struct Pool {
     pub token_a: Pubkey,
@@ -206,8 +707,29 @@ pub struct Pool {
     pub reserve_a: u64,
     pub reserve_b: u64,
     pub total_lp_supply: u64,
+    pub curve_type: CurveType,
+    // May ramp the amplification of stable pools
+    pub admin: Pubkey,
+    // Borsh-encoded parameters of curve_type, zero padded; Shank needs a literal length
+    pub curve_params: [u8; 32],
+    // Vault and LP mint PDA bumps, so their addresses are checked with
+    // create_program_address
+    pub vault_a_bump: u8,
+    pub vault_b_bump: u8,
+    pub lp_mint_bump: u8,
+    // Spot prices of token A and token B (see oracle) times the seconds they
+    // held, accumulated up to last_update_timestamp, which is zero until the
+    // first reserve change of a pool created before the accumulators
+    pub price_a_cumulative: u128,
+    pub price_b_cumulative: u128,
+    pub last_update_timestamp: i64,
+    // Set by InitOracle; every reserve change then records to the oracle,
+    // which instructions must pass
+    pub has_oracle: bool,
 }
 
+const _: () = assert!(CURVE_PARAMS_LEN == 32);
+
 impl Sealed for Pool {}
 
 impl IsInitialized for Pool {
@@ -217,17 +739,103 @@ impl IsInitialized for Pool {
 }
 
 impl Pack for Pool {
-    const LEN: usize = 32 + 32 + 1 + 8 + 8 + 8; // 89 bytes
-    
+    const LEN: usize = 1 + POOL_FIELDS_LEN + POOL_RESERVED_LEN; // 283 bytes
+
+    // Legacy pools can still be read, but are only written by MigratePool
+    fn unpack_unchecked(input: &[u8]) -> Result<Self, ProgramError> {
+        if !Pool::is_pool_len(input.len()) {
+            return Err(ProgramError::InvalidAccountData);
+        }
+        Self::unpack_from_slice(input)
+    }
+
+    fn pack(src: Self, dst: &mut [u8]) -> Result<(), ProgramError> {
+        if dst.len() != Self::LEN {
+            return Err(ProgramError::InvalidAccountData);
+        }
+        src.pack_into_slice(dst);
+        Ok(())
+    }
+
+    // The legacy layout is a prefix of the fields, and the fields it lacks
+    // (constant product curve, no admin, no stored bumps) are all zeros
     fn unpack_from_slice(src: &[u8]) -> Result<Self, ProgramError> {
-        let pool = Pool::try_from_slice(src)
-            .map_err(|_| ProgramError::InvalidAccountData)?;
-        Ok(pool)
+        Pool::try_from(&PoolState::from_bytes(src)?)
     }
-    
+
     fn pack_into_slice(&self, dst: &mut [u8]) {
-        let data = self.try_to_vec().unwrap();
-        dst[..data.len()].copy_from_slice(&data);
+        PoolState::from(self).write_fields(dst);
+    }
+}
+
+impl Pool {
+    pub fn is_pool_len(len: usize) -> bool {
+        len == Self::LEN || len == LEGACY_POOL_LEN
+    }
+
+    // Pricing curve of this pool; stable pools are priced at the current amplification
+    pub fn curve(&self) -> Result<Box<dyn SwapCurve>, ProgramError> {
+        self.curve_type.curve(&self.curve_params)
+    }
+
+    pub fn stable_params(&self) -> Result<StableParams, ProgramError> {
+        if self.curve_type != CurveType::Stable {
+            return Err(ProgramError::InvalidArgument);
+        }
+        curve::unpack_curve_params(&self.curve_params)
+    }
+
+    pub fn set_stable_params(&mut self, params: &StableParams) -> Result<(), ProgramError> {
+        self.curve_params = curve::pack_curve_params(params)?;
+        Ok(())
+    }
+
+    // Output for swapping `amount_in` through this pool (with 0.3% fee)
+    pub fn swap_output(&self, amount_in: u64, direction_a_to_b: bool) -> Result<u64, ProgramError> {
+        self.curve()?
+            .swap_exact_in(amount_in, self.reserve_a, self.reserve_b, direction_a_to_b)
+    }
+
+    // Input (with 0.3% fee) needed to receive exactly `amount_out` from this pool
+    pub fn swap_input(&self, amount_out: u64, direction_a_to_b: bool) -> Result<u64, ProgramError> {
+        self.curve()?
+            .swap_exact_out(amount_out, self.reserve_a, self.reserve_b, direction_a_to_b)
+    }
+
+    // LP tokens for a ratio-matched deposit
+    pub fn lp_tokens_to_mint(&self, amount_a: u64, amount_b: u64) -> Result<u64, ProgramError> {
+        self.curve()?.deposit_lp_tokens(
+            amount_a,
+            amount_b,
+            self.reserve_a,
+            self.reserve_b,
+            self.total_lp_supply,
+        )
+    }
+
+    // Tokens returned for burning `lp_amount`
+    pub fn withdraw_amounts(&self, lp_amount: u64) -> Result<(u64, u64), ProgramError> {
+        self.curve()?
+            .withdraw_amounts(lp_amount, self.reserve_a, self.reserve_b, self.total_lp_supply)
+    }
+
+    // Price of token A in token B, scaled by curve::PRICE_SCALE
+    pub fn spot_price(&self) -> Result<u128, ProgramError> {
+        self.curve()?.spot_price(self.reserve_a, self.reserve_b)
+    }
+
+    // Accumulated prices extended to `now`
+    pub fn observe(&self, now: i64) -> Result<PriceObservation, ProgramError> {
+        PoolState::from(self).observe(now)
+    }
+
+    // Accumulates prices up to `now`; called before every reserve change
+    pub fn update_prices(&mut self, now: i64) -> ProgramResult {
+        let observation = self.observe(now)?;
+        self.price_a_cumulative = observation.price_a_cumulative;
+        self.price_b_cumulative = observation.price_b_cumulative;
+        self.last_update_timestamp = observation.timestamp;
+        Ok(())
     }
 }
 
@@ -239,10 +847,17 @@ pub fn process_instruction(
 ) -> ProgramResult {
     let instruction = TestProjectInstruction::try_from_slice(instruction_data)
         .map_err(|_| ProgramError::InvalidInstructionData)?;
-        
+    process(program_id, accounts, instruction)
+}
+
+fn process(
+    program_id: &Pubkey,
+    accounts: &[AccountInfo],
+    instruction: TestProjectInstruction,
+) -> ProgramResult {
     match instruction {
         TestProjectInstruction::InitPool { amount_a, amount_b } => {
-            process_init_pool(program_id, accounts, amount_a, amount_b)
+            process_init_pool(program_id, accounts, amount_a, amount_b, CurveParams::ConstantProduct)
         }
         TestProjectInstruction::AddLiquidity { amount_a, amount_b } => {
             process_add_liquidity(program_id, accounts, amount_a, amount_b)
@@ -254,10 +869,10 @@ pub fn process_instruction(
             process_swap(program_id, accounts, amount_in, direction_a_to_b)
         }
         TestProjectInstruction::MultihopSwap { amount_in, minimum_amount_out } => {
-            process_multihop_swap(program_id, accounts, amount_in, minimum_amount_out)
+            process_multihop_swap(program_id, accounts, amount_in, minimum_amount_out, &[])
         }
         TestProjectInstruction::MultihopSwapWithPath { amount_in, minimum_amount_out, token_path } => {
-            process_multihop_swap_with_path(program_id, accounts, amount_in, minimum_amount_out, token_path)
+            process_multihop_swap_with_path(program_id, accounts, amount_in, minimum_amount_out, token_path, &[])
         }
         TestProjectInstruction::GetPoolInfo => {
             process_get_pool_info(program_id, accounts)
@@ -274,14 +889,116 @@ pub fn process_instruction(
         TestProjectInstruction::GetMultihopQuote { amount_in, token_path } => {
             process_get_multihop_quote(program_id, accounts, amount_in, token_path)
         }
-    }
-}
-
+        TestProjectInstruction::InitPoolWithCurve { amount_a, amount_b, curve } => {
+            process_init_pool(program_id, accounts, amount_a, amount_b, curve)
+        }
+        TestProjectInstruction::RampAmp { target_amp, ramp_stop_ts } => {
+            process_ramp_amp(program_id, accounts, target_amp, ramp_stop_ts)
+        }
+        TestProjectInstruction::StopRampAmp => {
+            process_stop_ramp_amp(program_id, accounts)
+        }
+        TestProjectInstruction::InitMultiPool { amounts } => {
+            process_init_multi_pool(program_id, accounts, amounts)
+        }
+        TestProjectInstruction::MultiPoolSwap { amount_in, minimum_amount_out } => {
+            process_multi_pool_swap(program_id, accounts, amount_in, minimum_amount_out)
+        }
+        TestProjectInstruction::AddMultiLiquidity { amounts } => {
+            process_add_multi_liquidity(program_id, accounts, amounts)
+        }
+        TestProjectInstruction::RemoveMultiLiquidity { lp_amount } => {
+            process_remove_multi_liquidity(program_id, accounts, lp_amount)
+        }
+        TestProjectInstruction::AddMultiLiquiditySingle { amount_in, minimum_lp_out } => {
+            process_add_multi_liquidity_single(program_id, accounts, amount_in, minimum_lp_out)
+        }
+        TestProjectInstruction::RemoveMultiLiquiditySingle { lp_amount, minimum_amount_out } => {
+            process_remove_multi_liquidity_single(program_id, accounts, lp_amount, minimum_amount_out)
+        }
+        TestProjectInstruction::InitConcentratedPool { tick_spacing, initial_sqrt_price } => {
+            process_init_concentrated_pool(program_id, accounts, tick_spacing, initial_sqrt_price)
+        }
+        TestProjectInstruction::OpenPosition { tick_lower, tick_upper } => {
+            process_open_position(program_id, accounts, tick_lower, tick_upper)
+        }
+        TestProjectInstruction::IncreaseLiquidity { liquidity, maximum_amount_a, maximum_amount_b } => {
+            process_increase_liquidity(program_id, accounts, liquidity, maximum_amount_a, maximum_amount_b)
+        }
+        TestProjectInstruction::DecreaseLiquidity { liquidity, minimum_amount_a, minimum_amount_b } => {
+            process_decrease_liquidity(program_id, accounts, liquidity, minimum_amount_a, minimum_amount_b)
+        }
+        TestProjectInstruction::CollectFees => {
+            process_collect_fees(program_id, accounts)
+        }
+        TestProjectInstruction::ConcentratedSwap { amount_in, minimum_amount_out, direction_a_to_b } => {
+            process_concentrated_swap(program_id, accounts, amount_in, minimum_amount_out, direction_a_to_b)
+        }
+        TestProjectInstruction::FlashLoan { amount } => {
+            process_flash_loan(program_id, accounts, amount)
+        }
+        TestProjectInstruction::FlashSwap { amount_a_out, amount_b_out } => {
+            process_flash_swap(program_id, accounts, amount_a_out, amount_b_out)
+        }
+        TestProjectInstruction::WithNative { wrap_amount, instruction_data } => {
+            process_with_native(program_id, accounts, wrap_amount, &instruction_data)
+        }
+        TestProjectInstruction::MigratePool => {
+            process_migrate_pool(program_id, accounts)
+        }
+        TestProjectInstruction::CompactMultihopSwap { amount_in, minimum_amount_out, minimum_hop_amounts_out } => {
+            process_compact_multihop_swap(
+                program_id,
+                accounts,
+                amount_in,
+                minimum_amount_out,
+                &minimum_hop_amounts_out,
+            )
+        }
+        TestProjectInstruction::SplitRouteSwap { routes, minimum_amount_out } => {
+            process_split_route_swap(program_id, accounts, &routes, minimum_amount_out)
+        }
+        TestProjectInstruction::GetTwap { since } => {
+            process_get_twap(program_id, accounts, since)
+        }
+        TestProjectInstruction::InitOracle { capacity } => {
+            process_init_oracle(program_id, accounts, capacity)
+        }
+        TestProjectInstruction::GrowOracle { capacity } => {
+            process_grow_oracle(program_id, accounts, capacity)
+        }
+        TestProjectInstruction::Observe { seconds_agos } => {
+            process_observe(program_id, accounts, &seconds_agos)
+        }
+        TestProjectInstruction::MultihopSwapWithHopMinimums {
+            amount_in,
+            minimum_amount_out,
+            minimum_hop_amounts_out,
+        } => {
+            process_multihop_swap(program_id, accounts, amount_in, minimum_amount_out, &minimum_hop_amounts_out)
+        }
+        TestProjectInstruction::MultihopSwapWithPathAndHopMinimums {
+            amount_in,
+            minimum_amount_out,
+            token_path,
+            minimum_hop_amounts_out,
+        } => process_multihop_swap_with_path(
+            program_id,
+            accounts,
+            amount_in,
+            minimum_amount_out,
+            token_path,
+            &minimum_hop_amounts_out,
+        ),
+    }
+}
+
 fn process_init_pool(
     program_id: &Pubkey,
     accounts: &[AccountInfo],
     amount_a: u64,
     amount_b: u64,
+    curve: CurveParams,
 ) -> ProgramResult {
     let account_info_iter = &mut accounts.iter();
     let pool_info = next_account_info(account_info_iter)?;
@@ -318,11 +1035,78 @@ fn process_init_pool(
         return Err(ProgramError::InvalidSeeds);
     }
 
+    let (lp_mint_pubkey, lp_mint_bump) = Pubkey::find_program_address(
+        &[b"mint", pool_info.key.as_ref()],
+        program_id,
+    );
+    if lp_mint_pubkey != *lp_mint_info.key {
+        return Err(ProgramError::InvalidSeeds);
+    }
+
+    let (curve_type, curve_params) = match curve {
+        CurveParams::ConstantProduct => (CurveType::ConstantProduct, [0; CURVE_PARAMS_LEN]),
+        // Stable pools start with a fixed amplification and no ramp in progress
+        CurveParams::Stable { amp } => {
+            if !(stable::MIN_AMP..=stable::MAX_AMP).contains(&amp) {
+                return Err(ProgramError::InvalidArgument);
+            }
+            let now = Clock::get()?.unix_timestamp;
+            let params = StableParams {
+                initial_amp: amp,
+                target_amp: amp,
+                ramp_start_ts: now,
+                ramp_stop_ts: now,
+            };
+            (CurveType::Stable, curve::pack_curve_params(&params)?)
+        }
+        CurveParams::ConstantPrice { token_b_price } => {
+            if token_b_price == 0 {
+                return Err(ProgramError::InvalidArgument);
+            }
+            let params = ConstantPriceCurve { token_b_price };
+            (CurveType::ConstantPrice, curve::pack_curve_params(&params)?)
+        }
+        // Each side needs real or virtual liquidity to be priced
+        CurveParams::Offset { token_a_offset, token_b_offset } => {
+            if amount_a.saturating_add(token_a_offset) == 0
+                || amount_b.saturating_add(token_b_offset) == 0
+            {
+                return Err(ProgramError::InvalidArgument);
+            }
+            let params = OffsetCurve { token_a_offset, token_b_offset };
+            (CurveType::Offset, curve::pack_curve_params(&params)?)
+        }
+        CurveParams::Weighted { weight_a, weight_b } => {
+            let params = WeightedCurve { weight_a, weight_b };
+            params.validate()?;
+            (CurveType::Weighted, curve::pack_curve_params(&params)?)
+        }
+    };
+    let mut pool = Pool {
+        token_a: *token_a_info.key,
+        token_b: *token_b_info.key,
+        bump: pool_bump,
+        reserve_a: 0,
+        reserve_b: 0,
+        total_lp_supply: 0,
+        curve_type,
+        admin: *user_info.key,
+        curve_params,
+        vault_a_bump,
+        vault_b_bump,
+        lp_mint_bump,
+        price_a_cumulative: 0,
+        price_b_cumulative: 0,
+        last_update_timestamp: Clock::get()?.unix_timestamp,
+        has_oracle: false,
+    };
+
+    let token_a_program_info = mint_token_program(token_a_info, accounts)?;
+    let token_b_program_info = mint_token_program(token_b_info, accounts)?;
+
     let rent = Rent::from_account_info(rent_info)?;
     let pool_space = Pool::LEN;
-    let vault_space = 165; // Token account size
     let pool_lamports = rent.minimum_balance(pool_space);
-    let vault_lamports = rent.minimum_balance(vault_space);
 
     // Create pool account
     let pool_signer_seeds: &[&[_]] = &[
@@ -348,96 +1132,42 @@ fn process_init_pool(
         &[pool_signer_seeds],
     )?;
 
-    // Create vault A as PDA
+    // Create vaults A and B as PDAs under their mints' token programs
     let vault_a_signer_seeds: &[&[_]] = &[
         b"vault",
         pool_info.key.as_ref(),
         token_a_info.key.as_ref(),
         &[vault_a_bump],
     ];
-
-    invoke_signed(
-        &system_instruction::create_account(
-            user_info.key,
-            vault_a_info.key,
-            vault_lamports,
-            vault_space as u64,
-            &Pubkey::from_str(GORBCHAIN_SPL_TOKEN_PROGRAM).unwrap(),
-        ),
-        &[
-            user_info.clone(),
-            vault_a_info.clone(),
-            system_program_info.clone(),
-        ],
-        &[vault_a_signer_seeds],
+    create_vault(
+        user_info,
+        vault_a_info,
+        token_a_info,
+        token_a_program_info,
+        system_program_info,
+        rent_info,
+        vault_a_signer_seeds,
     )?;
 
-    // Create vault B as PDA
     let vault_b_signer_seeds: &[&[_]] = &[
         b"vault",
         pool_info.key.as_ref(),
         token_b_info.key.as_ref(),
         &[vault_b_bump],
     ];
-
-    invoke_signed(
-        &system_instruction::create_account(
-            user_info.key,
-            vault_b_info.key,
-            vault_lamports,
-            vault_space as u64,
-            &Pubkey::from_str(GORBCHAIN_SPL_TOKEN_PROGRAM).unwrap(),
-        ),
-        &[
-            user_info.clone(),
-            vault_b_info.clone(),
-            system_program_info.clone(),
-        ],
-        &[vault_b_signer_seeds],
-    )?;
-
-    // Initialize vault A as token account
-    invoke_signed(
-        &create_initialize_account_instruction(
-            vault_a_info.key,
-            token_a_info.key,
-            vault_a_info.key, // Vault is its own authority
-        ),
-        &[
-            vault_a_info.clone(),
-            token_a_info.clone(),
-            vault_a_info.clone(),
-            rent_info.clone(),
-        ],
-        &[vault_a_signer_seeds],
-    )?;
-
-    // Initialize vault B as token account
-    invoke_signed(
-        &create_initialize_account_instruction(
-            vault_b_info.key,
-            token_b_info.key,
-            vault_b_info.key, // Vault is its own authority
-        ),
-        &[
-            vault_b_info.clone(),
-            token_b_info.clone(),
-            vault_b_info.clone(),
-            rent_info.clone(),
-        ],
-        &[vault_b_signer_seeds],
+    create_vault(
+        user_info,
+        vault_b_info,
+        token_b_info,
+        token_b_program_info,
+        system_program_info,
+        rent_info,
+        vault_b_signer_seeds,
     )?;
 
     // Create LP mint account
     let mint_space = 82; // Mint account size
     let mint_lamports = rent.minimum_balance(mint_space);
-    let (lp_mint_pubkey, lp_mint_bump) = Pubkey::find_program_address(
-        &[b"mint", pool_info.key.as_ref()],
-        program_id,
-    );
-    if lp_mint_pubkey != *lp_mint_info.key {
-        return Err(ProgramError::InvalidSeeds);
-    }
     let lp_mint_signer_seeds: &[&[_]] = &[
         b"mint",
         pool_info.key.as_ref(),
@@ -450,7 +1180,7 @@ fn process_init_pool(
             lp_mint_info.key,
             mint_lamports,
             mint_space as u64,
-            &Pubkey::from_str(GORBCHAIN_SPL_TOKEN_PROGRAM).unwrap(),
+            &GORBCHAIN_SPL_TOKEN_PROGRAM,
         ),
         &[
             user_info.clone(),
@@ -475,35 +1205,24 @@ fn process_init_pool(
         &[lp_mint_signer_seeds],
     )?;
 
-    // Transfer tokens to vaults
-    invoke(
-        &create_transfer_instruction(
-            user_token_a_info.key,
-            vault_a_info.key,
-            user_info.key,
-            amount_a,
-        ),
-        &[
-            user_token_a_info.clone(),
-            vault_a_info.clone(),
-            user_info.clone(),
-            token_program_info.clone(),
-        ],
+    // Transfer tokens to vaults; reserves start at what the vaults received
+    let received_a = transfer_checked(
+        token_a_program_info,
+        user_token_a_info,
+        token_a_info,
+        vault_a_info,
+        user_info,
+        amount_a,
+        &[],
     )?;
-
-    invoke(
-        &create_transfer_instruction(
-            user_token_b_info.key,
-            vault_b_info.key,
-            user_info.key,
-            amount_b,
-        ),
-        &[
-            user_token_b_info.clone(),
-            vault_b_info.clone(),
-            user_info.clone(),
-            token_program_info.clone(),
-        ],
+    let received_b = transfer_checked(
+        token_b_program_info,
+        user_token_b_info,
+        token_b_info,
+        vault_b_info,
+        user_info,
+        amount_b,
+        &[],
     )?;
 
     // Create user LP ATA if it doesn't exist
@@ -526,10 +1245,7 @@ fn process_init_pool(
     )?;
 
     // Calculate liquidity
-    let liquidity: u64 = (amount_a as u128)
-        .checked_mul(amount_b as u128)
-        .unwrap()
-        .integer_sqrt() as u64;
+    let liquidity = pool.lp_tokens_to_mint(received_a, received_b)?;
 
     // Mint LP tokens
     invoke_signed(
@@ -549,14 +1265,9 @@ fn process_init_pool(
     )?;
 
     // Initialize pool state
-    let pool = Pool {
-        token_a: *token_a_info.key,
-        token_b: *token_b_info.key,
-        bump: pool_bump,
-        reserve_a: amount_a,
-        reserve_b: amount_b,
-        total_lp_supply: liquidity,
-    };
+    pool.reserve_a = received_a;
+    pool.reserve_b = received_b;
+    pool.total_lp_supply = liquidity;
     Pool::pack(pool, &mut pool_info.data.borrow_mut())?;
 
     Ok(())
@@ -581,98 +1292,77 @@ fn process_add_liquidity(
     let user_info = next_account_info(account_info_iter)?;
     let token_program_info = next_account_info(account_info_iter)?;
 
-    let mut pool = Pool::unpack(&pool_info.data.borrow())?;
+    let mut pool = PoolState::load_mut(program_id, pool_info)?;
+    let oracle_info = next_oracle_info(pool.has_oracle != 0, account_info_iter)?;
     
-    // Store token addresses before pool is moved
+    // Store token addresses for the pool signer seeds
     let token_a = pool.token_a;
     let token_b = pool.token_b;
+    let bump = pool.bump;
     
     // Verify pool seeds
-    let (pool_pubkey, _) = Pubkey::find_program_address(
-        &[b"pool", token_a_info.key.as_ref(), token_b_info.key.as_ref()],
+    let pool_pubkey = Pubkey::create_program_address(
+        &[b"pool", token_a_info.key.as_ref(), token_b_info.key.as_ref(), &[pool.bump]],
         program_id,
-    );
+    )?;
     if pool_pubkey != *pool_info.key {
         return Err(ProgramError::InvalidSeeds);
     }
 
-    // Verify vault addresses and get bumps
-    let (vault_a_pubkey, vault_a_bump) = get_vault_address(pool_info.key, &token_a, program_id);
-    let (vault_b_pubkey, vault_b_bump) = get_vault_address(pool_info.key, &token_b, program_id);
-    
-    if vault_a_pubkey != *vault_a_info.key || vault_b_pubkey != *vault_b_info.key {
-        return Err(ProgramError::InvalidSeeds);
+    // Verify vault and LP mint addresses against the bumps stored at init
+    verify_vaults(program_id, &pool, pool_info.key, vault_a_info, vault_b_info)?;
+    verify_lp_mint(program_id, pool_info.key, pool.lp_mint_bump, lp_mint_info)?;
+
+    if !pool.curve()?.allows_deposits() {
+        return Err(ProgramError::InvalidArgument);
     }
 
     let reserve_a = pool.reserve_a;
     let reserve_b = pool.reserve_b;
-    let supply = pool.total_lp_supply;
 
     // Calculate final amounts maintaining ratio
-    let (final_amount_a, final_amount_b) = if reserve_a > 0 && reserve_b > 0 {
-        let required_b = (amount_a as u128)
-            .checked_mul(reserve_b as u128).unwrap()
-            .checked_div(reserve_a as u128).unwrap() as u64;
-        if required_b <= amount_b {
-            (amount_a, required_b)
-        } else {
-            let required_a = (amount_b as u128)
-                .checked_mul(reserve_a as u128).unwrap()
-                .checked_div(reserve_b as u128).unwrap() as u64;
-            (required_a, amount_b)
-        }
-    } else {
-        (amount_a, amount_b)
-    };
+    let (final_amount_a, final_amount_b) =
+        calculate_deposit_amounts(amount_a, amount_b, reserve_a, reserve_b)?;
 
-    // Transfer tokens to vaults
-    invoke(
-        &create_transfer_instruction(
-            user_token_a_info.key,
-            vault_a_info.key,
-            user_info.key,
-            final_amount_a,
-        ),
-        &[
-            user_token_a_info.clone(),
-            vault_a_info.clone(),
-            user_info.clone(),
-            token_program_info.clone(),
-        ],
+    // Transfer only the ratio-matched amounts; any excess the user offered
+    // simply stays in their accounts (Uniswap pattern)
+    let received_a = transfer_checked(
+        mint_token_program(token_a_info, accounts)?,
+        user_token_a_info,
+        token_a_info,
+        vault_a_info,
+        user_info,
+        final_amount_a,
+        &[],
     )?;
-
-    invoke(
-        &create_transfer_instruction(
-            user_token_b_info.key,
-            vault_b_info.key,
-            user_info.key,
-            final_amount_b,
-        ),
-        &[
-            user_token_b_info.clone(),
-            vault_b_info.clone(),
-            user_info.clone(),
-            token_program_info.clone(),
-        ],
+    let received_b = transfer_checked(
+        mint_token_program(token_b_info, accounts)?,
+        user_token_b_info,
+        token_b_info,
+        vault_b_info,
+        user_info,
+        final_amount_b,
+        &[],
     )?;
 
-    // Calculate liquidity to mint
-    let liquidity = if supply == 0 {
-        (final_amount_a as u128)
-            .checked_mul(final_amount_b as u128).unwrap()
-            .integer_sqrt() as u64
-    } else {
-        (final_amount_a as u128)
-            .checked_mul(supply as u128).unwrap()
-            .checked_div(reserve_a as u128).unwrap() as u64
-    };
+    // Calculate liquidity to mint from what the vaults received
+    let liquidity = pool.lp_tokens_to_mint(received_a, received_b)?;
+
+    // Update pool state, releasing the account before the pool signs the mint
+    let now = Clock::get()?.unix_timestamp;
+    pool.update_prices(now)?;
+    record_observation(program_id, pool_info.key, &pool, oracle_info, now)?;
+    pool.reserve_a = pool.reserve_a.checked_add(received_a).unwrap();
+    pool.reserve_b = pool.reserve_b.checked_add(received_b).unwrap();
+    pool.total_lp_supply = pool.total_lp_supply.checked_add(liquidity).unwrap();
+    drop(pool);
 
     // Mint LP tokens
     let pool_signer_seeds: &[&[_]] = &[
         b"pool",
-        pool.token_a.as_ref(),
-        pool.token_b.as_ref(),
-        &[pool.bump],
+        token_a.as_ref(),
+        token_b.as_ref(),
+        &[bump],
     ];
     
     invoke_signed(
@@ -691,67 +1381,6 @@ fn process_add_liquidity(
         &[pool_signer_seeds],
     )?;
 
-    // Update pool state
-    pool.reserve_a = pool.reserve_a.checked_add(final_amount_a).unwrap();
-    pool.reserve_b = pool.reserve_b.checked_add(final_amount_b).unwrap();
-    pool.total_lp_supply = pool.total_lp_supply.checked_add(liquidity).unwrap();
-    
-    Pool::pack(pool, &mut pool_info.data.borrow_mut())?;
-
-    // Refund unused tokens back to user (Uniswap pattern)
-    // If user provided more than the calculated final amount, return the excess
-    if amount_a > final_amount_a {
-        let excess_a = amount_a - final_amount_a;
-        let vault_a_signer_seeds: &[&[_]] = &[
-            b"vault",
-            pool_info.key.as_ref(),
-            &token_a.as_ref(),
-            &[vault_a_bump],
-        ];
-        
-        invoke_signed(
-            &create_transfer_instruction(
-                vault_a_info.key,
-                user_token_a_info.key,
-                vault_a_info.key,
-                excess_a,
-            ),
-            &[
-                vault_a_info.clone(),
-                user_token_a_info.clone(),
-                vault_a_info.clone(),
-                token_program_info.clone(),
-            ],
-            &[vault_a_signer_seeds],
-        )?;
-    }
-
-    if amount_b > final_amount_b {
-        let excess_b = amount_b - final_amount_b;
-        let vault_b_signer_seeds: &[&[_]] = &[
-            b"vault",
-            pool_info.key.as_ref(),
-            &token_b.as_ref(),
-            &[vault_b_bump],
-        ];
-        
-        invoke_signed(
-            &create_transfer_instruction(
-                vault_b_info.key,
-                user_token_b_info.key,
-                vault_b_info.key,
-                excess_b,
-            ),
-            &[
-                vault_b_info.clone(),
-                user_token_b_info.clone(),
-                vault_b_info.clone(),
-                token_program_info.clone(),
-            ],
-            &[vault_b_signer_seeds],
-        )?;
-    }
-
     Ok(())
 }
 
@@ -773,36 +1402,25 @@ fn process_remove_liquidity(
     let user_info = next_account_info(account_info_iter)?;
     let token_program_info = next_account_info(account_info_iter)?;
 
-    let mut pool = Pool::unpack(&pool_info.data.borrow())?;
+    let mut pool = PoolState::load_mut(program_id, pool_info)?;
+    let oracle_info = next_oracle_info(pool.has_oracle != 0, account_info_iter)?;
     
     // Verify pool seeds
-    let (pool_pubkey, _) = Pubkey::find_program_address(
-        &[b"pool", token_a_info.key.as_ref(), token_b_info.key.as_ref()],
+    let pool_pubkey = Pubkey::create_program_address(
+        &[b"pool", token_a_info.key.as_ref(), token_b_info.key.as_ref(), &[pool.bump]],
         program_id,
-    );
+    )?;
     if pool_pubkey != *pool_info.key {
         return Err(ProgramError::InvalidSeeds);
     }
 
-    // Verify vault addresses and get bumps
-    let (vault_a_pubkey, vault_a_bump) = get_vault_address(pool_info.key, &pool.token_a, program_id);
-    let (vault_b_pubkey, vault_b_bump) = get_vault_address(pool_info.key, &pool.token_b, program_id);
-    
-    if vault_a_pubkey != *vault_a_info.key || vault_b_pubkey != *vault_b_info.key {
-        return Err(ProgramError::InvalidSeeds);
-    }
-
-    let reserve_a = pool.reserve_a;
-    let reserve_b = pool.reserve_b;
-    let supply = pool.total_lp_supply;
+    // Verify vault and LP mint addresses against the bumps stored at init
+    verify_vaults(program_id, &pool, pool_info.key, vault_a_info, vault_b_info)?;
+    verify_lp_mint(program_id, pool_info.key, pool.lp_mint_bump, lp_mint_info)?;
+    let (vault_a_bump, vault_b_bump) = (pool.vault_a_bump, pool.vault_b_bump);
 
     // Calculate amounts to withdraw
-    let amount_a = (lp_amount as u128)
-        .checked_mul(reserve_a as u128).unwrap()
-        .checked_div(supply as u128).unwrap() as u64;
-    let amount_b = (lp_amount as u128)
-        .checked_mul(reserve_b as u128).unwrap()
-        .checked_div(supply as u128).unwrap() as u64;
+    let (amount_a, amount_b) = pool.withdraw_amounts(lp_amount)?;
 
     // Burn LP tokens
     invoke(
@@ -835,44 +1453,32 @@ fn process_remove_liquidity(
         &[vault_b_bump],
     ];
 
-    invoke_signed(
-        &create_transfer_instruction(
-            vault_a_info.key,
-            user_token_a_info.key,
-            vault_a_info.key,
-            amount_a,
-        ),
-        &[
-            vault_a_info.clone(),
-            user_token_a_info.clone(),
-            vault_a_info.clone(),
-            token_program_info.clone(),
-        ],
+    transfer_checked(
+        mint_token_program(token_a_info, accounts)?,
+        vault_a_info,
+        token_a_info,
+        user_token_a_info,
+        vault_a_info,
+        amount_a,
         &[vault_a_signer_seeds],
     )?;
-
-    invoke_signed(
-        &create_transfer_instruction(
-            vault_b_info.key,
-            user_token_b_info.key,
-            vault_b_info.key,
-            amount_b,
-        ),
-        &[
-            vault_b_info.clone(),
-            user_token_b_info.clone(),
-            vault_b_info.clone(),
-            token_program_info.clone(),
-        ],
+    transfer_checked(
+        mint_token_program(token_b_info, accounts)?,
+        vault_b_info,
+        token_b_info,
+        user_token_b_info,
+        vault_b_info,
+        amount_b,
         &[vault_b_signer_seeds],
     )?;
 
     // Update pool state
+    let now = Clock::get()?.unix_timestamp;
+    pool.update_prices(now)?;
+    record_observation(program_id, pool_info.key, &pool, oracle_info, now)?;
     pool.reserve_a = pool.reserve_a.checked_sub(amount_a).unwrap();
     pool.reserve_b = pool.reserve_b.checked_sub(amount_b).unwrap();
     pool.total_lp_supply = pool.total_lp_supply.checked_sub(lp_amount).unwrap();
-    
-    Pool::pack(pool, &mut pool_info.data.borrow_mut())?;
 
     Ok(())
 }
@@ -892,140 +1498,92 @@ fn process_swap(
     let user_in_info = next_account_info(account_info_iter)?;
     let user_out_info = next_account_info(account_info_iter)?;
     let user_info = next_account_info(account_info_iter)?;
-    let token_program_info = next_account_info(account_info_iter)?;
+    let _token_program_info = next_account_info(account_info_iter)?;
 
-    let mut pool = Pool::unpack(&pool_info.data.borrow())?;
+    let mut pool = PoolState::load_mut(program_id, pool_info)?;
+    let oracle_info = next_oracle_info(pool.has_oracle != 0, account_info_iter)?;
     
     // Verify pool seeds
-    let (pool_pubkey, _) = Pubkey::find_program_address(
-        &[b"pool", token_a_info.key.as_ref(), token_b_info.key.as_ref()],
+    let pool_pubkey = Pubkey::create_program_address(
+        &[b"pool", token_a_info.key.as_ref(), token_b_info.key.as_ref(), &[pool.bump]],
         program_id,
-    );
+    )?;
     if pool_pubkey != *pool_info.key {
         return Err(ProgramError::InvalidSeeds);
     }
 
-    // Verify vault addresses and get bumps
-    let (vault_a_pubkey, vault_a_bump) = get_vault_address(pool_info.key, &pool.token_a, program_id);
-    let (vault_b_pubkey, vault_b_bump) = get_vault_address(pool_info.key, &pool.token_b, program_id);
-    
-    if vault_a_pubkey != *vault_a_info.key || vault_b_pubkey != *vault_b_info.key {
-        return Err(ProgramError::InvalidSeeds);
-    }
-
-    // Transfer input tokens to vault
-    if direction_a_to_b {
-        invoke(
-            &create_transfer_instruction(
-                user_in_info.key,
-                vault_a_info.key,
-                user_info.key,
-                amount_in,
-            ),
-            &[
-                user_in_info.clone(),
-                vault_a_info.clone(),
-                user_info.clone(),
-                token_program_info.clone(),
-            ],
-        )?;
-    } else {
-        invoke(
-            &create_transfer_instruction(
-                user_in_info.key,
-                vault_b_info.key,
-                user_info.key,
-                amount_in,
-            ),
-            &[
-                user_in_info.clone(),
-                vault_b_info.clone(),
-                user_info.clone(),
-                token_program_info.clone(),
-            ],
-        )?;
-    }
+    // Verify vault addresses against the bumps stored at init
+    verify_vaults(program_id, &pool, pool_info.key, vault_a_info, vault_b_info)?;
+    let (vault_a_bump, vault_b_bump) = (pool.vault_a_bump, pool.vault_b_bump);
 
-    // Calculate output amount (with 0.3% fee)
-    let (reserve_in, reserve_out) = if direction_a_to_b {
-        (pool.reserve_a, pool.reserve_b)
+    let (mint_in_info, vault_in_info, mint_out_info, vault_out_info) = if direction_a_to_b {
+        (token_a_info, vault_a_info, token_b_info, vault_b_info)
     } else {
-        (pool.reserve_b, pool.reserve_a)
+        (token_b_info, vault_b_info, token_a_info, vault_a_info)
     };
-    let amount_out = calculate_swap_output(amount_in, reserve_in, reserve_out)?;
+
+    // Transfer input tokens to vault
+    let amount_received = transfer_checked(
+        mint_token_program(mint_in_info, accounts)?,
+        user_in_info,
+        mint_in_info,
+        vault_in_info,
+        user_info,
+        amount_in,
+        &[],
+    )?;
+
+    // Calculate output amount (with 0.3% fee) on the pool's curve, from what
+    // the vault received after any transfer fee
+    let amount_out = pool.swap_output(amount_received, direction_a_to_b)?;
 
     // Transfer output tokens from vault to user using vault PDA as authority
+    let vault_out_bump = if direction_a_to_b { vault_b_bump } else { vault_a_bump };
+    let vault_out_signer_seeds: &[&[_]] = &[
+        b"vault",
+        pool_info.key.as_ref(),
+        mint_out_info.key.as_ref(),
+        &[vault_out_bump],
+    ];
+    let output_received = transfer_checked(
+        mint_token_program(mint_out_info, accounts)?,
+        vault_out_info,
+        mint_out_info,
+        user_out_info,
+        vault_out_info,
+        amount_out,
+        &[vault_out_signer_seeds],
+    )?;
+
+    // Update pool reserves
+    let now = Clock::get()?.unix_timestamp;
+    pool.update_prices(now)?;
+    record_observation(program_id, pool_info.key, &pool, oracle_info, now)?;
     if direction_a_to_b {
-        let vault_b_signer_seeds: &[&[_]] = &[
-            b"vault",
-            pool_info.key.as_ref(),
-            pool.token_b.as_ref(),
-            &[vault_b_bump],
-        ];
-        
-        invoke_signed(
-            &create_transfer_instruction(
-                vault_b_info.key,
-                user_out_info.key,
-                vault_b_info.key,
-                amount_out,
-            ),
-            &[
-                vault_b_info.clone(),
-                user_out_info.clone(),
-                vault_b_info.clone(),
-                token_program_info.clone(),
-            ],
-            &[vault_b_signer_seeds],
-        )?;
+        pool.reserve_a = pool.reserve_a.checked_add(amount_received).unwrap();
+        pool.reserve_b = pool.reserve_b.checked_sub(amount_out).unwrap();
     } else {
-        let vault_a_signer_seeds: &[&[_]] = &[
-            b"vault",
-            pool_info.key.as_ref(),
-            pool.token_a.as_ref(),
-            &[vault_a_bump],
-        ];
-        
-        invoke_signed(
-            &create_transfer_instruction(
-                vault_a_info.key,
-                user_out_info.key,
-                vault_a_info.key,
-                amount_out,
-            ),
-            &[
-                vault_a_info.clone(),
-                user_out_info.clone(),
-                vault_a_info.clone(),
-                token_program_info.clone(),
-            ],
-            &[vault_a_signer_seeds],
-        )?;
-    }
-
-    // Update pool reserves
-    if direction_a_to_b {
-        pool.reserve_a = pool.reserve_a.checked_add(amount_in).unwrap();
-        pool.reserve_b = pool.reserve_b.checked_sub(amount_out).unwrap();
-    } else {
-        pool.reserve_b = pool.reserve_b.checked_add(amount_in).unwrap();
+        pool.reserve_b = pool.reserve_b.checked_add(amount_received).unwrap();
         pool.reserve_a = pool.reserve_a.checked_sub(amount_out).unwrap();
     }
 
-    Pool::pack(pool, &mut pool_info.data.borrow_mut())?;
+    let result = SwapResult::from_pool(&pool, amount_received, output_received, direction_a_to_b)?;
+    set_return_data(&result.try_to_vec().unwrap());
 
     Ok(())
 }
 
+// `minimum_hop_amounts_out` is empty, or bounds what each hop delivers
 fn process_multihop_swap(
     program_id: &Pubkey,
     accounts: &[AccountInfo],
     amount_in: u64,
     minimum_amount_out: u64,
+    minimum_hop_amounts_out: &[u64],
 ) -> ProgramResult {
     let account_info_iter = &mut accounts.iter();
     let user_info = next_account_info(account_info_iter)?;
-    let token_program_info = next_account_info(account_info_iter)?;
+    let _token_program_info = next_account_info(account_info_iter)?;
     
     // First account is user's input token account
     let user_input_account = next_account_info(account_info_iter)?;
@@ -1037,20 +1595,24 @@ fn process_multihop_swap(
         remaining_accounts.push(account);
     }
     
-    if remaining_accounts.len() < 7 {
+    // The hops' oracles follow the last hop, then the Token-2022 program
+    // when a pool on the route uses a Token-2022 mint
+    let num_hops = count_route_hops(program_id, &accounts[3..], 7, 7, 0);
+    if num_hops == 0 {
         return Err(ProgramError::NotEnoughAccountKeys);
     }
-    
-    let num_hops = remaining_accounts.len() / 7;
-    if remaining_accounts.len() % 7 != 0 {
-        return Err(ProgramError::InvalidAccountData);
-    }
+    let pool_infos: Vec<&AccountInfo> = (0..num_hops).map(|hop| remaining_accounts[hop * 7]).collect();
+    let oracles = route_oracles(program_id, &pool_infos, &accounts[3 + num_hops * 7..])?;
+    check_hop_minimums(minimum_hop_amounts_out, num_hops)?;
+    let pools: Vec<&Pubkey> = pool_infos.iter().map(|pool_info| pool_info.key).collect();
+    check_route(&pools)?;
     
     let mut current_amount = amount_in;
+    let mut hops = Vec::with_capacity(num_hops);
     let mut current_input_account = user_input_account;
     
     // Process each hop
-    for hop in 0..num_hops {
+    for (hop, &oracle_info) in oracles.iter().enumerate() {
         let base_idx = hop * 7;
         let pool_info = remaining_accounts[base_idx];
         let token_a_info = remaining_accounts[base_idx + 1];
@@ -1060,37 +1622,54 @@ fn process_multihop_swap(
         let intermediate_account = remaining_accounts[base_idx + 5];
         let output_account = remaining_accounts[base_idx + 6];
         
-        let mut pool = Pool::unpack(&pool_info.data.borrow())?;
-        
-        // Verify pool seeds
-        let (pool_pubkey, _) = Pubkey::find_program_address(
-            &[b"pool", token_a_info.key.as_ref(), token_b_info.key.as_ref()],
-            program_id,
-        );
+        // Use final output account for last hop, intermediate for others
+        let target_output_account = if hop == num_hops - 1 {
+            output_account
+        } else {
+            intermediate_account
+        };
         
-        if pool_pubkey != *pool_info.key {
-            return Err(ProgramError::InvalidSeeds);
+        // Multi-asset pools take the same accounts, read as
+        // [pool, mint_in, mint_out, vault_in, vault_out, intermediate, output]
+        if pool_info.data_len() == MultiPool::LEN {
+            if token_account_mint(target_output_account)? != *token_b_info.key {
+                return Err(ProgramError::InvalidArgument);
+            }
+            let result = multi_pool_swap(
+                program_id,
+                pool_info,
+                token_a_info,
+                token_b_info,
+                vault_a_info,
+                vault_b_info,
+                Some(current_input_account),
+                target_output_account,
+                user_info,
+                accounts,
+                current_amount,
+            )?;
+            current_amount = result.amount_out;
+            hops.push(result);
+            check_hop_minimum(minimum_hop_amounts_out, hop, current_amount)?;
+            current_input_account = target_output_account;
+            continue;
         }
         
-        // Verify vault addresses and get bumps
-        let (vault_a_pubkey, vault_a_bump) = get_vault_address(pool_info.key, &pool.token_a, program_id);
-        let (vault_b_pubkey, vault_b_bump) = get_vault_address(pool_info.key, &pool.token_b, program_id);
+        // Verify pool ownership and seeds
+        let mut pool = load_hop_pool(program_id, pool_info)?;
         
-        if vault_a_pubkey != *vault_a_info.key || vault_b_pubkey != *vault_b_info.key {
-            return Err(ProgramError::InvalidSeeds);
+        // The mints are passed for TransferChecked
+        if *token_a_info.key != pool.token_a || *token_b_info.key != pool.token_b {
+            return Err(ProgramError::InvalidArgument);
         }
         
+        // Verify vault addresses against the bumps stored at init
+        verify_vaults(program_id, &pool, pool_info.key, vault_a_info, vault_b_info)?;
+        let (vault_a_bump, vault_b_bump) = (pool.vault_a_bump, pool.vault_b_bump);
+        
         // Determine swap direction by checking which token the user is providing
         // We need to check the token mint of the input account, not the account address
-        let input_token_mint = {
-            // Read the token account to get its mint
-            let token_account_data = current_input_account.data.borrow();
-            if token_account_data.len() < 64 {
-                return Err(ProgramError::InvalidAccountData);
-            }
-            // Token account mint is at offset 0-32
-            Pubkey::new_from_array(token_account_data[0..32].try_into().unwrap())
-        };
+        let input_token_mint = token_account_mint(current_input_account)?;
         
         let direction_a_to_b = if input_token_mint == pool.token_a {
             // User is providing token_a mint, so swap A->B
@@ -1102,126 +1681,63 @@ fn process_multihop_swap(
             return Err(ProgramError::InvalidArgument);
         };
         
-        // Debug: Log the direction for troubleshooting
-        solana_program::log::sol_log(&format!("Hop {}: pool.token_a = {}, pool.token_b = {}, input_token_mint = {}, direction_a_to_b = {}", 
-            hop, 
-            pool.token_a.to_string(), 
-            pool.token_b.to_string(), 
-            input_token_mint.to_string(), 
-            direction_a_to_b
-        ));
-        
-        let (reserve_in, reserve_out) = if direction_a_to_b {
-            (pool.reserve_a, pool.reserve_b)
+        let (mint_in_info, vault_in_info, mint_out_info, vault_out_info) = if direction_a_to_b {
+            (token_a_info, vault_a_info, token_b_info, vault_b_info)
         } else {
-            (pool.reserve_b, pool.reserve_a)
+            (token_b_info, vault_b_info, token_a_info, vault_a_info)
         };
+        if token_account_mint(target_output_account)? != *mint_out_info.key {
+            return Err(ProgramError::InvalidArgument);
+        }
         
         // Transfer input tokens to vault
-        if direction_a_to_b {
-            invoke(
-                &create_transfer_instruction(
-                    current_input_account.key,
-                    vault_a_info.key,
-                    user_info.key,
-                    current_amount,
-                ),
-                &[
-                    current_input_account.clone(),
-                    vault_a_info.clone(),
-                    user_info.clone(),
-                    token_program_info.clone(),
-                ],
-            )?;
-        } else {
-            invoke(
-                &create_transfer_instruction(
-                    current_input_account.key,
-                    vault_b_info.key,
-                    user_info.key,
-                    current_amount,
-                ),
-                &[
-                    current_input_account.clone(),
-                    vault_b_info.clone(),
-                    user_info.clone(),
-                    token_program_info.clone(),
-                ],
-            )?;
-        }
+        let amount_received = transfer_checked(
+            mint_token_program(mint_in_info, accounts)?,
+            current_input_account,
+            mint_in_info,
+            vault_in_info,
+            user_info,
+            current_amount,
+            &[],
+        )?;
         
         // Calculate output amount
-        let amount_out = calculate_swap_output(current_amount, reserve_in, reserve_out)?;
-        
-        // Use final output account for last hop, intermediate for others
-        let target_output_account = if hop == num_hops - 1 {
-            output_account
-        } else {
-            intermediate_account
-        };
+        let amount_out = pool.swap_output(amount_received, direction_a_to_b)?;
         
         // Transfer output tokens using vault PDA as authority
-        if direction_a_to_b {
-            let vault_b_signer_seeds: &[&[_]] = &[
-                b"vault",
-                pool_info.key.as_ref(),
-                pool.token_b.as_ref(),
-                &[vault_b_bump],
-            ];
-            
-            invoke_signed(
-                &create_transfer_instruction(
-                    vault_b_info.key,
-                    target_output_account.key,
-                    vault_b_info.key,
-                    amount_out,
-                ),
-                &[
-                    vault_b_info.clone(),
-                    target_output_account.clone(),
-                    vault_b_info.clone(),
-                    token_program_info.clone(),
-                ],
-                &[vault_b_signer_seeds],
-            )?;
-        } else {
-            let vault_a_signer_seeds: &[&[_]] = &[
-                b"vault",
-                pool_info.key.as_ref(),
-                pool.token_a.as_ref(),
-                &[vault_a_bump],
-            ];
-            
-            invoke_signed(
-                &create_transfer_instruction(
-                    vault_a_info.key,
-                    target_output_account.key,
-                    vault_a_info.key,
-                    amount_out,
-                ),
-                &[
-                    vault_a_info.clone(),
-                    target_output_account.clone(),
-                    vault_a_info.clone(),
-                    token_program_info.clone(),
-                ],
-                &[vault_a_signer_seeds],
-            )?;
-        }
+        let vault_out_bump = if direction_a_to_b { vault_b_bump } else { vault_a_bump };
+        let vault_out_signer_seeds: &[&[_]] = &[
+            b"vault",
+            pool_info.key.as_ref(),
+            mint_out_info.key.as_ref(),
+            &[vault_out_bump],
+        ];
+        let output_received = transfer_checked(
+            mint_token_program(mint_out_info, accounts)?,
+            vault_out_info,
+            mint_out_info,
+            target_output_account,
+            vault_out_info,
+            amount_out,
+            &[vault_out_signer_seeds],
+        )?;
         
         // Update pool reserves
+        let now = Clock::get()?.unix_timestamp;
+        pool.update_prices(now)?;
+        record_observation(program_id, pool_info.key, &pool, oracle_info, now)?;
         if direction_a_to_b {
-            pool.reserve_a = pool.reserve_a.checked_add(current_amount).unwrap();
+            pool.reserve_a = pool.reserve_a.checked_add(amount_received).unwrap();
             pool.reserve_b = pool.reserve_b.checked_sub(amount_out).unwrap();
         } else {
-            pool.reserve_b = pool.reserve_b.checked_add(current_amount).unwrap();
+            pool.reserve_b = pool.reserve_b.checked_add(amount_received).unwrap();
             pool.reserve_a = pool.reserve_a.checked_sub(amount_out).unwrap();
         }
+        hops.push(SwapResult::from_pool(&pool, amount_received, output_received, direction_a_to_b)?);
         
-        Pool::pack(pool, &mut pool_info.data.borrow_mut())?;
-        
-        // Set up for next hop
-        current_amount = amount_out;
+        // Set up for next hop with what the output account actually received
+        current_amount = output_received;
+        check_hop_minimum(minimum_hop_amounts_out, hop, current_amount)?;
         current_input_account = target_output_account;
     }
     
@@ -1229,20 +1745,28 @@ fn process_multihop_swap(
     if current_amount < minimum_amount_out {
         return Err(ProgramError::InsufficientFunds);
     }
+    let result = MultihopSwapResult {
+        amount_in,
+        amount_out: current_amount,
+        hops,
+    };
+    set_return_data(&result.try_to_vec().unwrap());
     
     Ok(())
 }
 
+// `minimum_hop_amounts_out` is empty, or bounds what each hop delivers
 fn process_multihop_swap_with_path(
     program_id: &Pubkey,
     accounts: &[AccountInfo],
     amount_in: u64,
     minimum_amount_out: u64,
     token_path: Vec<Pubkey>,
+    minimum_hop_amounts_out: &[u64],
 ) -> ProgramResult {
     let account_info_iter = &mut accounts.iter();
     let user_info = next_account_info(account_info_iter)?;
-    let token_program_info = next_account_info(account_info_iter)?;
+    let _token_program_info = next_account_info(account_info_iter)?;
     
     // First account is user's input token account
     let user_input_account = next_account_info(account_info_iter)?;
@@ -1258,30 +1782,77 @@ fn process_multihop_swap_with_path(
     }
     
     let num_hops = token_path.len() - 1;
+    if num_hops > MAX_HOPS {
+        return Err(ProgramError::InvalidArgument);
+    }
+    if remaining_accounts.len() < num_hops * 7 {
+        return Err(ProgramError::NotEnoughAccountKeys);
+    }
+    let pool_infos: Vec<&AccountInfo> = (0..num_hops).map(|hop| remaining_accounts[hop * 7]).collect();
+    let oracles = route_oracles(program_id, &pool_infos, &accounts[3 + num_hops * 7..])?;
+    check_hop_minimums(minimum_hop_amounts_out, num_hops)?;
+    let pools: Vec<&Pubkey> = pool_infos.iter().map(|pool_info| pool_info.key).collect();
+    check_route(&pools)?;
+    
     let mut current_amount = amount_in;
+    let mut hops = Vec::with_capacity(num_hops);
     let mut current_input_account = user_input_account;
     
     // Process each hop based on token path
     for hop in 0..num_hops {
         let base_idx = hop * 7; // Assuming 7 accounts per hop
-        if base_idx + 6 >= remaining_accounts.len() {
-            return Err(ProgramError::NotEnoughAccountKeys);
-        }
         
         let pool_info = remaining_accounts[base_idx];
-        let _token_a_info = remaining_accounts[base_idx + 1];
-        let _token_b_info = remaining_accounts[base_idx + 2];
+        let token_a_info = remaining_accounts[base_idx + 1];
+        let token_b_info = remaining_accounts[base_idx + 2];
         let vault_a_info = remaining_accounts[base_idx + 3];
         let vault_b_info = remaining_accounts[base_idx + 4];
         let intermediate_account = remaining_accounts[base_idx + 5];
         let output_account = remaining_accounts[base_idx + 6];
         
-        let mut pool = Pool::unpack(&pool_info.data.borrow())?;
-        
         // Verify pool matches the token path
         let input_token = token_path[hop];
         let output_token = token_path[hop + 1];
         
+        // Use final output account for last hop, intermediate for others
+        let target_output_account = if hop == num_hops - 1 {
+            output_account
+        } else {
+            intermediate_account
+        };
+        if token_account_mint(target_output_account)? != output_token {
+            return Err(ProgramError::InvalidArgument);
+        }
+        
+        // Multi-asset pools trade any pair on the path; the hop's mints and
+        // vaults are read as the input and output ones
+        if pool_info.data_len() == MultiPool::LEN {
+            if *token_a_info.key != input_token || *token_b_info.key != output_token {
+                return Err(ProgramError::InvalidArgument);
+            }
+            let result = multi_pool_swap(
+                program_id,
+                pool_info,
+                token_a_info,
+                token_b_info,
+                vault_a_info,
+                vault_b_info,
+                Some(current_input_account),
+                target_output_account,
+                user_info,
+                accounts,
+                current_amount,
+            )?;
+            current_amount = result.amount_out;
+            hops.push(result);
+            check_hop_minimum(minimum_hop_amounts_out, hop, current_amount)?;
+            current_input_account = target_output_account;
+            continue;
+        }
+        
+        // Verify pool ownership and seeds
+        let mut pool = load_hop_pool(program_id, pool_info)?;
+        
         let direction_a_to_b = if pool.token_a == input_token && pool.token_b == output_token {
             true
         } else if pool.token_b == input_token && pool.token_a == output_token {
@@ -1290,121 +1861,2170 @@ fn process_multihop_swap_with_path(
             return Err(ProgramError::InvalidArgument);
         };
         
-        // Verify vault addresses
-        let (vault_a_pubkey, vault_a_bump) = get_vault_address(pool_info.key, &pool.token_a, program_id);
-        let (vault_b_pubkey, vault_b_bump) = get_vault_address(pool_info.key, &pool.token_b, program_id);
+        // Verify vault addresses against the bumps stored at init
+        verify_vaults(program_id, &pool, pool_info.key, vault_a_info, vault_b_info)?;
+        let (vault_a_bump, vault_b_bump) = (pool.vault_a_bump, pool.vault_b_bump);
         
-        if vault_a_pubkey != *vault_a_info.key || vault_b_pubkey != *vault_b_info.key {
-            return Err(ProgramError::InvalidSeeds);
+        // The mints are passed for TransferChecked
+        if *token_a_info.key != pool.token_a || *token_b_info.key != pool.token_b {
+            return Err(ProgramError::InvalidArgument);
         }
         
-        let (reserve_in, reserve_out, vault_in, _vault_out, _out_bump) = if direction_a_to_b {
-            (pool.reserve_a, pool.reserve_b, vault_a_info, vault_b_info, vault_b_bump)
-        } else {
-            (pool.reserve_b, pool.reserve_a, vault_b_info, vault_a_info, vault_a_bump)
-        };
+        let (mint_in_info, vault_in_info, mint_out_info, vault_out_info, vault_out_bump) =
+            if direction_a_to_b {
+                (token_a_info, vault_a_info, token_b_info, vault_b_info, vault_b_bump)
+            } else {
+                (token_b_info, vault_b_info, token_a_info, vault_a_info, vault_a_bump)
+            };
         
         // Transfer input tokens to vault
-        invoke(
-            &create_transfer_instruction(
-                current_input_account.key,
-                vault_in.key,
+        let amount_received = transfer_checked(
+            mint_token_program(mint_in_info, accounts)?,
+            current_input_account,
+            mint_in_info,
+            vault_in_info,
+            user_info,
+            current_amount,
+            &[],
+        )?;
+        
+        // Calculate output amount
+        let amount_out = pool.swap_output(amount_received, direction_a_to_b)?;
+        
+        // Transfer output tokens using vault PDA as authority
+        let vault_out_signer_seeds: &[&[_]] = &[
+            b"vault",
+            pool_info.key.as_ref(),
+            mint_out_info.key.as_ref(),
+            &[vault_out_bump],
+        ];
+        let output_received = transfer_checked(
+            mint_token_program(mint_out_info, accounts)?,
+            vault_out_info,
+            mint_out_info,
+            target_output_account,
+            vault_out_info,
+            amount_out,
+            &[vault_out_signer_seeds],
+        )?;
+        
+        // Update pool reserves
+        let now = Clock::get()?.unix_timestamp;
+        pool.update_prices(now)?;
+        record_observation(program_id, pool_info.key, &pool, oracles[hop], now)?;
+        if direction_a_to_b {
+            pool.reserve_a = pool.reserve_a.checked_add(amount_received).unwrap();
+            pool.reserve_b = pool.reserve_b.checked_sub(amount_out).unwrap();
+        } else {
+            pool.reserve_b = pool.reserve_b.checked_add(amount_received).unwrap();
+            pool.reserve_a = pool.reserve_a.checked_sub(amount_out).unwrap();
+        }
+        hops.push(SwapResult::from_pool(&pool, amount_received, output_received, direction_a_to_b)?);
+        
+        // Set up for next hop with what the output account actually received
+        current_amount = output_received;
+        check_hop_minimum(minimum_hop_amounts_out, hop, current_amount)?;
+        current_input_account = target_output_account;
+    }
+    
+    // Ensure final output is above minimum
+    if current_amount < minimum_amount_out {
+        return Err(ProgramError::InsufficientFunds);
+    }
+    let result = MultihopSwapResult {
+        amount_in,
+        amount_out: current_amount,
+        hops,
+    };
+    set_return_data(&result.try_to_vec().unwrap());
+    
+    Ok(())
+}
+
+// Multihop swap over N hops with accounts:
+// [user, token_program, (pool, vault_in, vault_out) x N, mint x (N + 1), user_in, user_out]
+// Hop i swaps mint i for mint i + 1. Only the first hop is paid from the user;
+// every hop pays its output straight into the next hop's input vault and the
+// last one into user_out, so the user holds no intermediate token accounts.
+// The oracles of the route's pools, then the Token-2022 program, may follow
+// user_out. `minimum_hop_amounts_out` is empty, or bounds what each hop
+// delivers.
+fn process_compact_multihop_swap(
+    program_id: &Pubkey,
+    accounts: &[AccountInfo],
+    amount_in: u64,
+    minimum_amount_out: u64,
+    minimum_hop_amounts_out: &[u64],
+) -> ProgramResult {
+    let account_info_iter = &mut accounts.iter();
+    let user_info = next_account_info(account_info_iter)?;
+    let _token_program_info = next_account_info(account_info_iter)?;
+    let route_accounts = account_info_iter.as_slice();
+
+    let num_hops = count_route_hops(program_id, route_accounts, 3, 4, 3);
+    if num_hops == 0 {
+        return Err(ProgramError::NotEnoughAccountKeys);
+    }
+    let (hop_accounts, path_accounts) = route_accounts.split_at(num_hops * 3);
+    let (mints, path_accounts) = path_accounts.split_at(num_hops + 1);
+    let (user_in_info, user_out_info) = (&path_accounts[0], &path_accounts[1]);
+    let pool_infos: Vec<&AccountInfo> = hop_accounts.iter().step_by(3).collect();
+    let oracles = route_oracles(program_id, &pool_infos, &path_accounts[2..])?;
+    check_hop_minimums(minimum_hop_amounts_out, num_hops)?;
+
+    let result = swap_compact_route(
+        program_id,
+        accounts,
+        user_info,
+        hop_accounts,
+        mints,
+        user_in_info,
+        user_out_info,
+        &oracles,
+        amount_in,
+        minimum_hop_amounts_out,
+    )?;
+
+    if result.amount_out < minimum_amount_out {
+        return Err(ProgramError::InsufficientFunds);
+    }
+    set_return_data(&result.try_to_vec().unwrap());
+
+    Ok(())
+}
+
+// The only account allowed after a route and its oracles is the Token-2022
+// program, once
+fn check_token_2022_only(accounts: &[AccountInfo]) -> ProgramResult {
+    match accounts {
+        [] => Ok(()),
+        [program] if *program.key == SPL_TOKEN_2022_PROGRAM => Ok(()),
+        _ => Err(ProgramError::InvalidAccountData),
+    }
+}
+
+// Whether a route account is a pool with an oracle. Anything else reads as
+// without one and is refused when its hop loads it.
+fn route_pool_has_oracle(program_id: &Pubkey, pool_info: &AccountInfo) -> bool {
+    pool_info.owner == program_id
+        && pool_info.data_len() == Pool::LEN
+        && pool_info
+            .try_borrow_data()
+            .is_ok_and(|data| PoolState::from_bytes(&data).is_ok_and(|pool| pool.has_oracle != 0))
+}
+
+// Hops on a route whose hops take `hop_len` accounts, with a pool every
+// `pool_stride` accounts from the start and `fixed_len` other accounts after
+// them: the most hops that fit alongside the oracles of their pools
+fn count_route_hops(
+    program_id: &Pubkey,
+    accounts: &[AccountInfo],
+    pool_stride: usize,
+    hop_len: usize,
+    fixed_len: usize,
+) -> usize {
+    let mut num_hops = 0;
+    let mut num_oracles = 0;
+    while let Some(pool_info) = accounts.get(num_hops * pool_stride) {
+        let oracles = num_oracles + route_pool_has_oracle(program_id, pool_info) as usize;
+        if accounts.len() < (num_hops + 1) * hop_len + fixed_len + oracles {
+            break;
+        }
+        num_hops += 1;
+        num_oracles = oracles;
+    }
+    num_hops
+}
+
+// The oracles that follow a route, one for each of its pools that has one in
+// hop order, then at most the Token-2022 program. Returns each hop's oracle.
+fn route_oracles<'a, 'b>(
+    program_id: &Pubkey,
+    pools: &[&AccountInfo<'b>],
+    trailing: &'a [AccountInfo<'b>],
+) -> Result<Vec<Option<&'a AccountInfo<'b>>>, ProgramError> {
+    let trailing_iter = &mut trailing.iter();
+    let oracles = pools
+        .iter()
+        .map(|pool_info| match route_pool_has_oracle(program_id, pool_info) {
+            true => next_account_info(trailing_iter).map(Some),
+            false => Ok(None),
+        })
+        .collect::<Result<Vec<_>, _>>()?;
+    check_token_2022_only(trailing_iter.as_slice())?;
+    Ok(oracles)
+}
+
+// Swap `amount_in` from user_in along one compact route: `hop_accounts` holds
+// [pool, vault_in, vault_out] per hop, `mints` the route's N + 1 mints and
+// `oracles` each hop's oracle, if its pool has one. Each hop pays into the next
+// hop's input vault, the last one into user_out. `accounts` are the
+// instruction's, where the mints' token programs are found.
+#[allow(clippy::too_many_arguments)]
+fn swap_compact_route<'a>(
+    program_id: &Pubkey,
+    accounts: &[AccountInfo<'a>],
+    user_info: &AccountInfo<'a>,
+    hop_accounts: &[AccountInfo<'a>],
+    mints: &[AccountInfo<'a>],
+    user_in_info: &AccountInfo<'a>,
+    user_out_info: &AccountInfo<'a>,
+    oracles: &[Option<&AccountInfo<'a>>],
+    amount_in: u64,
+    minimum_hop_amounts_out: &[u64],
+) -> Result<MultihopSwapResult, ProgramError> {
+    let num_hops = hop_accounts.len() / 3;
+    let pools: Vec<&Pubkey> = hop_accounts.iter().step_by(3).map(|pool_info| pool_info.key).collect();
+    check_route(&pools)?;
+    if token_account_mint(user_out_info)? != *mints[num_hops].key {
+        return Err(ProgramError::InvalidArgument);
+    }
+
+    // What the current hop's input vault holds for it
+    let mut current_amount = amount_in;
+    let mut hops = Vec::with_capacity(num_hops);
+    for hop in 0..num_hops {
+        let pool_info = &hop_accounts[hop * 3];
+        let vault_in_info = &hop_accounts[hop * 3 + 1];
+        let vault_out_info = &hop_accounts[hop * 3 + 2];
+        let mint_in_info = &mints[hop];
+        let mint_out_info = &mints[hop + 1];
+        let source_info = if hop == 0 { Some(user_in_info) } else { None };
+        let destination_info = if hop + 1 < num_hops {
+            &hop_accounts[(hop + 1) * 3 + 1]
+        } else {
+            user_out_info
+        };
+
+        if pool_info.data_len() == MultiPool::LEN {
+            let result = multi_pool_swap(
+                program_id,
+                pool_info,
+                mint_in_info,
+                mint_out_info,
+                vault_in_info,
+                vault_out_info,
+                source_info,
+                destination_info,
+                user_info,
+                accounts,
+                current_amount,
+            )?;
+            current_amount = result.amount_out;
+            hops.push(result);
+            check_hop_minimum(minimum_hop_amounts_out, hop, current_amount)?;
+            continue;
+        }
+
+        // The pool's own mints are its seeds, and the route decides the direction
+        let mut pool = load_hop_pool(program_id, pool_info)?;
+        let direction_a_to_b = if pool.token_a == *mint_in_info.key && pool.token_b == *mint_out_info.key {
+            true
+        } else if pool.token_b == *mint_in_info.key && pool.token_a == *mint_out_info.key {
+            false
+        } else {
+            return Err(ProgramError::InvalidArgument);
+        };
+
+        let (vault_a_info, vault_b_info) = if direction_a_to_b {
+            (vault_in_info, vault_out_info)
+        } else {
+            (vault_out_info, vault_in_info)
+        };
+        verify_vaults(program_id, &pool, pool_info.key, vault_a_info, vault_b_info)?;
+        let vault_out_bump = if direction_a_to_b { pool.vault_b_bump } else { pool.vault_a_bump };
+
+        let amount_received = match source_info {
+            Some(source_info) => transfer_checked(
+                mint_token_program(mint_in_info, accounts)?,
+                source_info,
+                mint_in_info,
+                vault_in_info,
+                user_info,
+                current_amount,
+                &[],
+            )?,
+            None => current_amount,
+        };
+
+        let amount_out = pool.swap_output(amount_received, direction_a_to_b)?;
+
+        let vault_out_signer_seeds: &[&[_]] = &[
+            b"vault",
+            pool_info.key.as_ref(),
+            mint_out_info.key.as_ref(),
+            &[vault_out_bump],
+        ];
+        let output_received = transfer_checked(
+            mint_token_program(mint_out_info, accounts)?,
+            vault_out_info,
+            mint_out_info,
+            destination_info,
+            vault_out_info,
+            amount_out,
+            &[vault_out_signer_seeds],
+        )?;
+
+        let now = Clock::get()?.unix_timestamp;
+        pool.update_prices(now)?;
+        record_observation(program_id, pool_info.key, &pool, oracles[hop], now)?;
+        if direction_a_to_b {
+            pool.reserve_a = pool.reserve_a.checked_add(amount_received).unwrap();
+            pool.reserve_b = pool.reserve_b.checked_sub(amount_out).unwrap();
+        } else {
+            pool.reserve_b = pool.reserve_b.checked_add(amount_received).unwrap();
+            pool.reserve_a = pool.reserve_a.checked_sub(amount_out).unwrap();
+        }
+        hops.push(SwapResult::from_pool(&pool, amount_received, output_received, direction_a_to_b)?);
+
+        // Net of any transfer fee, as received by the next vault or user_out
+        current_amount = output_received;
+        check_hop_minimum(minimum_hop_amounts_out, hop, current_amount)?;
+    }
+
+    Ok(MultihopSwapResult {
+        amount_in,
+        amount_out: current_amount,
+        hops,
+    })
+}
+
+// Swap along several compact routes between the same two tokens. Accounts:
+// user, token program, user input, user output, then per route its hop
+// accounts and mints as in CompactMultihopSwap, then the oracles of all the
+// routes' pools in order, then the Token-2022 program if any mint needs it.
+fn process_split_route_swap(
+    program_id: &Pubkey,
+    accounts: &[AccountInfo],
+    routes: &[SplitRoute],
+    minimum_amount_out: u64,
+) -> ProgramResult {
+    if routes.is_empty() || routes.len() > MAX_SPLIT_ROUTES {
+        return Err(ProgramError::InvalidArgument);
+    }
+    if routes.iter().any(|route| route.num_hops == 0) {
+        return Err(ProgramError::InvalidArgument);
+    }
+    let route_accounts_len: usize = routes
+        .iter()
+        .map(|route| route.num_hops as usize * 4 + 1)
+        .sum();
+    if accounts.len() < 4 + route_accounts_len {
+        return Err(ProgramError::NotEnoughAccountKeys);
+    }
+
+    let account_info_iter = &mut accounts.iter();
+    let user_info = next_account_info(account_info_iter)?;
+    let _token_program_info = next_account_info(account_info_iter)?;
+    let user_in_info = next_account_info(account_info_iter)?;
+    let user_out_info = next_account_info(account_info_iter)?;
+    let (mut route_accounts, rest) = account_info_iter.as_slice().split_at(route_accounts_len);
+    let mut pool_infos = Vec::new();
+    let mut route_start = 0;
+    for route in routes {
+        let num_hops = route.num_hops as usize;
+        pool_infos.extend(route_accounts[route_start..].iter().step_by(3).take(num_hops));
+        route_start += num_hops * 4 + 1;
+    }
+    let all_oracles = route_oracles(program_id, &pool_infos, rest)?;
+    let mut oracles = all_oracles.as_slice();
+
+    // Every route starts at the input account's mint; swap_compact_route
+    // checks that each ends at the output account's
+    let mint_in = token_account_mint(user_in_info)?;
+    let mut all_pools = Vec::new();
+    let mut results = Vec::with_capacity(routes.len());
+    let mut amount_in = 0u64;
+    let mut amount_out = 0u64;
+    for route in routes {
+        let num_hops = route.num_hops as usize;
+        let (hop_accounts, after_hops) = route_accounts.split_at(num_hops * 3);
+        let (mints, next_route) = after_hops.split_at(num_hops + 1);
+        route_accounts = next_route;
+        let (hop_oracles, next_oracles) = oracles.split_at(num_hops);
+        oracles = next_oracles;
+        if *mints[0].key != mint_in {
+            return Err(ProgramError::InvalidArgument);
+        }
+        // A pool shared by two routes would just price both legs in turn
+        all_pools.extend(hop_accounts.iter().step_by(3).map(|pool_info| pool_info.key));
+        check_distinct_pools(&all_pools)?;
+
+        let result = swap_compact_route(
+            program_id,
+            accounts,
+            user_info,
+            hop_accounts,
+            mints,
+            user_in_info,
+            user_out_info,
+            hop_oracles,
+            route.amount_in,
+            &[],
+        )?;
+        amount_in = amount_in.checked_add(result.amount_in).ok_or(ProgramError::InvalidArgument)?;
+        amount_out = amount_out.checked_add(result.amount_out).ok_or(ProgramError::InvalidArgument)?;
+        results.push(result);
+    }
+
+    if amount_out < minimum_amount_out {
+        return Err(ProgramError::InsufficientFunds);
+    }
+    let result = SplitRouteSwapResult {
+        amount_in,
+        amount_out,
+        routes: results,
+    };
+    set_return_data(&result.try_to_vec().unwrap());
+
+    Ok(())
+}
+
+// Per-hop slippage bound of a route, when the caller gave one per hop
+// Per-hop minimums are either left out or given for every hop
+fn check_hop_minimums(minimum_hop_amounts_out: &[u64], num_hops: usize) -> ProgramResult {
+    if !minimum_hop_amounts_out.is_empty() && minimum_hop_amounts_out.len() != num_hops {
+        return Err(ProgramError::InvalidArgument);
+    }
+    Ok(())
+}
+
+fn check_hop_minimum(minimum_hop_amounts_out: &[u64], hop: usize, amount_out: u64) -> ProgramResult {
+    match minimum_hop_amounts_out.get(hop) {
+        Some(&minimum) if amount_out < minimum => Err(ProgramError::InsufficientFunds),
+        _ => Ok(()),
+    }
+}
+
+// Start ramping a stable pool's amplification towards target_amp by ramp_stop_ts
+fn process_ramp_amp(
+    program_id: &Pubkey,
+    accounts: &[AccountInfo],
+    target_amp: u64,
+    ramp_stop_ts: i64,
+) -> ProgramResult {
+    let account_info_iter = &mut accounts.iter();
+    let pool_info = next_account_info(account_info_iter)?;
+    let admin_info = next_account_info(account_info_iter)?;
+
+    let mut pool = load_stable_pool_for_admin(program_id, pool_info, admin_info)?;
+
+    let now = Clock::get()?.unix_timestamp;
+    let params = pool.stable_params()?;
+    let current_amp = params.current_amp(now);
+    stable::validate_ramp(current_amp, target_amp, params.ramp_start_ts, ramp_stop_ts, now)?;
+
+    pool.set_stable_params(&StableParams {
+        initial_amp: current_amp,
+        target_amp,
+        ramp_start_ts: now,
+        ramp_stop_ts,
+    })?;
+    Pool::pack(pool, &mut pool_info.data.borrow_mut())?;
+
+    solana_program::log::sol_log(&format!(
+        "Ramping amplification {} -> {} until {}",
+        current_amp, target_amp, ramp_stop_ts
+    ));
+
+    Ok(())
+}
+
+// Freeze a stable pool's amplification at its current value
+fn process_stop_ramp_amp(
+    program_id: &Pubkey,
+    accounts: &[AccountInfo],
+) -> ProgramResult {
+    let account_info_iter = &mut accounts.iter();
+    let pool_info = next_account_info(account_info_iter)?;
+    let admin_info = next_account_info(account_info_iter)?;
+
+    let mut pool = load_stable_pool_for_admin(program_id, pool_info, admin_info)?;
+
+    let now = Clock::get()?.unix_timestamp;
+    let current_amp = pool.stable_params()?.current_amp(now);
+    pool.set_stable_params(&StableParams {
+        initial_amp: current_amp,
+        target_amp: current_amp,
+        ramp_start_ts: now,
+        ramp_stop_ts: now,
+    })?;
+    Pool::pack(pool, &mut pool_info.data.borrow_mut())?;
+
+    solana_program::log::sol_log(&format!("Amplification fixed at {}", current_amp));
+
+    Ok(())
+}
+
+// Loads a stable pool owned by this program and checks the admin signed
+fn load_stable_pool_for_admin(
+    program_id: &Pubkey,
+    pool_info: &AccountInfo,
+    admin_info: &AccountInfo,
+) -> Result<Pool, ProgramError> {
+    if pool_info.owner != program_id {
+        return Err(ProgramError::IncorrectProgramId);
+    }
+    let pool = Pool::unpack(&pool_info.data.borrow())?;
+
+    let pool_pubkey = Pubkey::create_program_address(
+        &[b"pool", pool.token_a.as_ref(), pool.token_b.as_ref(), &[pool.bump]],
+        program_id,
+    )?;
+    if pool_pubkey != *pool_info.key {
+        return Err(ProgramError::InvalidSeeds);
+    }
+
+    if pool.curve_type != CurveType::Stable {
+        return Err(ProgramError::InvalidArgument);
+    }
+    if !admin_info.is_signer || *admin_info.key != pool.admin {
+        return Err(ProgramError::MissingRequiredSignature);
+    }
+
+    Ok(pool)
+}
+
+// Create a multi-asset pool.
+// Accounts: [pool, lp_mint, user, user_lp, token_program, system_program, rent,
+// ata_program], then [mint, vault, user_token] for each token in ascending mint order
+fn process_init_multi_pool(
+    program_id: &Pubkey,
+    accounts: &[AccountInfo],
+    amounts: Vec<u64>,
+) -> ProgramResult {
+    let account_info_iter = &mut accounts.iter();
+    let pool_info = next_account_info(account_info_iter)?;
+    let lp_mint_info = next_account_info(account_info_iter)?;
+    let user_info = next_account_info(account_info_iter)?;
+    let user_lp_info = next_account_info(account_info_iter)?;
+    let token_program_info = next_account_info(account_info_iter)?;
+    let system_program_info = next_account_info(account_info_iter)?;
+    let rent_info = next_account_info(account_info_iter)?;
+    let _ata_program_info = next_account_info(account_info_iter)?;
+
+    // [mint, vault, user_token] per token, then the Token-2022 program if a mint needs it
+    let token_accounts = account_info_iter.as_slice();
+    let (token_accounts, rest) = token_accounts.split_at(token_accounts.len() - token_accounts.len() % 3);
+    check_token_2022_only(rest)?;
+    if token_accounts.len() != amounts.len() * 3 {
+        return Err(ProgramError::NotEnoughAccountKeys);
+    }
+    let tokens: Vec<Pubkey> = token_accounts.chunks(3).map(|chunk| *chunk[0].key).collect();
+    multi_pool::validate_tokens(&tokens)?;
+
+    // Derive pool address and bump
+    let (pool_pubkey, pool_bump) =
+        Pubkey::find_program_address(&multi_pool::pool_seeds(&tokens), program_id);
+    if pool_pubkey != *pool_info.key {
+        return Err(ProgramError::InvalidSeeds);
+    }
+
+    let rent = Rent::from_account_info(rent_info)?;
+    let pool_space = MultiPool::LEN;
+    let pool_lamports = rent.minimum_balance(pool_space);
+
+    // Create pool account
+    let pool_bump_seed = [pool_bump];
+    let mut pool_signer_seeds = multi_pool::pool_seeds(&tokens);
+    pool_signer_seeds.push(&pool_bump_seed);
+
+    invoke_signed(
+        &system_instruction::create_account(
+            user_info.key,
+            pool_info.key,
+            pool_lamports,
+            pool_space as u64,
+            program_id,
+        ),
+        &[
+            user_info.clone(),
+            pool_info.clone(),
+            system_program_info.clone(),
+        ],
+        &[&pool_signer_seeds],
+    )?;
+
+    // Create, initialize and fund one vault per token under its mint's token
+    // program; reserves are what the vaults received after any transfer fee
+    let mut vault_bumps = [0; multi_pool::MAX_TOKENS];
+    let mut reserves = Vec::with_capacity(amounts.len());
+    for (index, (chunk, &amount)) in token_accounts.chunks(3).zip(&amounts).enumerate() {
+        let (mint_info, vault_info, user_token_info) = (&chunk[0], &chunk[1], &chunk[2]);
+        let mint_token_program_info = mint_token_program(mint_info, accounts)?;
+
+        let (vault_pubkey, vault_bump) = get_vault_address(pool_info.key, mint_info.key, program_id);
+        if vault_pubkey != *vault_info.key {
+            return Err(ProgramError::InvalidSeeds);
+        }
+        vault_bumps[index] = vault_bump;
+        let vault_signer_seeds: &[&[_]] = &[
+            b"vault",
+            pool_info.key.as_ref(),
+            mint_info.key.as_ref(),
+            &[vault_bump],
+        ];
+        create_vault(
+            user_info,
+            vault_info,
+            mint_info,
+            mint_token_program_info,
+            system_program_info,
+            rent_info,
+            vault_signer_seeds,
+        )?;
+
+        reserves.push(transfer_checked(
+            mint_token_program_info,
+            user_token_info,
+            mint_info,
+            vault_info,
+            user_info,
+            amount,
+            &[],
+        )?);
+    }
+    let liquidity = multi_pool::initial_lp_tokens(&reserves)?;
+
+    // Create LP mint account
+    let mint_space = 82; // Mint account size
+    let mint_lamports = rent.minimum_balance(mint_space);
+    let (lp_mint_pubkey, lp_mint_bump) = Pubkey::find_program_address(
+        &[b"mint", pool_info.key.as_ref()],
+        program_id,
+    );
+    if lp_mint_pubkey != *lp_mint_info.key {
+        return Err(ProgramError::InvalidSeeds);
+    }
+    let lp_mint_signer_seeds: &[&[_]] = &[
+        b"mint",
+        pool_info.key.as_ref(),
+        &[lp_mint_bump],
+    ];
+
+    invoke_signed(
+        &system_instruction::create_account(
+            user_info.key,
+            lp_mint_info.key,
+            mint_lamports,
+            mint_space as u64,
+            &GORBCHAIN_SPL_TOKEN_PROGRAM,
+        ),
+        &[
+            user_info.clone(),
+            lp_mint_info.clone(),
+            system_program_info.clone(),
+        ],
+        &[lp_mint_signer_seeds],
+    )?;
+
+    invoke_signed(
+        &create_initialize_mint_instruction(
+            lp_mint_info.key,
+            0, // decimals (0 for LP token)
+            pool_info.key, // mint authority is pool
+            None,          // no freeze authority
+        ),
+        &[
+            lp_mint_info.clone(),
+            rent_info.clone(),
+        ],
+        &[lp_mint_signer_seeds],
+    )?;
+
+    // Create user LP ATA if it doesn't exist
+    invoke(
+        &create_associated_token_account_instruction(
+            user_info.key,
+            user_lp_info.key,
+            user_info.key,
+            lp_mint_info.key,
+        ),
+        &[
+            user_info.clone(),
+            user_lp_info.clone(),
+            user_info.clone(),
+            lp_mint_info.clone(),
+            rent_info.clone(),
+            token_program_info.clone(),
+            system_program_info.clone(),
+        ],
+    )?;
+
+    invoke_signed(
+        &create_mint_to_instruction(
+            lp_mint_info.key,
+            user_lp_info.key,
+            pool_info.key,
+            liquidity,
+        ),
+        &[
+            lp_mint_info.clone(),
+            user_lp_info.clone(),
+            pool_info.clone(),
+            token_program_info.clone(),
+        ],
+        &[&pool_signer_seeds],
+    )?;
+
+    // Initialize pool state
+    let mut pool = MultiPool {
+        bump: pool_bump,
+        num_tokens: tokens.len() as u8,
+        tokens: [Pubkey::default(); multi_pool::MAX_TOKENS],
+        reserves: [0; multi_pool::MAX_TOKENS],
+        total_lp_supply: liquidity,
+        vault_bumps,
+        lp_mint_bump,
+    };
+    pool.tokens[..tokens.len()].copy_from_slice(&tokens);
+    pool.reserves[..reserves.len()].copy_from_slice(&reserves);
+    MultiPool::pack(pool, &mut pool_info.data.borrow_mut())?;
+
+    Ok(())
+}
+
+// Swap between any two tokens of a multi-asset pool.
+// Accounts: [pool, mint_in, mint_out, vault_in, vault_out, user_in, user_out, user, token_program]
+fn process_multi_pool_swap(
+    program_id: &Pubkey,
+    accounts: &[AccountInfo],
+    amount_in: u64,
+    minimum_amount_out: u64,
+) -> ProgramResult {
+    let account_info_iter = &mut accounts.iter();
+    let pool_info = next_account_info(account_info_iter)?;
+    let mint_in_info = next_account_info(account_info_iter)?;
+    let mint_out_info = next_account_info(account_info_iter)?;
+    let vault_in_info = next_account_info(account_info_iter)?;
+    let vault_out_info = next_account_info(account_info_iter)?;
+    let user_in_info = next_account_info(account_info_iter)?;
+    let user_out_info = next_account_info(account_info_iter)?;
+    let user_info = next_account_info(account_info_iter)?;
+    let _token_program_info = next_account_info(account_info_iter)?;
+
+    let result = multi_pool_swap(
+        program_id,
+        pool_info,
+        mint_in_info,
+        mint_out_info,
+        vault_in_info,
+        vault_out_info,
+        Some(user_in_info),
+        user_out_info,
+        user_info,
+        accounts,
+        amount_in,
+    )?;
+
+    if result.amount_out < minimum_amount_out {
+        return Err(ProgramError::InsufficientFunds);
+    }
+    set_return_data(&result.try_to_vec().unwrap());
+
+    Ok(())
+}
+
+// Proportional deposit into a multi-asset pool, trimmed to the pool ratio.
+// Accounts: [pool, lp_mint, user_lp, user, token_program], then [mint, vault, user_token]
+// for each token in pool order
+fn process_add_multi_liquidity(
+    program_id: &Pubkey,
+    accounts: &[AccountInfo],
+    amounts: Vec<u64>,
+) -> ProgramResult {
+    let account_info_iter = &mut accounts.iter();
+    let pool_info = next_account_info(account_info_iter)?;
+    let lp_mint_info = next_account_info(account_info_iter)?;
+    let user_lp_info = next_account_info(account_info_iter)?;
+    let user_info = next_account_info(account_info_iter)?;
+    let token_program_info = next_account_info(account_info_iter)?;
+
+    let mut pool = load_multi_pool(program_id, pool_info)?;
+    verify_lp_mint(program_id, pool_info.key, pool.lp_mint_bump, lp_mint_info)?;
+
+    let token_accounts = multi_pool_token_accounts(&pool, account_info_iter.as_slice())?;
+
+    let (deposits, _) = pool.deposit_amounts(&amounts)?;
+
+    let mut received = Vec::with_capacity(deposits.len());
+    for (index, chunk) in token_accounts.chunks(3).enumerate() {
+        let (mint_info, vault_info, user_token_info) = (&chunk[0], &chunk[1], &chunk[2]);
+        verify_vault(program_id, pool_info.key, mint_info.key, pool.vault_bumps[index], vault_info)?;
+
+        received.push(transfer_checked(
+            mint_token_program(mint_info, accounts)?,
+            user_token_info,
+            mint_info,
+            vault_info,
+            user_info,
+            deposits[index],
+            &[],
+        )?);
+    }
+
+    // LP tokens for what the vaults received after any transfer fee
+    let (_, liquidity) = pool.deposit_amounts(&received)?;
+    for (reserve, &amount) in pool.reserves.iter_mut().zip(&received) {
+        *reserve = reserve.checked_add(amount).unwrap();
+    }
+
+    mint_multi_pool_lp(&pool, pool_info, lp_mint_info, user_lp_info, token_program_info, liquidity)?;
+
+    pool.total_lp_supply = pool.total_lp_supply.checked_add(liquidity).unwrap();
+    MultiPool::pack(pool, &mut pool_info.data.borrow_mut())?;
+
+    Ok(())
+}
+
+// Proportional withdrawal from a multi-asset pool.
+// Accounts: [pool, lp_mint, user_lp, user, token_program], then [mint, vault, user_token]
+// for each token in pool order
+fn process_remove_multi_liquidity(
+    program_id: &Pubkey,
+    accounts: &[AccountInfo],
+    lp_amount: u64,
+) -> ProgramResult {
+    let account_info_iter = &mut accounts.iter();
+    let pool_info = next_account_info(account_info_iter)?;
+    let lp_mint_info = next_account_info(account_info_iter)?;
+    let user_lp_info = next_account_info(account_info_iter)?;
+    let user_info = next_account_info(account_info_iter)?;
+    let token_program_info = next_account_info(account_info_iter)?;
+
+    let mut pool = load_multi_pool(program_id, pool_info)?;
+    verify_lp_mint(program_id, pool_info.key, pool.lp_mint_bump, lp_mint_info)?;
+
+    let token_accounts = multi_pool_token_accounts(&pool, account_info_iter.as_slice())?;
+
+    let withdrawals = pool.withdraw_amounts(lp_amount)?;
+
+    invoke(
+        &create_burn_instruction(
+            user_lp_info.key,
+            lp_mint_info.key,
+            user_info.key,
+            lp_amount,
+        ),
+        &[
+            user_lp_info.clone(),
+            lp_mint_info.clone(),
+            user_info.clone(),
+            token_program_info.clone(),
+        ],
+    )?;
+
+    for (index, chunk) in token_accounts.chunks(3).enumerate() {
+        let (mint_info, vault_info, user_token_info) = (&chunk[0], &chunk[1], &chunk[2]);
+        transfer_from_vault(
+            program_id,
+            pool_info.key,
+            mint_info,
+            pool.vault_bumps[index],
+            vault_info,
+            user_token_info,
+            accounts,
+            withdrawals[index],
+        )?;
+        pool.reserves[index] = pool.reserves[index].checked_sub(withdrawals[index]).unwrap();
+    }
+
+    pool.total_lp_supply = pool.total_lp_supply.checked_sub(lp_amount).unwrap();
+    MultiPool::pack(pool, &mut pool_info.data.borrow_mut())?;
+
+    Ok(())
+}
+
+// The [mint, vault, user_token] accounts of every token of a multi-asset pool,
+// in pool order. A Token-2022 program may follow them.
+fn multi_pool_token_accounts<'b, 'a>(
+    pool: &MultiPool,
+    accounts: &'b [AccountInfo<'a>],
+) -> Result<&'b [AccountInfo<'a>], ProgramError> {
+    let len = pool.num_tokens as usize * 3;
+    if accounts.len() < len {
+        return Err(ProgramError::NotEnoughAccountKeys);
+    }
+    let (token_accounts, rest) = accounts.split_at(len);
+    check_token_2022_only(rest)?;
+    if token_accounts
+        .iter()
+        .step_by(3)
+        .zip(pool.tokens())
+        .any(|(mint_info, mint)| mint_info.key != mint)
+    {
+        return Err(ProgramError::InvalidArgument);
+    }
+    Ok(token_accounts)
+}
+
+// Deposit a single token into a multi-asset pool.
+// Accounts: [pool, mint_in, vault_in, lp_mint, user_in, user_lp, user, token_program]
+fn process_add_multi_liquidity_single(
+    program_id: &Pubkey,
+    accounts: &[AccountInfo],
+    amount_in: u64,
+    minimum_lp_out: u64,
+) -> ProgramResult {
+    let account_info_iter = &mut accounts.iter();
+    let pool_info = next_account_info(account_info_iter)?;
+    let mint_in_info = next_account_info(account_info_iter)?;
+    let vault_in_info = next_account_info(account_info_iter)?;
+    let lp_mint_info = next_account_info(account_info_iter)?;
+    let user_in_info = next_account_info(account_info_iter)?;
+    let user_lp_info = next_account_info(account_info_iter)?;
+    let user_info = next_account_info(account_info_iter)?;
+    let token_program_info = next_account_info(account_info_iter)?;
+
+    let mut pool = load_multi_pool(program_id, pool_info)?;
+    verify_lp_mint(program_id, pool_info.key, pool.lp_mint_bump, lp_mint_info)?;
+
+    let index = pool.token_index(mint_in_info.key)?;
+    verify_vault(program_id, pool_info.key, mint_in_info.key, pool.vault_bumps[index], vault_in_info)?;
+
+    // LP tokens for what the vault received after any transfer fee
+    let amount_received = transfer_checked(
+        mint_token_program(mint_in_info, accounts)?,
+        user_in_info,
+        mint_in_info,
+        vault_in_info,
+        user_info,
+        amount_in,
+        &[],
+    )?;
+    let liquidity = pool.single_deposit_lp_tokens(index, amount_received)?;
+    if liquidity < minimum_lp_out {
+        return Err(ProgramError::InsufficientFunds);
+    }
+
+    mint_multi_pool_lp(&pool, pool_info, lp_mint_info, user_lp_info, token_program_info, liquidity)?;
+
+    pool.reserves[index] = pool.reserves[index].checked_add(amount_received).unwrap();
+    pool.total_lp_supply = pool.total_lp_supply.checked_add(liquidity).unwrap();
+    MultiPool::pack(pool, &mut pool_info.data.borrow_mut())?;
+
+    Ok(())
+}
+
+// Withdraw a single token from a multi-asset pool.
+// Accounts: [pool, mint_out, vault_out, lp_mint, user_lp, user_out, user, token_program]
+fn process_remove_multi_liquidity_single(
+    program_id: &Pubkey,
+    accounts: &[AccountInfo],
+    lp_amount: u64,
+    minimum_amount_out: u64,
+) -> ProgramResult {
+    let account_info_iter = &mut accounts.iter();
+    let pool_info = next_account_info(account_info_iter)?;
+    let mint_out_info = next_account_info(account_info_iter)?;
+    let vault_out_info = next_account_info(account_info_iter)?;
+    let lp_mint_info = next_account_info(account_info_iter)?;
+    let user_lp_info = next_account_info(account_info_iter)?;
+    let user_out_info = next_account_info(account_info_iter)?;
+    let user_info = next_account_info(account_info_iter)?;
+    let token_program_info = next_account_info(account_info_iter)?;
+
+    let mut pool = load_multi_pool(program_id, pool_info)?;
+    verify_lp_mint(program_id, pool_info.key, pool.lp_mint_bump, lp_mint_info)?;
+
+    let index = pool.token_index(mint_out_info.key)?;
+    let amount_out = pool.single_withdraw_amount(index, lp_amount)?;
+
+    invoke(
+        &create_burn_instruction(
+            user_lp_info.key,
+            lp_mint_info.key,
+            user_info.key,
+            lp_amount,
+        ),
+        &[
+            user_lp_info.clone(),
+            lp_mint_info.clone(),
+            user_info.clone(),
+            token_program_info.clone(),
+        ],
+    )?;
+
+    // The minimum bounds what the user received after any transfer fee
+    let received = transfer_from_vault(
+        program_id,
+        pool_info.key,
+        mint_out_info,
+        pool.vault_bumps[index],
+        vault_out_info,
+        user_out_info,
+        accounts,
+        amount_out,
+    )?;
+    if received < minimum_amount_out {
+        return Err(ProgramError::InsufficientFunds);
+    }
+
+    pool.reserves[index] = pool.reserves[index].checked_sub(amount_out).unwrap();
+    pool.total_lp_supply = pool.total_lp_supply.checked_sub(lp_amount).unwrap();
+    MultiPool::pack(pool, &mut pool_info.data.borrow_mut())?;
+
+    Ok(())
+}
+
+// Loads a multi-asset pool owned by this program and checks its address
+fn load_multi_pool(program_id: &Pubkey, pool_info: &AccountInfo) -> Result<MultiPool, ProgramError> {
+    if pool_info.owner != program_id {
+        return Err(ProgramError::IncorrectProgramId);
+    }
+    let pool = MultiPool::unpack(&pool_info.data.borrow())?;
+
+    let bump_seed = [pool.bump];
+    let mut seeds = multi_pool::pool_seeds(pool.tokens());
+    seeds.push(&bump_seed);
+    let pool_pubkey = Pubkey::create_program_address(&seeds, program_id)?;
+    if pool_pubkey != *pool_info.key {
+        return Err(ProgramError::InvalidSeeds);
+    }
+
+    Ok(pool)
+}
+
+// Checks the LP mint of any pool against its stored bump
+fn verify_lp_mint(
+    program_id: &Pubkey,
+    pool: &Pubkey,
+    lp_mint_bump: u8,
+    lp_mint_info: &AccountInfo,
+) -> ProgramResult {
+    let lp_mint_pubkey =
+        Pubkey::create_program_address(&[b"mint", pool.as_ref(), &[lp_mint_bump]], program_id)?;
+    if lp_mint_pubkey != *lp_mint_info.key {
+        return Err(ProgramError::InvalidSeeds);
+    }
+    Ok(())
+}
+
+// Mint LP tokens of a multi-asset pool, signed by the pool PDA
+fn mint_multi_pool_lp<'a>(
+    pool: &MultiPool,
+    pool_info: &AccountInfo<'a>,
+    lp_mint_info: &AccountInfo<'a>,
+    user_lp_info: &AccountInfo<'a>,
+    token_program_info: &AccountInfo<'a>,
+    amount: u64,
+) -> ProgramResult {
+    let bump_seed = [pool.bump];
+    let mut pool_signer_seeds = multi_pool::pool_seeds(pool.tokens());
+    pool_signer_seeds.push(&bump_seed);
+
+    invoke_signed(
+        &create_mint_to_instruction(
+            lp_mint_info.key,
+            user_lp_info.key,
+            pool_info.key,
+            amount,
+        ),
+        &[
+            lp_mint_info.clone(),
+            user_lp_info.clone(),
+            pool_info.clone(),
+            token_program_info.clone(),
+        ],
+        &[&pool_signer_seeds],
+    )
+}
+
+// Pay out of a pool vault through the mint's token program, using the vault
+// PDA as authority. Returns what `destination_info` was credited.
+#[allow(clippy::too_many_arguments)]
+fn transfer_from_vault<'a>(
+    program_id: &Pubkey,
+    pool: &Pubkey,
+    mint_info: &AccountInfo<'a>,
+    vault_bump: u8,
+    vault_info: &AccountInfo<'a>,
+    destination_info: &AccountInfo<'a>,
+    accounts: &[AccountInfo<'a>],
+    amount: u64,
+) -> Result<u64, ProgramError> {
+    verify_vault(program_id, pool, mint_info.key, vault_bump, vault_info)?;
+    let vault_signer_seeds: &[&[_]] = &[
+        b"vault",
+        pool.as_ref(),
+        mint_info.key.as_ref(),
+        &[vault_bump],
+    ];
+
+    transfer_checked(
+        mint_token_program(mint_info, accounts)?,
+        vault_info,
+        mint_info,
+        destination_info,
+        vault_info,
+        amount,
+        &[vault_signer_seeds],
+    )
+}
+
+// Swap `amount_in` of mint_in for mint_out through a multi-asset pool, paying
+// the output into `destination_info`. Shared by MultiPoolSwap and multihop routes;
+// without a source the input is already in vault_in, paid there by the previous hop.
+// `accounts` are the instruction's, where the mints' token programs are found.
+#[allow(clippy::too_many_arguments)]
+fn multi_pool_swap<'a>(
+    program_id: &Pubkey,
+    pool_info: &AccountInfo<'a>,
+    mint_in_info: &AccountInfo<'a>,
+    mint_out_info: &AccountInfo<'a>,
+    vault_in_info: &AccountInfo<'a>,
+    vault_out_info: &AccountInfo<'a>,
+    source_info: Option<&AccountInfo<'a>>,
+    destination_info: &AccountInfo<'a>,
+    user_info: &AccountInfo<'a>,
+    accounts: &[AccountInfo<'a>],
+    amount_in: u64,
+) -> Result<SwapResult, ProgramError> {
+    let mut pool = load_multi_pool(program_id, pool_info)?;
+    let index_in = pool.token_index(mint_in_info.key)?;
+    let index_out = pool.token_index(mint_out_info.key)?;
+
+    verify_vault(program_id, pool_info.key, mint_in_info.key, pool.vault_bumps[index_in], vault_in_info)?;
+
+    // Priced on what the vault received after any transfer fee
+    let amount_received = match source_info {
+        Some(source_info) => transfer_checked(
+            mint_token_program(mint_in_info, accounts)?,
+            source_info,
+            mint_in_info,
+            vault_in_info,
+            user_info,
+            amount_in,
+            &[],
+        )?,
+        None => amount_in,
+    };
+    let amount_out = pool.swap_output(index_in, index_out, amount_received)?;
+
+    let output_received = transfer_from_vault(
+        program_id,
+        pool_info.key,
+        mint_out_info,
+        pool.vault_bumps[index_out],
+        vault_out_info,
+        destination_info,
+        accounts,
+        amount_out,
+    )?;
+
+    pool.reserves[index_in] = pool.reserves[index_in].checked_add(amount_received).unwrap();
+    pool.reserves[index_out] = pool.reserves[index_out].checked_sub(amount_out).unwrap();
+    let result = SwapResult {
+        amount_in: amount_received,
+        amount_out: output_received,
+        fee: pool.swap_fee(amount_received),
+        reserve_in: pool.reserves[index_in],
+        reserve_out: pool.reserves[index_out],
+    };
+    MultiPool::pack(pool, &mut pool_info.data.borrow_mut())?;
+
+    Ok(result)
+}
+
+// Create a concentrated liquidity pool with no positions.
+// Accounts: [pool, token_a, token_b, vault_a, vault_b, payer, token_program, system_program, rent]
+fn process_init_concentrated_pool(
+    program_id: &Pubkey,
+    accounts: &[AccountInfo],
+    tick_spacing: u16,
+    initial_sqrt_price: u128,
+) -> ProgramResult {
+    let account_info_iter = &mut accounts.iter();
+    let pool_info = next_account_info(account_info_iter)?;
+    let token_a_info = next_account_info(account_info_iter)?;
+    let token_b_info = next_account_info(account_info_iter)?;
+    let vault_a_info = next_account_info(account_info_iter)?;
+    let vault_b_info = next_account_info(account_info_iter)?;
+    let user_info = next_account_info(account_info_iter)?;
+    let _token_program_info = next_account_info(account_info_iter)?;
+    let system_program_info = next_account_info(account_info_iter)?;
+    let rent_info = next_account_info(account_info_iter)?;
+
+    if tick_spacing == 0 || tick_spacing > concentrated::MAX_TICK_SPACING {
+        return Err(ProgramError::InvalidArgument);
+    }
+    let current_tick = concentrated::tick_at_sqrt_price(initial_sqrt_price)?;
+
+    // Derive pool address and bump
+    let (pool_pubkey, pool_bump) = Pubkey::find_program_address(
+        &[b"cl_pool", token_a_info.key.as_ref(), token_b_info.key.as_ref()],
+        program_id,
+    );
+    if pool_pubkey != *pool_info.key {
+        return Err(ProgramError::InvalidSeeds);
+    }
+
+    let rent = Rent::from_account_info(rent_info)?;
+    let pool_space = ConcentratedPool::LEN;
+    let pool_lamports = rent.minimum_balance(pool_space);
+
+    // Create pool account
+    let pool_signer_seeds: &[&[_]] = &[
+        b"cl_pool",
+        token_a_info.key.as_ref(),
+        token_b_info.key.as_ref(),
+        &[pool_bump],
+    ];
+
+    invoke_signed(
+        &system_instruction::create_account(
+            user_info.key,
+            pool_info.key,
+            pool_lamports,
+            pool_space as u64,
+            program_id,
+        ),
+        &[
+            user_info.clone(),
+            pool_info.clone(),
+            system_program_info.clone(),
+        ],
+        &[pool_signer_seeds],
+    )?;
+
+    // Create both vaults as PDAs under their mints' token programs
+    let mut vault_bumps = [0; 2];
+    for (index, (mint_info, vault_info)) in
+        [(token_a_info, vault_a_info), (token_b_info, vault_b_info)].into_iter().enumerate()
+    {
+        let (vault_pubkey, vault_bump) = get_vault_address(pool_info.key, mint_info.key, program_id);
+        if vault_pubkey != *vault_info.key {
+            return Err(ProgramError::InvalidSeeds);
+        }
+        vault_bumps[index] = vault_bump;
+        let vault_signer_seeds: &[&[_]] = &[
+            b"vault",
+            pool_info.key.as_ref(),
+            mint_info.key.as_ref(),
+            &[vault_bump],
+        ];
+        create_vault(
+            user_info,
+            vault_info,
+            mint_info,
+            mint_token_program(mint_info, accounts)?,
+            system_program_info,
+            rent_info,
+            vault_signer_seeds,
+        )?;
+    }
+
+    let pool = ConcentratedPool {
+        token_a: *token_a_info.key,
+        token_b: *token_b_info.key,
+        bump: pool_bump,
+        vault_a_bump: vault_bumps[0],
+        vault_b_bump: vault_bumps[1],
+        tick_spacing,
+        sqrt_price: initial_sqrt_price,
+        current_tick,
+        liquidity: 0,
+        fee_growth_global_a: 0,
+        fee_growth_global_b: 0,
+        ticks: Vec::new(),
+    };
+    ConcentratedPool::pack(pool, &mut pool_info.data.borrow_mut())?;
+
+    Ok(())
+}
+
+// Create an empty position over [tick_lower, tick_upper) for the owner.
+// Accounts: [pool, position, owner, system_program]
+fn process_open_position(
+    program_id: &Pubkey,
+    accounts: &[AccountInfo],
+    tick_lower: i32,
+    tick_upper: i32,
+) -> ProgramResult {
+    let account_info_iter = &mut accounts.iter();
+    let pool_info = next_account_info(account_info_iter)?;
+    let position_info = next_account_info(account_info_iter)?;
+    let owner_info = next_account_info(account_info_iter)?;
+    let system_program_info = next_account_info(account_info_iter)?;
+
+    if !owner_info.is_signer {
+        return Err(ProgramError::MissingRequiredSignature);
+    }
+    let pool = load_concentrated_pool(program_id, pool_info)?;
+    pool.validate_range(tick_lower, tick_upper)?;
+
+    let (position_pubkey, position_bump) = Pubkey::find_program_address(
+        &[
+            b"position",
+            pool_info.key.as_ref(),
+            owner_info.key.as_ref(),
+            &tick_lower.to_le_bytes(),
+            &tick_upper.to_le_bytes(),
+        ],
+        program_id,
+    );
+    if position_pubkey != *position_info.key {
+        return Err(ProgramError::InvalidSeeds);
+    }
+    let position_signer_seeds: &[&[_]] = &[
+        b"position",
+        pool_info.key.as_ref(),
+        owner_info.key.as_ref(),
+        &tick_lower.to_le_bytes(),
+        &tick_upper.to_le_bytes(),
+        &[position_bump],
+    ];
+
+    let rent = Rent::get()?;
+    invoke_signed(
+        &system_instruction::create_account(
+            owner_info.key,
+            position_info.key,
+            rent.minimum_balance(Position::LEN),
+            Position::LEN as u64,
+            program_id,
+        ),
+        &[
+            owner_info.clone(),
+            position_info.clone(),
+            system_program_info.clone(),
+        ],
+        &[position_signer_seeds],
+    )?;
+
+    let position = Position {
+        pool: *pool_info.key,
+        owner: *owner_info.key,
+        tick_lower,
+        tick_upper,
+        liquidity: 0,
+        fee_growth_inside_last_a: 0,
+        fee_growth_inside_last_b: 0,
+        tokens_owed_a: 0,
+        tokens_owed_b: 0,
+        bump: position_bump,
+    };
+    Position::pack(position, &mut position_info.data.borrow_mut())?;
+
+    Ok(())
+}
+
+// Add liquidity to a position, paying whatever token amounts its range needs.
+// Accounts: [pool, position, token_a, token_b, vault_a, vault_b, user_token_a, user_token_b,
+// owner, token_program]
+fn process_increase_liquidity(
+    program_id: &Pubkey,
+    accounts: &[AccountInfo],
+    liquidity: u128,
+    maximum_amount_a: u64,
+    maximum_amount_b: u64,
+) -> ProgramResult {
+    let account_info_iter = &mut accounts.iter();
+    let pool_info = next_account_info(account_info_iter)?;
+    let position_info = next_account_info(account_info_iter)?;
+    let token_a_info = next_account_info(account_info_iter)?;
+    let token_b_info = next_account_info(account_info_iter)?;
+    let vault_a_info = next_account_info(account_info_iter)?;
+    let vault_b_info = next_account_info(account_info_iter)?;
+    let user_token_a_info = next_account_info(account_info_iter)?;
+    let user_token_b_info = next_account_info(account_info_iter)?;
+    let owner_info = next_account_info(account_info_iter)?;
+    let _token_program_info = next_account_info(account_info_iter)?;
+
+    let mut pool = load_concentrated_pool(program_id, pool_info)?;
+    let mut position = load_position(program_id, pool_info, position_info, owner_info)?;
+    check_concentrated_mints(&pool, token_a_info, token_b_info)?;
+
+    let liquidity_delta = i128::try_from(liquidity).map_err(|_| ProgramError::InvalidArgument)?;
+    let (amount_a, amount_b) = pool.modify_position(&mut position, liquidity_delta)?;
+    if amount_a > maximum_amount_a || amount_b > maximum_amount_b {
+        return Err(ProgramError::InsufficientFunds);
+    }
+
+    transfer_to_vault(
+        program_id,
+        pool_info.key,
+        token_a_info,
+        pool.vault_a_bump,
+        vault_a_info,
+        user_token_a_info,
+        owner_info,
+        accounts,
+        amount_a,
+    )?;
+    transfer_to_vault(
+        program_id,
+        pool_info.key,
+        token_b_info,
+        pool.vault_b_bump,
+        vault_b_info,
+        user_token_b_info,
+        owner_info,
+        accounts,
+        amount_b,
+    )?;
+
+    ConcentratedPool::pack(pool, &mut pool_info.data.borrow_mut())?;
+    Position::pack(position, &mut position_info.data.borrow_mut())?;
+
+    Ok(())
+}
+
+// Remove liquidity from a position and pay out the tokens behind it.
+// Accounts: [pool, position, token_a, token_b, vault_a, vault_b, user_token_a, user_token_b,
+// owner, token_program]
+fn process_decrease_liquidity(
+    program_id: &Pubkey,
+    accounts: &[AccountInfo],
+    liquidity: u128,
+    minimum_amount_a: u64,
+    minimum_amount_b: u64,
+) -> ProgramResult {
+    let account_info_iter = &mut accounts.iter();
+    let pool_info = next_account_info(account_info_iter)?;
+    let position_info = next_account_info(account_info_iter)?;
+    let token_a_info = next_account_info(account_info_iter)?;
+    let token_b_info = next_account_info(account_info_iter)?;
+    let vault_a_info = next_account_info(account_info_iter)?;
+    let vault_b_info = next_account_info(account_info_iter)?;
+    let user_token_a_info = next_account_info(account_info_iter)?;
+    let user_token_b_info = next_account_info(account_info_iter)?;
+    let owner_info = next_account_info(account_info_iter)?;
+    let _token_program_info = next_account_info(account_info_iter)?;
+
+    let mut pool = load_concentrated_pool(program_id, pool_info)?;
+    let mut position = load_position(program_id, pool_info, position_info, owner_info)?;
+    check_concentrated_mints(&pool, token_a_info, token_b_info)?;
+
+    let liquidity_delta = i128::try_from(liquidity)
+        .map_err(|_| ProgramError::InvalidArgument)?
+        .checked_neg()
+        .ok_or(ProgramError::InvalidArgument)?;
+    let (amount_a, amount_b) = pool.modify_position(&mut position, liquidity_delta)?;
+    if amount_a < minimum_amount_a || amount_b < minimum_amount_b {
+        return Err(ProgramError::InsufficientFunds);
+    }
+
+    if amount_a > 0 {
+        transfer_from_vault(
+            program_id,
+            pool_info.key,
+            token_a_info,
+            pool.vault_a_bump,
+            vault_a_info,
+            user_token_a_info,
+            accounts,
+            amount_a,
+        )?;
+    }
+    if amount_b > 0 {
+        transfer_from_vault(
+            program_id,
+            pool_info.key,
+            token_b_info,
+            pool.vault_b_bump,
+            vault_b_info,
+            user_token_b_info,
+            accounts,
+            amount_b,
+        )?;
+    }
+
+    ConcentratedPool::pack(pool, &mut pool_info.data.borrow_mut())?;
+    Position::pack(position, &mut position_info.data.borrow_mut())?;
+
+    Ok(())
+}
+
+// Pay out the swap fees a position has earned.
+// Accounts: [pool, position, token_a, token_b, vault_a, vault_b, user_token_a, user_token_b,
+// owner, token_program]
+fn process_collect_fees(
+    program_id: &Pubkey,
+    accounts: &[AccountInfo],
+) -> ProgramResult {
+    let account_info_iter = &mut accounts.iter();
+    let pool_info = next_account_info(account_info_iter)?;
+    let position_info = next_account_info(account_info_iter)?;
+    let token_a_info = next_account_info(account_info_iter)?;
+    let token_b_info = next_account_info(account_info_iter)?;
+    let vault_a_info = next_account_info(account_info_iter)?;
+    let vault_b_info = next_account_info(account_info_iter)?;
+    let user_token_a_info = next_account_info(account_info_iter)?;
+    let user_token_b_info = next_account_info(account_info_iter)?;
+    let owner_info = next_account_info(account_info_iter)?;
+    let _token_program_info = next_account_info(account_info_iter)?;
+
+    let pool = load_concentrated_pool(program_id, pool_info)?;
+    let mut position = load_position(program_id, pool_info, position_info, owner_info)?;
+    check_concentrated_mints(&pool, token_a_info, token_b_info)?;
+
+    pool.update_fees(&mut position)?;
+
+    if position.tokens_owed_a > 0 {
+        transfer_from_vault(
+            program_id,
+            pool_info.key,
+            token_a_info,
+            pool.vault_a_bump,
+            vault_a_info,
+            user_token_a_info,
+            accounts,
+            position.tokens_owed_a,
+        )?;
+    }
+    if position.tokens_owed_b > 0 {
+        transfer_from_vault(
+            program_id,
+            pool_info.key,
+            token_b_info,
+            pool.vault_b_bump,
+            vault_b_info,
+            user_token_b_info,
+            accounts,
+            position.tokens_owed_b,
+        )?;
+    }
+
+    solana_program::log::sol_log(&format!(
+        "Collected fees: {} A, {} B",
+        position.tokens_owed_a, position.tokens_owed_b
+    ));
+    position.tokens_owed_a = 0;
+    position.tokens_owed_b = 0;
+    Position::pack(position, &mut position_info.data.borrow_mut())?;
+
+    Ok(())
+}
+
+// Swap through a concentrated liquidity pool. Input the active ranges cannot
+// absorb stays with the user.
+// Accounts: [pool, token_a, token_b, vault_a, vault_b, user_in, user_out, user, token_program]
+fn process_concentrated_swap(
+    program_id: &Pubkey,
+    accounts: &[AccountInfo],
+    amount_in: u64,
+    minimum_amount_out: u64,
+    direction_a_to_b: bool,
+) -> ProgramResult {
+    let account_info_iter = &mut accounts.iter();
+    let pool_info = next_account_info(account_info_iter)?;
+    let token_a_info = next_account_info(account_info_iter)?;
+    let token_b_info = next_account_info(account_info_iter)?;
+    let vault_a_info = next_account_info(account_info_iter)?;
+    let vault_b_info = next_account_info(account_info_iter)?;
+    let user_in_info = next_account_info(account_info_iter)?;
+    let user_out_info = next_account_info(account_info_iter)?;
+    let user_info = next_account_info(account_info_iter)?;
+    let _token_program_info = next_account_info(account_info_iter)?;
+
+    let mut pool = load_concentrated_pool(program_id, pool_info)?;
+    check_concentrated_mints(&pool, token_a_info, token_b_info)?;
+
+    let (amount_used, amount_out, fee) = pool.swap(amount_in, direction_a_to_b)?;
+    if amount_out == 0 {
+        return Err(ProgramError::InvalidArgument);
+    }
+
+    let (mint_in_info, vault_in_bump, vault_in_info, mint_out_info, vault_out_bump, vault_out_info) =
+        if direction_a_to_b {
+            (token_a_info, pool.vault_a_bump, vault_a_info, token_b_info, pool.vault_b_bump, vault_b_info)
+        } else {
+            (token_b_info, pool.vault_b_bump, vault_b_info, token_a_info, pool.vault_a_bump, vault_a_info)
+        };
+    transfer_to_vault(
+        program_id,
+        pool_info.key,
+        mint_in_info,
+        vault_in_bump,
+        vault_in_info,
+        user_in_info,
+        user_info,
+        accounts,
+        amount_used,
+    )?;
+    // The minimum bounds what the user received after any transfer fee
+    let output_received = transfer_from_vault(
+        program_id,
+        pool_info.key,
+        mint_out_info,
+        vault_out_bump,
+        vault_out_info,
+        user_out_info,
+        accounts,
+        amount_out,
+    )?;
+    if output_received < minimum_amount_out {
+        return Err(ProgramError::InsufficientFunds);
+    }
+
+    ConcentratedPool::pack(pool, &mut pool_info.data.borrow_mut())?;
+
+    // Liquidity is spread over ranges, so the reserves are the vault balances
+    let result = SwapResult {
+        amount_in: amount_used,
+        amount_out: output_received,
+        fee,
+        reserve_in: token_account_amount(vault_in_info)?,
+        reserve_out: token_account_amount(vault_out_info)?,
+    };
+    set_return_data(&result.try_to_vec().unwrap());
+
+    Ok(())
+}
+
+// The mints passed to a concentrated pool instruction must be the pool's,
+// as they move its tokens
+fn check_concentrated_mints(
+    pool: &ConcentratedPool,
+    token_a_info: &AccountInfo,
+    token_b_info: &AccountInfo,
+) -> ProgramResult {
+    if *token_a_info.key != pool.token_a || *token_b_info.key != pool.token_b {
+        return Err(ProgramError::InvalidArgument);
+    }
+    Ok(())
+}
+
+// Loads a concentrated liquidity pool owned by this program and checks its address
+fn load_concentrated_pool(
+    program_id: &Pubkey,
+    pool_info: &AccountInfo,
+) -> Result<ConcentratedPool, ProgramError> {
+    if pool_info.owner != program_id {
+        return Err(ProgramError::IncorrectProgramId);
+    }
+    let pool = ConcentratedPool::unpack(&pool_info.data.borrow())?;
+
+    let pool_pubkey = Pubkey::create_program_address(
+        &[b"cl_pool", pool.token_a.as_ref(), pool.token_b.as_ref(), &[pool.bump]],
+        program_id,
+    )?;
+    if pool_pubkey != *pool_info.key {
+        return Err(ProgramError::InvalidSeeds);
+    }
+
+    Ok(pool)
+}
+
+// Loads a position of `pool_info` and checks its owner signed
+fn load_position(
+    program_id: &Pubkey,
+    pool_info: &AccountInfo,
+    position_info: &AccountInfo,
+    owner_info: &AccountInfo,
+) -> Result<Position, ProgramError> {
+    if position_info.owner != program_id {
+        return Err(ProgramError::IncorrectProgramId);
+    }
+    let position = Position::unpack(&position_info.data.borrow())?;
+    if position.pool != *pool_info.key {
+        return Err(ProgramError::InvalidArgument);
+    }
+    if !owner_info.is_signer || *owner_info.key != position.owner {
+        return Err(ProgramError::MissingRequiredSignature);
+    }
+    Ok(position)
+}
+
+// Pay into a concentrated pool vault from a user account; nothing moves for a
+// zero amount. Ranges are priced on exact amounts, so a transfer fee withheld
+// on the way in fails with InvalidArgument.
+#[allow(clippy::too_many_arguments)]
+fn transfer_to_vault<'a>(
+    program_id: &Pubkey,
+    pool: &Pubkey,
+    mint_info: &AccountInfo<'a>,
+    vault_bump: u8,
+    vault_info: &AccountInfo<'a>,
+    source_info: &AccountInfo<'a>,
+    user_info: &AccountInfo<'a>,
+    accounts: &[AccountInfo<'a>],
+    amount: u64,
+) -> ProgramResult {
+    verify_vault(program_id, pool, mint_info.key, vault_bump, vault_info)?;
+    if amount == 0 {
+        return Ok(());
+    }
+
+    let received = transfer_checked(
+        mint_token_program(mint_info, accounts)?,
+        source_info,
+        mint_info,
+        vault_info,
+        user_info,
+        amount,
+        &[],
+    )?;
+    if received != amount {
+        return Err(ProgramError::InvalidArgument);
+    }
+    Ok(())
+}
+
+// Lend `amount` from a vault of a two-token or multi-asset pool for the
+// duration of a call into the receiver program. The fee is added to the reserve.
+// Accounts: [pool, mint, vault, receiver_token, receiver_program, token_program,
+// oracle if the pool has one, ...receiver accounts]
+fn process_flash_loan(
+    program_id: &Pubkey,
+    accounts: &[AccountInfo],
+    amount: u64,
+) -> ProgramResult {
+    let account_info_iter = &mut accounts.iter();
+    let pool_info = next_account_info(account_info_iter)?;
+    let mint_info = next_account_info(account_info_iter)?;
+    let vault_info = next_account_info(account_info_iter)?;
+    let receiver_token_info = next_account_info(account_info_iter)?;
+    let receiver_program_info = next_account_info(account_info_iter)?;
+    let token_program_info = next_account_info(account_info_iter)?;
+
+    if amount == 0 {
+        return Err(ProgramError::InvalidArgument);
+    }
+    // The mint's token program gets the vault's signature and reports its balance
+    if token_program_info.key != mint_info.owner {
+        return Err(ProgramError::IncorrectProgramId);
+    }
+
+    // The vault must be the pool's vault of the mint
+    let (vault_bump, reserve, oracle_info) = if pool_info.data_len() == MultiPool::LEN {
+        let pool = load_multi_pool(program_id, pool_info)?;
+        let index = pool.token_index(mint_info.key)?;
+        (pool.vault_bumps[index], pool.reserves[index], None)
+    } else {
+        let pool = load_hop_pool(program_id, pool_info)?;
+        let oracle_info = next_oracle_info(pool.has_oracle != 0, account_info_iter)?;
+        if *mint_info.key == pool.token_a {
+            (pool.vault_a_bump, pool.reserve_a, oracle_info)
+        } else if *mint_info.key == pool.token_b {
+            (pool.vault_b_bump, pool.reserve_b, oracle_info)
+        } else {
+            return Err(ProgramError::InvalidArgument);
+        }
+    };
+    let receiver_accounts = account_info_iter.as_slice();
+    if amount > reserve {
+        return Err(ProgramError::InsufficientFunds);
+    }
+    let fee = flash_loan::flash_loan_fee(amount)?;
+    let balance_before = token_account_amount(vault_info)?;
+
+    transfer_from_vault(
+        program_id,
+        pool_info.key,
+        mint_info,
+        vault_bump,
+        vault_info,
+        receiver_token_info,
+        accounts,
+        amount,
+    )?;
+
+    invoke_flash_receiver(
+        receiver_program_info,
+        &FlashReceiverInstruction::ReceiveFlashLoan { amount, fee },
+        &[
+            (receiver_token_info, true),
+            (mint_info, false),
+            (vault_info, true),
+            (token_program_info, false),
+        ],
+        receiver_accounts,
+    )?;
+
+    // The loan and at least the fee must be back in the vault
+    let balance_after = token_account_amount(vault_info)?;
+    let repaid = balance_after
+        .checked_sub(balance_before)
+        .filter(|&surplus| surplus >= fee)
+        .ok_or(ProgramError::InsufficientFunds)?;
+
+    if pool_info.data_len() == MultiPool::LEN {
+        let mut pool = MultiPool::unpack(&pool_info.data.borrow())?;
+        let index = pool.token_index(mint_info.key)?;
+        pool.reserves[index] = pool.reserves[index]
+            .checked_add(repaid)
+            .ok_or(ProgramError::InvalidArgument)?;
+        MultiPool::pack(pool, &mut pool_info.data.borrow_mut())?;
+    } else {
+        let mut pool = load_hop_pool(program_id, pool_info)?;
+        let now = Clock::get()?.unix_timestamp;
+        pool.update_prices(now)?;
+        record_observation(program_id, pool_info.key, &pool, oracle_info, now)?;
+        if *mint_info.key == pool.token_a {
+            pool.reserve_a = pool.reserve_a.checked_add(repaid).ok_or(ProgramError::InvalidArgument)?;
+        } else {
+            pool.reserve_b = pool.reserve_b.checked_add(repaid).ok_or(ProgramError::InvalidArgument)?;
+        }
+    }
+
+    solana_program::log::sol_log(&format!("Flash loan: {} lent, {} earned", amount, repaid));
+
+    Ok(())
+}
+
+// Borrow pool tokens, pay them back in either token, all inside one call into
+// the receiver program. Output is sent first; afterwards the vault balances,
+// less the swap fee on what came in, must keep the curve invariant.
+// Accounts: [pool, token_a, token_b, vault_a, vault_b, user_token_a, user_token_b,
+// receiver_program, token_a_program, token_b_program, oracle if the pool has
+// one, ...receiver accounts]
+fn process_flash_swap(
+    program_id: &Pubkey,
+    accounts: &[AccountInfo],
+    amount_a_out: u64,
+    amount_b_out: u64,
+) -> ProgramResult {
+    let account_info_iter = &mut accounts.iter();
+    let pool_info = next_account_info(account_info_iter)?;
+    let token_a_info = next_account_info(account_info_iter)?;
+    let token_b_info = next_account_info(account_info_iter)?;
+    let vault_a_info = next_account_info(account_info_iter)?;
+    let vault_b_info = next_account_info(account_info_iter)?;
+    let user_token_a_info = next_account_info(account_info_iter)?;
+    let user_token_b_info = next_account_info(account_info_iter)?;
+    let receiver_program_info = next_account_info(account_info_iter)?;
+    let token_a_program_info = next_account_info(account_info_iter)?;
+    let token_b_program_info = next_account_info(account_info_iter)?;
+
+    if amount_a_out == 0 && amount_b_out == 0 {
+        return Err(ProgramError::InvalidArgument);
+    }
+    // Each mint's token program gets its vault's signature and reports its balance
+    if token_a_program_info.key != token_a_info.owner || token_b_program_info.key != token_b_info.owner {
+        return Err(ProgramError::IncorrectProgramId);
+    }
+
+    // A copy, since the receiver gets the pool account while the vaults are out
+    let pool = *load_hop_pool(program_id, pool_info)?;
+    let oracle_info = next_oracle_info(pool.has_oracle != 0, account_info_iter)?;
+    let receiver_accounts = account_info_iter.as_slice();
+    if *token_a_info.key != pool.token_a || *token_b_info.key != pool.token_b {
+        return Err(ProgramError::InvalidArgument);
+    }
+    verify_vaults(program_id, &pool, pool_info.key, vault_a_info, vault_b_info)?;
+    if amount_a_out >= pool.reserve_a || amount_b_out >= pool.reserve_b {
+        return Err(ProgramError::InsufficientFunds);
+    }
+    let balance_a_before = token_account_amount(vault_a_info)?;
+    let balance_b_before = token_account_amount(vault_b_info)?;
+
+    if amount_a_out > 0 {
+        transfer_from_vault(
+            program_id,
+            pool_info.key,
+            token_a_info,
+            pool.vault_a_bump,
+            vault_a_info,
+            user_token_a_info,
+            accounts,
+            amount_a_out,
+        )?;
+    }
+    if amount_b_out > 0 {
+        transfer_from_vault(
+            program_id,
+            pool_info.key,
+            token_b_info,
+            pool.vault_b_bump,
+            vault_b_info,
+            user_token_b_info,
+            accounts,
+            amount_b_out,
+        )?;
+    }
+
+    invoke_flash_receiver(
+        receiver_program_info,
+        &FlashReceiverInstruction::ReceiveFlashSwap { amount_a_out, amount_b_out },
+        &[
+            (pool_info, false),
+            (token_a_info, false),
+            (token_b_info, false),
+            (vault_a_info, true),
+            (vault_b_info, true),
+            (token_a_program_info, false),
+            (token_b_program_info, false),
+        ],
+        receiver_accounts,
+    )?;
+
+    // Whatever the vaults hold beyond what was left after the payout is input
+    let balance_a_after = token_account_amount(vault_a_info)?;
+    let balance_b_after = token_account_amount(vault_b_info)?;
+    let amount_a_in = balance_a_after.saturating_sub(balance_a_before - amount_a_out);
+    let amount_b_in = balance_b_after.saturating_sub(balance_b_before - amount_b_out);
+    if amount_a_in == 0 && amount_b_in == 0 {
+        return Err(ProgramError::InsufficientFunds);
+    }
+
+    let reserve_a = (pool.reserve_a - amount_a_out)
+        .checked_add(amount_a_in)
+        .ok_or(ProgramError::InvalidArgument)?;
+    let reserve_b = (pool.reserve_b - amount_b_out)
+        .checked_add(amount_b_in)
+        .ok_or(ProgramError::InvalidArgument)?;
+
+    // The fee the pool's curve keeps on the input stays in the pool but does
+    // not count towards the invariant
+    let curve = pool.curve()?;
+    let (fee_a, fee_b) = (curve.swap_fee(amount_a_in), curve.swap_fee(amount_b_in));
+    let invariant_before = curve.invariant(pool.reserve_a, pool.reserve_b)?;
+    let invariant_after = curve.invariant(reserve_a - fee_a, reserve_b - fee_b)?;
+    if invariant_after < invariant_before {
+        return Err(ProgramError::InsufficientFunds);
+    }
+
+    // Reported as a swap of the token whose reserve grew for the other, by
+    // the net change of each reserve, with the fee charged on the input side
+    let result = if reserve_a > pool.reserve_a {
+        SwapResult {
+            amount_in: reserve_a - pool.reserve_a,
+            amount_out: pool.reserve_b.saturating_sub(reserve_b),
+            fee: fee_a,
+            reserve_in: reserve_a,
+            reserve_out: reserve_b,
+        }
+    } else {
+        SwapResult {
+            amount_in: reserve_b.saturating_sub(pool.reserve_b),
+            amount_out: pool.reserve_a.saturating_sub(reserve_a),
+            fee: fee_b,
+            reserve_in: reserve_b,
+            reserve_out: reserve_a,
+        }
+    };
+
+    let mut pool = load_hop_pool(program_id, pool_info)?;
+    let now = Clock::get()?.unix_timestamp;
+    pool.update_prices(now)?;
+    record_observation(program_id, pool_info.key, &pool, oracle_info, now)?;
+    pool.reserve_a = reserve_a;
+    pool.reserve_b = reserve_b;
+
+    solana_program::log::sol_log(&format!(
+        "Flash swap: out {} A, {} B; in {} A, {} B",
+        amount_a_out, amount_b_out, amount_a_in, amount_b_in
+    ));
+    set_return_data(&result.try_to_vec().unwrap());
+
+    Ok(())
+}
+
+// Run an inner instruction with a temporary wrapped GOR account at
+// [b"native", user], which the inner accounts use as the user's GOR token
+// account. `wrap_amount` lamports are wrapped into it first; afterwards it is
+// closed to the user, unwrapping whatever it holds, native output included.
+// Accounts: [user, wrap_account, native_mint, system_program, token_program, ...inner instruction accounts]
+fn process_with_native(
+    program_id: &Pubkey,
+    accounts: &[AccountInfo],
+    wrap_amount: u64,
+    instruction_data: &[u8],
+) -> ProgramResult {
+    let account_info_iter = &mut accounts.iter();
+    let user_info = next_account_info(account_info_iter)?;
+    let wrap_info = next_account_info(account_info_iter)?;
+    let native_mint_info = next_account_info(account_info_iter)?;
+    let system_program_info = next_account_info(account_info_iter)?;
+    let token_program_info = next_account_info(account_info_iter)?;
+    let inner_accounts = account_info_iter.as_slice();
+
+    if !user_info.is_signer {
+        return Err(ProgramError::MissingRequiredSignature);
+    }
+    if *native_mint_info.key != NATIVE_MINT {
+        return Err(ProgramError::InvalidArgument);
+    }
+    if *token_program_info.key != GORBCHAIN_SPL_TOKEN_PROGRAM {
+        return Err(ProgramError::IncorrectProgramId);
+    }
+
+    let instruction = TestProjectInstruction::try_from_slice(instruction_data)
+        .map_err(|_| ProgramError::InvalidInstructionData)?;
+    if !matches!(
+        instruction,
+        TestProjectInstruction::InitPool { .. }
+            | TestProjectInstruction::InitPoolWithCurve { .. }
+            | TestProjectInstruction::AddLiquidity { .. }
+            | TestProjectInstruction::RemoveLiquidity { .. }
+            | TestProjectInstruction::Swap { .. }
+            | TestProjectInstruction::MultihopSwap { .. }
+            | TestProjectInstruction::MultihopSwapWithPath { .. }
+            | TestProjectInstruction::MultihopSwapWithHopMinimums { .. }
+            | TestProjectInstruction::MultihopSwapWithPathAndHopMinimums { .. }
+            | TestProjectInstruction::CompactMultihopSwap { .. }
+            | TestProjectInstruction::SplitRouteSwap { .. }
+    ) {
+        return Err(ProgramError::InvalidInstructionData);
+    }
+
+    let (wrap_pubkey, wrap_bump) =
+        Pubkey::find_program_address(&[b"native", user_info.key.as_ref()], program_id);
+    if wrap_pubkey != *wrap_info.key {
+        return Err(ProgramError::InvalidSeeds);
+    }
+    let wrap_signer_seeds: &[&[_]] = &[b"native", user_info.key.as_ref(), &[wrap_bump]];
+
+    let rent = Rent::get()?;
+    let lamports = rent
+        .minimum_balance(spl_token::state::Account::LEN)
+        .checked_add(wrap_amount)
+        .ok_or(ProgramError::InvalidArgument)?;
+    let token_program_id = GORBCHAIN_SPL_TOKEN_PROGRAM;
+    let system_accounts = [
+        user_info.clone(),
+        wrap_info.clone(),
+        system_program_info.clone(),
+    ];
+    if wrap_info.lamports() == 0 {
+        invoke_signed(
+            &system_instruction::create_account(
                 user_info.key,
-                current_amount,
+                wrap_info.key,
+                lamports,
+                spl_token::state::Account::LEN as u64,
+                &token_program_id,
             ),
+            &system_accounts,
+            &[wrap_signer_seeds],
+        )?;
+    } else {
+        // Lamports sent to the address beforehand would make create_account fail;
+        // they simply end up wrapped and returned to the user
+        invoke(
+            &system_instruction::transfer(user_info.key, wrap_info.key, lamports),
+            &system_accounts,
+        )?;
+        invoke_signed(
+            &system_instruction::allocate(wrap_info.key, spl_token::state::Account::LEN as u64),
+            &system_accounts,
+            &[wrap_signer_seeds],
+        )?;
+        invoke_signed(
+            &system_instruction::assign(wrap_info.key, &token_program_id),
+            &system_accounts,
+            &[wrap_signer_seeds],
+        )?;
+    }
+    invoke(
+        &create_initialize_account3_instruction(wrap_info.key, native_mint_info.key, user_info.key),
+        &[
+            wrap_info.clone(),
+            native_mint_info.clone(),
+            token_program_info.clone(),
+        ],
+    )?;
+
+    process(program_id, inner_accounts, instruction)?;
+    // The close below clears the inner swap's return data, so it is set again after
+    let return_data = get_return_data().filter(|(program, _)| program == program_id);
+
+    invoke(
+        &create_close_account_instruction(wrap_info.key, user_info.key, user_info.key),
+        &[
+            wrap_info.clone(),
+            user_info.clone(),
+            token_program_info.clone(),
+        ],
+    )?;
+    if let Some((_, data)) = return_data {
+        set_return_data(&data);
+    }
+    Ok(())
+}
+
+// Realloc a legacy pool to the versioned layout, with the payer covering the
+// extra rent, and store its PDA bumps. Pools already on the current layout
+// are left as they are.
+fn process_migrate_pool(program_id: &Pubkey, accounts: &[AccountInfo]) -> ProgramResult {
+    let account_info_iter = &mut accounts.iter();
+    let pool_info = next_account_info(account_info_iter)?;
+    let payer_info = next_account_info(account_info_iter)?;
+    let system_program_info = next_account_info(account_info_iter)?;
+
+    if !payer_info.is_signer {
+        return Err(ProgramError::MissingRequiredSignature);
+    }
+    let mut pool = PoolState::from(&load_pool(program_id, pool_info)?);
+    if pool_info.data_len() == Pool::LEN {
+        return Ok(());
+    }
+    // Legacy pools predate the stored bumps, so they are searched for once here
+    pool.derive_bumps(program_id, pool_info.key);
+
+    let rent = Rent::get()?;
+    let lamports = rent
+        .minimum_balance(Pool::LEN)
+        .saturating_sub(pool_info.lamports());
+    if lamports > 0 {
+        invoke(
+            &system_instruction::transfer(payer_info.key, pool_info.key, lamports),
             &[
-                current_input_account.clone(),
-                vault_in.clone(),
-                user_info.clone(),
-                token_program_info.clone(),
+                payer_info.clone(),
+                pool_info.clone(),
+                system_program_info.clone(),
             ],
         )?;
-        
-        // Calculate output amount
-        let amount_out = calculate_swap_output(current_amount, reserve_in, reserve_out)?;
-        
-        // Use final output account for last hop, intermediate for others
-        let target_output_account = if hop == num_hops - 1 {
-            output_account
-        } else {
-            intermediate_account
-        };
-        
-        // Transfer output tokens using vault PDA as authority
-        if direction_a_to_b {
-            let vault_b_signer_seeds: &[&[_]] = &[
-                b"vault",
-                pool_info.key.as_ref(),
-                pool.token_b.as_ref(),
-                &[vault_b_bump],
-            ];
-            
-            invoke_signed(
-                &create_transfer_instruction(
-                    vault_b_info.key,
-                    target_output_account.key,
-                    vault_b_info.key,
-                    amount_out,
-                ),
-                &[
-                    vault_b_info.clone(),
-                    target_output_account.clone(),
-                    vault_b_info.clone(),
-                    token_program_info.clone(),
-                ],
-                &[vault_b_signer_seeds],
-            )?;
-        } else {
-            let vault_a_signer_seeds: &[&[_]] = &[
-                b"vault",
-                pool_info.key.as_ref(),
-                pool.token_a.as_ref(),
-                &[vault_a_bump],
-            ];
-            
-            invoke_signed(
-                &create_transfer_instruction(
-                    vault_a_info.key,
-                    target_output_account.key,
-                    vault_a_info.key,
-                    amount_out,
-                ),
-                &[
-                    vault_a_info.clone(),
-                    target_output_account.clone(),
-                    vault_a_info.clone(),
-                    token_program_info.clone(),
-                ],
-                &[vault_a_signer_seeds],
-            )?;
-        }
-        
-        // Update pool reserves
-        if direction_a_to_b {
-            pool.reserve_a = pool.reserve_a.checked_add(current_amount).unwrap();
-            pool.reserve_b = pool.reserve_b.checked_sub(amount_out).unwrap();
-        } else {
-            pool.reserve_b = pool.reserve_b.checked_add(current_amount).unwrap();
-            pool.reserve_a = pool.reserve_a.checked_sub(amount_out).unwrap();
-        }
-        
-        Pool::pack(pool, &mut pool_info.data.borrow_mut())?;
-        
-        // Set up for next hop
-        current_amount = amount_out;
-        current_input_account = target_output_account;
-    }
-    
-    // Ensure final output is above minimum
-    if current_amount < minimum_amount_out {
-        return Err(ProgramError::InsufficientFunds);
     }
-    
+    pool_info.realloc(Pool::LEN, true)?;
+    pool.write_fields(&mut pool_info.data.borrow_mut());
     Ok(())
 }
 
+// Call the receiver program of a flash loan or flash swap. `accounts` are
+// passed as (account, writable) ahead of the receiver's own accounts.
+fn invoke_flash_receiver<'a>(
+    receiver_program_info: &AccountInfo<'a>,
+    data: &FlashReceiverInstruction,
+    accounts: &[(&AccountInfo<'a>, bool)],
+    receiver_accounts: &[AccountInfo<'a>],
+) -> ProgramResult {
+    let metas = accounts
+        .iter()
+        .map(|(account, writable)| {
+            if *writable {
+                solana_program::instruction::AccountMeta::new(*account.key, false)
+            } else {
+                solana_program::instruction::AccountMeta::new_readonly(*account.key, false)
+            }
+        })
+        .collect();
+    let instruction = flash_loan::receiver_instruction(
+        receiver_program_info.key,
+        data,
+        metas,
+        receiver_accounts,
+    )?;
+
+    let mut account_infos: Vec<AccountInfo<'a>> =
+        accounts.iter().map(|(account, _)| (*account).clone()).collect();
+    account_infos.extend(receiver_accounts.iter().cloned());
+    account_infos.push(receiver_program_info.clone());
+    invoke(&instruction, &account_infos)
+}
+
+// Loads a two-token pool owned by this program and checks its address
+fn load_pool(program_id: &Pubkey, pool_info: &AccountInfo) -> Result<Pool, ProgramError> {
+    if pool_info.owner != program_id {
+        return Err(ProgramError::IncorrectProgramId);
+    }
+    let pool = Pool::unpack(&pool_info.data.borrow())?;
+
+    let pool_pubkey = Pubkey::create_program_address(
+        &[b"pool", pool.token_a.as_ref(), pool.token_b.as_ref(), &[pool.bump]],
+        program_id,
+    )?;
+    if pool_pubkey != *pool_info.key {
+        return Err(ProgramError::InvalidSeeds);
+    }
+
+    Ok(pool)
+}
+
 // Helper function to calculate output amount for a single swap
-fn calculate_swap_output(
+pub fn calculate_swap_output(
     amount_in: u64,
     reserve_in: u64,
     reserve_out: u64,
@@ -1426,11 +4046,78 @@ fn calculate_swap_output(
     Ok((numerator / denominator) as u64)
 }
 
-// Helper function to calculate the expected output amount for a multihop swap
-// Removed unused functions: calculate_multihop_output and determine_swap_direction
+// Helper function to trim a deposit to the current pool ratio.
+// Returns the amounts actually taken from the user; the side that would push
+// the pool off its ratio is reduced, the other is used in full.
+pub fn calculate_deposit_amounts(
+    amount_a: u64,
+    amount_b: u64,
+    reserve_a: u64,
+    reserve_b: u64,
+) -> Result<(u64, u64), ProgramError> {
+    if reserve_a == 0 || reserve_b == 0 {
+        return Ok((amount_a, amount_b));
+    }
+
+    let required_b = (amount_a as u128)
+        .checked_mul(reserve_b as u128).ok_or(ProgramError::InvalidArgument)?
+        .checked_div(reserve_a as u128).ok_or(ProgramError::InvalidArgument)?;
+    if required_b <= amount_b as u128 {
+        return Ok((amount_a, required_b as u64));
+    }
+
+    let required_a = (amount_b as u128)
+        .checked_mul(reserve_a as u128).ok_or(ProgramError::InvalidArgument)?
+        .checked_div(reserve_b as u128).ok_or(ProgramError::InvalidArgument)?;
+    // required_b > amount_b implies required_a < amount_a, so this fits in u64
+    Ok((required_a as u64, amount_b))
+}
+
+// Helper function to calculate LP tokens minted for a ratio-matched deposit.
+// Takes the smaller of the two proportional shares so rounding in
+// calculate_deposit_amounts can never mint LP for tokens that were not deposited.
+pub fn calculate_lp_tokens_to_mint(
+    amount_a: u64,
+    amount_b: u64,
+    reserve_a: u64,
+    reserve_b: u64,
+    supply: u64,
+) -> Result<u64, ProgramError> {
+    if supply == 0 || reserve_a == 0 || reserve_b == 0 {
+        let liquidity = (amount_a as u128)
+            .checked_mul(amount_b as u128).ok_or(ProgramError::InvalidArgument)?
+            .integer_sqrt();
+        return u64::try_from(liquidity).map_err(|_| ProgramError::InvalidArgument);
+    }
+
+    let share_a = (amount_a as u128)
+        .checked_mul(supply as u128).ok_or(ProgramError::InvalidArgument)?
+        / reserve_a as u128;
+    let share_b = (amount_b as u128)
+        .checked_mul(supply as u128).ok_or(ProgramError::InvalidArgument)?
+        / reserve_b as u128;
+    u64::try_from(share_a.min(share_b)).map_err(|_| ProgramError::InvalidArgument)
+}
+
+// Helper function to calculate the tokens returned for burning LP tokens
+pub fn calculate_withdraw_amounts(
+    lp_amount: u64,
+    reserve_a: u64,
+    reserve_b: u64,
+    supply: u64,
+) -> Result<(u64, u64), ProgramError> {
+    if supply == 0 || lp_amount > supply {
+        return Err(ProgramError::InvalidArgument);
+    }
+
+    // lp_amount <= supply keeps both results within the reserves
+    let amount_a = (lp_amount as u128) * (reserve_a as u128) / (supply as u128);
+    let amount_b = (lp_amount as u128) * (reserve_b as u128) / (supply as u128);
+    Ok((amount_a as u64, amount_b as u64))
+}
 
 // Integer square root implementation for u128
-trait IntegerSqrt {
+pub trait IntegerSqrt {
     fn integer_sqrt(self) -> Self;
 }
 
@@ -1440,7 +4127,8 @@ impl IntegerSqrt for u128 {
             return self;
         }
         let mut x = self;
-        let mut y = (self + 1) / 2;
+        // (self + 1) / 2 without overflowing at u128::MAX
+        let mut y = self / 2 + (self & 1);
         while y < x {
             x = y;
             y = (x + self / x) / 2;
@@ -1460,11 +4148,18 @@ fn process_get_pool_info(
     // The pool account is already provided, no need to verify seeds
     // Just check if it's a valid pool account by trying to deserialize it
     
+    if pool_info.data_len() == MultiPool::LEN {
+        return log_multi_pool_info(pool_info);
+    }
+    if pool_info.data_len() == ConcentratedPool::LEN {
+        return log_concentrated_pool_info(pool_info);
+    }
+    
     // Deserialize pool data
     let pool = Pool::unpack(&pool_info.data.borrow())?;
     
     // Log pool information
-    solana_program::log::sol_log(&format!("Pool Info:"));
+    solana_program::log::sol_log("Pool Info:");
     solana_program::log::sol_log(&format!("  Pool PDA: {}", pool_info.key));
     solana_program::log::sol_log(&format!("  Token A: {}", pool.token_a));
     solana_program::log::sol_log(&format!("  Token B: {}", pool.token_b));
@@ -1472,6 +4167,40 @@ fn process_get_pool_info(
     solana_program::log::sol_log(&format!("  Reserve B: {}", pool.reserve_b));
     solana_program::log::sol_log(&format!("  Total LP Supply: {}", pool.total_lp_supply));
     solana_program::log::sol_log(&format!("  Bump: {}", pool.bump));
+    // Legacy pools report version 0 until migrated
+    let version = if pool_info.data_len() == Pool::LEN { pool_info.data.borrow()[0] } else { 0 };
+    solana_program::log::sol_log(&format!("  Version: {}", version));
+    solana_program::log::sol_log(&format!("  Curve: {:?}", pool.curve_type));
+    match pool.curve_type {
+        CurveType::Stable => {
+            let params = pool.stable_params()?;
+            let amp = params.current_amp(Clock::get()?.unix_timestamp);
+            solana_program::log::sol_log(&format!("  Amplification: {}", amp));
+            solana_program::log::sol_log(&format!("  Target Amplification: {}", params.target_amp));
+            solana_program::log::sol_log(&format!("  Ramp Stop: {}", params.ramp_stop_ts));
+        }
+        CurveType::ConstantPrice => {
+            let params = curve::unpack_curve_params::<ConstantPriceCurve>(&pool.curve_params)?;
+            solana_program::log::sol_log(&format!("  Token B Price: {}", params.token_b_price));
+        }
+        CurveType::Offset => {
+            let params = curve::unpack_curve_params::<OffsetCurve>(&pool.curve_params)?;
+            solana_program::log::sol_log(&format!("  Token A Offset: {}", params.token_a_offset));
+            solana_program::log::sol_log(&format!("  Token B Offset: {}", params.token_b_offset));
+        }
+        CurveType::Weighted => {
+            let params = curve::unpack_curve_params::<WeightedCurve>(&pool.curve_params)?;
+            solana_program::log::sol_log(&format!(
+                "  Weights: {}/{}",
+                params.weight_a, params.weight_b
+            ));
+        }
+        CurveType::ConstantProduct => {}
+    }
+    if let Ok(price) = pool.spot_price() {
+        let price = price as f64 / curve::PRICE_SCALE as f64;
+        solana_program::log::sol_log(&format!("  Spot Price A->B: {:.6}", price));
+    }
     
     // Calculate ratio
     if pool.reserve_b > 0 {
@@ -1486,6 +4215,38 @@ fn process_get_pool_info(
     Ok(())
 }
 
+fn log_multi_pool_info(pool_info: &AccountInfo) -> ProgramResult {
+    let pool = MultiPool::unpack(&pool_info.data.borrow())?;
+    
+    solana_program::log::sol_log("Multi-Asset Pool Info:");
+    solana_program::log::sol_log(&format!("  Pool PDA: {}", pool_info.key));
+    for (index, (token, reserve)) in pool.tokens().iter().zip(pool.reserves()).enumerate() {
+        solana_program::log::sol_log(&format!("  Token {}: {}", index, token));
+        solana_program::log::sol_log(&format!("  Reserve {}: {}", index, reserve));
+    }
+    solana_program::log::sol_log(&format!("  Total LP Supply: {}", pool.total_lp_supply));
+    solana_program::log::sol_log(&format!("  Bump: {}", pool.bump));
+    
+    Ok(())
+}
+
+fn log_concentrated_pool_info(pool_info: &AccountInfo) -> ProgramResult {
+    let pool = ConcentratedPool::unpack(&pool_info.data.borrow())?;
+    
+    solana_program::log::sol_log("Concentrated Pool Info:");
+    solana_program::log::sol_log(&format!("  Pool PDA: {}", pool_info.key));
+    solana_program::log::sol_log(&format!("  Token A: {}", pool.token_a));
+    solana_program::log::sol_log(&format!("  Token B: {}", pool.token_b));
+    solana_program::log::sol_log(&format!("  Tick Spacing: {}", pool.tick_spacing));
+    solana_program::log::sol_log(&format!("  Current Tick: {}", pool.current_tick));
+    solana_program::log::sol_log(&format!("  Active Liquidity: {}", pool.liquidity));
+    solana_program::log::sol_log(&format!("  Initialized Ticks: {}", pool.ticks.len()));
+    let sqrt_price = pool.sqrt_price as f64 / curve::fixed_point::ONE as f64;
+    solana_program::log::sol_log(&format!("  Spot Price A->B: {:.6}", sqrt_price * sqrt_price));
+    
+    Ok(())
+}
+
 // Get total number of pools (placeholder - would need a global counter in production)
 fn process_get_total_pools(
     _program_id: &Pubkey,
@@ -1517,6 +4278,30 @@ fn process_find_pools_by_token(
     Ok(())
 }
 
+// Quoted output for one pool: exactly what Swap would pay out, fee included.
+// Nothing in or an empty pool quotes as zero.
+fn quote_output(pool: &Pool, amount_in: u64, direction_a_to_b: bool) -> Result<u64, ProgramError> {
+    if amount_in == 0 || pool.total_lp_supply == 0 {
+        return Ok(0);
+    }
+    pool.swap_output(amount_in, direction_a_to_b)
+}
+
+// Quoted output for one pair of a multi-asset pool, on the same terms as quote_output
+fn quote_multi_pool_output(
+    pool: &MultiPool,
+    amount_in: u64,
+    token_in: &Pubkey,
+    token_out: &Pubkey,
+) -> Result<u64, ProgramError> {
+    let index_in = pool.token_index(token_in)?;
+    let index_out = pool.token_index(token_out)?;
+    if amount_in == 0 || pool.total_lp_supply == 0 {
+        return Ok(0);
+    }
+    pool.swap_output(index_in, index_out, amount_in)
+}
+
 // Get swap quote - calculate output amount for a given input
 fn process_get_swap_quote(
     _program_id: &Pubkey,
@@ -1542,22 +4327,15 @@ fn process_get_swap_quote(
         return Err(ProgramError::InvalidArgument);
     };
     
-    // Calculate output using constant product formula
     let (reserve_in, reserve_out) = if direction_a_to_b {
         (pool.reserve_a, pool.reserve_b)
     } else {
         (pool.reserve_b, pool.reserve_a)
     };
-    
-    // Apply constant product formula: amount_out = (amount_in * reserve_out) / (reserve_in + amount_in)
-    let amount_out = if reserve_in == 0 || amount_in == 0 {
-        0
-    } else {
-        (amount_in * reserve_out) / (reserve_in + amount_in)
-    };
+    let amount_out = quote_output(&pool, amount_in, direction_a_to_b)?;
     
     // Log quote information
-    solana_program::log::sol_log(&format!("Swap Quote:"));
+    solana_program::log::sol_log("Swap Quote:");
     solana_program::log::sol_log(&format!("  Pool PDA: {}", pool_info.key));
     solana_program::log::sol_log(&format!("  Token In: {}", token_in));
     solana_program::log::sol_log(&format!("  Amount In: {}", amount_in));
@@ -1602,7 +4380,7 @@ fn process_get_multihop_quote(
     let account_info_iter = &mut accounts.iter();
     let mut current_amount = amount_in;
     
-    solana_program::log::sol_log(&format!("Multihop Quote:"));
+    solana_program::log::sol_log("Multihop Quote:");
     solana_program::log::sol_log(&format!("  Token Path: {:?}", token_path));
     solana_program::log::sol_log(&format!("  Amount In: {}", amount_in));
     
@@ -1613,28 +4391,24 @@ fn process_get_multihop_quote(
         
         // Get pool account for this hop
         let pool_info = next_account_info(account_info_iter)?;
-        let pool = Pool::unpack(&pool_info.data.borrow())?;
-        
-        // Determine swap direction
-        let direction_a_to_b = if token_in == pool.token_a {
-            true
-        } else if token_in == pool.token_b {
-            false
-        } else {
-            return Err(ProgramError::InvalidArgument);
-        };
         
         // Calculate output for this hop
-        let (reserve_in, reserve_out) = if direction_a_to_b {
-            (pool.reserve_a, pool.reserve_b)
-        } else {
-            (pool.reserve_b, pool.reserve_a)
-        };
-        
-        let hop_amount_out = if reserve_in == 0 || current_amount == 0 {
-            0
+        let hop_amount_out = if pool_info.data_len() == MultiPool::LEN {
+            let pool = MultiPool::unpack(&pool_info.data.borrow())?;
+            quote_multi_pool_output(&pool, current_amount, &token_in, &token_out)?
         } else {
-            (current_amount * reserve_out) / (reserve_in + current_amount)
+            let pool = Pool::unpack(&pool_info.data.borrow())?;
+            
+            // Determine swap direction
+            let direction_a_to_b = if token_in == pool.token_a {
+                true
+            } else if token_in == pool.token_b {
+                false
+            } else {
+                return Err(ProgramError::InvalidArgument);
+            };
+            
+            quote_output(&pool, current_amount, direction_a_to_b)?
         };
         
         solana_program::log::sol_log(&format!("  Hop {}: {} -> {}", hop + 1, token_in, token_out));
@@ -1658,3 +4432,253 @@ fn process_get_multihop_quote(
     
     Ok(())
 }
+
+// Time-weighted average prices from `since` until now. The current
+// observation is returned with them, to average from on the next call; the
+// first one can be read from the pool account with Pool::observe.
+// Accounts: [pool]
+fn process_get_twap(
+    program_id: &Pubkey,
+    accounts: &[AccountInfo],
+    since: PriceObservation,
+) -> ProgramResult {
+    let account_info_iter = &mut accounts.iter();
+    let pool_info = next_account_info(account_info_iter)?;
+
+    let pool = load_pool(program_id, pool_info)?;
+    let observation = pool.observe(Clock::get()?.unix_timestamp)?;
+    let (price_a, price_b) = oracle::twap(&since, &observation)?;
+
+    let result = TwapResult {
+        price_a,
+        price_b,
+        observation,
+    };
+    set_return_data(&result.try_to_vec().unwrap());
+
+    Ok(())
+}
+
+// Oracle account of a two-token pool, checked against the pool's key and at
+// the PDA of it
+fn load_oracle<'a>(
+    program_id: &Pubkey,
+    pool_key: &Pubkey,
+    oracle_info: &'a AccountInfo,
+) -> Result<std::cell::Ref<'a, [u8]>, ProgramError> {
+    if oracle_info.owner != program_id {
+        return Err(ProgramError::IncorrectProgramId);
+    }
+    let data = std::cell::Ref::map(oracle_info.data.borrow(), |data| &**data);
+    let header = ObservationRing::from_bytes(&data)?.header;
+    if header.pool != *pool_key {
+        return Err(ProgramError::InvalidArgument);
+    }
+    let oracle_pubkey = Pubkey::create_program_address(
+        &[b"oracle", pool_key.as_ref(), &[header.bump]],
+        program_id,
+    )?;
+    if oracle_pubkey != *oracle_info.key {
+        return Err(ProgramError::InvalidSeeds);
+    }
+    Ok(data)
+}
+
+// The next account, as the oracle of a pool that has one
+fn next_oracle_info<'a, 'b, I: Iterator<Item = &'a AccountInfo<'b>>>(
+    has_oracle: bool,
+    iter: &mut I,
+) -> Result<Option<&'a AccountInfo<'b>>, ProgramError> {
+    if !has_oracle {
+        return Ok(None);
+    }
+    next_account_info(iter).map(Some)
+}
+
+// Writes the pool's observation at `now` to its oracle, which every
+// instruction changing the reserves of a pool with one passes. `pool` is
+// already updated to `now`; its liquidity before the change counts back to
+// the newest observation. Only the first change of a second records, so the
+// liquidity always matches the reserves it held.
+fn record_observation(
+    program_id: &Pubkey,
+    pool_key: &Pubkey,
+    pool: &PoolState,
+    oracle_info: Option<&AccountInfo>,
+    now: i64,
+) -> ProgramResult {
+    let Some(oracle_info) = oracle_info else {
+        return Ok(());
+    };
+    let newest = {
+        let data = load_oracle(program_id, pool_key, oracle_info)?;
+        ObservationRing::from_bytes(&data)?.newest()?
+    };
+    if now <= newest.timestamp {
+        return Ok(());
+    }
+    let liquidity = oracle::liquidity(pool.reserve_a, pool.reserve_b);
+    let observation = newest.next(&pool.observe(now)?, liquidity);
+    oracle::push_observation(&mut oracle_info.data.borrow_mut(), &observation)
+}
+
+// Creates the oracle PDA ["oracle", pool] with its first observation, now, and
+// marks the pool, whose reserve changes must pass the oracle from then on.
+// Accounts: [pool (writable), oracle (writable), admin (signer, writable), system_program]
+fn process_init_oracle(
+    program_id: &Pubkey,
+    accounts: &[AccountInfo],
+    capacity: u16,
+) -> ProgramResult {
+    let account_info_iter = &mut accounts.iter();
+    let pool_info = next_account_info(account_info_iter)?;
+    let oracle_info = next_account_info(account_info_iter)?;
+    let payer_info = next_account_info(account_info_iter)?;
+    let system_program_info = next_account_info(account_info_iter)?;
+
+    if capacity == 0 || capacity as usize > oracle::MAX_ORACLE_GROWTH {
+        return Err(ProgramError::InvalidArgument);
+    }
+    // Pools created before versioning migrate first, so they keep their
+    // accumulators
+    if pool_info.data_len() != Pool::LEN {
+        return Err(ProgramError::InvalidAccountData);
+    }
+    // An oracle adds an account to every instruction on the pool, so only
+    // its admin may create one
+    let admin = load_hop_pool(program_id, pool_info)?.admin;
+    if !payer_info.is_signer || *payer_info.key != admin {
+        return Err(ProgramError::MissingRequiredSignature);
+    }
+
+    let (oracle_pubkey, oracle_bump) =
+        Pubkey::find_program_address(&[b"oracle", pool_info.key.as_ref()], program_id);
+    if oracle_pubkey != *oracle_info.key {
+        return Err(ProgramError::InvalidSeeds);
+    }
+    let oracle_signer_seeds: &[&[_]] = &[b"oracle", pool_info.key.as_ref(), &[oracle_bump]];
+
+    let len = oracle::oracle_len(capacity as usize);
+    invoke_signed(
+        &system_instruction::create_account(
+            payer_info.key,
+            oracle_info.key,
+            Rent::get()?.minimum_balance(len),
+            len as u64,
+            program_id,
+        ),
+        &[
+            payer_info.clone(),
+            oracle_info.clone(),
+            system_program_info.clone(),
+        ],
+        &[oracle_signer_seeds],
+    )?;
+
+    let now = Clock::get()?.unix_timestamp;
+    let mut pool = load_hop_pool(program_id, pool_info)?;
+    pool.update_prices(now)?;
+    pool.has_oracle = 1;
+    let observation = Observation::default().next(&pool.observe(now)?, 0);
+    let header = OracleHeader {
+        pool: *pool_info.key,
+        bump: oracle_bump,
+        index: 0,
+        cardinality: 1,
+    };
+    let mut data = oracle_info.data.borrow_mut();
+    header
+        .serialize(&mut &mut data[..OracleHeader::LEN])
+        .map_err(|_| ProgramError::InvalidAccountData)?;
+    observation
+        .serialize(&mut &mut data[OracleHeader::LEN..])
+        .map_err(|_| ProgramError::InvalidAccountData)?;
+
+    Ok(())
+}
+
+// Grows an oracle account to `capacity` observations, by at most
+// MAX_ORACLE_GROWTH at once; anyone may pay for it.
+// Accounts: [oracle (writable), payer (signer, writable), system_program]
+fn process_grow_oracle(
+    program_id: &Pubkey,
+    accounts: &[AccountInfo],
+    capacity: u16,
+) -> ProgramResult {
+    let account_info_iter = &mut accounts.iter();
+    let oracle_info = next_account_info(account_info_iter)?;
+    let payer_info = next_account_info(account_info_iter)?;
+    let system_program_info = next_account_info(account_info_iter)?;
+
+    if !payer_info.is_signer {
+        return Err(ProgramError::MissingRequiredSignature);
+    }
+    if oracle_info.owner != program_id {
+        return Err(ProgramError::IncorrectProgramId);
+    }
+    let pool_key = ObservationRing::from_bytes(&oracle_info.data.borrow())?.header.pool;
+    let current = ObservationRing::from_bytes(&load_oracle(program_id, &pool_key, oracle_info)?)?
+        .capacity();
+    let capacity = capacity as usize;
+    if capacity <= current || capacity - current > oracle::MAX_ORACLE_GROWTH {
+        return Err(ProgramError::InvalidArgument);
+    }
+
+    let len = oracle::oracle_len(capacity);
+    let lamports = Rent::get()?
+        .minimum_balance(len)
+        .saturating_sub(oracle_info.lamports());
+    if lamports > 0 {
+        invoke(
+            &system_instruction::transfer(payer_info.key, oracle_info.key, lamports),
+            &[
+                payer_info.clone(),
+                oracle_info.clone(),
+                system_program_info.clone(),
+            ],
+        )?;
+    }
+    oracle_info.realloc(len, true)
+}
+
+// Averages over windows ending now, from the pool's oracle. Each window must
+// start no earlier than the oldest observation.
+// Accounts: [pool, oracle]
+fn process_observe(
+    program_id: &Pubkey,
+    accounts: &[AccountInfo],
+    seconds_agos: &[u32],
+) -> ProgramResult {
+    let account_info_iter = &mut accounts.iter();
+    let pool_info = next_account_info(account_info_iter)?;
+    let oracle_info = next_account_info(account_info_iter)?;
+
+    if seconds_agos.is_empty()
+        || seconds_agos.len() > oracle::MAX_OBSERVE_WINDOWS
+        || seconds_agos.contains(&0)
+    {
+        return Err(ProgramError::InvalidArgument);
+    }
+    let pool = load_pool(program_id, pool_info)?;
+    let data = load_oracle(program_id, pool_info.key, oracle_info)?;
+    let ring = ObservationRing::from_bytes(&data)?;
+
+    let now = Clock::get()?.unix_timestamp;
+    let liquidity = oracle::liquidity(pool.reserve_a, pool.reserve_b);
+    let current = ring.newest()?.next(&pool.observe(now)?, liquidity);
+    let windows = seconds_agos
+        .iter()
+        .map(|&seconds_ago| {
+            let start = ring.observation_at(&current, now - seconds_ago as i64)?;
+            Ok(oracle::window_twap(&start, &current, seconds_ago))
+        })
+        .collect::<Result<Vec<_>, ProgramError>>()?;
+
+    let result = ObserveResult {
+        timestamp: now,
+        windows,
+    };
+    set_return_data(&result.try_to_vec().unwrap());
+
+    Ok(())
+}
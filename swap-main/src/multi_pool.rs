@@ -0,0 +1,250 @@
+// Multi-asset pools: up to MAX_TOKENS mints with one vault each.
+//
+// Every token carries the same weight, so the pool keeps the product of all
+// reserves constant. A swap between any two tokens only moves those two
+// reserves and prices exactly like a constant product pool over that pair.
+// Single-asset deposits and withdrawals follow Balancer: the fee is charged
+// on the share of the amount that is implicitly swapped into the other tokens.
+
+use crate::{
+    calculate_swap_output,
+    curve::{
+        fixed_point::{exp, ln, mul_div, pow_down, pow_up, MAX_POW_RELATIVE_ERROR, ONE},
+        ConstantProductCurve, SwapCurve, FEE_DENOMINATOR, FEE_NUMERATOR,
+    },
+};
+use borsh::{BorshDeserialize, BorshSerialize};
+use shank::ShankAccount;
+use solana_program::{
+    program_error::ProgramError,
+    program_pack::{IsInitialized, Pack, Sealed},
+    pubkey::Pubkey,
+};
+
+pub const MIN_TOKENS: usize = 3;
+pub const MAX_TOKENS: usize = 8;
+
+// A single-asset deposit or withdrawal may move at most 30% of the reserve
+const MAX_RATIO_NUMERATOR: u128 = 3;
+const MAX_RATIO_DENOMINATOR: u128 = 10;
+
+// Multi-asset pool state. Only the first `num_tokens` entries are in use;
+// mints are stored in strictly ascending order. Shank needs literal array lengths.
+#[derive(BorshSerialize, BorshDeserialize, Debug, ShankAccount)]
+pub struct MultiPool {
+    pub bump: u8,
+    pub num_tokens: u8,
+    pub tokens: [Pubkey; 8],
+    pub reserves: [u64; 8],
+    pub total_lp_supply: u64,
+    // Vault PDA bump of each token and the LP mint PDA bump, so their
+    // addresses are checked with create_program_address
+    pub vault_bumps: [u8; 8],
+    pub lp_mint_bump: u8,
+}
+
+const _: () = assert!(MAX_TOKENS == 8);
+
+impl Sealed for MultiPool {}
+
+impl IsInitialized for MultiPool {
+    fn is_initialized(&self) -> bool {
+        self.num_tokens as usize >= MIN_TOKENS
+    }
+}
+
+impl Pack for MultiPool {
+    const LEN: usize = 1 + 1 + 32 * MAX_TOKENS + 8 * MAX_TOKENS + 8 + MAX_TOKENS + 1; // 339 bytes
+
+    fn unpack_from_slice(src: &[u8]) -> Result<Self, ProgramError> {
+        let pool = MultiPool::try_from_slice(src)
+            .map_err(|_| ProgramError::InvalidAccountData)?;
+        if !(MIN_TOKENS..=MAX_TOKENS).contains(&(pool.num_tokens as usize)) {
+            return Err(ProgramError::InvalidAccountData);
+        }
+        Ok(pool)
+    }
+
+    fn pack_into_slice(&self, dst: &mut [u8]) {
+        let data = self.try_to_vec().unwrap();
+        dst[..data.len()].copy_from_slice(&data);
+    }
+}
+
+// Seeds of a multi-asset pool PDA, without the bump
+pub fn pool_seeds(tokens: &[Pubkey]) -> Vec<&[u8]> {
+    let mut seeds: Vec<&[u8]> = vec![b"multi_pool"];
+    seeds.extend(tokens.iter().map(|token| token.as_ref()));
+    seeds
+}
+
+// Mints must be distinct and sorted so every token set has exactly one pool
+pub fn validate_tokens(tokens: &[Pubkey]) -> Result<(), ProgramError> {
+    if !(MIN_TOKENS..=MAX_TOKENS).contains(&tokens.len()) {
+        return Err(ProgramError::InvalidArgument);
+    }
+    if tokens.windows(2).any(|pair| pair[0] >= pair[1]) {
+        return Err(ProgramError::InvalidArgument);
+    }
+    Ok(())
+}
+
+// Geometric mean of the initial deposit, so a pool of equal amounts mints that amount
+pub fn initial_lp_tokens(amounts: &[u64]) -> Result<u64, ProgramError> {
+    if amounts.is_empty() || amounts.contains(&0) {
+        return Err(ProgramError::InvalidArgument);
+    }
+    let mut ln_sum: i128 = 0;
+    for &amount in amounts {
+        ln_sum += ln(amount as u128 * ONE)?;
+    }
+    let mean = exp(ln_sum / amounts.len() as i128)?;
+    // Round down past the error of ln and exp
+    let error = mul_div(mean, MAX_POW_RELATIVE_ERROR, ONE, true)? + 1;
+    let liquidity = mean.saturating_sub(error) / ONE;
+    if liquidity == 0 {
+        return Err(ProgramError::InvalidArgument);
+    }
+    u64::try_from(liquidity).map_err(|_| ProgramError::InvalidArgument)
+}
+
+fn exceeds_max_ratio(amount: u128, reserve: u128) -> bool {
+    amount * MAX_RATIO_DENOMINATOR > reserve * MAX_RATIO_NUMERATOR
+}
+
+impl MultiPool {
+    pub fn tokens(&self) -> &[Pubkey] {
+        &self.tokens[..self.num_tokens as usize]
+    }
+
+    pub fn reserves(&self) -> &[u64] {
+        &self.reserves[..self.num_tokens as usize]
+    }
+
+    pub fn token_index(&self, mint: &Pubkey) -> Result<usize, ProgramError> {
+        self.tokens()
+            .iter()
+            .position(|token| token == mint)
+            .ok_or(ProgramError::InvalidArgument)
+    }
+
+    // Output for swapping `amount_in` of token `index_in` into token `index_out` (with 0.3% fee)
+    pub fn swap_output(
+        &self,
+        index_in: usize,
+        index_out: usize,
+        amount_in: u64,
+    ) -> Result<u64, ProgramError> {
+        if index_in == index_out {
+            return Err(ProgramError::InvalidArgument);
+        }
+        calculate_swap_output(amount_in, self.reserves[index_in], self.reserves[index_out])
+    }
+
+    // Fee kept out of `amount_in` by swap_output, which prices every pair on
+    // the constant product curve
+    pub fn swap_fee(&self, amount_in: u64) -> u64 {
+        ConstantProductCurve.swap_fee(amount_in)
+    }
+
+    // Trims a deposit to the pool ratio. Returns the amounts taken, rounded up,
+    // and the LP tokens they mint.
+    pub fn deposit_amounts(&self, amounts: &[u64]) -> Result<(Vec<u64>, u64), ProgramError> {
+        if amounts.len() != self.num_tokens as usize || self.total_lp_supply == 0 {
+            return Err(ProgramError::InvalidArgument);
+        }
+        let supply = self.total_lp_supply as u128;
+        let mut liquidity = u128::MAX;
+        for (&amount, &reserve) in amounts.iter().zip(self.reserves()) {
+            if reserve == 0 {
+                return Err(ProgramError::InvalidArgument);
+            }
+            liquidity = liquidity.min(amount as u128 * supply / reserve as u128);
+        }
+        if liquidity == 0 {
+            return Err(ProgramError::InvalidArgument);
+        }
+        let deposits = self
+            .reserves()
+            .iter()
+            .map(|&reserve| Ok(mul_div(liquidity, reserve as u128, supply, true)? as u64))
+            .collect::<Result<Vec<_>, ProgramError>>()?;
+        let liquidity = u64::try_from(liquidity).map_err(|_| ProgramError::InvalidArgument)?;
+        Ok((deposits, liquidity))
+    }
+
+    // Tokens returned for burning `lp_amount`, in pool order
+    pub fn withdraw_amounts(&self, lp_amount: u64) -> Result<Vec<u64>, ProgramError> {
+        if self.total_lp_supply == 0 || lp_amount > self.total_lp_supply {
+            return Err(ProgramError::InvalidArgument);
+        }
+        // lp_amount <= supply keeps every result within its reserve
+        Ok(self
+            .reserves()
+            .iter()
+            .map(|&reserve| {
+                (lp_amount as u128 * reserve as u128 / self.total_lp_supply as u128) as u64
+            })
+            .collect())
+    }
+
+    // Fraction of a single-asset amount that is not charged the swap fee, as
+    // (numerator, denominator): only the (n - 1) / n implicitly traded away pays it
+    fn single_asset_fee_factor(&self) -> (u128, u128) {
+        let n = self.num_tokens as u128;
+        let denominator = FEE_DENOMINATOR as u128 * n;
+        (denominator - FEE_NUMERATOR as u128 * (n - 1), denominator)
+    }
+
+    // LP tokens for depositing `amount_in` of one token:
+    // supply * ((1 + net_in / reserve)^(1 / n) - 1)
+    pub fn single_deposit_lp_tokens(&self, index: usize, amount_in: u64) -> Result<u64, ProgramError> {
+        let reserve = self.reserves[index] as u128;
+        if amount_in == 0 || reserve == 0 || self.total_lp_supply == 0 {
+            return Err(ProgramError::InvalidArgument);
+        }
+        let (numerator, denominator) = self.single_asset_fee_factor();
+        let net_in = amount_in as u128 * numerator / denominator;
+        if exceeds_max_ratio(net_in, reserve) {
+            return Err(ProgramError::InvalidArgument);
+        }
+
+        // Every rounding step shrinks the LP tokens minted
+        let ratio = mul_div(reserve + net_in, ONE, reserve, false)?;
+        let exponent = ONE / self.num_tokens as u128;
+        let pool_ratio = pow_down(ratio, exponent)?;
+        let liquidity = mul_div(
+            self.total_lp_supply as u128,
+            pool_ratio.saturating_sub(ONE),
+            ONE,
+            false,
+        )?;
+        if liquidity == 0 {
+            return Err(ProgramError::InvalidArgument);
+        }
+        u64::try_from(liquidity).map_err(|_| ProgramError::InvalidArgument)
+    }
+
+    // Tokens of one kind returned for burning `lp_amount`:
+    // reserve * (1 - ((supply - lp) / supply)^n), less the fee
+    pub fn single_withdraw_amount(&self, index: usize, lp_amount: u64) -> Result<u64, ProgramError> {
+        let reserve = self.reserves[index] as u128;
+        let supply = self.total_lp_supply as u128;
+        // Burning the whole supply leaves nothing to price against
+        if lp_amount == 0 || lp_amount as u128 >= supply {
+            return Err(ProgramError::InvalidArgument);
+        }
+
+        // Every rounding step shrinks the tokens paid out
+        let pool_ratio = mul_div(supply - lp_amount as u128, ONE, supply, true)?;
+        let token_ratio = pow_up(pool_ratio, self.num_tokens as u128 * ONE)?.min(ONE);
+        let remaining = mul_div(reserve, token_ratio, ONE, true)?;
+        let gross_out = reserve.saturating_sub(remaining);
+        let (numerator, denominator) = self.single_asset_fee_factor();
+        let amount_out = gross_out * numerator / denominator;
+        if amount_out == 0 || exceeds_max_ratio(amount_out, reserve) {
+            return Err(ProgramError::InvalidArgument);
+        }
+        Ok(amount_out as u64)
+    }
+}
@@ -0,0 +1,296 @@
+// Time-weighted average prices of two-token pools.
+//
+// Each pool accumulates the spot price of either token, scaled by
+// curve::PRICE_SCALE, times the seconds it held, and the time of the last
+// update. Processors accumulate before every reserve change, so the price a
+// trade moves to only counts from the next update on, and moving the price
+// within one transaction leaves the accumulators unchanged. The average price
+// between two observations is the difference of their accumulators over the
+// seconds between them. Accumulators wrap on overflow; the wrapping difference
+// is still exact for any window shorter than the wrap.
+//
+// A pool may also have an oracle account, the PDA ["oracle", pool], holding a
+// ring buffer of observations that also accumulate the pool's liquidity,
+// sqrt(reserve_a * reserve_b). Swaps passing the oracle write at most one
+// observation per second, and Observe averages over windows ending now by
+// finding the observations around each window's start and interpolating
+// between them. The buffer grows with GrowOracle; new slots fill before the
+// oldest observation is overwritten.
+
+use crate::{curve::fixed_point::mul_div, curve::PRICE_SCALE, IntegerSqrt};
+use borsh::{BorshDeserialize, BorshSerialize};
+use solana_program::{
+    entrypoint::MAX_PERMITTED_DATA_INCREASE, program_error::ProgramError, pubkey::Pubkey,
+};
+
+// Accumulated prices of a pool as of `timestamp`
+#[derive(BorshSerialize, BorshDeserialize, Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct PriceObservation {
+    pub timestamp: i64,
+    pub price_a_cumulative: u128,
+    pub price_b_cumulative: u128,
+}
+
+impl PriceObservation {
+    // Extended to `now` with token A at `price_a` in token B since `timestamp`
+    pub fn accumulate(&self, price_a: u128, now: i64) -> Self {
+        let elapsed = now.saturating_sub(self.timestamp).max(0) as u128;
+        PriceObservation {
+            timestamp: now.max(self.timestamp),
+            price_a_cumulative: self
+                .price_a_cumulative
+                .wrapping_add(price_a.wrapping_mul(elapsed)),
+            price_b_cumulative: self
+                .price_b_cumulative
+                .wrapping_add(inverse_price(price_a).wrapping_mul(elapsed)),
+        }
+    }
+}
+
+// Return data of GetTwap
+#[derive(BorshSerialize, BorshDeserialize, Clone, Copy, Debug, PartialEq, Eq)]
+pub struct TwapResult {
+    // Average prices of token A in token B and of token B in token A, scaled by
+    // PRICE_SCALE
+    pub price_a: u128,
+    pub price_b: u128,
+    // The pool's observation now, to average from next time
+    pub observation: PriceObservation,
+}
+
+// Average prices of token A and token B between two observations of the same
+// pool, `older` strictly before `newer`
+pub fn twap(
+    older: &PriceObservation,
+    newer: &PriceObservation,
+) -> Result<(u128, u128), ProgramError> {
+    let elapsed = newer
+        .timestamp
+        .checked_sub(older.timestamp)
+        .filter(|&elapsed| elapsed > 0)
+        .ok_or(ProgramError::InvalidArgument)? as u128;
+    let price_a = newer.price_a_cumulative.wrapping_sub(older.price_a_cumulative) / elapsed;
+    let price_b = newer.price_b_cumulative.wrapping_sub(older.price_b_cumulative) / elapsed;
+    Ok((price_a, price_b))
+}
+
+// Price of token B in token A for a price of token A in token B, both scaled
+// by PRICE_SCALE; zero when token A is worthless
+pub fn inverse_price(price_a: u128) -> u128 {
+    (PRICE_SCALE * PRICE_SCALE).checked_div(price_a).unwrap_or(0)
+}
+
+// Most windows one Observe returns, which keeps its return data within the
+// 1024-byte limit
+pub const MAX_OBSERVE_WINDOWS: usize = 16;
+
+// Most observations InitOracle creates or GrowOracle adds at once: an account
+// grows by at most MAX_PERMITTED_DATA_INCREASE per instruction
+pub const MAX_ORACLE_GROWTH: usize = MAX_PERMITTED_DATA_INCREASE / Observation::LEN;
+
+// One slot of an oracle account
+#[derive(BorshSerialize, BorshDeserialize, Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct Observation {
+    pub timestamp: i64,
+    pub price_a_cumulative: u128,
+    pub price_b_cumulative: u128,
+    // Liquidity times the seconds it held, wrapping like the prices
+    pub liquidity_cumulative: u128,
+}
+
+impl Observation {
+    pub const LEN: usize = 8 + 16 * 3;
+
+    // The observation following this one at `prices.timestamp`, with
+    // `liquidity` held since this one
+    pub fn next(&self, prices: &PriceObservation, liquidity: u128) -> Self {
+        let elapsed = prices.timestamp.saturating_sub(self.timestamp).max(0) as u128;
+        Observation {
+            timestamp: prices.timestamp,
+            price_a_cumulative: prices.price_a_cumulative,
+            price_b_cumulative: prices.price_b_cumulative,
+            liquidity_cumulative: self
+                .liquidity_cumulative
+                .wrapping_add(liquidity.wrapping_mul(elapsed)),
+        }
+    }
+
+    // Accumulators at `target`, between `self` and a later observation `newer`
+    fn interpolate(&self, newer: &Observation, target: i64) -> Result<Self, ProgramError> {
+        let span = (newer.timestamp - self.timestamp) as u128;
+        let part = (target - self.timestamp) as u128;
+        let step = |from: u128, to: u128| -> Result<u128, ProgramError> {
+            Ok(from.wrapping_add(mul_div(to.wrapping_sub(from), part, span, false)?))
+        };
+        Ok(Observation {
+            timestamp: target,
+            price_a_cumulative: step(self.price_a_cumulative, newer.price_a_cumulative)?,
+            price_b_cumulative: step(self.price_b_cumulative, newer.price_b_cumulative)?,
+            liquidity_cumulative: step(self.liquidity_cumulative, newer.liquidity_cumulative)?,
+        })
+    }
+}
+
+// Liquidity a pool accumulates in its oracle
+pub fn liquidity(reserve_a: u64, reserve_b: u64) -> u128 {
+    (reserve_a as u128 * reserve_b as u128).integer_sqrt()
+}
+
+// Start of an oracle account, followed by its observation slots
+#[derive(BorshSerialize, BorshDeserialize, Clone, Copy, Debug, PartialEq, Eq)]
+pub struct OracleHeader {
+    pub pool: Pubkey,
+    pub bump: u8,
+    // Slot of the newest observation
+    pub index: u16,
+    // Slots written so far; the oldest observation is the one after `index`
+    pub cardinality: u16,
+}
+
+impl OracleHeader {
+    pub const LEN: usize = 32 + 1 + 2 + 2;
+}
+
+pub fn oracle_len(capacity: usize) -> usize {
+    OracleHeader::LEN + capacity * Observation::LEN
+}
+
+fn read_observation(data: &[u8], slot: usize) -> Result<Observation, ProgramError> {
+    let start = OracleHeader::LEN + slot * Observation::LEN;
+    data.get(start..start + Observation::LEN)
+        .and_then(|bytes| Observation::try_from_slice(bytes).ok())
+        .ok_or(ProgramError::InvalidAccountData)
+}
+
+// Observations of an oracle account, read in place
+pub struct ObservationRing<'a> {
+    pub header: OracleHeader,
+    data: &'a [u8],
+}
+
+impl<'a> ObservationRing<'a> {
+    pub fn from_bytes(data: &'a [u8]) -> Result<Self, ProgramError> {
+        let capacity = data.len().saturating_sub(OracleHeader::LEN) / Observation::LEN;
+        if capacity == 0 || data.len() != oracle_len(capacity) {
+            return Err(ProgramError::InvalidAccountData);
+        }
+        let header = OracleHeader::try_from_slice(&data[..OracleHeader::LEN])
+            .map_err(|_| ProgramError::InvalidAccountData)?;
+        let ring = ObservationRing { header, data };
+        let (index, cardinality) = (header.index as usize, header.cardinality as usize);
+        if cardinality == 0 || index >= cardinality || cardinality > ring.capacity() {
+            return Err(ProgramError::InvalidAccountData);
+        }
+        Ok(ring)
+    }
+
+    pub fn capacity(&self) -> usize {
+        (self.data.len() - OracleHeader::LEN) / Observation::LEN
+    }
+
+    // The `position`th observation, oldest first
+    pub fn get(&self, position: usize) -> Result<Observation, ProgramError> {
+        let cardinality = self.header.cardinality as usize;
+        if position >= cardinality {
+            return Err(ProgramError::InvalidArgument);
+        }
+        read_observation(self.data, (self.header.index as usize + 1 + position) % cardinality)
+    }
+
+    pub fn newest(&self) -> Result<Observation, ProgramError> {
+        read_observation(self.data, self.header.index as usize)
+    }
+
+    // Every observation, oldest first
+    pub fn observations(&self) -> Result<Vec<Observation>, ProgramError> {
+        (0..self.header.cardinality as usize)
+            .map(|position| self.get(position))
+            .collect()
+    }
+
+    // Accumulators at `target`, given `current`, the pool's observation now.
+    // Fails for a target before the oldest observation.
+    pub fn observation_at(
+        &self,
+        current: &Observation,
+        target: i64,
+    ) -> Result<Observation, ProgramError> {
+        if target >= current.timestamp {
+            return Ok(*current);
+        }
+        let newest = self.newest()?;
+        if target >= newest.timestamp {
+            return if target == newest.timestamp {
+                Ok(newest)
+            } else {
+                newest.interpolate(current, target)
+            };
+        }
+        if target < self.get(0)?.timestamp {
+            return Err(ProgramError::InvalidArgument);
+        }
+        // get(low) is at or before the target, get(high) after it
+        let (mut low, mut high) = (0, self.header.cardinality as usize - 1);
+        while high - low > 1 {
+            let middle = (low + high) / 2;
+            if self.get(middle)?.timestamp <= target {
+                low = middle;
+            } else {
+                high = middle;
+            }
+        }
+        let older = self.get(low)?;
+        if older.timestamp == target {
+            return Ok(older);
+        }
+        older.interpolate(&self.get(high)?, target)
+    }
+}
+
+// Writes `observation` after the newest one: into a fresh slot while the
+// newest is the last written and capacity remains, over the oldest otherwise
+pub fn push_observation(data: &mut [u8], observation: &Observation) -> Result<(), ProgramError> {
+    let ring = ObservationRing::from_bytes(data)?;
+    let mut header = ring.header;
+    let capacity = ring.capacity();
+    if header.index + 1 == header.cardinality && (header.cardinality as usize) < capacity {
+        header.cardinality += 1;
+    }
+    header.index = (header.index + 1) % header.cardinality;
+
+    let start = OracleHeader::LEN + header.index as usize * Observation::LEN;
+    observation
+        .serialize(&mut &mut data[start..start + Observation::LEN])
+        .map_err(|_| ProgramError::InvalidAccountData)?;
+    header
+        .serialize(&mut &mut data[..OracleHeader::LEN])
+        .map_err(|_| ProgramError::InvalidAccountData)
+}
+
+// Averages over one Observe window, scaled like TwapResult
+#[derive(BorshSerialize, BorshDeserialize, Clone, Copy, Debug, PartialEq, Eq)]
+pub struct WindowTwap {
+    pub seconds_ago: u32,
+    pub price_a: u128,
+    pub price_b: u128,
+    pub liquidity: u128,
+}
+
+// Return data of Observe: one WindowTwap per requested window, in order, each
+// ending at `timestamp`
+#[derive(BorshSerialize, BorshDeserialize, Clone, Debug, PartialEq, Eq)]
+pub struct ObserveResult {
+    pub timestamp: i64,
+    pub windows: Vec<WindowTwap>,
+}
+
+// Averages between `start` and `end`, `seconds_ago` seconds apart
+pub fn window_twap(start: &Observation, end: &Observation, seconds_ago: u32) -> WindowTwap {
+    let elapsed = seconds_ago as u128;
+    WindowTwap {
+        seconds_ago,
+        price_a: end.price_a_cumulative.wrapping_sub(start.price_a_cumulative) / elapsed,
+        price_b: end.price_b_cumulative.wrapping_sub(start.price_b_cumulative) / elapsed,
+        liquidity: end.liquidity_cumulative.wrapping_sub(start.liquidity_cumulative) / elapsed,
+    }
+}
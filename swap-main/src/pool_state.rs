@@ -0,0 +1,272 @@
+// Zero-copy view of a two-token pool account.
+//
+// The versioned layout is a version byte, the Pool fields in Borsh order and
+// the reserved bytes. Borsh writes integers little-endian without padding, so
+// the same bytes are a packed repr(C) struct: processors read and update the
+// account in place instead of decoding and re-encoding it on every
+// instruction. Unversioned pools are worked on as a copy, with their bumps
+// searched for, and the fields their layout has written back, until they are
+// migrated.
+
+use crate::{
+    curve::{SwapCurve, CURVE_PARAMS_LEN},
+    oracle::PriceObservation,
+    CurveType, Pool, LEGACY_POOL_LEN, POOL_FIELDS_LEN, POOL_RESERVED_LEN, POOL_VERSION,
+};
+use bytemuck::{Pod, Zeroable};
+use solana_program::{
+    account_info::AccountInfo,
+    entrypoint::ProgramResult,
+    program_error::ProgramError,
+    program_pack::Pack,
+    pubkey::Pubkey,
+};
+use std::{
+    cell::RefMut,
+    ops::{Deref, DerefMut},
+};
+
+#[repr(C, packed)]
+#[derive(Clone, Copy, Pod, Zeroable)]
+pub struct PoolState {
+    pub version: u8,
+    pub token_a: Pubkey,
+    pub token_b: Pubkey,
+    pub bump: u8,
+    pub reserve_a: u64,
+    pub reserve_b: u64,
+    pub total_lp_supply: u64,
+    pub curve_type: u8,
+    pub admin: Pubkey,
+    pub curve_params: [u8; CURVE_PARAMS_LEN],
+    pub vault_a_bump: u8,
+    pub vault_b_bump: u8,
+    pub lp_mint_bump: u8,
+    pub price_a_cumulative: u128,
+    pub price_b_cumulative: u128,
+    pub last_update_timestamp: i64,
+    pub has_oracle: u8,
+    pub reserved: [u8; POOL_RESERVED_LEN],
+}
+
+const _: () = assert!(std::mem::size_of::<PoolState>() == <Pool as Pack>::LEN);
+
+impl PoolState {
+    // State of a pool account in either supported layout. Legacy pools lack
+    // the version byte and the trailing fields, which read as zeros (version
+    // 0, constant product, no admin, no stored bumps).
+    pub fn from_bytes(data: &[u8]) -> Result<Self, ProgramError> {
+        match data.len() {
+            LEGACY_POOL_LEN => {
+                let mut state = PoolState::zeroed();
+                bytemuck::bytes_of_mut(&mut state)[1..1 + LEGACY_POOL_LEN].copy_from_slice(data);
+                Ok(state)
+            }
+            _ => Self::from_versioned(data).copied(),
+        }
+    }
+
+    fn from_versioned(data: &[u8]) -> Result<&Self, ProgramError> {
+        if data.len() != Pool::LEN || data[0] != POOL_VERSION {
+            return Err(ProgramError::InvalidAccountData);
+        }
+        Ok(bytemuck::from_bytes(data))
+    }
+
+    // Writes the version and fields into a versioned pool account, leaving the
+    // reserved bytes as they are
+    pub fn write_fields(&self, dst: &mut [u8]) {
+        dst[..1 + POOL_FIELDS_LEN].copy_from_slice(&bytemuck::bytes_of(self)[..1 + POOL_FIELDS_LEN]);
+    }
+
+    // Borrows an initialized pool account for reading and writing its state.
+    // A legacy pool is loaded as a copy with the bumps it does not store, and
+    // written back when the guard drops; the accumulators and oracle flag its
+    // layout lacks are not kept.
+    pub fn load_mut<'a, 'b>(
+        program_id: &Pubkey,
+        pool_info: &'a AccountInfo<'b>,
+    ) -> Result<PoolStateMut<'a, 'b>, ProgramError> {
+        let data = pool_info.try_borrow_mut_data()?;
+        let legacy = if data.len() == LEGACY_POOL_LEN {
+            let mut state = Self::from_bytes(&data)?;
+            state.derive_bumps(program_id, pool_info.key);
+            Some(state)
+        } else {
+            Self::from_versioned(&data)?;
+            None
+        };
+        let state = PoolStateMut { data, legacy };
+        if !state.is_initialized() {
+            return Err(ProgramError::UninitializedAccount);
+        }
+        Ok(state)
+    }
+
+    // Searches for the vault and LP mint bumps of a pool that predates them
+    pub fn derive_bumps(&mut self, program_id: &Pubkey, pool_key: &Pubkey) {
+        let bump = |seeds: &[&[u8]]| Pubkey::find_program_address(seeds, program_id).1;
+        self.vault_a_bump = bump(&[b"vault", pool_key.as_ref(), self.token_a.as_ref()]);
+        self.vault_b_bump = bump(&[b"vault", pool_key.as_ref(), self.token_b.as_ref()]);
+        self.lp_mint_bump = bump(&[b"mint", pool_key.as_ref()]);
+    }
+
+    pub fn is_initialized(&self) -> bool {
+        self.token_a != Pubkey::default()
+    }
+
+    pub fn curve_type(&self) -> Result<CurveType, ProgramError> {
+        CurveType::try_from(self.curve_type)
+    }
+
+    // Pricing curve of this pool; stable pools are priced at the current amplification
+    pub fn curve(&self) -> Result<Box<dyn SwapCurve>, ProgramError> {
+        self.curve_type()?.curve(&self.curve_params)
+    }
+
+    // Output for swapping `amount_in` through this pool (with 0.3% fee)
+    pub fn swap_output(&self, amount_in: u64, direction_a_to_b: bool) -> Result<u64, ProgramError> {
+        self.curve()?
+            .swap_exact_in(amount_in, self.reserve_a, self.reserve_b, direction_a_to_b)
+    }
+
+    // Fee this pool's curve keeps out of `amount_in`
+    pub fn swap_fee(&self, amount_in: u64) -> Result<u64, ProgramError> {
+        Ok(self.curve()?.swap_fee(amount_in))
+    }
+
+    // LP tokens for a ratio-matched deposit
+    pub fn lp_tokens_to_mint(&self, amount_a: u64, amount_b: u64) -> Result<u64, ProgramError> {
+        self.curve()?.deposit_lp_tokens(
+            amount_a,
+            amount_b,
+            self.reserve_a,
+            self.reserve_b,
+            self.total_lp_supply,
+        )
+    }
+
+    // Tokens returned for burning `lp_amount`
+    pub fn withdraw_amounts(&self, lp_amount: u64) -> Result<(u64, u64), ProgramError> {
+        self.curve()?
+            .withdraw_amounts(lp_amount, self.reserve_a, self.reserve_b, self.total_lp_supply)
+    }
+
+    // Accumulated prices extended to `now` at the current spot price, which
+    // only accrues once the pool has been updated and while it holds both
+    // tokens
+    pub fn observe(&self, now: i64) -> Result<PriceObservation, ProgramError> {
+        let observation = PriceObservation {
+            timestamp: self.last_update_timestamp,
+            price_a_cumulative: self.price_a_cumulative,
+            price_b_cumulative: self.price_b_cumulative,
+        };
+        if observation.timestamp == 0 || self.reserve_a == 0 || self.reserve_b == 0 {
+            return Ok(PriceObservation {
+                timestamp: now.max(observation.timestamp),
+                ..observation
+            });
+        }
+        let price_a = if now > observation.timestamp {
+            self.curve()?.spot_price(self.reserve_a, self.reserve_b)?
+        } else {
+            0
+        };
+        Ok(observation.accumulate(price_a, now))
+    }
+
+    // Accumulates prices up to `now`; called before every reserve change
+    pub fn update_prices(&mut self, now: i64) -> ProgramResult {
+        let observation = self.observe(now)?;
+        self.price_a_cumulative = observation.price_a_cumulative;
+        self.price_b_cumulative = observation.price_b_cumulative;
+        self.last_update_timestamp = observation.timestamp;
+        Ok(())
+    }
+}
+
+impl From<&Pool> for PoolState {
+    fn from(pool: &Pool) -> Self {
+        PoolState {
+            version: POOL_VERSION,
+            token_a: pool.token_a,
+            token_b: pool.token_b,
+            bump: pool.bump,
+            reserve_a: pool.reserve_a,
+            reserve_b: pool.reserve_b,
+            total_lp_supply: pool.total_lp_supply,
+            curve_type: pool.curve_type as u8,
+            admin: pool.admin,
+            curve_params: pool.curve_params,
+            vault_a_bump: pool.vault_a_bump,
+            vault_b_bump: pool.vault_b_bump,
+            lp_mint_bump: pool.lp_mint_bump,
+            price_a_cumulative: pool.price_a_cumulative,
+            price_b_cumulative: pool.price_b_cumulative,
+            last_update_timestamp: pool.last_update_timestamp,
+            has_oracle: pool.has_oracle as u8,
+            reserved: [0; POOL_RESERVED_LEN],
+        }
+    }
+}
+
+impl TryFrom<&PoolState> for Pool {
+    type Error = ProgramError;
+
+    fn try_from(state: &PoolState) -> Result<Self, ProgramError> {
+        Ok(Pool {
+            token_a: state.token_a,
+            token_b: state.token_b,
+            bump: state.bump,
+            reserve_a: state.reserve_a,
+            reserve_b: state.reserve_b,
+            total_lp_supply: state.total_lp_supply,
+            curve_type: state.curve_type()?,
+            admin: state.admin,
+            curve_params: state.curve_params,
+            vault_a_bump: state.vault_a_bump,
+            vault_b_bump: state.vault_b_bump,
+            lp_mint_bump: state.lp_mint_bump,
+            price_a_cumulative: state.price_a_cumulative,
+            price_b_cumulative: state.price_b_cumulative,
+            last_update_timestamp: state.last_update_timestamp,
+            has_oracle: state.has_oracle != 0,
+        })
+    }
+}
+
+// Pool state borrowed from its account until dropped. The pool account
+// cannot be passed to a CPI while this is alive.
+pub struct PoolStateMut<'a, 'b> {
+    data: RefMut<'a, &'b mut [u8]>,
+    // Copy of a legacy pool, written back on drop
+    legacy: Option<PoolState>,
+}
+
+impl Deref for PoolStateMut<'_, '_> {
+    type Target = PoolState;
+
+    fn deref(&self) -> &PoolState {
+        match &self.legacy {
+            Some(state) => state,
+            None => bytemuck::from_bytes(&self.data),
+        }
+    }
+}
+
+impl DerefMut for PoolStateMut<'_, '_> {
+    fn deref_mut(&mut self) -> &mut PoolState {
+        match &mut self.legacy {
+            Some(state) => state,
+            None => bytemuck::from_bytes_mut(&mut self.data),
+        }
+    }
+}
+
+impl Drop for PoolStateMut<'_, '_> {
+    fn drop(&mut self) {
+        if let Some(state) = &self.legacy {
+            self.data.copy_from_slice(&bytemuck::bytes_of(state)[1..1 + LEGACY_POOL_LEN]);
+        }
+    }
+}
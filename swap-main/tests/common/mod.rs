@@ -0,0 +1,1851 @@
+// Shared harness for the solana-program-test integration suite.
+//
+// The program talks to GorbChain's SPL Token and Associated Token Account
+// programs by their GorbChain addresses, so the harness registers the upstream
+// `spl_token` processor under the GorbChain token program ID and a minimal
+// associated-token-account stand-in under the GorbChain ATA program ID.
+#![allow(dead_code)]
+
+use borsh::{BorshDeserialize, BorshSerialize};
+use cargo_swap::{
+    concentrated::{ConcentratedPool, Position},
+    cpi,
+    flash_loan::FlashReceiverInstruction,
+    multi_pool::MultiPool,
+    oracle::{Observation, ObservationRing, OracleHeader, PriceObservation},
+    CurveParams, Pool, SplitRoute, TestProjectInstruction, LEGACY_POOL_LEN,
+};
+use solana_program::{
+    account_info::{next_account_info, AccountInfo},
+    clock::Clock,
+    entrypoint::ProgramResult,
+    instruction::{AccountMeta, Instruction, InstructionError},
+    program::{invoke, invoke_signed},
+    program_error::ProgramError,
+    program_pack::Pack,
+    pubkey::Pubkey,
+    rent::Rent,
+    system_instruction, system_program, sysvar,
+    sysvar::Sysvar,
+};
+use solana_program_test::{processor, BanksClientError, ProgramTest, ProgramTestContext};
+use solana_sdk::{
+    account::Account,
+    bpf_loader,
+    message::Message,
+    signature::{Keypair, Signer},
+    transaction::{Transaction, TransactionError},
+};
+
+pub const TOKEN_PROGRAM_ID: Pubkey =
+    solana_program::pubkey!("G22oYgZ6LnVcy7v8eSNi2xpNk1NcZiPD8CVKSTut7oZ6");
+pub const ATA_PROGRAM_ID: Pubkey =
+    solana_program::pubkey!("GoATGVNeSXerFerPqTJ8hcED1msPWHHLxao2vwBYqowm");
+// Token-2022 is loaded into every ProgramTest at its upstream address
+pub const TOKEN_2022_PROGRAM_ID: Pubkey =
+    solana_program::pubkey!("TokenzQdBNbLqP5VEhdkAS6EPFLC1PHnBqCXEpPxuEb");
+pub const NATIVE_MINT: Pubkey =
+    solana_program::pubkey!("So11111111111111111111111111111111111111112");
+
+// Associated token account stand-in: only understands `Create` (discriminator 0),
+// which is all the swap program ever asks of it.
+fn process_ata_instruction(
+    program_id: &Pubkey,
+    accounts: &[AccountInfo],
+    data: &[u8],
+) -> ProgramResult {
+    if data.first().copied().unwrap_or(0) != 0 {
+        return Err(ProgramError::InvalidInstructionData);
+    }
+    let account_info_iter = &mut accounts.iter();
+    let payer_info = next_account_info(account_info_iter)?;
+    let ata_info = next_account_info(account_info_iter)?;
+    let owner_info = next_account_info(account_info_iter)?;
+    let mint_info = next_account_info(account_info_iter)?;
+    let rent_info = next_account_info(account_info_iter)?;
+    let token_program_info = next_account_info(account_info_iter)?;
+    let system_program_info = next_account_info(account_info_iter)?;
+
+    let (expected, bump) = Pubkey::find_program_address(
+        &[
+            owner_info.key.as_ref(),
+            token_program_info.key.as_ref(),
+            mint_info.key.as_ref(),
+        ],
+        program_id,
+    );
+    if expected != *ata_info.key {
+        return Err(ProgramError::InvalidSeeds);
+    }
+
+    let rent = Rent::get()?;
+    invoke_signed(
+        &system_instruction::create_account(
+            payer_info.key,
+            ata_info.key,
+            rent.minimum_balance(spl_token::state::Account::LEN),
+            spl_token::state::Account::LEN as u64,
+            token_program_info.key,
+        ),
+        &[
+            payer_info.clone(),
+            ata_info.clone(),
+            system_program_info.clone(),
+        ],
+        &[&[
+            owner_info.key.as_ref(),
+            token_program_info.key.as_ref(),
+            mint_info.key.as_ref(),
+            &[bump],
+        ]],
+    )?;
+
+    invoke_signed(
+        &Instruction {
+            program_id: *token_program_info.key,
+            accounts: vec![
+                AccountMeta::new(*ata_info.key, false),
+                AccountMeta::new_readonly(*mint_info.key, false),
+                AccountMeta::new_readonly(*owner_info.key, false),
+                AccountMeta::new_readonly(sysvar::rent::id(), false),
+            ],
+            data: vec![1],
+        },
+        &[
+            ata_info.clone(),
+            mint_info.clone(),
+            owner_info.clone(),
+            rent_info.clone(),
+            token_program_info.clone(),
+        ],
+        &[],
+    )
+}
+
+// Flash receivers that pay back what they owe, signed by the owner passed as
+// the last extra account: one pays in full, the other falls short.
+//  - ReceiveFlashLoan extra accounts: [owner]; the loan is repaid from the
+//    receiving account, the defaulter skips the fee.
+//  - ReceiveFlashSwap extra accounts: [source, owner]; the swap is paid for in
+//    the source account's token, the defaulter pays 1% less.
+pub const FLASH_RECEIVER_ID: Pubkey =
+    solana_program::pubkey!("F1ashRecv1111111111111111111111111111111111");
+pub const FLASH_DEFAULTER_ID: Pubkey =
+    solana_program::pubkey!("F1ashDefau1t1111111111111111111111111111111");
+
+// TransferChecked through `token_program`, the program of `mint`
+fn token_transfer<'a>(
+    source: &AccountInfo<'a>,
+    mint: &AccountInfo<'a>,
+    destination: &AccountInfo<'a>,
+    owner: &AccountInfo<'a>,
+    token_program: &AccountInfo<'a>,
+    amount: u64,
+) -> ProgramResult {
+    let decimals =
+        spl_token::state::Mint::unpack(&mint.data.borrow()[..spl_token::state::Mint::LEN])?.decimals;
+    let mut data = vec![12];
+    data.extend_from_slice(&amount.to_le_bytes());
+    data.push(decimals);
+    invoke(
+        &Instruction {
+            program_id: *token_program.key,
+            accounts: vec![
+                AccountMeta::new(*source.key, false),
+                AccountMeta::new_readonly(*mint.key, false),
+                AccountMeta::new(*destination.key, false),
+                AccountMeta::new_readonly(*owner.key, true),
+            ],
+            data,
+        },
+        &[source.clone(), mint.clone(), destination.clone(), owner.clone(), token_program.clone()],
+    )
+}
+
+fn process_flash_callback(accounts: &[AccountInfo], data: &[u8], honest: bool) -> ProgramResult {
+    let instruction = FlashReceiverInstruction::try_from_slice(data)
+        .map_err(|_| ProgramError::InvalidInstructionData)?;
+    let account_info_iter = &mut accounts.iter();
+    match instruction {
+        FlashReceiverInstruction::ReceiveFlashLoan { amount, fee } => {
+            let receiver_token_info = next_account_info(account_info_iter)?;
+            let mint_info = next_account_info(account_info_iter)?;
+            let vault_info = next_account_info(account_info_iter)?;
+            let token_program_info = next_account_info(account_info_iter)?;
+            let owner_info = next_account_info(account_info_iter)?;
+            let repayment = if honest { amount + fee } else { amount };
+            token_transfer(receiver_token_info, mint_info, vault_info, owner_info, token_program_info, repayment)
+        }
+        FlashReceiverInstruction::ReceiveFlashSwap { amount_a_out, amount_b_out } => {
+            let pool_info = next_account_info(account_info_iter)?;
+            let token_a_info = next_account_info(account_info_iter)?;
+            let token_b_info = next_account_info(account_info_iter)?;
+            let vault_a_info = next_account_info(account_info_iter)?;
+            let vault_b_info = next_account_info(account_info_iter)?;
+            let token_a_program_info = next_account_info(account_info_iter)?;
+            let token_b_program_info = next_account_info(account_info_iter)?;
+            let source_info = next_account_info(account_info_iter)?;
+            let owner_info = next_account_info(account_info_iter)?;
+
+            let pool = Pool::unpack(&pool_info.data.borrow())?;
+            let source = source_info.data.borrow();
+            let mint = spl_token::state::Account::unpack(&source[..spl_token::state::Account::LEN])?.mint;
+            drop(source);
+            let pay_in_a = mint == pool.token_a;
+            let (mint_info, vault_info, token_program_info) = if pay_in_a {
+                (token_a_info, vault_a_info, token_a_program_info)
+            } else {
+                (token_b_info, vault_b_info, token_b_program_info)
+            };
+            let owed = flash_swap_payment(&pool, amount_a_out, amount_b_out, pay_in_a)?;
+            let payment = if honest { owed } else { owed - owed / 100 };
+            token_transfer(source_info, mint_info, vault_info, owner_info, token_program_info, payment)
+        }
+    }
+}
+
+// Least a flash swap of `pool` has to be paid in one token. Same-token
+// repayment covers the fee on itself; the other side is the swap's exact-output
+// quote, topped up while the curve's fee, which rounds up, still takes the
+// pool below its invariant.
+pub fn flash_swap_payment(
+    pool: &Pool,
+    amount_a_out: u64,
+    amount_b_out: u64,
+    pay_in_a: bool,
+) -> Result<u64, ProgramError> {
+    let (same_out, other_out) = if pay_in_a {
+        (amount_a_out, amount_b_out)
+    } else {
+        (amount_b_out, amount_a_out)
+    };
+    let mut owed = cargo_swap::curve::ceil_div(same_out as u128 * 1000, 997) as u64;
+    if other_out > 0 {
+        owed += pool.swap_input(other_out, pay_in_a)?;
+    }
+    let curve = pool.curve()?;
+    let invariant = curve.invariant(pool.reserve_a, pool.reserve_b)?;
+    let (reserve_a, reserve_b) = (pool.reserve_a - amount_a_out, pool.reserve_b - amount_b_out);
+    loop {
+        let net = owed - curve.swap_fee(owed);
+        let after = if pay_in_a {
+            curve.invariant(reserve_a + net, reserve_b)?
+        } else {
+            curve.invariant(reserve_a, reserve_b + net)?
+        };
+        if after >= invariant {
+            return Ok(owed);
+        }
+        owed += 1;
+    }
+}
+
+fn process_flash_receiver(_: &Pubkey, accounts: &[AccountInfo], data: &[u8]) -> ProgramResult {
+    process_flash_callback(accounts, data, true)
+}
+
+fn process_flash_defaulter(_: &Pubkey, accounts: &[AccountInfo], data: &[u8]) -> ProgramResult {
+    process_flash_callback(accounts, data, false)
+}
+
+// Example caller of `cargo_swap::cpi`: a vault strategy whose tokens are held
+// by its PDA `["authority"]`, which signs every call into the swap. The swap
+// program comes first in each instruction's accounts, then the authority.
+pub const STRATEGY_PROGRAM_ID: Pubkey =
+    solana_program::pubkey!("Strategy11111111111111111111111111111111111");
+
+#[derive(BorshSerialize, BorshDeserialize)]
+pub enum StrategyInstruction {
+    // Swap's accounts after the authority, which takes the user's place.
+    // Fails with InsufficientFunds when the returned amount out is short.
+    Swap { amount_in: u64, direction_a_to_b: bool, minimum_amount_out: u64 },
+    // CompactMultihopSwap's accounts after the authority, without the user
+    MultihopSwap { amount_in: u64, minimum_amount_out: u64 },
+    // AddLiquidity / RemoveLiquidity accounts after the authority, without the user
+    Deposit { amount_a: u64, amount_b: u64 },
+    Withdraw { lp_amount: u64 },
+    // GetTwap's pool. Fails with InvalidArgument when token A averaged more
+    // than `maximum_price_a` since `since`.
+    PriceGuard { since: PriceObservation, maximum_price_a: u128 },
+}
+
+pub fn strategy_authority() -> (Pubkey, u8) {
+    Pubkey::find_program_address(&[b"authority"], &STRATEGY_PROGRAM_ID)
+}
+
+fn liquidity_accounts<'a>(
+    swap_program: &AccountInfo<'a>,
+    authority: &AccountInfo<'a>,
+    accounts: &[AccountInfo<'a>],
+    lp_first: bool,
+) -> Result<cpi::LiquidityAccounts<'a>, ProgramError> {
+    let account_info_iter = &mut accounts.iter();
+    let pool = next_account_info(account_info_iter)?;
+    let token_a = next_account_info(account_info_iter)?;
+    let token_b = next_account_info(account_info_iter)?;
+    let vault_a = next_account_info(account_info_iter)?;
+    let vault_b = next_account_info(account_info_iter)?;
+    let lp_mint = next_account_info(account_info_iter)?;
+    let (user_lp, user_token_a, user_token_b) = if lp_first {
+        let user_lp = next_account_info(account_info_iter)?;
+        (user_lp, next_account_info(account_info_iter)?, next_account_info(account_info_iter)?)
+    } else {
+        let user_token_a = next_account_info(account_info_iter)?;
+        let user_token_b = next_account_info(account_info_iter)?;
+        (next_account_info(account_info_iter)?, user_token_a, user_token_b)
+    };
+    let token_program = next_account_info(account_info_iter)?;
+    Ok(cpi::LiquidityAccounts {
+        swap_program: swap_program.clone(),
+        pool: pool.clone(),
+        token_a: token_a.clone(),
+        token_b: token_b.clone(),
+        vault_a: vault_a.clone(),
+        vault_b: vault_b.clone(),
+        lp_mint: lp_mint.clone(),
+        user_token_a: user_token_a.clone(),
+        user_token_b: user_token_b.clone(),
+        user_lp: user_lp.clone(),
+        user: authority.clone(),
+        token_program: token_program.clone(),
+        oracle: None,
+        token_2022_program: account_info_iter.next().cloned(),
+    })
+}
+
+fn process_strategy(_: &Pubkey, accounts: &[AccountInfo], data: &[u8]) -> ProgramResult {
+    let instruction = StrategyInstruction::try_from_slice(data)
+        .map_err(|_| ProgramError::InvalidInstructionData)?;
+    let account_info_iter = &mut accounts.iter();
+    let swap_program = next_account_info(account_info_iter)?;
+    let authority = next_account_info(account_info_iter)?;
+    let (_, bump) = strategy_authority();
+    let authority_seeds: &[&[u8]] = &[b"authority", &[bump]];
+
+    match instruction {
+        StrategyInstruction::Swap { amount_in, direction_a_to_b, minimum_amount_out } => {
+            let swap_accounts = cpi::SwapAccounts {
+                swap_program: swap_program.clone(),
+                pool: next_account_info(account_info_iter)?.clone(),
+                token_a: next_account_info(account_info_iter)?.clone(),
+                token_b: next_account_info(account_info_iter)?.clone(),
+                vault_a: next_account_info(account_info_iter)?.clone(),
+                vault_b: next_account_info(account_info_iter)?.clone(),
+                user_in: next_account_info(account_info_iter)?.clone(),
+                user_out: next_account_info(account_info_iter)?.clone(),
+                user: authority.clone(),
+                token_program: next_account_info(account_info_iter)?.clone(),
+                oracle: None,
+                token_2022_program: account_info_iter.next().cloned(),
+            };
+            let result = cpi::swap_signed(
+                &swap_accounts,
+                amount_in,
+                direction_a_to_b,
+                &[authority_seeds],
+            )?;
+            if result.amount_out < minimum_amount_out {
+                return Err(ProgramError::InsufficientFunds);
+            }
+            Ok(())
+        }
+        StrategyInstruction::MultihopSwap { amount_in, minimum_amount_out } => {
+            let token_program = next_account_info(account_info_iter)?;
+            let route = account_info_iter.as_slice();
+            let num_hops = (route.len() - 3) / 4;
+            let (hops, rest) = route.split_at(num_hops * 3);
+            let (mints, rest) = rest.split_at(num_hops + 1);
+            let multihop_accounts = cpi::MultihopSwapAccounts {
+                swap_program: swap_program.clone(),
+                user: authority.clone(),
+                token_program: token_program.clone(),
+                hops: hops
+                    .chunks(3)
+                    .map(|hop| cpi::HopAccounts {
+                        pool: hop[0].clone(),
+                        vault_in: hop[1].clone(),
+                        vault_out: hop[2].clone(),
+                        oracle: None,
+                    })
+                    .collect(),
+                mints: mints.to_vec(),
+                user_in: rest[0].clone(),
+                user_out: rest[1].clone(),
+                token_2022_program: rest.get(2).cloned(),
+            };
+            cpi::multihop_swap_signed(
+                &multihop_accounts,
+                amount_in,
+                minimum_amount_out,
+                Vec::new(),
+                &[authority_seeds],
+            )?;
+            Ok(())
+        }
+        StrategyInstruction::Deposit { amount_a, amount_b } => {
+            let rest = account_info_iter.as_slice();
+            let liquidity = liquidity_accounts(swap_program, authority, rest, false)?;
+            cpi::add_liquidity_signed(&liquidity, amount_a, amount_b, &[authority_seeds])
+        }
+        StrategyInstruction::Withdraw { lp_amount } => {
+            let rest = account_info_iter.as_slice();
+            let liquidity = liquidity_accounts(swap_program, authority, rest, true)?;
+            cpi::remove_liquidity_signed(&liquidity, lp_amount, &[authority_seeds])
+        }
+        StrategyInstruction::PriceGuard { since, maximum_price_a } => {
+            let pool = next_account_info(account_info_iter)?;
+            let twap = cpi::get_twap(swap_program, pool, since)?;
+            if twap.price_a > maximum_price_a {
+                return Err(ProgramError::InvalidArgument);
+            }
+            Ok(())
+        }
+    }
+}
+
+pub fn program_test() -> ProgramTest {
+    let mut program_test = ProgramTest::new(
+        "cargo_swap",
+        cargo_swap::id(),
+        processor!(cargo_swap::process_instruction),
+    );
+    add_test_programs(&mut program_test);
+    program_test
+}
+
+// The same programs with this one loaded from its SBF build, so its compute
+// units are metered as on chain. Everything else stays native.
+pub fn sbf_program_test() -> ProgramTest {
+    let out_dir = std::env::var("SBF_OUT_DIR")
+        .or_else(|_| std::env::var("BPF_OUT_DIR"))
+        .unwrap_or_else(|_| concat!(env!("CARGO_MANIFEST_DIR"), "/target/deploy").to_string());
+    let program = std::fs::read(std::path::Path::new(&out_dir).join("cargo_swap.so"))
+        .expect("cargo_swap.so not found, run `cargo build-sbf` first");
+
+    let mut program_test = ProgramTest::default();
+    program_test.prefer_bpf(false);
+    program_test.add_account(
+        cargo_swap::id(),
+        Account {
+            lamports: Rent::default().minimum_balance(program.len()),
+            data: program,
+            owner: bpf_loader::id(),
+            executable: true,
+            rent_epoch: 0,
+        },
+    );
+    add_test_programs(&mut program_test);
+    program_test
+}
+
+fn add_test_programs(program_test: &mut ProgramTest) {
+    program_test.add_program(
+        "gorbchain_spl_token",
+        TOKEN_PROGRAM_ID,
+        processor!(spl_token::processor::Processor::process),
+    );
+    program_test.add_program(
+        "gorbchain_ata",
+        ATA_PROGRAM_ID,
+        processor!(process_ata_instruction),
+    );
+    // The wrapped GOR mint, owned by the GorbChain token program
+    let mut native_mint = vec![0; spl_token::state::Mint::LEN];
+    spl_token::state::Mint {
+        decimals: 9,
+        is_initialized: true,
+        ..Default::default()
+    }
+    .pack_into_slice(&mut native_mint);
+    program_test.add_account(
+        NATIVE_MINT,
+        Account {
+            lamports: Rent::default().minimum_balance(native_mint.len()),
+            data: native_mint,
+            owner: TOKEN_PROGRAM_ID,
+            executable: false,
+            rent_epoch: 0,
+        },
+    );
+    program_test.add_program(
+        "flash_receiver",
+        FLASH_RECEIVER_ID,
+        processor!(process_flash_receiver),
+    );
+    program_test.add_program(
+        "flash_defaulter",
+        FLASH_DEFAULTER_ID,
+        processor!(process_flash_defaulter),
+    );
+    program_test.add_program(
+        "strategy",
+        STRATEGY_PROGRAM_ID,
+        processor!(process_strategy),
+    );
+}
+
+pub fn pool_address(token_a: &Pubkey, token_b: &Pubkey) -> Pubkey {
+    Pubkey::find_program_address(
+        &[b"pool", token_a.as_ref(), token_b.as_ref()],
+        &cargo_swap::id(),
+    )
+    .0
+}
+
+pub fn vault_address(pool: &Pubkey, mint: &Pubkey) -> Pubkey {
+    Pubkey::find_program_address(
+        &[b"vault", pool.as_ref(), mint.as_ref()],
+        &cargo_swap::id(),
+    )
+    .0
+}
+
+// Vault address from a bump a pool stored, which must be a valid PDA
+pub fn vault_pda(pool: &Pubkey, mint: &Pubkey, bump: u8) -> Pubkey {
+    Pubkey::create_program_address(&[b"vault", pool.as_ref(), mint.as_ref(), &[bump]], &cargo_swap::id())
+        .unwrap()
+}
+
+pub fn lp_mint_address(pool: &Pubkey) -> Pubkey {
+    Pubkey::find_program_address(&[b"mint", pool.as_ref()], &cargo_swap::id()).0
+}
+
+pub fn associated_token_address(owner: &Pubkey, mint: &Pubkey) -> Pubkey {
+    Pubkey::find_program_address(
+        &[owner.as_ref(), TOKEN_PROGRAM_ID.as_ref(), mint.as_ref()],
+        &ATA_PROGRAM_ID,
+    )
+    .0
+}
+
+// Addresses of everything that belongs to one pool.
+#[derive(Clone, Copy, Debug)]
+pub struct PoolKeys {
+    pub pool: Pubkey,
+    pub token_a: Pubkey,
+    pub token_b: Pubkey,
+    pub vault_a: Pubkey,
+    pub vault_b: Pubkey,
+    pub lp_mint: Pubkey,
+}
+
+impl PoolKeys {
+    pub fn new(token_a: Pubkey, token_b: Pubkey) -> Self {
+        let pool = pool_address(&token_a, &token_b);
+        Self {
+            pool,
+            token_a,
+            token_b,
+            vault_a: vault_address(&pool, &token_a),
+            vault_b: vault_address(&pool, &token_b),
+            lp_mint: lp_mint_address(&pool),
+        }
+    }
+}
+
+fn instruction_data(instruction: &TestProjectInstruction) -> Vec<u8> {
+    instruction.try_to_vec().unwrap()
+}
+
+pub fn init_pool_ix(
+    keys: &PoolKeys,
+    user: &Pubkey,
+    user_token_a: &Pubkey,
+    user_token_b: &Pubkey,
+    amount_a: u64,
+    amount_b: u64,
+) -> Instruction {
+    Instruction {
+        program_id: cargo_swap::id(),
+        accounts: vec![
+            AccountMeta::new(keys.pool, false),
+            AccountMeta::new_readonly(keys.token_a, false),
+            AccountMeta::new_readonly(keys.token_b, false),
+            AccountMeta::new(keys.vault_a, false),
+            AccountMeta::new(keys.vault_b, false),
+            AccountMeta::new(keys.lp_mint, false),
+            AccountMeta::new(*user, true),
+            AccountMeta::new(*user_token_a, false),
+            AccountMeta::new(*user_token_b, false),
+            AccountMeta::new(associated_token_address(user, &keys.lp_mint), false),
+            AccountMeta::new_readonly(TOKEN_PROGRAM_ID, false),
+            AccountMeta::new_readonly(system_program::id(), false),
+            AccountMeta::new_readonly(sysvar::rent::id(), false),
+            AccountMeta::new_readonly(ATA_PROGRAM_ID, false),
+        ],
+        data: instruction_data(&TestProjectInstruction::InitPool { amount_a, amount_b }),
+    }
+}
+
+#[allow(clippy::too_many_arguments)]
+pub fn add_liquidity_ix(
+    keys: &PoolKeys,
+    user: &Pubkey,
+    user_token_a: &Pubkey,
+    user_token_b: &Pubkey,
+    user_lp: &Pubkey,
+    amount_a: u64,
+    amount_b: u64,
+) -> Instruction {
+    Instruction {
+        program_id: cargo_swap::id(),
+        accounts: vec![
+            AccountMeta::new(keys.pool, false),
+            AccountMeta::new_readonly(keys.token_a, false),
+            AccountMeta::new_readonly(keys.token_b, false),
+            AccountMeta::new(keys.vault_a, false),
+            AccountMeta::new(keys.vault_b, false),
+            AccountMeta::new(keys.lp_mint, false),
+            AccountMeta::new(*user_token_a, false),
+            AccountMeta::new(*user_token_b, false),
+            AccountMeta::new(*user_lp, false),
+            AccountMeta::new(*user, true),
+            AccountMeta::new_readonly(TOKEN_PROGRAM_ID, false),
+        ],
+        data: instruction_data(&TestProjectInstruction::AddLiquidity { amount_a, amount_b }),
+    }
+}
+
+pub fn remove_liquidity_ix(
+    keys: &PoolKeys,
+    user: &Pubkey,
+    user_lp: &Pubkey,
+    user_token_a: &Pubkey,
+    user_token_b: &Pubkey,
+    lp_amount: u64,
+) -> Instruction {
+    Instruction {
+        program_id: cargo_swap::id(),
+        accounts: vec![
+            AccountMeta::new(keys.pool, false),
+            AccountMeta::new_readonly(keys.token_a, false),
+            AccountMeta::new_readonly(keys.token_b, false),
+            AccountMeta::new(keys.vault_a, false),
+            AccountMeta::new(keys.vault_b, false),
+            AccountMeta::new(keys.lp_mint, false),
+            AccountMeta::new(*user_lp, false),
+            AccountMeta::new(*user_token_a, false),
+            AccountMeta::new(*user_token_b, false),
+            AccountMeta::new(*user, true),
+            AccountMeta::new_readonly(TOKEN_PROGRAM_ID, false),
+        ],
+        data: instruction_data(&TestProjectInstruction::RemoveLiquidity { lp_amount }),
+    }
+}
+
+pub fn swap_ix(
+    keys: &PoolKeys,
+    user: &Pubkey,
+    user_in: &Pubkey,
+    user_out: &Pubkey,
+    amount_in: u64,
+    direction_a_to_b: bool,
+) -> Instruction {
+    Instruction {
+        program_id: cargo_swap::id(),
+        accounts: vec![
+            AccountMeta::new(keys.pool, false),
+            AccountMeta::new_readonly(keys.token_a, false),
+            AccountMeta::new_readonly(keys.token_b, false),
+            AccountMeta::new(keys.vault_a, false),
+            AccountMeta::new(keys.vault_b, false),
+            AccountMeta::new(*user_in, false),
+            AccountMeta::new(*user_out, false),
+            AccountMeta::new(*user, true),
+            AccountMeta::new_readonly(TOKEN_PROGRAM_ID, false),
+        ],
+        data: instruction_data(&TestProjectInstruction::Swap {
+            amount_in,
+            direction_a_to_b,
+        }),
+    }
+}
+
+// One hop of the 7-accounts-per-hop multihop layout.
+pub struct Hop {
+    pub keys: PoolKeys,
+    pub intermediate: Pubkey,
+    pub output: Pubkey,
+}
+
+fn multihop_accounts(user: &Pubkey, user_input: &Pubkey, hops: &[Hop]) -> Vec<AccountMeta> {
+    let mut accounts = vec![
+        AccountMeta::new(*user, true),
+        AccountMeta::new_readonly(TOKEN_PROGRAM_ID, false),
+        AccountMeta::new(*user_input, false),
+    ];
+    for hop in hops {
+        accounts.extend([
+            AccountMeta::new(hop.keys.pool, false),
+            AccountMeta::new_readonly(hop.keys.token_a, false),
+            AccountMeta::new_readonly(hop.keys.token_b, false),
+            AccountMeta::new(hop.keys.vault_a, false),
+            AccountMeta::new(hop.keys.vault_b, false),
+            AccountMeta::new(hop.intermediate, false),
+            AccountMeta::new(hop.output, false),
+        ]);
+    }
+    accounts
+}
+
+pub fn multihop_swap_ix(
+    user: &Pubkey,
+    user_input: &Pubkey,
+    hops: &[Hop],
+    amount_in: u64,
+    minimum_amount_out: u64,
+) -> Instruction {
+    Instruction {
+        program_id: cargo_swap::id(),
+        accounts: multihop_accounts(user, user_input, hops),
+        data: instruction_data(&TestProjectInstruction::MultihopSwap {
+            amount_in,
+            minimum_amount_out,
+        }),
+    }
+}
+
+pub fn multihop_swap_with_path_ix(
+    user: &Pubkey,
+    user_input: &Pubkey,
+    hops: &[Hop],
+    amount_in: u64,
+    minimum_amount_out: u64,
+    token_path: Vec<Pubkey>,
+) -> Instruction {
+    Instruction {
+        program_id: cargo_swap::id(),
+        accounts: multihop_accounts(user, user_input, hops),
+        data: instruction_data(&TestProjectInstruction::MultihopSwapWithPath {
+            amount_in,
+            minimum_amount_out,
+            token_path,
+        }),
+    }
+}
+
+// MultihopSwapWithHopMinimums: MultihopSwap with one output bound per hop
+pub fn multihop_swap_with_hop_minimums_ix(
+    user: &Pubkey,
+    user_input: &Pubkey,
+    hops: &[Hop],
+    amount_in: u64,
+    minimum_amount_out: u64,
+    minimum_hop_amounts_out: Vec<u64>,
+) -> Instruction {
+    Instruction {
+        program_id: cargo_swap::id(),
+        accounts: multihop_accounts(user, user_input, hops),
+        data: instruction_data(&TestProjectInstruction::MultihopSwapWithHopMinimums {
+            amount_in,
+            minimum_amount_out,
+            minimum_hop_amounts_out,
+        }),
+    }
+}
+
+#[allow(clippy::too_many_arguments)]
+pub fn multihop_swap_with_path_and_hop_minimums_ix(
+    user: &Pubkey,
+    user_input: &Pubkey,
+    hops: &[Hop],
+    amount_in: u64,
+    minimum_amount_out: u64,
+    token_path: Vec<Pubkey>,
+    minimum_hop_amounts_out: Vec<u64>,
+) -> Instruction {
+    Instruction {
+        program_id: cargo_swap::id(),
+        accounts: multihop_accounts(user, user_input, hops),
+        data: instruction_data(&TestProjectInstruction::MultihopSwapWithPathAndHopMinimums {
+            amount_in,
+            minimum_amount_out,
+            token_path,
+            minimum_hop_amounts_out,
+        }),
+    }
+}
+
+// [pool, vault_in, vault_out] per hop of a compact route, then its mints
+fn compact_route_accounts(pools: &[PoolKeys], mints: &[Pubkey]) -> Vec<AccountMeta> {
+    let mut accounts = Vec::new();
+    for (keys, mint_in) in pools.iter().zip(mints) {
+        let (vault_in, vault_out) = if keys.token_a == *mint_in {
+            (keys.vault_a, keys.vault_b)
+        } else {
+            (keys.vault_b, keys.vault_a)
+        };
+        accounts.extend([
+            AccountMeta::new(keys.pool, false),
+            AccountMeta::new(vault_in, false),
+            AccountMeta::new(vault_out, false),
+        ]);
+    }
+    accounts.extend(mints.iter().map(|mint| AccountMeta::new_readonly(*mint, false)));
+    accounts
+}
+
+// CompactMultihopSwap along `mints`, with `pools[i]` trading mints[i] for
+// mints[i + 1]. Only the user's input and final output accounts take part.
+#[allow(clippy::too_many_arguments)]
+pub fn compact_multihop_swap_ix(
+    user: &Pubkey,
+    pools: &[PoolKeys],
+    mints: &[Pubkey],
+    user_in: &Pubkey,
+    user_out: &Pubkey,
+    amount_in: u64,
+    minimum_amount_out: u64,
+    minimum_hop_amounts_out: Vec<u64>,
+) -> Instruction {
+    let mut accounts = vec![
+        AccountMeta::new(*user, true),
+        AccountMeta::new_readonly(TOKEN_PROGRAM_ID, false),
+    ];
+    accounts.extend(compact_route_accounts(pools, mints));
+    accounts.extend([
+        AccountMeta::new(*user_in, false),
+        AccountMeta::new(*user_out, false),
+    ]);
+    Instruction {
+        program_id: cargo_swap::id(),
+        accounts,
+        data: instruction_data(&TestProjectInstruction::CompactMultihopSwap {
+            amount_in,
+            minimum_amount_out,
+            minimum_hop_amounts_out,
+        }),
+    }
+}
+
+// One route of a SplitRouteSwap, in the compact layout
+pub struct SplitLeg {
+    pub pools: Vec<PoolKeys>,
+    pub mints: Vec<Pubkey>,
+    pub amount_in: u64,
+}
+
+pub fn split_route_swap_ix(
+    user: &Pubkey,
+    user_in: &Pubkey,
+    user_out: &Pubkey,
+    legs: &[SplitLeg],
+    minimum_amount_out: u64,
+) -> Instruction {
+    let mut accounts = vec![
+        AccountMeta::new(*user, true),
+        AccountMeta::new_readonly(TOKEN_PROGRAM_ID, false),
+        AccountMeta::new(*user_in, false),
+        AccountMeta::new(*user_out, false),
+    ];
+    for leg in legs {
+        accounts.extend(compact_route_accounts(&leg.pools, &leg.mints));
+    }
+    let routes = legs
+        .iter()
+        .map(|leg| SplitRoute {
+            amount_in: leg.amount_in,
+            num_hops: leg.pools.len() as u8,
+        })
+        .collect();
+    Instruction {
+        program_id: cargo_swap::id(),
+        accounts,
+        data: instruction_data(&TestProjectInstruction::SplitRouteSwap {
+            routes,
+            minimum_amount_out,
+        }),
+    }
+}
+
+// Appends the Token-2022 program account that pools with a Token-2022 mint need
+pub fn with_token_2022(mut instruction: Instruction) -> Instruction {
+    instruction
+        .accounts
+        .push(AccountMeta::new_readonly(TOKEN_2022_PROGRAM_ID, false));
+    instruction
+}
+
+// `instruction` of the swap program, built with the strategy authority as its
+// user, sent through the strategy program instead
+pub fn through_strategy(instruction: Instruction, data: &StrategyInstruction) -> Instruction {
+    let (authority, _) = strategy_authority();
+    let mut accounts = vec![
+        AccountMeta::new_readonly(cargo_swap::id(), false),
+        AccountMeta::new(authority, false),
+    ];
+    accounts.extend(
+        instruction
+            .accounts
+            .into_iter()
+            .filter(|account| account.pubkey != authority),
+    );
+    Instruction {
+        program_id: STRATEGY_PROGRAM_ID,
+        accounts,
+        data: data.try_to_vec().unwrap(),
+    }
+}
+
+// Amount withheld by a Token-2022 transfer fee of `basis_points`, without a cap
+pub fn transfer_fee(amount: u64, basis_points: u16) -> u64 {
+    cargo_swap::curve::ceil_div(amount as u128 * basis_points as u128, 10_000) as u64
+}
+
+pub fn init_pool_with_curve_ix(
+    keys: &PoolKeys,
+    user: &Pubkey,
+    user_token_a: &Pubkey,
+    user_token_b: &Pubkey,
+    amount_a: u64,
+    amount_b: u64,
+    curve: CurveParams,
+) -> Instruction {
+    let mut instruction = init_pool_ix(keys, user, user_token_a, user_token_b, amount_a, amount_b);
+    instruction.data = instruction_data(&TestProjectInstruction::InitPoolWithCurve {
+        amount_a,
+        amount_b,
+        curve,
+    });
+    instruction
+}
+
+// RampAmp / StopRampAmp: [pool, admin]
+pub fn admin_ix(instruction: TestProjectInstruction, pool: &Pubkey, admin: &Pubkey) -> Instruction {
+    Instruction {
+        program_id: cargo_swap::id(),
+        accounts: vec![
+            AccountMeta::new(*pool, false),
+            AccountMeta::new_readonly(*admin, true),
+        ],
+        data: instruction_data(&instruction),
+    }
+}
+
+// Addresses of everything that belongs to one multi-asset pool, tokens in ascending order.
+#[derive(Clone, Debug)]
+pub struct MultiPoolKeys {
+    pub pool: Pubkey,
+    pub tokens: Vec<Pubkey>,
+    pub vaults: Vec<Pubkey>,
+    pub lp_mint: Pubkey,
+}
+
+impl MultiPoolKeys {
+    pub fn new(mut tokens: Vec<Pubkey>) -> Self {
+        tokens.sort();
+        let mut seeds: Vec<&[u8]> = vec![b"multi_pool"];
+        seeds.extend(tokens.iter().map(|token| token.as_ref()));
+        let pool = Pubkey::find_program_address(&seeds, &cargo_swap::id()).0;
+        Self {
+            pool,
+            vaults: tokens.iter().map(|token| vault_address(&pool, token)).collect(),
+            tokens,
+            lp_mint: lp_mint_address(&pool),
+        }
+    }
+
+    // Keys laid out as one multihop hop from tokens[index_in] to tokens[index_out]
+    pub fn hop_keys(&self, index_in: usize, index_out: usize) -> PoolKeys {
+        PoolKeys {
+            pool: self.pool,
+            token_a: self.tokens[index_in],
+            token_b: self.tokens[index_out],
+            vault_a: self.vaults[index_in],
+            vault_b: self.vaults[index_out],
+            lp_mint: self.lp_mint,
+        }
+    }
+}
+
+// `user_tokens` holds one user account per token of `keys`, in the same order.
+pub fn init_multi_pool_ix(
+    keys: &MultiPoolKeys,
+    user: &Pubkey,
+    user_tokens: &[Pubkey],
+    amounts: Vec<u64>,
+) -> Instruction {
+    let mut accounts = vec![
+        AccountMeta::new(keys.pool, false),
+        AccountMeta::new(keys.lp_mint, false),
+        AccountMeta::new(*user, true),
+        AccountMeta::new(associated_token_address(user, &keys.lp_mint), false),
+        AccountMeta::new_readonly(TOKEN_PROGRAM_ID, false),
+        AccountMeta::new_readonly(system_program::id(), false),
+        AccountMeta::new_readonly(sysvar::rent::id(), false),
+        AccountMeta::new_readonly(ATA_PROGRAM_ID, false),
+    ];
+    for ((token, vault), user_token) in keys.tokens.iter().zip(&keys.vaults).zip(user_tokens) {
+        accounts.extend([
+            AccountMeta::new_readonly(*token, false),
+            AccountMeta::new(*vault, false),
+            AccountMeta::new(*user_token, false),
+        ]);
+    }
+    Instruction {
+        program_id: cargo_swap::id(),
+        accounts,
+        data: instruction_data(&TestProjectInstruction::InitMultiPool { amounts }),
+    }
+}
+
+#[allow(clippy::too_many_arguments)]
+pub fn multi_pool_swap_ix(
+    keys: &MultiPoolKeys,
+    user: &Pubkey,
+    index_in: usize,
+    index_out: usize,
+    user_in: &Pubkey,
+    user_out: &Pubkey,
+    amount_in: u64,
+    minimum_amount_out: u64,
+) -> Instruction {
+    Instruction {
+        program_id: cargo_swap::id(),
+        accounts: vec![
+            AccountMeta::new(keys.pool, false),
+            AccountMeta::new_readonly(keys.tokens[index_in], false),
+            AccountMeta::new_readonly(keys.tokens[index_out], false),
+            AccountMeta::new(keys.vaults[index_in], false),
+            AccountMeta::new(keys.vaults[index_out], false),
+            AccountMeta::new(*user_in, false),
+            AccountMeta::new(*user_out, false),
+            AccountMeta::new(*user, true),
+            AccountMeta::new_readonly(TOKEN_PROGRAM_ID, false),
+        ],
+        data: instruction_data(&TestProjectInstruction::MultiPoolSwap {
+            amount_in,
+            minimum_amount_out,
+        }),
+    }
+}
+
+// AddMultiLiquidity / RemoveMultiLiquidity: [pool, lp_mint, user_lp, user, token_program]
+// followed by [mint, vault, user_token] per token
+pub fn multi_liquidity_ix(
+    instruction: TestProjectInstruction,
+    keys: &MultiPoolKeys,
+    user: &Pubkey,
+    user_lp: &Pubkey,
+    user_tokens: &[Pubkey],
+) -> Instruction {
+    let mut accounts = vec![
+        AccountMeta::new(keys.pool, false),
+        AccountMeta::new(keys.lp_mint, false),
+        AccountMeta::new(*user_lp, false),
+        AccountMeta::new(*user, true),
+        AccountMeta::new_readonly(TOKEN_PROGRAM_ID, false),
+    ];
+    for ((token, vault), user_token) in keys.tokens.iter().zip(&keys.vaults).zip(user_tokens) {
+        accounts.extend([
+            AccountMeta::new_readonly(*token, false),
+            AccountMeta::new(*vault, false),
+            AccountMeta::new(*user_token, false),
+        ]);
+    }
+    Instruction {
+        program_id: cargo_swap::id(),
+        accounts,
+        data: instruction_data(&instruction),
+    }
+}
+
+pub fn add_multi_liquidity_single_ix(
+    keys: &MultiPoolKeys,
+    user: &Pubkey,
+    index: usize,
+    user_in: &Pubkey,
+    user_lp: &Pubkey,
+    amount_in: u64,
+    minimum_lp_out: u64,
+) -> Instruction {
+    Instruction {
+        program_id: cargo_swap::id(),
+        accounts: vec![
+            AccountMeta::new(keys.pool, false),
+            AccountMeta::new_readonly(keys.tokens[index], false),
+            AccountMeta::new(keys.vaults[index], false),
+            AccountMeta::new(keys.lp_mint, false),
+            AccountMeta::new(*user_in, false),
+            AccountMeta::new(*user_lp, false),
+            AccountMeta::new(*user, true),
+            AccountMeta::new_readonly(TOKEN_PROGRAM_ID, false),
+        ],
+        data: instruction_data(&TestProjectInstruction::AddMultiLiquiditySingle {
+            amount_in,
+            minimum_lp_out,
+        }),
+    }
+}
+
+pub fn remove_multi_liquidity_single_ix(
+    keys: &MultiPoolKeys,
+    user: &Pubkey,
+    index: usize,
+    user_lp: &Pubkey,
+    user_out: &Pubkey,
+    lp_amount: u64,
+    minimum_amount_out: u64,
+) -> Instruction {
+    Instruction {
+        program_id: cargo_swap::id(),
+        accounts: vec![
+            AccountMeta::new(keys.pool, false),
+            AccountMeta::new_readonly(keys.tokens[index], false),
+            AccountMeta::new(keys.vaults[index], false),
+            AccountMeta::new(keys.lp_mint, false),
+            AccountMeta::new(*user_lp, false),
+            AccountMeta::new(*user_out, false),
+            AccountMeta::new(*user, true),
+            AccountMeta::new_readonly(TOKEN_PROGRAM_ID, false),
+        ],
+        data: instruction_data(&TestProjectInstruction::RemoveMultiLiquiditySingle {
+            lp_amount,
+            minimum_amount_out,
+        }),
+    }
+}
+
+pub fn concentrated_pool_address(token_a: &Pubkey, token_b: &Pubkey) -> Pubkey {
+    Pubkey::find_program_address(
+        &[b"cl_pool", token_a.as_ref(), token_b.as_ref()],
+        &cargo_swap::id(),
+    )
+    .0
+}
+
+pub fn position_address(pool: &Pubkey, owner: &Pubkey, tick_lower: i32, tick_upper: i32) -> Pubkey {
+    Pubkey::find_program_address(
+        &[
+            b"position",
+            pool.as_ref(),
+            owner.as_ref(),
+            &tick_lower.to_le_bytes(),
+            &tick_upper.to_le_bytes(),
+        ],
+        &cargo_swap::id(),
+    )
+    .0
+}
+
+// Concentrated liquidity pools have no LP mint; `lp_mint` is left default.
+pub fn concentrated_pool_keys(token_a: Pubkey, token_b: Pubkey) -> PoolKeys {
+    let pool = concentrated_pool_address(&token_a, &token_b);
+    PoolKeys {
+        pool,
+        token_a,
+        token_b,
+        vault_a: vault_address(&pool, &token_a),
+        vault_b: vault_address(&pool, &token_b),
+        lp_mint: Pubkey::default(),
+    }
+}
+
+pub fn init_concentrated_pool_ix(
+    keys: &PoolKeys,
+    payer: &Pubkey,
+    tick_spacing: u16,
+    initial_sqrt_price: u128,
+) -> Instruction {
+    Instruction {
+        program_id: cargo_swap::id(),
+        accounts: vec![
+            AccountMeta::new(keys.pool, false),
+            AccountMeta::new_readonly(keys.token_a, false),
+            AccountMeta::new_readonly(keys.token_b, false),
+            AccountMeta::new(keys.vault_a, false),
+            AccountMeta::new(keys.vault_b, false),
+            AccountMeta::new(*payer, true),
+            AccountMeta::new_readonly(TOKEN_PROGRAM_ID, false),
+            AccountMeta::new_readonly(system_program::id(), false),
+            AccountMeta::new_readonly(sysvar::rent::id(), false),
+        ],
+        data: instruction_data(&TestProjectInstruction::InitConcentratedPool {
+            tick_spacing,
+            initial_sqrt_price,
+        }),
+    }
+}
+
+pub fn open_position_ix(keys: &PoolKeys, owner: &Pubkey, tick_lower: i32, tick_upper: i32) -> Instruction {
+    Instruction {
+        program_id: cargo_swap::id(),
+        accounts: vec![
+            AccountMeta::new_readonly(keys.pool, false),
+            AccountMeta::new(position_address(&keys.pool, owner, tick_lower, tick_upper), false),
+            AccountMeta::new(*owner, true),
+            AccountMeta::new_readonly(system_program::id(), false),
+        ],
+        data: instruction_data(&TestProjectInstruction::OpenPosition {
+            tick_lower,
+            tick_upper,
+        }),
+    }
+}
+
+// IncreaseLiquidity / DecreaseLiquidity / CollectFees share one account layout
+pub fn position_ix(
+    instruction: TestProjectInstruction,
+    keys: &PoolKeys,
+    position: &Pubkey,
+    owner: &Pubkey,
+    user_token_a: &Pubkey,
+    user_token_b: &Pubkey,
+) -> Instruction {
+    Instruction {
+        program_id: cargo_swap::id(),
+        accounts: vec![
+            AccountMeta::new(keys.pool, false),
+            AccountMeta::new(*position, false),
+            AccountMeta::new_readonly(keys.token_a, false),
+            AccountMeta::new_readonly(keys.token_b, false),
+            AccountMeta::new(keys.vault_a, false),
+            AccountMeta::new(keys.vault_b, false),
+            AccountMeta::new(*user_token_a, false),
+            AccountMeta::new(*user_token_b, false),
+            AccountMeta::new(*owner, true),
+            AccountMeta::new_readonly(TOKEN_PROGRAM_ID, false),
+        ],
+        data: instruction_data(&instruction),
+    }
+}
+
+pub fn concentrated_swap_ix(
+    keys: &PoolKeys,
+    user: &Pubkey,
+    user_in: &Pubkey,
+    user_out: &Pubkey,
+    amount_in: u64,
+    minimum_amount_out: u64,
+    direction_a_to_b: bool,
+) -> Instruction {
+    Instruction {
+        program_id: cargo_swap::id(),
+        accounts: vec![
+            AccountMeta::new(keys.pool, false),
+            AccountMeta::new_readonly(keys.token_a, false),
+            AccountMeta::new_readonly(keys.token_b, false),
+            AccountMeta::new(keys.vault_a, false),
+            AccountMeta::new(keys.vault_b, false),
+            AccountMeta::new(*user_in, false),
+            AccountMeta::new(*user_out, false),
+            AccountMeta::new(*user, true),
+            AccountMeta::new_readonly(TOKEN_PROGRAM_ID, false),
+        ],
+        data: instruction_data(&TestProjectInstruction::ConcentratedSwap {
+            amount_in,
+            minimum_amount_out,
+            direction_a_to_b,
+        }),
+    }
+}
+
+// The payer is passed on to the receiver to sign the repayment. The token
+// program slot holds SPL Token; replace it for a Token-2022 mint.
+pub fn flash_loan_ix(
+    pool: &Pubkey,
+    mint: &Pubkey,
+    vault: &Pubkey,
+    receiver_token: &Pubkey,
+    receiver_program: &Pubkey,
+    payer: &Pubkey,
+    amount: u64,
+) -> Instruction {
+    Instruction {
+        program_id: cargo_swap::id(),
+        accounts: vec![
+            AccountMeta::new(*pool, false),
+            AccountMeta::new_readonly(*mint, false),
+            AccountMeta::new(*vault, false),
+            AccountMeta::new(*receiver_token, false),
+            AccountMeta::new_readonly(*receiver_program, false),
+            AccountMeta::new_readonly(TOKEN_PROGRAM_ID, false),
+            AccountMeta::new_readonly(*payer, true),
+        ],
+        data: instruction_data(&TestProjectInstruction::FlashLoan { amount }),
+    }
+}
+
+// `source` pays for the swap inside the receiver, signed by the payer. Both
+// token program slots hold SPL Token; replace them for Token-2022 mints.
+#[allow(clippy::too_many_arguments)]
+pub fn flash_swap_ix(
+    keys: &PoolKeys,
+    user_token_a: &Pubkey,
+    user_token_b: &Pubkey,
+    receiver_program: &Pubkey,
+    source: &Pubkey,
+    payer: &Pubkey,
+    amount_a_out: u64,
+    amount_b_out: u64,
+) -> Instruction {
+    Instruction {
+        program_id: cargo_swap::id(),
+        accounts: vec![
+            AccountMeta::new(keys.pool, false),
+            AccountMeta::new_readonly(keys.token_a, false),
+            AccountMeta::new_readonly(keys.token_b, false),
+            AccountMeta::new(keys.vault_a, false),
+            AccountMeta::new(keys.vault_b, false),
+            AccountMeta::new(*user_token_a, false),
+            AccountMeta::new(*user_token_b, false),
+            AccountMeta::new_readonly(*receiver_program, false),
+            AccountMeta::new_readonly(TOKEN_PROGRAM_ID, false),
+            AccountMeta::new_readonly(TOKEN_PROGRAM_ID, false),
+            AccountMeta::new(*source, false),
+            AccountMeta::new_readonly(*payer, true),
+        ],
+        data: instruction_data(&TestProjectInstruction::FlashSwap {
+            amount_a_out,
+            amount_b_out,
+        }),
+    }
+}
+
+pub fn native_wrap_address(user: &Pubkey) -> Pubkey {
+    Pubkey::find_program_address(&[b"native", user.as_ref()], &cargo_swap::id()).0
+}
+
+// Runs `inner` through WithNative; `inner` should use native_wrap_address(user)
+// as the user's GOR token account
+pub fn with_native_ix(user: &Pubkey, wrap_amount: u64, inner: Instruction) -> Instruction {
+    let mut accounts = vec![
+        AccountMeta::new(*user, true),
+        AccountMeta::new(native_wrap_address(user), false),
+        AccountMeta::new_readonly(NATIVE_MINT, false),
+        AccountMeta::new_readonly(system_program::id(), false),
+        AccountMeta::new_readonly(TOKEN_PROGRAM_ID, false),
+    ];
+    accounts.extend(inner.accounts);
+    Instruction {
+        program_id: cargo_swap::id(),
+        accounts,
+        data: instruction_data(&TestProjectInstruction::WithNative {
+            wrap_amount,
+            instruction_data: inner.data,
+        }),
+    }
+}
+
+// MigratePool: [pool, payer, system program]
+pub fn migrate_pool_ix(pool: &Pubkey, payer: &Pubkey) -> Instruction {
+    Instruction {
+        program_id: cargo_swap::id(),
+        accounts: vec![
+            AccountMeta::new(*pool, false),
+            AccountMeta::new(*payer, true),
+            AccountMeta::new_readonly(system_program::id(), false),
+        ],
+        data: instruction_data(&TestProjectInstruction::MigratePool),
+    }
+}
+
+pub fn oracle_address(pool: &Pubkey) -> Pubkey {
+    Pubkey::find_program_address(&[b"oracle", pool.as_ref()], &cargo_swap::id()).0
+}
+
+pub fn init_oracle_ix(pool: &Pubkey, admin: &Pubkey, capacity: u16) -> Instruction {
+    Instruction {
+        program_id: cargo_swap::id(),
+        accounts: vec![
+            AccountMeta::new(*pool, false),
+            AccountMeta::new(oracle_address(pool), false),
+            AccountMeta::new(*admin, true),
+            AccountMeta::new_readonly(system_program::id(), false),
+        ],
+        data: instruction_data(&TestProjectInstruction::InitOracle { capacity }),
+    }
+}
+
+pub fn grow_oracle_ix(pool: &Pubkey, payer: &Pubkey, capacity: u16) -> Instruction {
+    Instruction {
+        program_id: cargo_swap::id(),
+        accounts: vec![
+            AccountMeta::new(oracle_address(pool), false),
+            AccountMeta::new(*payer, true),
+            AccountMeta::new_readonly(system_program::id(), false),
+        ],
+        data: instruction_data(&TestProjectInstruction::GrowOracle { capacity }),
+    }
+}
+
+pub fn observe_ix(pool: &Pubkey, seconds_agos: Vec<u32>) -> Instruction {
+    query_ix(
+        TestProjectInstruction::Observe { seconds_agos },
+        &[*pool, oracle_address(pool)],
+    )
+}
+
+// Passes the pool's oracle to a Swap, AddLiquidity or RemoveLiquidity, after
+// its token program
+pub fn with_oracle(instruction: Instruction) -> Instruction {
+    let index = instruction.accounts.len();
+    with_oracle_at(instruction, index)
+}
+
+// Passes the pool's oracle at `index`: 6 for a FlashLoan and 10 for a
+// FlashSwap, before the receiver accounts
+pub fn with_oracle_at(mut instruction: Instruction, index: usize) -> Instruction {
+    let oracle = oracle_address(&instruction.accounts[0].pubkey);
+    instruction.accounts.insert(index, AccountMeta::new(oracle, false));
+    instruction
+}
+
+// Appends the oracles of the pools on a route, in hop order
+pub fn with_route_oracles(mut instruction: Instruction, pools: &[Pubkey]) -> Instruction {
+    instruction
+        .accounts
+        .extend(pools.iter().map(|pool| AccountMeta::new(oracle_address(pool), false)));
+    instruction
+}
+
+pub fn query_ix(instruction: TestProjectInstruction, pools: &[Pubkey]) -> Instruction {
+    Instruction {
+        program_id: cargo_swap::id(),
+        accounts: pools
+            .iter()
+            .map(|pool| AccountMeta::new_readonly(*pool, false))
+            .collect(),
+        data: instruction_data(&instruction),
+    }
+}
+
+// Constant-product output with the 0.3% fee, mirroring the on-chain formula.
+pub fn expected_swap_output(amount_in: u64, reserve_in: u64, reserve_out: u64) -> u64 {
+    let amount_in_with_fee = amount_in as u128 * 997;
+    (amount_in_with_fee * reserve_out as u128
+        / (reserve_in as u128 * 1000 + amount_in_with_fee)) as u64
+}
+
+pub struct TestEnv {
+    pub context: ProgramTestContext,
+}
+
+impl TestEnv {
+    pub async fn start() -> Self {
+        Self {
+            context: program_test().start_with_context().await,
+        }
+    }
+
+    pub async fn start_sbf() -> Self {
+        Self {
+            context: sbf_program_test().start_with_context().await,
+        }
+    }
+
+    pub fn payer(&self) -> Pubkey {
+        self.context.payer.pubkey()
+    }
+
+    pub async fn process(
+        &mut self,
+        instructions: &[Instruction],
+        signers: &[&Keypair],
+    ) -> Result<(), BanksClientError> {
+        let blockhash = self.context.get_new_latest_blockhash().await.unwrap();
+        let mut all_signers: Vec<&Keypair> = vec![&self.context.payer];
+        all_signers.extend_from_slice(signers);
+        let transaction = Transaction::new_signed_with_payer(
+            instructions,
+            Some(&self.context.payer.pubkey()),
+            &all_signers,
+            blockhash,
+        );
+        self.context.banks_client.process_transaction(transaction).await
+    }
+
+    // Runs the instructions through the simulator and returns the program logs.
+    pub async fn simulate_logs(&mut self, instructions: &[Instruction]) -> Vec<String> {
+        let transaction = Transaction::new_signed_with_payer(
+            instructions,
+            Some(&self.context.payer.pubkey()),
+            &[&self.context.payer],
+            self.context.last_blockhash,
+        );
+        let simulation = self
+            .context
+            .banks_client
+            .simulate_transaction(transaction)
+            .await
+            .unwrap();
+        simulation.result.unwrap().unwrap();
+        simulation.simulation_details.unwrap().logs
+    }
+
+    // Compute units the instructions consume, from the simulator
+    pub async fn simulate_units(&mut self, instructions: &[Instruction]) -> u64 {
+        let transaction = Transaction::new_signed_with_payer(
+            instructions,
+            Some(&self.context.payer.pubkey()),
+            &[&self.context.payer],
+            self.context.last_blockhash,
+        );
+        let simulation = self
+            .context
+            .banks_client
+            .simulate_transaction(transaction)
+            .await
+            .unwrap();
+        simulation.result.unwrap().unwrap();
+        simulation.simulation_details.unwrap().units_consumed
+    }
+
+    // Borsh-decoded return data the swap program leaves, from the simulator
+    pub async fn simulate_return<T: BorshDeserialize>(
+        &mut self,
+        instructions: &[Instruction],
+        signers: &[&Keypair],
+    ) -> T {
+        let mut all_signers: Vec<&Keypair> = vec![&self.context.payer];
+        all_signers.extend_from_slice(signers);
+        let transaction = Transaction::new_signed_with_payer(
+            instructions,
+            Some(&self.context.payer.pubkey()),
+            &all_signers,
+            self.context.last_blockhash,
+        );
+        let simulation = self
+            .context
+            .banks_client
+            .simulate_transaction(transaction)
+            .await
+            .unwrap();
+        simulation.result.unwrap().unwrap();
+        let return_data = simulation.simulation_details.unwrap().return_data.unwrap();
+        assert_eq!(return_data.program_id, cargo_swap::id());
+        T::try_from_slice(&return_data.data).unwrap()
+    }
+
+    // Wire size of a transaction of the instructions with the payer paying fees
+    pub fn transaction_size(&self, instructions: &[Instruction]) -> usize {
+        let message = Message::new(instructions, Some(&self.context.payer.pubkey()));
+        // Compact-u16 signature count, then the signatures and the message
+        1 + message.header.num_required_signatures as usize * 64 + message.serialize().len()
+    }
+
+    pub async fn lamports(&mut self, address: &Pubkey) -> u64 {
+        self.context.banks_client.get_balance(*address).await.unwrap()
+    }
+
+    // A fresh keypair holding `lamports`, for balances the fee payer would blur
+    pub async fn create_user(&mut self, lamports: u64) -> Keypair {
+        let user = Keypair::new();
+        let payer = self.payer();
+        self.process(&[system_instruction::transfer(&payer, &user.pubkey(), lamports)], &[])
+            .await
+            .unwrap();
+        user
+    }
+
+    pub fn set_account(&mut self, address: &Pubkey, account: Account) {
+        self.context.set_account(address, &account.into());
+    }
+
+    // Rewrites a pool in the unversioned 89-byte layout, rent-exempt for that
+    // size only
+    pub async fn downgrade_pool(&mut self, pool: &Pubkey) {
+        let mut account = self.get_account(pool).await.unwrap();
+        let state = Pool::unpack(&account.data).unwrap();
+        account.data = state.try_to_vec().unwrap()[..LEGACY_POOL_LEN].to_vec();
+        account.lamports = Rent::default().minimum_balance(LEGACY_POOL_LEN);
+        self.set_account(pool, account);
+    }
+
+    pub async fn get_account(&mut self, address: &Pubkey) -> Option<Account> {
+        self.context.banks_client.get_account(*address).await.unwrap()
+    }
+
+    pub async fn create_mint(&mut self, decimals: u8) -> Pubkey {
+        self.create_mint_with_keypair(Keypair::new(), decimals).await
+    }
+
+    // Mint at the address of `mint`, for tests that pick their mint addresses
+    pub async fn create_mint_with_keypair(&mut self, mint: Keypair, decimals: u8) -> Pubkey {
+        let rent = self.context.banks_client.get_rent().await.unwrap();
+        let payer = self.payer();
+        let instructions = [
+            system_instruction::create_account(
+                &payer,
+                &mint.pubkey(),
+                rent.minimum_balance(spl_token::state::Mint::LEN),
+                spl_token::state::Mint::LEN as u64,
+                &TOKEN_PROGRAM_ID,
+            ),
+            spl_token_ix(
+                vec![
+                    AccountMeta::new(mint.pubkey(), false),
+                    AccountMeta::new_readonly(sysvar::rent::id(), false),
+                ],
+                {
+                    let mut data = vec![0, decimals];
+                    data.extend_from_slice(payer.as_ref());
+                    data.push(0);
+                    data
+                },
+            ),
+        ];
+        self.process(&instructions, &[&mint]).await.unwrap();
+        mint.pubkey()
+    }
+
+    // Token-2022 mints with extensions and no authorities; fees are in basis points
+    pub async fn create_token_2022_mint(&mut self, decimals: u8, transfer_fee: Option<u16>) -> Pubkey {
+        use spl_token_2022::extension::{transfer_fee::instruction as fee_instruction, ExtensionType};
+
+        let mint = Keypair::new();
+        let rent = self.context.banks_client.get_rent().await.unwrap();
+        let payer = self.payer();
+        let extensions: Vec<ExtensionType> =
+            transfer_fee.iter().map(|_| ExtensionType::TransferFeeConfig).collect();
+        let space = ExtensionType::try_calculate_account_len::<spl_token_2022::state::Mint>(&extensions)
+            .unwrap();
+        let mut instructions = vec![system_instruction::create_account(
+            &payer,
+            &mint.pubkey(),
+            rent.minimum_balance(space),
+            space as u64,
+            &TOKEN_2022_PROGRAM_ID,
+        )];
+        if let Some(basis_points) = transfer_fee {
+            instructions.push(
+                fee_instruction::initialize_transfer_fee_config(
+                    &TOKEN_2022_PROGRAM_ID,
+                    &mint.pubkey(),
+                    None,
+                    None,
+                    basis_points,
+                    u64::MAX,
+                )
+                .unwrap(),
+            );
+        }
+        instructions.push(
+            spl_token_2022::instruction::initialize_mint(
+                &TOKEN_2022_PROGRAM_ID,
+                &mint.pubkey(),
+                &payer,
+                None,
+                decimals,
+            )
+            .unwrap(),
+        );
+        self.process(&instructions, &[&mint]).await.unwrap();
+        mint.pubkey()
+    }
+
+    // Token accounts live under the mint's token program, with room for the
+    // extensions a Token-2022 mint requires
+    pub async fn create_token_account(&mut self, mint: &Pubkey, owner: &Pubkey) -> Pubkey {
+        use spl_token_2022::extension::{BaseStateWithExtensions, ExtensionType, StateWithExtensions};
+
+        let account = Keypair::new();
+        let rent = self.context.banks_client.get_rent().await.unwrap();
+        let payer = self.payer();
+        let mint_account = self.get_account(mint).await.expect("mint missing");
+        let space = if mint_account.owner == TOKEN_2022_PROGRAM_ID {
+            let mint_state =
+                StateWithExtensions::<spl_token_2022::state::Mint>::unpack(&mint_account.data).unwrap();
+            let extensions =
+                ExtensionType::get_required_init_account_extensions(&mint_state.get_extension_types().unwrap());
+            ExtensionType::try_calculate_account_len::<spl_token_2022::state::Account>(&extensions).unwrap()
+        } else {
+            spl_token::state::Account::LEN
+        };
+        let instructions = [
+            system_instruction::create_account(
+                &payer,
+                &account.pubkey(),
+                rent.minimum_balance(space),
+                space as u64,
+                &mint_account.owner,
+            ),
+            Instruction {
+                program_id: mint_account.owner,
+                accounts: vec![
+                    AccountMeta::new(account.pubkey(), false),
+                    AccountMeta::new_readonly(*mint, false),
+                    AccountMeta::new_readonly(*owner, false),
+                    AccountMeta::new_readonly(sysvar::rent::id(), false),
+                ],
+                data: vec![1],
+            },
+        ];
+        self.process(&instructions, &[&account]).await.unwrap();
+        account.pubkey()
+    }
+
+    // Mint authority is always the payer (see `create_mint`).
+    pub async fn mint_to(&mut self, mint: &Pubkey, destination: &Pubkey, amount: u64) {
+        let payer = self.payer();
+        let mut data = vec![7];
+        data.extend_from_slice(&amount.to_le_bytes());
+        let token_program = self.get_account(mint).await.expect("mint missing").owner;
+        let instruction = Instruction {
+            program_id: token_program,
+            accounts: vec![
+                AccountMeta::new(*mint, false),
+                AccountMeta::new(*destination, false),
+                AccountMeta::new_readonly(payer, true),
+            ],
+            data,
+        };
+        self.process(&[instruction], &[]).await.unwrap();
+    }
+
+    pub async fn create_funded_account(&mut self, mint: &Pubkey, amount: u64) -> Pubkey {
+        let payer = self.payer();
+        let account = self.create_token_account(mint, &payer).await;
+        if amount > 0 {
+            self.mint_to(mint, &account, amount).await;
+        }
+        account
+    }
+
+    // Token-2022 extensions follow the SPL Token layout, so both programs'
+    // accounts unpack from the same prefix
+    pub async fn token_balance(&mut self, account: &Pubkey) -> u64 {
+        let account = self.get_account(account).await.expect("token account missing");
+        spl_token::state::Account::unpack(&account.data[..spl_token::state::Account::LEN])
+            .unwrap()
+            .amount
+    }
+
+    pub async fn mint_supply(&mut self, mint: &Pubkey) -> u64 {
+        let account = self.get_account(mint).await.expect("mint missing");
+        spl_token::state::Mint::unpack(&account.data[..spl_token::state::Mint::LEN])
+            .unwrap()
+            .supply
+    }
+
+    pub async fn pool(&mut self, pool: &Pubkey) -> Pool {
+        let account = self.get_account(pool).await.expect("pool missing");
+        Pool::unpack(&account.data).unwrap()
+    }
+
+    // Initializes a pool owned by the payer and returns its keys.
+    pub async fn init_pool(
+        &mut self,
+        token_a: Pubkey,
+        token_b: Pubkey,
+        user_token_a: &Pubkey,
+        user_token_b: &Pubkey,
+        amount_a: u64,
+        amount_b: u64,
+    ) -> PoolKeys {
+        let keys = PoolKeys::new(token_a, token_b);
+        let payer = self.payer();
+        let instruction = init_pool_ix(&keys, &payer, user_token_a, user_token_b, amount_a, amount_b);
+        self.process(&[instruction], &[]).await.unwrap();
+        keys
+    }
+
+    // Initializes a pool on the given curve owned by the payer and returns its keys.
+    #[allow(clippy::too_many_arguments)]
+    pub async fn init_pool_with_curve(
+        &mut self,
+        token_a: Pubkey,
+        token_b: Pubkey,
+        user_token_a: &Pubkey,
+        user_token_b: &Pubkey,
+        amount_a: u64,
+        amount_b: u64,
+        curve: CurveParams,
+    ) -> PoolKeys {
+        let keys = PoolKeys::new(token_a, token_b);
+        let payer = self.payer();
+        let instruction = init_pool_with_curve_ix(
+            &keys,
+            &payer,
+            user_token_a,
+            user_token_b,
+            amount_a,
+            amount_b,
+            curve,
+        );
+        self.process(&[instruction], &[]).await.unwrap();
+        keys
+    }
+
+    pub async fn multi_pool(&mut self, pool: &Pubkey) -> MultiPool {
+        let account = self.get_account(pool).await.expect("multi-asset pool missing");
+        MultiPool::unpack(&account.data).unwrap()
+    }
+
+    pub async fn concentrated_pool(&mut self, pool: &Pubkey) -> ConcentratedPool {
+        let account = self.get_account(pool).await.expect("concentrated pool missing");
+        ConcentratedPool::unpack(&account.data).unwrap()
+    }
+
+    pub async fn position(&mut self, position: &Pubkey) -> Position {
+        let account = self.get_account(position).await.expect("position missing");
+        Position::unpack(&account.data).unwrap()
+    }
+
+    // Header and observations, oldest first, of a pool's oracle
+    pub async fn oracle(&mut self, pool: &Pubkey) -> (OracleHeader, Vec<Observation>) {
+        let account = self.get_account(&oracle_address(pool)).await.expect("oracle missing");
+        let ring = ObservationRing::from_bytes(&account.data).unwrap();
+        (ring.header, ring.observations().unwrap())
+    }
+
+    pub async fn clock(&mut self) -> Clock {
+        self.context.banks_client.get_sysvar::<Clock>().await.unwrap()
+    }
+
+    // Moves the bank clock to `unix_timestamp`.
+    pub async fn set_unix_timestamp(&mut self, unix_timestamp: i64) {
+        let mut clock = self.clock().await;
+        clock.unix_timestamp = unix_timestamp;
+        self.context.set_sysvar(&clock);
+    }
+
+    // Vault balances must always match the reserves recorded in the pool.
+    pub async fn assert_pool_consistent(&mut self, keys: &PoolKeys) -> Pool {
+        let pool = self.pool(&keys.pool).await;
+        assert_eq!(self.token_balance(&keys.vault_a).await, pool.reserve_a);
+        assert_eq!(self.token_balance(&keys.vault_b).await, pool.reserve_b);
+        assert_eq!(self.mint_supply(&keys.lp_mint).await, pool.total_lp_supply);
+        // The stored bumps lead to the pool's own PDAs
+        let pda = |seeds: &[&[u8]], bump: u8| {
+            Pubkey::create_program_address(&[seeds, &[&[bump]]].concat(), &cargo_swap::id()).unwrap()
+        };
+        let (pool_key, token_a, token_b) = (keys.pool.as_ref(), keys.token_a.as_ref(), keys.token_b.as_ref());
+        assert_eq!(pda(&[b"vault", pool_key, token_a], pool.vault_a_bump), keys.vault_a);
+        assert_eq!(pda(&[b"vault", pool_key, token_b], pool.vault_b_bump), keys.vault_b);
+        assert_eq!(pda(&[b"mint", pool_key], pool.lp_mint_bump), keys.lp_mint);
+        pool
+    }
+
+    // Every vault must match its reserve in the multi-asset pool.
+    pub async fn assert_multi_pool_consistent(&mut self, keys: &MultiPoolKeys) -> MultiPool {
+        let pool = self.multi_pool(&keys.pool).await;
+        assert_eq!(pool.tokens(), &keys.tokens[..]);
+        for (index, (vault, &reserve)) in keys.vaults.iter().zip(pool.reserves()).enumerate() {
+            assert_eq!(self.token_balance(vault).await, reserve);
+            // The stored bumps lead to the pool's own PDAs
+            assert_eq!(vault_pda(&keys.pool, &keys.tokens[index], pool.vault_bumps[index]), *vault);
+        }
+        let lp_mint =
+            Pubkey::create_program_address(&[b"mint", keys.pool.as_ref(), &[pool.lp_mint_bump]], &cargo_swap::id());
+        assert_eq!(lp_mint.unwrap(), keys.lp_mint);
+        assert_eq!(self.mint_supply(&keys.lp_mint).await, pool.total_lp_supply);
+        pool
+    }
+}
+
+fn spl_token_ix(accounts: Vec<AccountMeta>, data: Vec<u8>) -> Instruction {
+    Instruction {
+        program_id: TOKEN_PROGRAM_ID,
+        accounts,
+        data,
+    }
+}
+
+pub fn unwrap_program_error(result: Result<(), BanksClientError>) -> TransactionError {
+    result.expect_err("transaction should have failed").unwrap()
+}
+
+pub fn instruction_error(index: u8, error: ProgramError) -> TransactionError {
+    TransactionError::InstructionError(index, InstructionError::from(u64::from(error)))
+}
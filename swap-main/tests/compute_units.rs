@@ -0,0 +1,276 @@
+// Compute unit benchmarks for the zero-copy pool state and PDA checks.
+//
+// Versioned pools are read and written in place; the same instruction on the
+// pools rewritten in the legacy layout, which are worked on as a copy, must
+// cost more.
+//
+// Two-token, multi-asset and concentrated pools store their vault and LP mint
+// bumps at init, and instructions check those addresses with
+// create_program_address. A bump search with find_program_address would
+// instead pay one derivation for every bump tried from 255 down, so each
+// instruction is simulated on pools whose vault bumps are all 255 and again on
+// pools whose vault bumps are all at most LOW_BUMP, and both must cost the
+// same to within one derivation. Legacy pools store no bumps and search for
+// them with find_program_address, so on the low-bump pools Swap and every
+// multihop hop must save at least those searches over the legacy layout.
+// Native runs are not metered, so these need the SBF build:
+//
+//     cargo build-sbf && cargo test --test compute_units -- --ignored --nocapture
+mod common;
+
+use common::*;
+use solana_program::{instruction::Instruction, pubkey::Pubkey};
+use solana_sdk::signature::{Keypair, Signer};
+
+// Every address derivation, including each attempt of a bump search
+const PDA_UNITS: u64 = 1_500;
+
+// Searching for this bump takes at least five derivations
+const LOW_BUMP: u8 = 251;
+
+// Least a legacy pool with vault bumps of at most LOW_BUMP pays for its bump
+// searches: five derivations for each vault and one for the LP mint
+const LOW_BUMP_SEARCH_UNITS: u64 = (2 * (u8::MAX - LOW_BUMP + 1) as u64 + 1) * PDA_UNITS;
+
+#[derive(Clone, Copy, Debug)]
+enum Bumps {
+    Highest,
+    Low,
+}
+
+impl Bumps {
+    fn accepts(self, bump: u8) -> bool {
+        match self {
+            Bumps::Highest => bump == u8::MAX,
+            Bumps::Low => bump <= LOW_BUMP,
+        }
+    }
+}
+
+fn vault_bump(pool: &Pubkey, mint: &Pubkey) -> u8 {
+    Pubkey::find_program_address(&[b"vault", pool.as_ref(), mint.as_ref()], &cargo_swap::id()).1
+}
+
+// Keypairs for `hops + 1` mints where each pool of neighbouring mints has
+// vault bumps matching `bumps`
+fn grind_mints(hops: usize, bumps: Bumps) -> Vec<Keypair> {
+    let mut mints = vec![Keypair::new()];
+    while mints.len() <= hops {
+        let mint = Keypair::new();
+        let previous = mints[mints.len() - 1].pubkey();
+        let pool = pool_address(&previous, &mint.pubkey());
+        if [previous, mint.pubkey()]
+            .iter()
+            .all(|token| bumps.accepts(vault_bump(&pool, token)))
+        {
+            mints.push(mint);
+        }
+    }
+    mints
+}
+
+// Keypairs for two mints that make a three-token multi-asset pool with
+// `token` whose vault bumps all match `bumps`
+fn grind_multi_pool_mints(token: &Pubkey, bumps: Bumps) -> [Keypair; 2] {
+    loop {
+        let mints = [Keypair::new(), Keypair::new()];
+        let keys = MultiPoolKeys::new(vec![*token, mints[0].pubkey(), mints[1].pubkey()]);
+        if keys
+            .tokens
+            .iter()
+            .all(|token| bumps.accepts(vault_bump(&keys.pool, token)))
+        {
+            return mints;
+        }
+    }
+}
+
+// Constant product pools chained through the mints, each with 1_000_000 of both
+async fn setup_route(hops: usize, bumps: Bumps) -> (TestEnv, Vec<Hop>, Vec<Pubkey>) {
+    let mut env = TestEnv::start_sbf().await;
+    let mut mints = Vec::new();
+    let mut users = Vec::new();
+    for keypair in grind_mints(hops, bumps) {
+        let mint = env.create_mint_with_keypair(keypair, 6).await;
+        users.push(env.create_funded_account(&mint, 10_000_000).await);
+        mints.push(mint);
+    }
+    let mut route = Vec::new();
+    for i in 0..hops {
+        let keys = env
+            .init_pool(mints[i], mints[i + 1], &users[i], &users[i + 1], 1_000_000, 1_000_000)
+            .await;
+        route.push(Hop {
+            keys,
+            intermediate: users[i + 1],
+            output: users[i + 1],
+        });
+    }
+    (env, route, users)
+}
+
+async fn swap_units(bumps: Bumps) -> u64 {
+    let (mut env, route, users) = setup_route(1, bumps).await;
+    let payer = env.payer();
+    let swap = swap_ix(&route[0].keys, &payer, &users[0], &users[1], 100_000, true);
+    env.simulate_units(&[swap]).await
+}
+
+async fn multihop_units(bumps: Bumps) -> u64 {
+    let (mut env, route, users) = setup_route(4, bumps).await;
+    let payer = env.payer();
+    let multihop = multihop_swap_ix(&payer, &users[0], &route, 10_000, 0);
+    env.simulate_units(&[multihop]).await
+}
+
+// Two constant product hops, then one through a multi-asset pool of the last
+// mint and two more
+async fn multi_pool_route_units(bumps: Bumps) -> u64 {
+    let (mut env, mut route, users) = setup_route(2, bumps).await;
+    let last = route[1].keys.token_b;
+    let mut mints = vec![last];
+    for keypair in grind_multi_pool_mints(&last, bumps) {
+        mints.push(env.create_mint_with_keypair(keypair, 6).await);
+    }
+    let keys = MultiPoolKeys::new(mints);
+    let mut user_tokens = Vec::new();
+    for token in &keys.tokens {
+        let user_token = if *token == last {
+            users[2]
+        } else {
+            env.create_funded_account(token, 10_000_000).await
+        };
+        user_tokens.push(user_token);
+    }
+    let payer = env.payer();
+    env.process(
+        &[init_multi_pool_ix(&keys, &payer, &user_tokens, vec![1_000_000; 3])],
+        &[],
+    )
+    .await
+    .unwrap();
+
+    let index_in = keys.tokens.iter().position(|token| *token == last).unwrap();
+    let index_out = (index_in + 1) % keys.tokens.len();
+    route.push(Hop {
+        keys: keys.hop_keys(index_in, index_out),
+        intermediate: user_tokens[index_out],
+        output: user_tokens[index_out],
+    });
+    let multihop = multihop_swap_ix(&payer, &users[0], &route, 10_000, 0);
+    env.simulate_units(&[multihop]).await
+}
+
+// Units for the instruction on versioned pools, then on the same pools
+// rewritten in the legacy layout, which are restored afterwards
+async fn compare_units(env: &mut TestEnv, pools: &[Pubkey], instruction: &Instruction) -> (u64, u64) {
+    let instructions = std::slice::from_ref(instruction);
+    let versioned = env.simulate_units(instructions).await;
+    let mut accounts = Vec::new();
+    for pool in pools {
+        accounts.push(env.get_account(pool).await.unwrap());
+        env.downgrade_pool(pool).await;
+    }
+    let legacy = env.simulate_units(instructions).await;
+    for (pool, account) in pools.iter().zip(accounts) {
+        env.set_account(pool, account);
+    }
+    (versioned, legacy)
+}
+
+fn route_pools(route: &[Hop]) -> Vec<Pubkey> {
+    route.iter().map(|hop| hop.keys.pool).collect()
+}
+
+fn assert_saves_searches(name: &str, stored: u64, searched: u64, pools: u64) {
+    println!(
+        "{}: {} CU with stored bumps, {} CU searching for them",
+        name, stored, searched
+    );
+    assert!(searched >= stored + pools * LOW_BUMP_SEARCH_UNITS);
+}
+
+fn assert_same_units(name: &str, highest: u64, low: u64) {
+    println!(
+        "{}: {} CU with vault bumps of 255, {} CU with vault bumps of at most {}",
+        name, highest, low, LOW_BUMP
+    );
+    assert!(highest.abs_diff(low) < PDA_UNITS);
+}
+
+#[tokio::test]
+#[ignore = "needs the SBF build"]
+async fn swap_units_do_not_depend_on_bumps() {
+    let highest = swap_units(Bumps::Highest).await;
+    let low = swap_units(Bumps::Low).await;
+    assert_same_units("Swap", highest, low);
+}
+
+#[tokio::test]
+#[ignore = "needs the SBF build"]
+async fn multihop_swap_4_hops_units_do_not_depend_on_bumps() {
+    let highest = multihop_units(Bumps::Highest).await;
+    let low = multihop_units(Bumps::Low).await;
+    assert_same_units("MultihopSwap", highest, low);
+}
+
+#[tokio::test]
+#[ignore = "needs the SBF build"]
+async fn multi_pool_hop_units_do_not_depend_on_bumps() {
+    let highest = multi_pool_route_units(Bumps::Highest).await;
+    let low = multi_pool_route_units(Bumps::Low).await;
+    assert_same_units("MultihopSwap through a multi-asset pool", highest, low);
+}
+
+#[tokio::test]
+#[ignore = "needs the SBF build"]
+async fn swap_compute_units() {
+    let (mut env, route, users) = setup_route(1, Bumps::Highest).await;
+    let payer = env.payer();
+    let swap = swap_ix(&route[0].keys, &payer, &users[0], &users[1], 100_000, true);
+    let (zero_copy, legacy) = compare_units(&mut env, &route_pools(&route), &swap).await;
+    println!("Swap: {} CU zero-copy, {} CU legacy layout", zero_copy, legacy);
+    assert!(zero_copy < legacy);
+}
+
+#[tokio::test]
+#[ignore = "needs the SBF build"]
+async fn multihop_swap_4_hops_compute_units() {
+    let (mut env, route, users) = setup_route(4, Bumps::Highest).await;
+    let payer = env.payer();
+    let multihop = multihop_swap_ix(&payer, &users[0], &route, 10_000, 0);
+    let (zero_copy, legacy) = compare_units(&mut env, &route_pools(&route), &multihop).await;
+    println!("MultihopSwap, 4 hops: {} CU zero-copy, {} CU legacy layout", zero_copy, legacy);
+    assert!(zero_copy < legacy);
+}
+
+#[tokio::test]
+#[ignore = "needs the SBF build"]
+async fn swap_units_save_the_bump_searches() {
+    let (mut env, route, users) = setup_route(1, Bumps::Low).await;
+    let payer = env.payer();
+    let swap = swap_ix(&route[0].keys, &payer, &users[0], &users[1], 100_000, true);
+    let (stored, searched) = compare_units(&mut env, &route_pools(&route), &swap).await;
+    assert_saves_searches("Swap", stored, searched, 1);
+}
+
+#[tokio::test]
+#[ignore = "needs the SBF build"]
+async fn multihop_swap_units_save_the_bump_searches_on_every_hop() {
+    let (mut env, route, users) = setup_route(4, Bumps::Low).await;
+    let payer = env.payer();
+    let mut previous = (0, 0);
+    for hops in 1..=route.len() {
+        let multihop = multihop_swap_ix(&payer, &users[0], &route[..hops], 10_000, 0);
+        let (stored, searched) = compare_units(&mut env, &route_pools(&route[..hops]), &multihop).await;
+        assert_saves_searches(&format!("MultihopSwap, {} hops", hops), stored, searched, hops as u64);
+        // Each hop on its own, so a regression in any one of them fails
+        assert_saves_searches(
+            &format!("MultihopSwap, hop {}", hops),
+            stored - previous.0,
+            searched - previous.1,
+            1,
+        );
+        previous = (stored, searched);
+    }
+}
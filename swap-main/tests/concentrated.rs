@@ -0,0 +1,369 @@
+mod common;
+
+use cargo_swap::{
+    concentrated::{amounts_for_liquidity, sqrt_price_at_tick, ConcentratedPool},
+    curve::fixed_point::ONE,
+    SwapResult, TestProjectInstruction,
+};
+use common::*;
+use solana_program::{program_error::ProgramError, pubkey::Pubkey};
+
+const TICK_SPACING: u16 = 10;
+const LIQUIDITY: u128 = 1_000_000_000;
+
+struct ConcentratedFixture {
+    env: TestEnv,
+    keys: PoolKeys,
+    user_a: Pubkey,
+    user_b: Pubkey,
+}
+
+// Pool priced at 1.0 (tick 0) with no positions yet
+async fn setup() -> ConcentratedFixture {
+    let mut env = TestEnv::start().await;
+    let token_a = env.create_mint(6).await;
+    let token_b = env.create_mint(6).await;
+    let user_a = env.create_funded_account(&token_a, 1_000_000_000_000).await;
+    let user_b = env.create_funded_account(&token_b, 1_000_000_000_000).await;
+    let keys = concentrated_pool_keys(token_a, token_b);
+    let payer = env.payer();
+    env.process(&[init_concentrated_pool_ix(&keys, &payer, TICK_SPACING, ONE)], &[])
+        .await
+        .unwrap();
+    ConcentratedFixture {
+        env,
+        keys,
+        user_a,
+        user_b,
+    }
+}
+
+impl ConcentratedFixture {
+    // Opens a payer-owned position over the range and deposits `liquidity` into it
+    async fn provide(&mut self, tick_lower: i32, tick_upper: i32, liquidity: u128) -> Pubkey {
+        let payer = self.env.payer();
+        let position = position_address(&self.keys.pool, &payer, tick_lower, tick_upper);
+        let instructions = [
+            open_position_ix(&self.keys, &payer, tick_lower, tick_upper),
+            self.position_ix(
+                TestProjectInstruction::IncreaseLiquidity {
+                    liquidity,
+                    maximum_amount_a: u64::MAX,
+                    maximum_amount_b: u64::MAX,
+                },
+                &position,
+            ),
+        ];
+        self.env.process(&instructions, &[]).await.unwrap();
+        position
+    }
+
+    fn position_ix(&self, instruction: TestProjectInstruction, position: &Pubkey) -> solana_program::instruction::Instruction {
+        let payer = self.env.payer();
+        position_ix(instruction, &self.keys, position, &payer, &self.user_a, &self.user_b)
+    }
+
+    fn swap_ix(&self, amount_in: u64, minimum_amount_out: u64, direction_a_to_b: bool) -> solana_program::instruction::Instruction {
+        let payer = self.env.payer();
+        let (user_in, user_out) = if direction_a_to_b {
+            (&self.user_a, &self.user_b)
+        } else {
+            (&self.user_b, &self.user_a)
+        };
+        concentrated_swap_ix(
+            &self.keys,
+            &payer,
+            user_in,
+            user_out,
+            amount_in,
+            minimum_amount_out,
+            direction_a_to_b,
+        )
+    }
+
+    async fn pool(&mut self) -> ConcentratedPool {
+        let pool = self.keys.pool;
+        self.env.concentrated_pool(&pool).await
+    }
+}
+
+fn range_amounts(sqrt_price: u128, tick_lower: i32, tick_upper: i32, liquidity: u128) -> (u64, u64) {
+    amounts_for_liquidity(
+        sqrt_price,
+        sqrt_price_at_tick(tick_lower).unwrap(),
+        sqrt_price_at_tick(tick_upper).unwrap(),
+        liquidity,
+        true,
+    )
+    .unwrap()
+}
+
+#[tokio::test]
+async fn init_concentrated_pool_sets_price_and_tick() {
+    let mut fixture = setup().await;
+    let pool = fixture.pool().await;
+    assert_eq!(pool.token_a, fixture.keys.token_a);
+    assert_eq!(pool.token_b, fixture.keys.token_b);
+    assert_eq!(pool.tick_spacing, TICK_SPACING);
+    assert_eq!(pool.sqrt_price, ONE);
+    assert_eq!(pool.current_tick, 0);
+    assert_eq!(pool.liquidity, 0);
+    assert!(pool.ticks.is_empty());
+    let keys = &fixture.keys;
+    assert_eq!(vault_pda(&keys.pool, &keys.token_a, pool.vault_a_bump), keys.vault_a);
+    assert_eq!(vault_pda(&keys.pool, &keys.token_b, pool.vault_b_bump), keys.vault_b);
+    assert_eq!(fixture.env.token_balance(&fixture.keys.vault_a).await, 0);
+    assert_eq!(fixture.env.token_balance(&fixture.keys.vault_b).await, 0);
+}
+
+#[tokio::test]
+async fn in_range_position_takes_both_tokens() {
+    let mut fixture = setup().await;
+    let before_a = fixture.env.token_balance(&fixture.user_a).await;
+    let before_b = fixture.env.token_balance(&fixture.user_b).await;
+
+    let position = fixture.provide(-1000, 1000, LIQUIDITY).await;
+
+    let (amount_a, amount_b) = range_amounts(ONE, -1000, 1000, LIQUIDITY);
+    assert!(amount_a > 0 && amount_b > 0);
+    assert_eq!(fixture.env.token_balance(&fixture.user_a).await, before_a - amount_a);
+    assert_eq!(fixture.env.token_balance(&fixture.user_b).await, before_b - amount_b);
+    assert_eq!(fixture.env.token_balance(&fixture.keys.vault_a).await, amount_a);
+    assert_eq!(fixture.env.token_balance(&fixture.keys.vault_b).await, amount_b);
+
+    let pool = fixture.pool().await;
+    assert_eq!(pool.liquidity, LIQUIDITY);
+    assert_eq!(pool.ticks.len(), 2);
+    let position = fixture.env.position(&position).await;
+    assert_eq!(position.liquidity, LIQUIDITY);
+    assert_eq!(position.owner, fixture.env.payer());
+}
+
+#[tokio::test]
+async fn out_of_range_positions_are_single_sided() {
+    let mut fixture = setup().await;
+
+    // Above the price the range holds only token A
+    fixture.provide(100, 200, LIQUIDITY).await;
+    let vault_a = fixture.env.token_balance(&fixture.keys.vault_a).await;
+    assert!(vault_a > 0);
+    assert_eq!(fixture.env.token_balance(&fixture.keys.vault_b).await, 0);
+
+    // Below the price it holds only token B
+    fixture.provide(-200, -100, LIQUIDITY).await;
+    assert_eq!(fixture.env.token_balance(&fixture.keys.vault_a).await, vault_a);
+    assert!(fixture.env.token_balance(&fixture.keys.vault_b).await > 0);
+
+    // Neither range covers the current price
+    assert_eq!(fixture.pool().await.liquidity, 0);
+}
+
+#[tokio::test]
+async fn swap_crosses_initialized_ticks() {
+    let mut fixture = setup().await;
+    fixture.provide(-1000, 1000, LIQUIDITY).await;
+    fixture.provide(-100, 100, LIQUIDITY).await;
+    assert_eq!(fixture.pool().await.liquidity, 2 * LIQUIDITY);
+
+    // Enough token A to push the price below the narrow range
+    let before_a = fixture.env.token_balance(&fixture.user_a).await;
+    let before_b = fixture.env.token_balance(&fixture.user_b).await;
+    let mut expected = fixture.pool().await;
+    let (amount_used, amount_out, _) = expected.swap(20_000_000, true).unwrap();
+    let swap = fixture.swap_ix(20_000_000, amount_out, true);
+    fixture.env.process(&[swap], &[]).await.unwrap();
+
+    assert_eq!(fixture.env.token_balance(&fixture.user_a).await, before_a - amount_used);
+    assert_eq!(fixture.env.token_balance(&fixture.user_b).await, before_b + amount_out);
+    let pool = fixture.pool().await;
+    assert!(pool.current_tick < -100);
+    assert!(pool.current_tick >= -1000);
+    assert_eq!(pool.liquidity, LIQUIDITY);
+    assert_eq!(pool.sqrt_price, expected.sqrt_price);
+
+    // Swapping back re-enters the narrow range
+    let swap = fixture.swap_ix(amount_out, 0, false);
+    fixture.env.process(&[swap], &[]).await.unwrap();
+    let pool = fixture.pool().await;
+    assert!(pool.current_tick >= -100);
+    assert_eq!(pool.liquidity, 2 * LIQUIDITY);
+}
+
+#[tokio::test]
+async fn swap_returns_amounts_fee_and_vault_balances() {
+    let mut fixture = setup().await;
+    fixture.provide(-1000, 1000, LIQUIDITY).await;
+    fixture.provide(-100, 100, LIQUIDITY).await;
+    let vault_a = fixture.env.token_balance(&fixture.keys.vault_a).await;
+    let vault_b = fixture.env.token_balance(&fixture.keys.vault_b).await;
+
+    // The fee of every range the swap crosses adds up
+    let mut expected = fixture.pool().await;
+    let (amount_used, amount_out, fee) = expected.swap(20_000_000, true).unwrap();
+    assert!(fee > 0);
+    let swap = fixture.swap_ix(20_000_000, 0, true);
+    let result: SwapResult = fixture.env.simulate_return(&[swap], &[]).await;
+    assert_eq!(
+        result,
+        SwapResult {
+            amount_in: amount_used,
+            amount_out,
+            fee,
+            reserve_in: vault_a + amount_used,
+            reserve_out: vault_b - amount_out,
+        }
+    );
+}
+
+#[tokio::test]
+async fn swap_stops_at_the_end_of_liquidity() {
+    let mut fixture = setup().await;
+    fixture.provide(-100, 100, LIQUIDITY).await;
+    let vault_b = fixture.env.token_balance(&fixture.keys.vault_b).await;
+
+    // Far more input than the range can absorb: the rest stays with the user
+    let before_a = fixture.env.token_balance(&fixture.user_a).await;
+    let swap = fixture.swap_ix(1_000_000_000, 0, true);
+    fixture.env.process(&[swap], &[]).await.unwrap();
+
+    let spent = before_a - fixture.env.token_balance(&fixture.user_a).await;
+    assert!(spent < 1_000_000_000);
+    assert!(fixture.env.token_balance(&fixture.keys.vault_b).await <= 1);
+    assert!(vault_b > 1);
+    assert_eq!(fixture.pool().await.liquidity, 0);
+}
+
+#[tokio::test]
+async fn fees_accrue_only_inside_the_range() {
+    let mut fixture = setup().await;
+    let in_range = fixture.provide(-1000, 1000, LIQUIDITY).await;
+    let out_of_range = fixture.provide(2000, 3000, LIQUIDITY).await;
+
+    let swap = fixture.swap_ix(1_000_000, 0, true);
+    fixture.env.process(&[swap], &[]).await.unwrap();
+    let swap = fixture.swap_ix(1_000_000, 0, false);
+    fixture.env.process(&[swap], &[]).await.unwrap();
+
+    let before_a = fixture.env.token_balance(&fixture.user_a).await;
+    let before_b = fixture.env.token_balance(&fixture.user_b).await;
+    let collect = fixture.position_ix(TestProjectInstruction::CollectFees, &out_of_range);
+    fixture.env.process(&[collect], &[]).await.unwrap();
+    assert_eq!(fixture.env.token_balance(&fixture.user_a).await, before_a);
+    assert_eq!(fixture.env.token_balance(&fixture.user_b).await, before_b);
+
+    // 0.3% of each swap, less rounding
+    let collect = fixture.position_ix(TestProjectInstruction::CollectFees, &in_range);
+    fixture.env.process(&[collect], &[]).await.unwrap();
+    let fee_a = fixture.env.token_balance(&fixture.user_a).await - before_a;
+    let fee_b = fixture.env.token_balance(&fixture.user_b).await - before_b;
+    assert!((2_990..=3_000).contains(&fee_a), "fee_a = {fee_a}");
+    assert!((2_990..=3_000).contains(&fee_b), "fee_b = {fee_b}");
+
+    // Fees are paid once
+    let collect = fixture.position_ix(TestProjectInstruction::CollectFees, &in_range);
+    fixture.env.process(&[collect], &[]).await.unwrap();
+    assert_eq!(fixture.env.token_balance(&fixture.user_a).await - before_a, fee_a);
+    let position = fixture.env.position(&in_range).await;
+    assert_eq!((position.tokens_owed_a, position.tokens_owed_b), (0, 0));
+}
+
+#[tokio::test]
+async fn decrease_liquidity_returns_tokens_and_closes_ticks() {
+    let mut fixture = setup().await;
+    let position = fixture.provide(-1000, 1000, LIQUIDITY).await;
+    let before_a = fixture.env.token_balance(&fixture.user_a).await;
+    let before_b = fixture.env.token_balance(&fixture.user_b).await;
+
+    let decrease = fixture.position_ix(
+        TestProjectInstruction::DecreaseLiquidity {
+            liquidity: LIQUIDITY,
+            minimum_amount_a: 0,
+            minimum_amount_b: 0,
+        },
+        &position,
+    );
+    fixture.env.process(&[decrease], &[]).await.unwrap();
+
+    // Withdrawals round down, so at most one unit per token stays behind
+    let vault_a = fixture.env.token_balance(&fixture.keys.vault_a).await;
+    let vault_b = fixture.env.token_balance(&fixture.keys.vault_b).await;
+    assert!(vault_a <= 1 && vault_b <= 1);
+    let (amount_a, amount_b) = range_amounts(ONE, -1000, 1000, LIQUIDITY);
+    assert_eq!(fixture.env.token_balance(&fixture.user_a).await, before_a + amount_a - vault_a);
+    assert_eq!(fixture.env.token_balance(&fixture.user_b).await, before_b + amount_b - vault_b);
+
+    let pool = fixture.pool().await;
+    assert_eq!(pool.liquidity, 0);
+    assert!(pool.ticks.is_empty());
+    assert_eq!(fixture.env.position(&position).await.liquidity, 0);
+}
+
+#[tokio::test]
+async fn liquidity_slippage_limits_are_enforced() {
+    let mut fixture = setup().await;
+    let position = fixture.provide(-1000, 1000, LIQUIDITY).await;
+    let (amount_a, amount_b) = range_amounts(ONE, -1000, 1000, LIQUIDITY);
+
+    let increase = fixture.position_ix(
+        TestProjectInstruction::IncreaseLiquidity {
+            liquidity: LIQUIDITY,
+            maximum_amount_a: amount_a - 1,
+            maximum_amount_b: amount_b,
+        },
+        &position,
+    );
+    let error = unwrap_program_error(fixture.env.process(&[increase], &[]).await);
+    assert_eq!(error, instruction_error(0, ProgramError::InsufficientFunds));
+
+    let decrease = fixture.position_ix(
+        TestProjectInstruction::DecreaseLiquidity {
+            liquidity: LIQUIDITY,
+            minimum_amount_a: amount_a + 1,
+            minimum_amount_b: 0,
+        },
+        &position,
+    );
+    let error = unwrap_program_error(fixture.env.process(&[decrease], &[]).await);
+    assert_eq!(error, instruction_error(0, ProgramError::InsufficientFunds));
+
+    // Cannot remove more than the position holds
+    let decrease = fixture.position_ix(
+        TestProjectInstruction::DecreaseLiquidity {
+            liquidity: LIQUIDITY + 1,
+            minimum_amount_a: 0,
+            minimum_amount_b: 0,
+        },
+        &position,
+    );
+    let error = unwrap_program_error(fixture.env.process(&[decrease], &[]).await);
+    assert_eq!(error, instruction_error(0, ProgramError::InvalidArgument));
+}
+
+#[tokio::test]
+async fn swap_enforces_minimum_output() {
+    let mut fixture = setup().await;
+    fixture.provide(-1000, 1000, LIQUIDITY).await;
+    let mut expected = fixture.pool().await;
+    let (_, amount_out, _) = expected.swap(1_000_000, true).unwrap();
+
+    let swap = fixture.swap_ix(1_000_000, amount_out + 1, true);
+    let error = unwrap_program_error(fixture.env.process(&[swap], &[]).await);
+    assert_eq!(error, instruction_error(0, ProgramError::InsufficientFunds));
+
+    // Without liquidity there is nothing to swap against
+    let mut empty = setup().await;
+    let swap = empty.swap_ix(1_000_000, 0, true);
+    let error = unwrap_program_error(empty.env.process(&[swap], &[]).await);
+    assert_eq!(error, instruction_error(0, ProgramError::InvalidArgument));
+}
+
+#[tokio::test]
+async fn open_position_rejects_invalid_ranges() {
+    let mut fixture = setup().await;
+    let payer = fixture.env.payer();
+    for (tick_lower, tick_upper) in [(-15, 100), (100, 100), (200, 100), (-443_640, 0)] {
+        let open = open_position_ix(&fixture.keys, &payer, tick_lower, tick_upper);
+        let error = unwrap_program_error(fixture.env.process(&[open], &[]).await);
+        assert_eq!(error, instruction_error(0, ProgramError::InvalidArgument));
+    }
+}
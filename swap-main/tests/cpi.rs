@@ -0,0 +1,204 @@
+mod common;
+
+use common::*;
+use solana_program::{program_error::ProgramError, pubkey::Pubkey};
+
+// Pools A/B and B/C, and token accounts of the strategy program's authority
+struct CpiFixture {
+    env: TestEnv,
+    ab: PoolKeys,
+    bc: PoolKeys,
+    authority: Pubkey,
+    strategy_a: Pubkey,
+    strategy_b: Pubkey,
+    strategy_c: Pubkey,
+    strategy_lp: Pubkey,
+}
+
+async fn setup() -> CpiFixture {
+    let mut env = TestEnv::start().await;
+    let mint_a = env.create_mint(6).await;
+    let mint_b = env.create_mint(6).await;
+    let mint_c = env.create_mint(6).await;
+    let user_a = env.create_funded_account(&mint_a, 10_000_000).await;
+    let user_b = env.create_funded_account(&mint_b, 10_000_000).await;
+    let user_c = env.create_funded_account(&mint_c, 10_000_000).await;
+    let ab = env
+        .init_pool(mint_a, mint_b, &user_a, &user_b, 1_000_000, 2_000_000)
+        .await;
+    let bc = env
+        .init_pool(mint_b, mint_c, &user_b, &user_c, 3_000_000, 1_500_000)
+        .await;
+
+    let (authority, _) = strategy_authority();
+    let strategy_a = env.create_token_account(&mint_a, &authority).await;
+    let strategy_b = env.create_token_account(&mint_b, &authority).await;
+    let strategy_c = env.create_token_account(&mint_c, &authority).await;
+    let strategy_lp = env.create_token_account(&ab.lp_mint, &authority).await;
+    env.mint_to(&mint_a, &strategy_a, 100_000).await;
+    env.mint_to(&mint_b, &strategy_b, 100_000).await;
+    CpiFixture {
+        env,
+        ab,
+        bc,
+        authority,
+        strategy_a,
+        strategy_b,
+        strategy_c,
+        strategy_lp,
+    }
+}
+
+#[tokio::test]
+async fn strategy_swaps_as_its_pda() {
+    let CpiFixture {
+        mut env,
+        ab,
+        authority,
+        strategy_a,
+        strategy_b,
+        ..
+    } = setup().await;
+
+    let expected = expected_swap_output(10_000, 1_000_000, 2_000_000);
+    let swap = swap_ix(&ab, &authority, &strategy_a, &strategy_b, 10_000, true);
+    let instruction = through_strategy(
+        swap,
+        &StrategyInstruction::Swap {
+            amount_in: 10_000,
+            direction_a_to_b: true,
+            minimum_amount_out: expected,
+        },
+    );
+    env.process(&[instruction], &[]).await.unwrap();
+
+    assert_eq!(env.token_balance(&strategy_a).await, 90_000);
+    assert_eq!(env.token_balance(&strategy_b).await, 100_000 + expected);
+    let pool = env.assert_pool_consistent(&ab).await;
+    assert_eq!(pool.reserve_a, 1_010_000);
+    assert_eq!(pool.reserve_b, 2_000_000 - expected);
+}
+
+#[tokio::test]
+async fn strategy_reads_the_returned_amount_out() {
+    let CpiFixture {
+        mut env,
+        ab,
+        authority,
+        strategy_a,
+        strategy_b,
+        ..
+    } = setup().await;
+
+    // Swap has no minimum of its own: the strategy checks the return data
+    let expected = expected_swap_output(10_000, 1_000_000, 2_000_000);
+    let swap = swap_ix(&ab, &authority, &strategy_a, &strategy_b, 10_000, true);
+    let instruction = through_strategy(
+        swap,
+        &StrategyInstruction::Swap {
+            amount_in: 10_000,
+            direction_a_to_b: true,
+            minimum_amount_out: expected + 1,
+        },
+    );
+    let error = unwrap_program_error(env.process(&[instruction], &[]).await);
+
+    assert_eq!(error, instruction_error(0, ProgramError::InsufficientFunds));
+    assert_eq!(env.token_balance(&strategy_a).await, 100_000);
+}
+
+#[tokio::test]
+async fn strategy_routes_a_multihop_swap() {
+    let CpiFixture {
+        mut env,
+        ab,
+        bc,
+        authority,
+        strategy_a,
+        strategy_c,
+        ..
+    } = setup().await;
+
+    let b_out = expected_swap_output(10_000, 1_000_000, 2_000_000);
+    let c_out = expected_swap_output(b_out, 3_000_000, 1_500_000);
+    let multihop = compact_multihop_swap_ix(
+        &authority,
+        &[ab, bc],
+        &[ab.token_a, ab.token_b, bc.token_b],
+        &strategy_a,
+        &strategy_c,
+        10_000,
+        0,
+        Vec::new(),
+    );
+    let instruction = through_strategy(
+        multihop,
+        &StrategyInstruction::MultihopSwap {
+            amount_in: 10_000,
+            minimum_amount_out: c_out,
+        },
+    );
+    env.process(&[instruction], &[]).await.unwrap();
+
+    assert_eq!(env.token_balance(&strategy_a).await, 90_000);
+    assert_eq!(env.token_balance(&strategy_c).await, c_out);
+    env.assert_pool_consistent(&ab).await;
+    env.assert_pool_consistent(&bc).await;
+}
+
+#[tokio::test]
+async fn strategy_deposits_and_withdraws_liquidity() {
+    let CpiFixture {
+        mut env,
+        ab,
+        authority,
+        strategy_a,
+        strategy_b,
+        strategy_lp,
+        ..
+    } = setup().await;
+    let supply_before = env.pool(&ab.pool).await.total_lp_supply;
+
+    let deposit = add_liquidity_ix(
+        &ab,
+        &authority,
+        &strategy_a,
+        &strategy_b,
+        &strategy_lp,
+        10_000,
+        20_000,
+    );
+    let instruction = through_strategy(
+        deposit,
+        &StrategyInstruction::Deposit {
+            amount_a: 10_000,
+            amount_b: 20_000,
+        },
+    );
+    env.process(&[instruction], &[]).await.unwrap();
+
+    let pool = env.assert_pool_consistent(&ab).await;
+    assert_eq!(pool.reserve_a, 1_010_000);
+    assert_eq!(pool.reserve_b, 2_020_000);
+    let lp_amount = env.token_balance(&strategy_lp).await;
+    assert_eq!(lp_amount, pool.total_lp_supply - supply_before);
+    assert!(lp_amount > 0);
+
+    let withdraw = remove_liquidity_ix(
+        &ab,
+        &authority,
+        &strategy_lp,
+        &strategy_a,
+        &strategy_b,
+        lp_amount,
+    );
+    let instruction = through_strategy(withdraw, &StrategyInstruction::Withdraw { lp_amount });
+    env.process(&[instruction], &[]).await.unwrap();
+
+    let pool = env.assert_pool_consistent(&ab).await;
+    assert_eq!(pool.total_lp_supply, supply_before);
+    assert_eq!(env.token_balance(&strategy_lp).await, 0);
+    // Withdrawals round down, so at most one unit of each token stays behind
+    assert!(env.token_balance(&strategy_a).await >= 99_999);
+    assert!(env.token_balance(&strategy_b).await >= 99_999);
+}
@@ -0,0 +1,559 @@
+mod common;
+
+use cargo_swap::{
+    curve::{OffsetCurve, SwapCurve, WeightedCurve},
+    CurveParams, CurveType, SwapResult, TestProjectInstruction,
+};
+use common::*;
+use solana_program::{program_error::ProgramError, pubkey::Pubkey};
+
+// One token B costs two token A
+const PRICE: u64 = 2;
+
+struct CurveFixture {
+    env: TestEnv,
+    keys: PoolKeys,
+    user_a: Pubkey,
+    user_b: Pubkey,
+}
+
+async fn setup(amount_a: u64, amount_b: u64, curve: CurveParams) -> CurveFixture {
+    let mut env = TestEnv::start().await;
+    let mint_a = env.create_mint(6).await;
+    let mint_b = env.create_mint(6).await;
+    let user_a = env.create_funded_account(&mint_a, 10_000_000).await;
+    let user_b = env.create_funded_account(&mint_b, 10_000_000).await;
+    let keys = env
+        .init_pool_with_curve(mint_a, mint_b, &user_a, &user_b, amount_a, amount_b, curve)
+        .await;
+    CurveFixture {
+        env,
+        keys,
+        user_a,
+        user_b,
+    }
+}
+
+async fn constant_price_setup() -> CurveFixture {
+    setup(
+        1_000_000,
+        500_000,
+        CurveParams::ConstantPrice {
+            token_b_price: PRICE,
+        },
+    )
+    .await
+}
+
+// A one-sided launch pool: no real token B, priced against 1_000_000 virtual B
+async fn offset_setup() -> CurveFixture {
+    setup(
+        1_000_000,
+        0,
+        CurveParams::Offset {
+            token_a_offset: 0,
+            token_b_offset: 1_000_000,
+        },
+    )
+    .await
+}
+
+// An 80/20 pool holding four times as much A as B, so both tokens start at the same price
+const WEIGHTED: WeightedCurve = WeightedCurve {
+    weight_a: 8_000,
+    weight_b: 2_000,
+};
+
+async fn weighted_setup() -> CurveFixture {
+    setup(
+        4_000_000,
+        1_000_000,
+        CurveParams::Weighted {
+            weight_a: WEIGHTED.weight_a,
+            weight_b: WEIGHTED.weight_b,
+        },
+    )
+    .await
+}
+
+fn assert_logged(logs: &[String], expected: &str) {
+    assert!(
+        logs.iter().any(|line| line.ends_with(expected)),
+        "missing log line {:?} in {:#?}",
+        expected,
+        logs
+    );
+}
+
+#[tokio::test]
+async fn constant_price_pool_swaps_at_fixed_rate() {
+    let CurveFixture {
+        mut env,
+        keys,
+        user_a,
+        user_b,
+    } = constant_price_setup().await;
+    let payer = env.payer();
+
+    let pool = env.assert_pool_consistent(&keys).await;
+    assert_eq!(pool.curve_type, CurveType::ConstantPrice);
+    // LP is minted for the pool's value in token A
+    assert_eq!(pool.total_lp_supply, 2_000_000);
+
+    // 10_000 A, 9_970 after the fee, buys 4_985 B
+    env.process(&[swap_ix(&keys, &payer, &user_a, &user_b, 10_000, true)], &[])
+        .await
+        .unwrap();
+    let pool = env.assert_pool_consistent(&keys).await;
+    assert_eq!((pool.reserve_a, pool.reserve_b), (1_010_000, 495_015));
+
+    // The rate does not move with the reserves: 1_000 B, 997 after the fee, buys 1_994 A
+    env.process(&[swap_ix(&keys, &payer, &user_b, &user_a, 1_000, false)], &[])
+        .await
+        .unwrap();
+    let pool = env.assert_pool_consistent(&keys).await;
+    assert_eq!((pool.reserve_a, pool.reserve_b), (1_008_006, 496_015));
+}
+
+#[tokio::test]
+async fn constant_price_pool_cannot_pay_out_more_than_it_holds() {
+    let CurveFixture {
+        mut env,
+        keys,
+        user_a,
+        user_b,
+    } = constant_price_setup().await;
+    let payer = env.payer();
+
+    let result = env
+        .process(&[swap_ix(&keys, &payer, &user_a, &user_b, 2_000_000, true)], &[])
+        .await;
+    assert_eq!(
+        unwrap_program_error(result),
+        instruction_error(0, ProgramError::InvalidArgument)
+    );
+    env.assert_pool_consistent(&keys).await;
+}
+
+#[tokio::test]
+async fn constant_price_deposits_are_valued_at_the_fixed_price() {
+    let CurveFixture {
+        mut env,
+        keys,
+        user_a,
+        user_b,
+    } = constant_price_setup().await;
+    let payer = env.payer();
+    let user_lp = associated_token_address(&payer, &keys.lp_mint);
+
+    env.process(&[swap_ix(&keys, &payer, &user_a, &user_b, 10_000, true)], &[])
+        .await
+        .unwrap();
+
+    // Ratio-matched to 1_010_000 : 495_015, worth 10_100 + 4_950 * 2 of 2_000_030 pool value
+    env.process(
+        &[add_liquidity_ix(&keys, &payer, &user_a, &user_b, &user_lp, 10_100, 10_000)],
+        &[],
+    )
+    .await
+    .unwrap();
+    let pool = env.assert_pool_consistent(&keys).await;
+    assert_eq!((pool.reserve_a, pool.reserve_b), (1_020_100, 499_965));
+    let minted = pool.total_lp_supply - 2_000_000;
+    assert_eq!(minted, (20_000u128 * 2_000_000 / 2_000_030) as u64);
+
+    env.process(
+        &[remove_liquidity_ix(&keys, &payer, &user_lp, &user_a, &user_b, pool.total_lp_supply)],
+        &[],
+    )
+    .await
+    .unwrap();
+    let pool = env.assert_pool_consistent(&keys).await;
+    assert_eq!((pool.reserve_a, pool.reserve_b, pool.total_lp_supply), (0, 0, 0));
+}
+
+#[tokio::test]
+async fn init_rejects_invalid_curve_params() {
+    let mut env = TestEnv::start().await;
+    let payer = env.payer();
+    let mint_a = env.create_mint(6).await;
+    let mint_b = env.create_mint(6).await;
+    let user_a = env.create_funded_account(&mint_a, 10_000_000).await;
+    let user_b = env.create_funded_account(&mint_b, 10_000_000).await;
+    let keys = PoolKeys::new(mint_a, mint_b);
+
+    for (amount_b, curve) in [
+        (1_000_000, CurveParams::ConstantPrice { token_b_price: 0 }),
+        (
+            0,
+            CurveParams::Offset {
+                token_a_offset: 1_000_000,
+                token_b_offset: 0,
+            },
+        ),
+    ] {
+        let result = env
+            .process(
+                &[init_pool_with_curve_ix(
+                    &keys, &payer, &user_a, &user_b, 1_000_000, amount_b, curve,
+                )],
+                &[],
+            )
+            .await;
+        assert_eq!(
+            unwrap_program_error(result),
+            instruction_error(0, ProgramError::InvalidArgument)
+        );
+    }
+    assert!(env.get_account(&keys.pool).await.is_none());
+}
+
+#[tokio::test]
+async fn offset_pool_prices_against_virtual_reserves() {
+    let CurveFixture {
+        mut env,
+        keys,
+        user_a,
+        user_b,
+    } = offset_setup().await;
+    let payer = env.payer();
+
+    let pool = env.assert_pool_consistent(&keys).await;
+    assert_eq!(pool.curve_type, CurveType::Offset);
+    assert_eq!(pool.total_lp_supply, 1_000_000);
+
+    // Buying A with B prices like a 1_000_000 : 1_000_000 constant product pool
+    let a_out = expected_swap_output(10_000, 1_000_000, 1_000_000);
+    env.process(&[swap_ix(&keys, &payer, &user_b, &user_a, 10_000, false)], &[])
+        .await
+        .unwrap();
+    let pool = env.assert_pool_consistent(&keys).await;
+    assert_eq!((pool.reserve_a, pool.reserve_b), (1_000_000 - a_out, 10_000));
+
+    // Selling A can only pay out real B, never the virtual offset
+    let result = env
+        .process(&[swap_ix(&keys, &payer, &user_a, &user_b, 50_000, true)], &[])
+        .await;
+    assert_eq!(
+        unwrap_program_error(result),
+        instruction_error(0, ProgramError::InvalidArgument)
+    );
+    env.process(&[swap_ix(&keys, &payer, &user_a, &user_b, 5_000, true)], &[])
+        .await
+        .unwrap();
+    env.assert_pool_consistent(&keys).await;
+}
+
+#[tokio::test]
+async fn offset_pool_rejects_deposits_but_allows_withdrawals() {
+    let CurveFixture {
+        mut env,
+        keys,
+        user_a,
+        user_b,
+    } = offset_setup().await;
+    let payer = env.payer();
+    let user_lp = associated_token_address(&payer, &keys.lp_mint);
+
+    env.process(&[swap_ix(&keys, &payer, &user_b, &user_a, 10_000, false)], &[])
+        .await
+        .unwrap();
+
+    let result = env
+        .process(
+            &[add_liquidity_ix(&keys, &payer, &user_a, &user_b, &user_lp, 10_000, 10_000)],
+            &[],
+        )
+        .await;
+    assert_eq!(
+        unwrap_program_error(result),
+        instruction_error(0, ProgramError::InvalidArgument)
+    );
+
+    // Withdrawals return a share of the real reserves only
+    let pool = env.pool(&keys.pool).await;
+    env.process(
+        &[remove_liquidity_ix(&keys, &payer, &user_lp, &user_a, &user_b, 500_000)],
+        &[],
+    )
+    .await
+    .unwrap();
+    let after = env.assert_pool_consistent(&keys).await;
+    assert_eq!(after.reserve_a, pool.reserve_a - pool.reserve_a / 2);
+    assert_eq!(after.reserve_b, 5_000);
+}
+
+#[tokio::test]
+async fn get_pool_info_logs_curve_params_and_spot_price() {
+    let CurveFixture { mut env, keys, .. } = constant_price_setup().await;
+    let logs = env
+        .simulate_logs(&[query_ix(TestProjectInstruction::GetPoolInfo, &[keys.pool])])
+        .await;
+    assert_logged(&logs, "Curve: ConstantPrice");
+    assert_logged(&logs, "Token B Price: 2");
+    assert_logged(&logs, "Spot Price A->B: 0.500000");
+
+    let CurveFixture { mut env, keys, .. } = offset_setup().await;
+    let logs = env
+        .simulate_logs(&[query_ix(TestProjectInstruction::GetPoolInfo, &[keys.pool])])
+        .await;
+    assert_logged(&logs, "Curve: Offset");
+    assert_logged(&logs, "Token B Offset: 1000000");
+    assert_logged(&logs, "Spot Price A->B: 1.000000");
+}
+
+#[tokio::test]
+async fn multihop_routes_across_curve_types() {
+    let CurveFixture {
+        mut env,
+        keys: price_keys,
+        user_a,
+        user_b,
+    } = constant_price_setup().await;
+    let payer = env.payer();
+    let mint_c = env.create_mint(6).await;
+    let mint_d = env.create_mint(6).await;
+    let user_c = env.create_funded_account(&mint_c, 10_000_000).await;
+    let user_d = env.create_funded_account(&mint_d, 10_000_000).await;
+    let cp_keys = env
+        .init_pool(price_keys.token_b, mint_c, &user_b, &user_c, 1_000_000, 2_000_000)
+        .await;
+    let offset_keys = env
+        .init_pool_with_curve(
+            mint_c,
+            mint_d,
+            &user_c,
+            &user_d,
+            0,
+            1_000_000,
+            CurveParams::Offset {
+                token_a_offset: 500_000,
+                token_b_offset: 0,
+            },
+        )
+        .await;
+
+    // A -(constant price)-> B -(constant product)-> C -(offset)-> D
+    let b_out = (10_000 * 997 / 1000) / PRICE;
+    let c_out = expected_swap_output(b_out, 1_000_000, 2_000_000);
+    let offset_curve = OffsetCurve {
+        token_a_offset: 500_000,
+        token_b_offset: 0,
+    };
+    let d_out = offset_curve.swap_exact_in(c_out, 0, 1_000_000, true).unwrap();
+    assert_eq!(d_out, expected_swap_output(c_out, 500_000, 1_000_000));
+
+    let path = vec![price_keys.token_a, price_keys.token_b, mint_c, mint_d];
+    let logs = env
+        .simulate_logs(&[query_ix(
+            TestProjectInstruction::GetMultihopQuote {
+                amount_in: 10_000,
+                token_path: path,
+            },
+            &[price_keys.pool, cp_keys.pool, offset_keys.pool],
+        )])
+        .await;
+    assert_logged(&logs, &format!("Final Amount Out: {}", d_out));
+
+    let user_d_before = env.token_balance(&user_d).await;
+    let hops = vec![
+        Hop {
+            keys: price_keys,
+            intermediate: user_b,
+            output: user_b,
+        },
+        Hop {
+            keys: cp_keys,
+            intermediate: user_c,
+            output: user_c,
+        },
+        Hop {
+            keys: offset_keys,
+            intermediate: user_d,
+            output: user_d,
+        },
+    ];
+    env.process(&[multihop_swap_ix(&payer, &user_a, &hops, 10_000, d_out)], &[])
+        .await
+        .unwrap();
+
+    assert_eq!(env.token_balance(&user_d).await, user_d_before + d_out);
+    env.assert_pool_consistent(&price_keys).await;
+    env.assert_pool_consistent(&cp_keys).await;
+    env.assert_pool_consistent(&offset_keys).await;
+}
+
+#[tokio::test]
+async fn weighted_pool_swaps_on_the_weighted_product() {
+    let CurveFixture {
+        mut env,
+        keys,
+        user_a,
+        user_b,
+    } = weighted_setup().await;
+    let payer = env.payer();
+
+    let pool = env.assert_pool_consistent(&keys).await;
+    assert_eq!(pool.curve_type, CurveType::Weighted);
+    // LP is minted for the invariant 4_000_000^0.8 * 1_000_000^0.2
+    let invariant = 4_000_000f64.powf(0.8) * 1_000_000f64.powf(0.2);
+    assert!((pool.total_lp_supply as f64 - invariant).abs() <= 1.0);
+
+    // out = 1_000_000 * (1 - (4_000_000 / (4_000_000 + 9_970))^4)
+    let expected_out = WEIGHTED.swap_exact_in(10_000, 4_000_000, 1_000_000, true).unwrap();
+    let float_out = 1_000_000f64 * (1.0 - (4_000_000f64 / 4_009_970f64).powi(4));
+    assert!((expected_out as f64 - float_out).abs() <= 1.0);
+
+    let logs = env
+        .simulate_logs(&[query_ix(
+            TestProjectInstruction::GetSwapQuote {
+                amount_in: 10_000,
+                token_in: keys.token_a,
+            },
+            &[keys.pool],
+        )])
+        .await;
+    assert_logged(&logs, &format!("Amount Out: {}", expected_out));
+
+    env.process(&[swap_ix(&keys, &payer, &user_a, &user_b, 10_000, true)], &[])
+        .await
+        .unwrap();
+    let pool = env.assert_pool_consistent(&keys).await;
+    assert_eq!((pool.reserve_a, pool.reserve_b), (4_010_000, 1_000_000 - expected_out));
+}
+
+#[tokio::test]
+async fn weighted_pool_logs_weights_and_spot_price() {
+    let CurveFixture { mut env, keys, .. } = weighted_setup().await;
+    let logs = env
+        .simulate_logs(&[query_ix(TestProjectInstruction::GetPoolInfo, &[keys.pool])])
+        .await;
+    assert_logged(&logs, "Curve: Weighted");
+    assert_logged(&logs, "Weights: 8000/2000");
+    // (1_000_000 / 0.2) / (4_000_000 / 0.8)
+    assert_logged(&logs, "Spot Price A->B: 1.000000");
+}
+
+#[tokio::test]
+async fn weighted_pool_rejects_bad_weights_and_oversized_trades() {
+    let mut env = TestEnv::start().await;
+    let payer = env.payer();
+    let mint_a = env.create_mint(6).await;
+    let mint_b = env.create_mint(6).await;
+    let user_a = env.create_funded_account(&mint_a, 10_000_000).await;
+    let user_b = env.create_funded_account(&mint_b, 10_000_000).await;
+    let keys = PoolKeys::new(mint_a, mint_b);
+
+    for (weight_a, weight_b) in [(5_000, 4_000), (50, 9_950), (0, 10_000)] {
+        let result = env
+            .process(
+                &[init_pool_with_curve_ix(
+                    &keys,
+                    &payer,
+                    &user_a,
+                    &user_b,
+                    4_000_000,
+                    1_000_000,
+                    CurveParams::Weighted { weight_a, weight_b },
+                )],
+                &[],
+            )
+            .await;
+        assert_eq!(
+            unwrap_program_error(result),
+            instruction_error(0, ProgramError::InvalidArgument)
+        );
+    }
+
+    let keys = env
+        .init_pool_with_curve(
+            mint_a,
+            mint_b,
+            &user_a,
+            &user_b,
+            4_000_000,
+            1_000_000,
+            CurveParams::Weighted {
+                weight_a: 8_000,
+                weight_b: 2_000,
+            },
+        )
+        .await;
+    // More than 30% of the input reserve in one trade
+    let result = env
+        .process(&[swap_ix(&keys, &payer, &user_b, &user_a, 400_000, false)], &[])
+        .await;
+    assert_eq!(
+        unwrap_program_error(result),
+        instruction_error(0, ProgramError::InvalidArgument)
+    );
+    env.assert_pool_consistent(&keys).await;
+}
+
+#[tokio::test]
+async fn weighted_liquidity_round_trip() {
+    let CurveFixture {
+        mut env,
+        keys,
+        user_a,
+        user_b,
+    } = weighted_setup().await;
+    let payer = env.payer();
+    let user_lp = associated_token_address(&payer, &keys.lp_mint);
+    let initial = env.pool(&keys.pool).await;
+
+    // Deposits keep the 4:1 ratio and mint proportionally
+    env.process(
+        &[add_liquidity_ix(&keys, &payer, &user_a, &user_b, &user_lp, 400_000, 500_000)],
+        &[],
+    )
+    .await
+    .unwrap();
+    let pool = env.assert_pool_consistent(&keys).await;
+    assert_eq!((pool.reserve_a, pool.reserve_b), (4_400_000, 1_100_000));
+    assert_eq!(pool.total_lp_supply, initial.total_lp_supply + initial.total_lp_supply / 10);
+
+    env.process(
+        &[remove_liquidity_ix(&keys, &payer, &user_lp, &user_a, &user_b, pool.total_lp_supply)],
+        &[],
+    )
+    .await
+    .unwrap();
+    let pool = env.assert_pool_consistent(&keys).await;
+    assert_eq!((pool.reserve_a, pool.reserve_b, pool.total_lp_supply), (0, 0, 0));
+}
+
+#[tokio::test]
+async fn swaps_return_the_fee_of_the_pool_curve() {
+    for (curve, setup) in [
+        (CurveParams::ConstantPrice { token_b_price: PRICE }, constant_price_setup().await),
+        (
+            CurveParams::Offset {
+                token_a_offset: 0,
+                token_b_offset: 1_000_000,
+            },
+            offset_setup().await,
+        ),
+        (
+            CurveParams::Weighted {
+                weight_a: WEIGHTED.weight_a,
+                weight_b: WEIGHTED.weight_b,
+            },
+            weighted_setup().await,
+        ),
+    ] {
+        let CurveFixture {
+            mut env,
+            keys,
+            user_a,
+            user_b,
+        } = setup;
+        let payer = env.payer();
+
+        // B for A, as the offset pool holds no real B to pay out
+        let swap = swap_ix(&keys, &payer, &user_b, &user_a, 10_001, false);
+        let result: SwapResult = env.simulate_return(&[swap], &[]).await;
+        assert_eq!(result.amount_in, 10_001);
+        assert_eq!(result.fee, curve.curve().swap_fee(10_001));
+    }
+}
@@ -0,0 +1,208 @@
+mod common;
+
+use cargo_swap::flash_loan::flash_loan_fee;
+use common::*;
+use solana_program::{program_error::ProgramError, pubkey::Pubkey};
+
+struct FlashLoanFixture {
+    env: TestEnv,
+    keys: PoolKeys,
+    user_a: Pubkey,
+    user_b: Pubkey,
+}
+
+async fn setup() -> FlashLoanFixture {
+    let mut env = TestEnv::start().await;
+    let mint_a = env.create_mint(6).await;
+    let mint_b = env.create_mint(6).await;
+    let user_a = env.create_funded_account(&mint_a, 10_000_000).await;
+    let user_b = env.create_funded_account(&mint_b, 10_000_000).await;
+    let keys = env
+        .init_pool(mint_a, mint_b, &user_a, &user_b, 1_000_000, 2_000_000)
+        .await;
+    FlashLoanFixture {
+        env,
+        keys,
+        user_a,
+        user_b,
+    }
+}
+
+#[test]
+fn flash_loan_fee_rounds_up() {
+    assert_eq!(flash_loan_fee(1).unwrap(), 1);
+    assert_eq!(flash_loan_fee(10_000).unwrap(), 9);
+    assert_eq!(flash_loan_fee(10_001).unwrap(), 10);
+    assert_eq!(flash_loan_fee(u64::MAX).unwrap(), 16_602_069_666_338_597);
+}
+
+#[tokio::test]
+async fn flash_loan_credits_fee_to_reserve() {
+    let FlashLoanFixture {
+        mut env,
+        keys,
+        user_a: _,
+        user_b,
+    } = setup().await;
+    let payer = env.payer();
+    let user_b_before = env.token_balance(&user_b).await;
+
+    let amount = 500_000;
+    let fee = flash_loan_fee(amount).unwrap();
+    let instruction = flash_loan_ix(
+        &keys.pool,
+        &keys.token_b,
+        &keys.vault_b,
+        &user_b,
+        &FLASH_RECEIVER_ID,
+        &payer,
+        amount,
+    );
+    env.process(&[instruction], &[]).await.unwrap();
+
+    let pool = env.assert_pool_consistent(&keys).await;
+    assert_eq!(pool.reserve_a, 1_000_000);
+    assert_eq!(pool.reserve_b, 2_000_000 + fee);
+    assert_eq!(env.token_balance(&user_b).await, user_b_before - fee);
+}
+
+#[tokio::test]
+async fn flash_loan_can_borrow_the_whole_reserve() {
+    let FlashLoanFixture {
+        mut env,
+        keys,
+        user_a,
+        user_b: _,
+    } = setup().await;
+    let payer = env.payer();
+
+    let instruction = flash_loan_ix(
+        &keys.pool,
+        &keys.token_a,
+        &keys.vault_a,
+        &user_a,
+        &FLASH_RECEIVER_ID,
+        &payer,
+        1_000_000,
+    );
+    env.process(&[instruction], &[]).await.unwrap();
+    let pool = env.assert_pool_consistent(&keys).await;
+    assert_eq!(pool.reserve_a, 1_000_000 + flash_loan_fee(1_000_000).unwrap());
+
+    let instruction = flash_loan_ix(
+        &keys.pool,
+        &keys.token_a,
+        &keys.vault_a,
+        &user_a,
+        &FLASH_RECEIVER_ID,
+        &payer,
+        2_000_000,
+    );
+    let error = unwrap_program_error(env.process(&[instruction], &[]).await);
+    assert_eq!(error, instruction_error(0, ProgramError::InsufficientFunds));
+}
+
+#[tokio::test]
+async fn flash_loan_without_fee_fails() {
+    let FlashLoanFixture {
+        mut env,
+        keys,
+        user_a,
+        user_b: _,
+    } = setup().await;
+    let payer = env.payer();
+    let user_a_before = env.token_balance(&user_a).await;
+
+    let instruction = flash_loan_ix(
+        &keys.pool,
+        &keys.token_a,
+        &keys.vault_a,
+        &user_a,
+        &FLASH_DEFAULTER_ID,
+        &payer,
+        100_000,
+    );
+    let error = unwrap_program_error(env.process(&[instruction], &[]).await);
+    assert_eq!(error, instruction_error(0, ProgramError::InsufficientFunds));
+
+    // Nothing moved
+    env.assert_pool_consistent(&keys).await;
+    assert_eq!(env.token_balance(&user_a).await, user_a_before);
+}
+
+#[tokio::test]
+async fn flash_loan_rejects_foreign_vault() {
+    let FlashLoanFixture {
+        mut env,
+        keys,
+        user_a,
+        user_b,
+    } = setup().await;
+    let payer = env.payer();
+
+    // A vault of a different pool cannot be drawn through this one
+    let mint_c = env.create_mint(6).await;
+    let user_c = env.create_funded_account(&mint_c, 10_000_000).await;
+    let other = env
+        .init_pool(keys.token_a, mint_c, &user_a, &user_c, 1_000_000, 1_000_000)
+        .await;
+    let instruction = flash_loan_ix(
+        &keys.pool,
+        &keys.token_a,
+        &other.vault_a,
+        &user_a,
+        &FLASH_RECEIVER_ID,
+        &payer,
+        1_000,
+    );
+    let error = unwrap_program_error(env.process(&[instruction], &[]).await);
+    assert_eq!(error, instruction_error(0, ProgramError::InvalidSeeds));
+
+    let instruction = flash_loan_ix(
+        &keys.pool,
+        &keys.token_b,
+        &keys.vault_b,
+        &user_b,
+        &FLASH_RECEIVER_ID,
+        &payer,
+        0,
+    );
+    let error = unwrap_program_error(env.process(&[instruction], &[]).await);
+    assert_eq!(error, instruction_error(0, ProgramError::InvalidArgument));
+}
+
+#[tokio::test]
+async fn flash_loan_from_multi_asset_pool() {
+    let mut env = TestEnv::start().await;
+    let mut mints = Vec::new();
+    for _ in 0..3 {
+        mints.push(env.create_mint(6).await);
+    }
+    let keys = MultiPoolKeys::new(mints);
+    let mut user_tokens = Vec::new();
+    for token in &keys.tokens {
+        user_tokens.push(env.create_funded_account(token, 10_000_000).await);
+    }
+    let payer = env.payer();
+    let amounts = vec![1_000_000, 2_000_000, 3_000_000];
+    env.process(&[init_multi_pool_ix(&keys, &payer, &user_tokens, amounts)], &[])
+        .await
+        .unwrap();
+
+    let instruction = flash_loan_ix(
+        &keys.pool,
+        &keys.tokens[2],
+        &keys.vaults[2],
+        &user_tokens[2],
+        &FLASH_RECEIVER_ID,
+        &payer,
+        1_500_000,
+    );
+    env.process(&[instruction], &[]).await.unwrap();
+
+    let pool = env.assert_multi_pool_consistent(&keys).await;
+    assert_eq!(
+        pool.reserves(),
+        &[1_000_000, 2_000_000, 3_000_000 + flash_loan_fee(1_500_000).unwrap()]
+    );
+}
@@ -0,0 +1,191 @@
+mod common;
+
+use cargo_swap::{curve, CurveParams, SwapResult};
+use common::*;
+use solana_program::{program_error::ProgramError, pubkey::Pubkey};
+
+struct FlashSwapFixture {
+    env: TestEnv,
+    keys: PoolKeys,
+    user_a: Pubkey,
+    user_b: Pubkey,
+}
+
+async fn setup(curve: CurveParams) -> FlashSwapFixture {
+    let mut env = TestEnv::start().await;
+    let mint_a = env.create_mint(6).await;
+    let mint_b = env.create_mint(6).await;
+    let user_a = env.create_funded_account(&mint_a, 10_000_000).await;
+    let user_b = env.create_funded_account(&mint_b, 10_000_000).await;
+    let keys = env
+        .init_pool_with_curve(mint_a, mint_b, &user_a, &user_b, 1_000_000, 2_000_000, curve)
+        .await;
+    FlashSwapFixture {
+        env,
+        keys,
+        user_a,
+        user_b,
+    }
+}
+
+#[tokio::test]
+async fn flash_swap_paid_in_other_token_costs_a_regular_swap() {
+    let FlashSwapFixture {
+        mut env,
+        keys,
+        user_a,
+        user_b,
+    } = setup(CurveParams::ConstantProduct).await;
+    let payer = env.payer();
+    let user_a_before = env.token_balance(&user_a).await;
+    let user_b_before = env.token_balance(&user_b).await;
+
+    // Take B first, pay with A inside the callback
+    let amount_out = 100_000;
+    let pool = env.pool(&keys.pool).await;
+    let quote = pool.swap_input(amount_out, true).unwrap();
+    let cost = flash_swap_payment(&pool, 0, amount_out, true).unwrap();
+    // The fee rounds up on the whole payment, which can cost 1 over the quote
+    assert!(cost == quote || cost == quote + 1);
+    let instruction = flash_swap_ix(&keys, &user_a, &user_b, &FLASH_RECEIVER_ID, &user_a, &payer, 0, amount_out);
+    env.process(&[instruction], &[]).await.unwrap();
+
+    let pool = env.assert_pool_consistent(&keys).await;
+    assert_eq!(pool.reserve_a, 1_000_000 + cost);
+    assert_eq!(pool.reserve_b, 2_000_000 - amount_out);
+    assert_eq!(env.token_balance(&user_a).await, user_a_before - cost);
+    assert_eq!(env.token_balance(&user_b).await, user_b_before + amount_out);
+    // The same trade through Swap would have been no cheaper
+    assert!(expected_swap_output(quote - 1, 1_000_000, 2_000_000) < amount_out);
+}
+
+#[tokio::test]
+async fn flash_swap_repaid_in_same_token_pays_the_fee() {
+    let FlashSwapFixture {
+        mut env,
+        keys,
+        user_a: _,
+        user_b,
+    } = setup(CurveParams::ConstantProduct).await;
+    let payer = env.payer();
+    let user_b_before = env.token_balance(&user_b).await;
+
+    let amount_out = 997_000;
+    let instruction = flash_swap_ix(&keys, &user_b, &user_b, &FLASH_RECEIVER_ID, &user_b, &payer, 0, amount_out);
+    env.process(&[instruction], &[]).await.unwrap();
+
+    // 1_000_000 repaid for 997_000 borrowed
+    let pool = env.assert_pool_consistent(&keys).await;
+    assert_eq!(pool.reserve_a, 1_000_000);
+    assert_eq!(pool.reserve_b, 2_003_000);
+    assert_eq!(env.token_balance(&user_b).await, user_b_before - 3_000);
+}
+
+#[tokio::test]
+async fn flash_swap_underpayment_fails() {
+    for curve in [CurveParams::ConstantProduct, CurveParams::Stable { amp: 100 }] {
+        let FlashSwapFixture {
+            mut env,
+            keys,
+            user_a,
+            user_b,
+        } = setup(curve).await;
+        let payer = env.payer();
+        let user_a_before = env.token_balance(&user_a).await;
+
+        let instruction = flash_swap_ix(&keys, &user_a, &user_b, &FLASH_DEFAULTER_ID, &user_a, &payer, 0, 100_000);
+        let error = unwrap_program_error(env.process(&[instruction], &[]).await);
+        assert_eq!(error, instruction_error(0, ProgramError::InsufficientFunds));
+
+        // Nothing moved
+        let pool = env.assert_pool_consistent(&keys).await;
+        assert_eq!((pool.reserve_a, pool.reserve_b), (1_000_000, 2_000_000));
+        assert_eq!(env.token_balance(&user_a).await, user_a_before);
+    }
+}
+
+#[tokio::test]
+async fn flash_swap_prices_through_the_pool_curve() {
+    let FlashSwapFixture {
+        mut env,
+        keys,
+        user_a,
+        user_b,
+    } = setup(CurveParams::Stable { amp: 100 }).await;
+    let payer = env.payer();
+
+    // Take A, pay with B
+    let amount_out = 200_000;
+    let quote = CurveParams::Stable { amp: 100 }
+        .curve()
+        .swap_exact_out(amount_out, 1_000_000, 2_000_000, false)
+        .unwrap();
+    let instruction = flash_swap_ix(&keys, &user_a, &user_b, &FLASH_RECEIVER_ID, &user_b, &payer, amount_out, 0);
+    env.process(&[instruction], &[]).await.unwrap();
+
+    // The exact-output quote, or 1 more where the fee rounds up
+    let pool = env.assert_pool_consistent(&keys).await;
+    assert_eq!(pool.reserve_a, 1_000_000 - amount_out);
+    assert!(pool.reserve_b == 2_000_000 + quote || pool.reserve_b == 2_000_000 + quote + 1);
+}
+
+#[tokio::test]
+async fn flash_swap_rejects_invalid_amounts() {
+    let FlashSwapFixture {
+        mut env,
+        keys,
+        user_a,
+        user_b,
+    } = setup(CurveParams::ConstantProduct).await;
+    let payer = env.payer();
+
+    let instruction = flash_swap_ix(&keys, &user_a, &user_b, &FLASH_RECEIVER_ID, &user_a, &payer, 0, 0);
+    let error = unwrap_program_error(env.process(&[instruction], &[]).await);
+    assert_eq!(error, instruction_error(0, ProgramError::InvalidArgument));
+
+    // The whole reserve can never be taken
+    let instruction = flash_swap_ix(&keys, &user_a, &user_b, &FLASH_RECEIVER_ID, &user_a, &payer, 0, 2_000_000);
+    let error = unwrap_program_error(env.process(&[instruction], &[]).await);
+    assert_eq!(error, instruction_error(0, ProgramError::InsufficientFunds));
+}
+
+#[tokio::test]
+async fn flash_swap_returns_the_net_trade() {
+    let FlashSwapFixture {
+        mut env,
+        keys,
+        user_a,
+        user_b,
+    } = setup(CurveParams::ConstantProduct).await;
+    let payer = env.payer();
+
+    // Take B, pay with A: a swap of A for B
+    let amount_out = 100_000;
+    let cost = flash_swap_payment(&env.pool(&keys.pool).await, 0, amount_out, true).unwrap();
+    let instruction = flash_swap_ix(&keys, &user_a, &user_b, &FLASH_RECEIVER_ID, &user_a, &payer, 0, amount_out);
+    let result: SwapResult = env.simulate_return(&[instruction], &[]).await;
+    assert_eq!(
+        result,
+        SwapResult {
+            amount_in: cost,
+            amount_out,
+            fee: curve::swap_fee(cost),
+            reserve_in: 1_000_000 + cost,
+            reserve_out: 2_000_000 - amount_out,
+        }
+    );
+
+    // Borrowed and repaid in B: only the fee comes in
+    let instruction = flash_swap_ix(&keys, &user_b, &user_b, &FLASH_RECEIVER_ID, &user_b, &payer, 0, 997_000);
+    let result: SwapResult = env.simulate_return(&[instruction], &[]).await;
+    assert_eq!(
+        result,
+        SwapResult {
+            amount_in: 3_000,
+            amount_out: 0,
+            fee: 3_000,
+            reserve_in: 2_003_000,
+            reserve_out: 1_000_000,
+        }
+    );
+}
@@ -0,0 +1,332 @@
+mod common;
+
+use common::*;
+use solana_program::program_error::ProgramError;
+use solana_sdk::signature::Signer;
+
+const TOKEN_A_AMOUNT: u64 = 10_000_000;
+const TOKEN_B_AMOUNT: u64 = 40_000_000;
+
+struct LiquidityFixture {
+    env: TestEnv,
+    keys: PoolKeys,
+    user_a: solana_program::pubkey::Pubkey,
+    user_b: solana_program::pubkey::Pubkey,
+    user_lp: solana_program::pubkey::Pubkey,
+}
+
+async fn setup() -> LiquidityFixture {
+    let mut env = TestEnv::start().await;
+    let mint_a = env.create_mint(6).await;
+    let mint_b = env.create_mint(6).await;
+    let user_a = env.create_funded_account(&mint_a, TOKEN_A_AMOUNT).await;
+    let user_b = env.create_funded_account(&mint_b, TOKEN_B_AMOUNT).await;
+    let keys = env
+        .init_pool(mint_a, mint_b, &user_a, &user_b, 1_000_000, 4_000_000)
+        .await;
+    let payer = env.payer();
+    let user_lp = associated_token_address(&payer, &keys.lp_mint);
+    LiquidityFixture {
+        env,
+        keys,
+        user_a,
+        user_b,
+        user_lp,
+    }
+}
+
+#[tokio::test]
+async fn init_pool_funds_vaults_and_mints_initial_liquidity() {
+    let LiquidityFixture {
+        mut env,
+        keys,
+        user_a,
+        user_b,
+        user_lp,
+    } = setup().await;
+
+    let pool = env.assert_pool_consistent(&keys).await;
+    assert_eq!(pool.token_a, keys.token_a);
+    assert_eq!(pool.token_b, keys.token_b);
+    assert_eq!(pool.reserve_a, 1_000_000);
+    assert_eq!(pool.reserve_b, 4_000_000);
+    // sqrt(1_000_000 * 4_000_000)
+    assert_eq!(pool.total_lp_supply, 2_000_000);
+    assert_eq!(env.token_balance(&user_lp).await, 2_000_000);
+    assert_eq!(env.token_balance(&user_a).await, TOKEN_A_AMOUNT - 1_000_000);
+    assert_eq!(env.token_balance(&user_b).await, TOKEN_B_AMOUNT - 4_000_000);
+
+    let pool_account = env.get_account(&keys.pool).await.unwrap();
+    assert_eq!(pool_account.owner, cargo_swap::id());
+    let vault_account = env.get_account(&keys.vault_a).await.unwrap();
+    assert_eq!(vault_account.owner, TOKEN_PROGRAM_ID);
+}
+
+#[tokio::test]
+async fn init_pool_twice_fails() {
+    let LiquidityFixture {
+        mut env,
+        keys,
+        user_a,
+        user_b,
+        ..
+    } = setup().await;
+
+    let payer = env.payer();
+    let result = env
+        .process(
+            &[init_pool_ix(&keys, &payer, &user_a, &user_b, 1_000, 1_000)],
+            &[],
+        )
+        .await;
+    assert!(result.is_err());
+    env.assert_pool_consistent(&keys).await;
+}
+
+#[tokio::test]
+async fn init_pool_rejects_wrong_vault() {
+    let mut env = TestEnv::start().await;
+    let mint_a = env.create_mint(6).await;
+    let mint_b = env.create_mint(6).await;
+    let user_a = env.create_funded_account(&mint_a, TOKEN_A_AMOUNT).await;
+    let user_b = env.create_funded_account(&mint_b, TOKEN_B_AMOUNT).await;
+
+    let mut keys = PoolKeys::new(mint_a, mint_b);
+    keys.vault_a = keys.vault_b;
+    let payer = env.payer();
+    let result = env
+        .process(
+            &[init_pool_ix(&keys, &payer, &user_a, &user_b, 1_000, 1_000)],
+            &[],
+        )
+        .await;
+    assert_eq!(
+        unwrap_program_error(result),
+        instruction_error(0, ProgramError::InvalidSeeds)
+    );
+    assert!(env.get_account(&keys.pool).await.is_none());
+}
+
+#[tokio::test]
+async fn add_liquidity_keeps_ratio_and_only_takes_required_amounts() {
+    let LiquidityFixture {
+        mut env,
+        keys,
+        user_a,
+        user_b,
+        user_lp,
+    } = setup().await;
+    let payer = env.payer();
+    let user_a_before = env.token_balance(&user_a).await;
+    let user_b_before = env.token_balance(&user_b).await;
+
+    // 100_000 A needs 400_000 B at the 1:4 ratio; the extra B is never taken.
+    env.process(
+        &[add_liquidity_ix(
+            &keys, &payer, &user_a, &user_b, &user_lp, 100_000, 500_000,
+        )],
+        &[],
+    )
+    .await
+    .unwrap();
+
+    let pool = env.assert_pool_consistent(&keys).await;
+    assert_eq!(pool.reserve_a, 1_100_000);
+    assert_eq!(pool.reserve_b, 4_400_000);
+    assert_eq!(pool.total_lp_supply, 2_200_000);
+    assert_eq!(env.token_balance(&user_lp).await, 2_200_000);
+    assert_eq!(env.token_balance(&user_a).await, user_a_before - 100_000);
+    assert_eq!(env.token_balance(&user_b).await, user_b_before - 400_000);
+}
+
+#[tokio::test]
+async fn add_liquidity_limited_by_token_b() {
+    let LiquidityFixture {
+        mut env,
+        keys,
+        user_a,
+        user_b,
+        user_lp,
+    } = setup().await;
+    let payer = env.payer();
+
+    // 400_000 B only supports 100_000 A, so A is the side that gets trimmed.
+    env.process(
+        &[add_liquidity_ix(
+            &keys, &payer, &user_a, &user_b, &user_lp, 300_000, 400_000,
+        )],
+        &[],
+    )
+    .await
+    .unwrap();
+
+    let pool = env.assert_pool_consistent(&keys).await;
+    assert_eq!(pool.reserve_a, 1_100_000);
+    assert_eq!(pool.reserve_b, 4_400_000);
+    assert_eq!(pool.total_lp_supply, 2_200_000);
+}
+
+#[tokio::test]
+async fn add_liquidity_rejects_mismatched_pool() {
+    let LiquidityFixture {
+        mut env,
+        keys,
+        user_a,
+        user_b,
+        user_lp,
+    } = setup().await;
+    let payer = env.payer();
+
+    let mut swapped = keys;
+    swapped.token_a = keys.token_b;
+    swapped.token_b = keys.token_a;
+    let result = env
+        .process(
+            &[add_liquidity_ix(
+                &swapped, &payer, &user_a, &user_b, &user_lp, 100_000, 400_000,
+            )],
+            &[],
+        )
+        .await;
+    assert_eq!(
+        unwrap_program_error(result),
+        instruction_error(0, ProgramError::InvalidSeeds)
+    );
+    let pool = env.assert_pool_consistent(&keys).await;
+    assert_eq!(pool.total_lp_supply, 2_000_000);
+}
+
+#[tokio::test]
+async fn remove_liquidity_returns_proportional_share() {
+    let LiquidityFixture {
+        mut env,
+        keys,
+        user_a,
+        user_b,
+        user_lp,
+    } = setup().await;
+    let payer = env.payer();
+    let user_a_before = env.token_balance(&user_a).await;
+    let user_b_before = env.token_balance(&user_b).await;
+
+    env.process(
+        &[remove_liquidity_ix(
+            &keys, &payer, &user_lp, &user_a, &user_b, 500_000,
+        )],
+        &[],
+    )
+    .await
+    .unwrap();
+
+    let pool = env.assert_pool_consistent(&keys).await;
+    assert_eq!(pool.reserve_a, 750_000);
+    assert_eq!(pool.reserve_b, 3_000_000);
+    assert_eq!(pool.total_lp_supply, 1_500_000);
+    assert_eq!(env.token_balance(&user_lp).await, 1_500_000);
+    assert_eq!(env.token_balance(&user_a).await, user_a_before + 250_000);
+    assert_eq!(env.token_balance(&user_b).await, user_b_before + 1_000_000);
+}
+
+#[tokio::test]
+async fn remove_liquidity_more_than_owned_fails() {
+    let LiquidityFixture {
+        mut env,
+        keys,
+        user_a,
+        user_b,
+        user_lp,
+    } = setup().await;
+
+    // Hand half of the LP tokens to someone else, then try to burn the full supply.
+    let other = solana_sdk::signature::Keypair::new();
+    let other_lp = env.create_token_account(&keys.lp_mint, &other.pubkey()).await;
+    let payer = env.payer();
+    let mut data = vec![3];
+    data.extend_from_slice(&1_000_000u64.to_le_bytes());
+    env.process(
+        &[solana_program::instruction::Instruction {
+            program_id: TOKEN_PROGRAM_ID,
+            accounts: vec![
+                solana_program::instruction::AccountMeta::new(user_lp, false),
+                solana_program::instruction::AccountMeta::new(other_lp, false),
+                solana_program::instruction::AccountMeta::new_readonly(payer, true),
+            ],
+            data,
+        }],
+        &[],
+    )
+    .await
+    .unwrap();
+
+    let result = env
+        .process(
+            &[remove_liquidity_ix(
+                &keys, &payer, &user_lp, &user_a, &user_b, 2_000_000,
+            )],
+            &[],
+        )
+        .await;
+    assert!(result.is_err());
+    let pool = env.assert_pool_consistent(&keys).await;
+    assert_eq!(pool.total_lp_supply, 2_000_000);
+    assert_eq!(env.token_balance(&user_lp).await, 1_000_000);
+}
+
+#[tokio::test]
+async fn remove_liquidity_rejects_foreign_vault() {
+    let LiquidityFixture {
+        mut env,
+        keys,
+        user_a,
+        user_b,
+        user_lp,
+    } = setup().await;
+    let payer = env.payer();
+
+    let mut wrong = keys;
+    wrong.vault_a = user_a;
+    let result = env
+        .process(
+            &[remove_liquidity_ix(
+                &wrong, &payer, &user_lp, &user_a, &user_b, 500_000,
+            )],
+            &[],
+        )
+        .await;
+    assert_eq!(
+        unwrap_program_error(result),
+        instruction_error(0, ProgramError::InvalidSeeds)
+    );
+    env.assert_pool_consistent(&keys).await;
+}
+
+// LP tokens of another mint cannot be burned for the pool's reserves
+#[tokio::test]
+async fn remove_liquidity_rejects_foreign_lp_mint() {
+    let LiquidityFixture {
+        mut env,
+        keys,
+        user_a,
+        user_b,
+        ..
+    } = setup().await;
+    let payer = env.payer();
+    let fake_mint = env.create_mint(0).await;
+    let fake_lp = env.create_funded_account(&fake_mint, 500_000).await;
+
+    let mut wrong = keys;
+    wrong.lp_mint = fake_mint;
+    let result = env
+        .process(
+            &[remove_liquidity_ix(
+                &wrong, &payer, &fake_lp, &user_a, &user_b, 500_000,
+            )],
+            &[],
+        )
+        .await;
+    assert_eq!(
+        unwrap_program_error(result),
+        instruction_error(0, ProgramError::InvalidSeeds)
+    );
+    env.assert_pool_consistent(&keys).await;
+}
@@ -0,0 +1,604 @@
+// Property tests for the AMM math shared by the swap and liquidity processors.
+
+use cargo_swap::{
+    calculate_deposit_amounts, calculate_lp_tokens_to_mint, calculate_swap_output,
+    calculate_withdraw_amounts,
+    concentrated::{self, ConcentratedPool, Position},
+    curve::{
+        fixed_point, stable, ConstantPriceCurve, ConstantProductCurve, SwapCurve, WeightedCurve,
+    },
+    multi_pool::{self, MultiPool},
+    CurveParams, IntegerSqrt,
+};
+use solana_program::pubkey::Pubkey;
+use proptest::prelude::*;
+
+// Pool state reachable through InitPool: reserves plus the LP supply minted for them.
+#[derive(Clone, Copy, Debug)]
+struct PoolState {
+    reserve_a: u64,
+    reserve_b: u64,
+    supply: u64,
+}
+
+fn pool_state() -> impl Strategy<Value = PoolState> {
+    (1_000u64..=1_000_000_000_000, 1_000u64..=1_000_000_000_000).prop_map(|(a, b)| PoolState {
+        reserve_a: a,
+        reserve_b: b,
+        supply: ((a as u128) * (b as u128)).integer_sqrt() as u64,
+    })
+}
+
+fn k(reserve_a: u64, reserve_b: u64) -> u128 {
+    reserve_a as u128 * reserve_b as u128
+}
+
+proptest! {
+    #[test]
+    fn swap_never_decreases_constant_product(
+        reserve_in in 1u64..=u64::MAX / 2,
+        reserve_out in 1u64..=1u64 << 60,
+        amount_in in 1u64..=1u64 << 50,
+    ) {
+        let amount_out = calculate_swap_output(amount_in, reserve_in, reserve_out).unwrap();
+        prop_assert!(amount_out < reserve_out);
+        let new_in = reserve_in as u128 + amount_in as u128;
+        let new_out = (reserve_out - amount_out) as u128;
+        prop_assert!(new_in * new_out >= k(reserve_in, reserve_out));
+    }
+
+    #[test]
+    fn swap_sequence_never_decreases_constant_product(
+        state in pool_state(),
+        swaps in prop::collection::vec((any::<bool>(), 1u64..=10_000_000_000), 1..32),
+    ) {
+        let (mut reserve_a, mut reserve_b) = (state.reserve_a, state.reserve_b);
+        for (a_to_b, amount_in) in swaps {
+            let before = k(reserve_a, reserve_b);
+            let (reserve_in, reserve_out) = if a_to_b {
+                (reserve_a, reserve_b)
+            } else {
+                (reserve_b, reserve_a)
+            };
+            let amount_out = match calculate_swap_output(amount_in, reserve_in, reserve_out) {
+                Ok(amount_out) => amount_out,
+                Err(_) => continue,
+            };
+            prop_assert!(amount_out < reserve_out);
+            if a_to_b {
+                reserve_a += amount_in;
+                reserve_b -= amount_out;
+            } else {
+                reserve_b += amount_in;
+                reserve_a -= amount_out;
+            }
+            prop_assert!(k(reserve_a, reserve_b) >= before);
+        }
+    }
+
+    #[test]
+    fn swap_output_is_monotone_in_input(
+        reserve_in in 1u64..=1_000_000_000_000_000,
+        reserve_out in 1u64..=1_000_000_000_000_000,
+        amount in 1u64..=1_000_000_000_000,
+        extra in 0u64..=1_000_000_000_000,
+    ) {
+        let smaller = calculate_swap_output(amount, reserve_in, reserve_out).unwrap();
+        let larger = calculate_swap_output(amount + extra, reserve_in, reserve_out).unwrap();
+        prop_assert!(smaller <= larger);
+    }
+
+    #[test]
+    fn deposit_never_exceeds_offer_and_keeps_ratio(
+        state in pool_state(),
+        amount_a in 0u64..=1_000_000_000_000,
+        amount_b in 0u64..=1_000_000_000_000,
+    ) {
+        let (final_a, final_b) =
+            calculate_deposit_amounts(amount_a, amount_b, state.reserve_a, state.reserve_b).unwrap();
+        prop_assert!(final_a <= amount_a);
+        prop_assert!(final_b <= amount_b);
+        // The trimmed side matches the pool ratio to within one unit of rounding.
+        let (ra, rb) = (state.reserve_a as u128, state.reserve_b as u128);
+        let (fa, fb) = (final_a as u128, final_b as u128);
+        prop_assert!(
+            (fb * ra <= fa * rb && fa * rb < (fb + 1) * ra)
+                || (fa * rb <= fb * ra && fb * ra < (fa + 1) * rb)
+        );
+        prop_assert!(final_a == amount_a || final_b == amount_b);
+    }
+
+    #[test]
+    fn add_then_remove_never_returns_more_than_deposited(
+        state in pool_state(),
+        amount_a in 0u64..=1_000_000_000_000,
+        amount_b in 0u64..=1_000_000_000_000,
+    ) {
+        let (final_a, final_b) =
+            calculate_deposit_amounts(amount_a, amount_b, state.reserve_a, state.reserve_b).unwrap();
+        let minted = calculate_lp_tokens_to_mint(
+            final_a,
+            final_b,
+            state.reserve_a,
+            state.reserve_b,
+            state.supply,
+        )
+        .unwrap();
+
+        let (out_a, out_b) = calculate_withdraw_amounts(
+            minted,
+            state.reserve_a + final_a,
+            state.reserve_b + final_b,
+            state.supply + minted,
+        )
+        .unwrap();
+        prop_assert!(out_a <= final_a);
+        prop_assert!(out_b <= final_b);
+    }
+
+    #[test]
+    fn remove_never_pays_out_more_than_reserves(
+        state in pool_state(),
+        fraction in 0u64..=1_000,
+    ) {
+        let lp_amount = (state.supply as u128 * fraction as u128 / 1_000) as u64;
+        let (out_a, out_b) =
+            calculate_withdraw_amounts(lp_amount, state.reserve_a, state.reserve_b, state.supply).unwrap();
+        prop_assert!(out_a <= state.reserve_a);
+        prop_assert!(out_b <= state.reserve_b);
+        if lp_amount == state.supply {
+            prop_assert_eq!((out_a, out_b), (state.reserve_a, state.reserve_b));
+        }
+        prop_assert!(calculate_withdraw_amounts(state.supply + 1, state.reserve_a, state.reserve_b, state.supply).is_err());
+    }
+
+    #[test]
+    fn lp_minting_is_monotone_in_deposit(
+        state in pool_state(),
+        amount_a in 0u64..=1_000_000_000_000,
+        amount_b in 0u64..=1_000_000_000_000,
+        extra_a in 0u64..=1_000_000_000_000,
+        extra_b in 0u64..=1_000_000_000_000,
+    ) {
+        let mint_for = |amount_a: u64, amount_b: u64| {
+            let (final_a, final_b) =
+                calculate_deposit_amounts(amount_a, amount_b, state.reserve_a, state.reserve_b).unwrap();
+            calculate_lp_tokens_to_mint(final_a, final_b, state.reserve_a, state.reserve_b, state.supply)
+                .unwrap()
+        };
+        prop_assert!(mint_for(amount_a, amount_b) <= mint_for(amount_a + extra_a, amount_b + extra_b));
+    }
+
+    #[test]
+    fn initial_liquidity_is_geometric_mean(
+        amount_a in any::<u64>(),
+        amount_b in any::<u64>(),
+    ) {
+        let minted = calculate_lp_tokens_to_mint(amount_a, amount_b, 0, 0, 0).unwrap();
+        let product = amount_a as u128 * amount_b as u128;
+        prop_assert!((minted as u128) * (minted as u128) <= product);
+        prop_assert!((minted as u128 + 1) * (minted as u128 + 1) > product);
+    }
+
+    #[test]
+    fn integer_sqrt_is_exact(n in any::<u128>()) {
+        let root = n.integer_sqrt();
+        prop_assert!(root.checked_mul(root).is_some_and(|square| square <= n));
+        prop_assert!((root + 1).checked_mul(root + 1).is_none_or(|square| square > n));
+    }
+}
+
+#[test]
+fn integer_sqrt_edge_cases() {
+    assert_eq!(0u128.integer_sqrt(), 0);
+    assert_eq!(1u128.integer_sqrt(), 1);
+    assert_eq!(2u128.integer_sqrt(), 1);
+    assert_eq!(3u128.integer_sqrt(), 1);
+    assert_eq!(4u128.integer_sqrt(), 2);
+    assert_eq!((u64::MAX as u128 * u64::MAX as u128).integer_sqrt(), u64::MAX as u128);
+    assert_eq!(u128::MAX.integer_sqrt(), u64::MAX as u128);
+    assert_eq!((u128::MAX - 1).integer_sqrt(), u64::MAX as u128);
+}
+
+#[test]
+fn swap_output_rejects_empty_inputs() {
+    assert!(calculate_swap_output(0, 1_000, 1_000).is_err());
+    assert!(calculate_swap_output(1_000, 0, 1_000).is_err());
+    assert!(calculate_swap_output(1_000, 1_000, 0).is_err());
+}
+
+proptest! {
+    #[test]
+    fn stable_swap_never_decreases_invariant(
+        amp in stable::MIN_AMP..=10_000u64,
+        reserve_in in 1_000u64..=1_000_000_000_000_000,
+        reserve_out in 1_000u64..=1_000_000_000_000_000,
+        amount_in in 1u64..=1_000_000_000_000_000,
+    ) {
+        let amount_out = match stable::calculate_swap_output(amount_in, reserve_in, reserve_out, amp) {
+            Ok(amount_out) => amount_out,
+            Err(_) => return Ok(()),
+        };
+        prop_assert!(amount_out < reserve_out);
+        let before = stable::compute_d(amp, reserve_in, reserve_out).unwrap();
+        let after = stable::compute_d(amp, reserve_in + amount_in, reserve_out - amount_out).unwrap();
+        prop_assert!(after >= before);
+    }
+
+    #[test]
+    fn stable_swap_beats_constant_product_when_balanced(
+        amp in 2u64..=10_000,
+        reserve in 1_000_000u64..=1_000_000_000_000,
+        fraction in 1u64..=100,
+    ) {
+        let amount_in = reserve / 1_000 * fraction;
+        let stable_out = stable::calculate_swap_output(amount_in, reserve, reserve, amp).unwrap();
+        let constant_product_out = calculate_swap_output(amount_in, reserve, reserve).unwrap();
+        prop_assert!(stable_out >= constant_product_out);
+        // Never better than 1:1 after the fee
+        prop_assert!(stable_out <= amount_in * 997 / 1000);
+    }
+
+    #[test]
+    fn stable_initial_lp_is_close_to_sum_when_balanced(
+        amp in stable::MIN_AMP..=stable::MAX_AMP,
+        reserve in 1u64..=u64::MAX / 2,
+    ) {
+        let minted = stable::calculate_initial_lp_tokens(amp, reserve, reserve).unwrap();
+        prop_assert!(minted.abs_diff(reserve * 2) <= 1);
+    }
+
+    #[test]
+    fn stable_amp_ramp_stays_between_endpoints(
+        initial in stable::MIN_AMP..=stable::MAX_AMP,
+        target in stable::MIN_AMP..=stable::MAX_AMP,
+        start in 0i64..=1_000_000_000,
+        duration in 1i64..=100_000_000,
+        offset in -1_000i64..=200_000_000,
+    ) {
+        let amp = stable::current_amp(initial, target, start, start + duration, start + offset);
+        prop_assert!(amp >= initial.min(target) && amp <= initial.max(target));
+        if offset <= 0 {
+            prop_assert_eq!(amp, initial);
+        }
+        if offset >= duration {
+            prop_assert_eq!(amp, target);
+        }
+    }
+}
+
+// Every curve the processors can price a pool with, parameters included.
+fn any_curve() -> impl Strategy<Value = CurveParams> {
+    prop_oneof![
+        Just(CurveParams::ConstantProduct),
+        (stable::MIN_AMP..=10_000u64).prop_map(|amp| CurveParams::Stable { amp }),
+        (1u64..=1_000_000).prop_map(|token_b_price| CurveParams::ConstantPrice { token_b_price }),
+        (0u64..=1_000_000_000_000, 0u64..=1_000_000_000_000).prop_map(
+            |(token_a_offset, token_b_offset)| CurveParams::Offset {
+                token_a_offset,
+                token_b_offset,
+            }
+        ),
+        (100u64..=9_900).prop_map(|weight_a| CurveParams::Weighted {
+            weight_a,
+            weight_b: 10_000 - weight_a,
+        }),
+    ]
+}
+
+proptest! {
+    #[test]
+    fn curve_swap_never_decreases_invariant(
+        params in any_curve(),
+        reserve_a in 1_000u64..=1_000_000_000_000,
+        reserve_b in 1_000u64..=1_000_000_000_000,
+        amount_in in 1u64..=1_000_000_000_000,
+        direction_a_to_b: bool,
+    ) {
+        let curve = params.curve();
+        let amount_out = match curve.swap_exact_in(amount_in, reserve_a, reserve_b, direction_a_to_b) {
+            Ok(amount_out) => amount_out,
+            Err(_) => return Ok(()),
+        };
+        let (after_a, after_b) = if direction_a_to_b {
+            prop_assert!(amount_out < reserve_b);
+            (reserve_a + amount_in, reserve_b - amount_out)
+        } else {
+            prop_assert!(amount_out < reserve_a);
+            (reserve_a - amount_out, reserve_b + amount_in)
+        };
+        let before = curve.invariant(reserve_a, reserve_b).unwrap();
+        let after = curve.invariant(after_a, after_b).unwrap();
+        prop_assert!(after >= before);
+    }
+
+    #[test]
+    fn curve_exact_out_pays_at_least_the_requested_amount(
+        params in any_curve(),
+        reserve_a in 1_000u64..=1_000_000_000_000,
+        reserve_b in 1_000u64..=1_000_000_000_000,
+        amount_out in 1u64..=1_000_000_000_000,
+        direction_a_to_b: bool,
+    ) {
+        let curve = params.curve();
+        let amount_in = match curve.swap_exact_out(amount_out, reserve_a, reserve_b, direction_a_to_b) {
+            Ok(amount_in) => amount_in,
+            Err(_) => return Ok(()),
+        };
+        if let Ok(received) = curve.swap_exact_in(amount_in, reserve_a, reserve_b, direction_a_to_b) {
+            prop_assert!(received >= amount_out);
+        }
+    }
+
+    #[test]
+    fn constant_product_exact_out_is_the_smallest_input(
+        state in pool_state(),
+        amount_out in 1u64..=1_000_000_000,
+    ) {
+        prop_assume!(amount_out < state.reserve_b);
+        let amount_in = ConstantProductCurve
+            .swap_exact_out(amount_out, state.reserve_a, state.reserve_b, true)
+            .unwrap();
+        let received = calculate_swap_output(amount_in, state.reserve_a, state.reserve_b).unwrap();
+        prop_assert!(received >= amount_out);
+        if amount_in > 1 {
+            let short = calculate_swap_output(amount_in - 1, state.reserve_a, state.reserve_b).unwrap();
+            prop_assert!(short < amount_out);
+        }
+    }
+
+    #[test]
+    fn constant_price_round_trip_never_profits(
+        token_b_price in 1u64..=1_000_000,
+        amount_in in 1u64..=1_000_000_000_000,
+    ) {
+        let curve = ConstantPriceCurve { token_b_price };
+        let reserve = u64::MAX / 4;
+        let b_out = match curve.swap_exact_in(amount_in, reserve, reserve, true) {
+            Ok(b_out) => b_out,
+            Err(_) => return Ok(()),
+        };
+        if let Ok(a_back) = curve.swap_exact_in(b_out, reserve, reserve, false) {
+            prop_assert!(a_back <= amount_in);
+        }
+    }
+}
+
+fn to_fixed(value: f64) -> u128 {
+    (value * fixed_point::ONE as f64) as u128
+}
+
+fn from_fixed(value: u128) -> f64 {
+    value as f64 / fixed_point::ONE as f64
+}
+
+proptest! {
+    #[test]
+    fn fixed_point_ln_and_exp_match_floats(x in 0.000_001f64..1e12, y in -40f64..40.0) {
+        let ln = fixed_point::ln(to_fixed(x)).unwrap() as f64 / fixed_point::ONE as f64;
+        prop_assert!((ln - x.ln()).abs() < 1e-9);
+        let exp = from_fixed(fixed_point::exp((y * fixed_point::ONE as f64) as i128).unwrap());
+        prop_assert!((exp - y.exp()).abs() <= y.exp() * 1e-9 + 1e-18);
+    }
+
+    #[test]
+    fn fixed_point_pow_brackets_the_true_power(base in 0.01f64..1.5, exponent in 0.01f64..99.0) {
+        let (x, y) = (to_fixed(base), to_fixed(exponent));
+        let expected = from_fixed(x).powf(from_fixed(y));
+        let (down, up) = (fixed_point::pow_down(x, y).unwrap(), fixed_point::pow_up(x, y).unwrap());
+        prop_assert!(down < up);
+        prop_assert!((from_fixed(up) - expected).abs() <= expected * 1e-9 + 1e-15);
+    }
+
+    #[test]
+    fn weighted_equal_weights_track_constant_product(
+        state in pool_state(),
+        amount_in in 1u64..=1_000_000_000,
+    ) {
+        prop_assume!(amount_in as u128 * 10 <= state.reserve_a as u128 * 3);
+        let curve = WeightedCurve { weight_a: 5_000, weight_b: 5_000 };
+        let weighted = curve.swap_exact_in(amount_in, state.reserve_a, state.reserve_b, true).unwrap();
+        let constant_product = calculate_swap_output(amount_in, state.reserve_a, state.reserve_b).unwrap();
+        // Never more than constant product; short by at most the unit of input
+        // the fee rounds away plus the power error
+        let tolerance = 1 + state.reserve_b / state.reserve_a + constant_product / 1_000_000_000;
+        prop_assert!(weighted <= constant_product + 1);
+        prop_assert!(constant_product - weighted.min(constant_product) <= tolerance);
+    }
+
+    #[test]
+    fn weighted_spot_price_follows_weights(
+        weight_a in 100u64..=9_900,
+        reserve_a in 1_000u64..=1_000_000_000_000,
+        reserve_b in 1_000u64..=1_000_000_000_000,
+    ) {
+        let curve = WeightedCurve { weight_a, weight_b: 10_000 - weight_a };
+        let price = curve.spot_price(reserve_a, reserve_b).unwrap() as f64 / 1e12;
+        let expected = (reserve_b as f64 / (10_000 - weight_a) as f64) / (reserve_a as f64 / weight_a as f64);
+        prop_assert!((price - expected).abs() <= expected * 1e-9 + 1e-12);
+    }
+}
+
+// Multi-asset pool over `reserves` with the geometric-mean LP supply InitMultiPool mints
+fn multi_pool_state(reserves: &[u64]) -> MultiPool {
+    let mut pool = MultiPool {
+        bump: 0,
+        num_tokens: reserves.len() as u8,
+        tokens: [Pubkey::default(); multi_pool::MAX_TOKENS],
+        reserves: [0; multi_pool::MAX_TOKENS],
+        total_lp_supply: multi_pool::initial_lp_tokens(reserves).unwrap(),
+        vault_bumps: [0; multi_pool::MAX_TOKENS],
+        lp_mint_bump: 0,
+    };
+    pool.reserves[..reserves.len()].copy_from_slice(reserves);
+    pool
+}
+
+fn multi_pool_reserves() -> impl Strategy<Value = Vec<u64>> {
+    prop::collection::vec(1_000u64..=1_000_000_000_000, multi_pool::MIN_TOKENS..=multi_pool::MAX_TOKENS)
+}
+
+proptest! {
+    #[test]
+    fn multi_pool_initial_lp_is_geometric_mean(reserves in multi_pool_reserves()) {
+        let lp = multi_pool::initial_lp_tokens(&reserves).unwrap() as f64;
+        let log_mean = reserves.iter().map(|&r| (r as f64).ln()).sum::<f64>() / reserves.len() as f64;
+        let mean = log_mean.exp();
+        prop_assert!(lp <= mean + 1.0);
+        prop_assert!(lp >= mean * (1.0 - 1e-9) - 1.0);
+    }
+
+    #[test]
+    fn multi_pool_single_asset_round_trip_never_profits(
+        reserves in multi_pool_reserves(),
+        index in 0usize..multi_pool::MAX_TOKENS,
+        fraction in 0.000_01f64..0.25,
+    ) {
+        let index = index % reserves.len();
+        let pool = multi_pool_state(&reserves);
+        let amount_in = (reserves[index] as f64 * fraction) as u64;
+        prop_assume!(amount_in > 0);
+        let Ok(minted) = pool.single_deposit_lp_tokens(index, amount_in) else {
+            return Ok(());
+        };
+
+        let mut after = multi_pool_state(&reserves);
+        after.reserves[index] += amount_in;
+        after.total_lp_supply += minted;
+        if let Ok(amount_out) = after.single_withdraw_amount(index, minted) {
+            prop_assert!(amount_out < amount_in);
+        }
+
+        // Proportional withdrawal of the minted LP is worth less than the deposit too
+        let withdrawn = after.withdraw_amounts(minted).unwrap();
+        prop_assert!(withdrawn[index] < amount_in);
+    }
+
+    #[test]
+    fn multi_pool_proportional_deposit_never_dilutes(
+        reserves in multi_pool_reserves(),
+        fraction in 0.000_01f64..2.0,
+    ) {
+        let pool = multi_pool_state(&reserves);
+        let offered: Vec<u64> = reserves.iter().map(|&r| (r as f64 * fraction) as u64 + 1).collect();
+        let Ok((deposits, minted)) = pool.deposit_amounts(&offered) else {
+            return Ok(());
+        };
+        let supply = pool.total_lp_supply as u128;
+        for ((&deposit, &offer), &reserve) in deposits.iter().zip(&offered).zip(&reserves) {
+            prop_assert!(deposit <= offer);
+            // Each token's value per LP token can only rise
+            prop_assert!(deposit as u128 * supply >= minted as u128 * reserve as u128);
+        }
+    }
+}
+
+// Pool priced at `tick` with one position of `liquidity` over [-tick_range, tick_range)
+fn concentrated_pool_state(tick: i32, tick_range: i32, liquidity: u128) -> ConcentratedPool {
+    let sqrt_price = concentrated::sqrt_price_at_tick(tick).unwrap();
+    let mut pool = ConcentratedPool {
+        token_a: Pubkey::new_unique(),
+        token_b: Pubkey::new_unique(),
+        bump: 255,
+        vault_a_bump: 255,
+        vault_b_bump: 255,
+        tick_spacing: 1,
+        sqrt_price,
+        current_tick: tick,
+        liquidity: 0,
+        fee_growth_global_a: 0,
+        fee_growth_global_b: 0,
+        ticks: Vec::new(),
+    };
+    let mut position = Position {
+        pool: Pubkey::new_unique(),
+        owner: Pubkey::new_unique(),
+        tick_lower: -tick_range,
+        tick_upper: tick_range,
+        liquidity: 0,
+        fee_growth_inside_last_a: 0,
+        fee_growth_inside_last_b: 0,
+        tokens_owed_a: 0,
+        tokens_owed_b: 0,
+        bump: 255,
+    };
+    pool.modify_position(&mut position, liquidity as i128).unwrap();
+    pool
+}
+
+proptest! {
+    #[test]
+    fn concentrated_tick_round_trips_through_sqrt_price(
+        tick in concentrated::MIN_TICK..concentrated::MAX_TICK,
+    ) {
+        let sqrt_price = concentrated::sqrt_price_at_tick(tick).unwrap();
+        prop_assert!(sqrt_price < concentrated::sqrt_price_at_tick(tick + 1).unwrap());
+        prop_assert_eq!(concentrated::tick_at_sqrt_price(sqrt_price).unwrap(), tick);
+        // Any price inside the tick maps back to it
+        let next = concentrated::sqrt_price_at_tick(tick + 1).unwrap();
+        prop_assert_eq!(concentrated::tick_at_sqrt_price(next - 1).unwrap(), tick);
+    }
+
+    #[test]
+    fn concentrated_swap_step_never_overpays(
+        tick in -100_000i32..100_000,
+        distance in 1i32..10_000,
+        liquidity in 1_000u128..=1u128 << 64,
+        amount_remaining in 1u64..=1u64 << 48,
+        direction_a_to_b in any::<bool>(),
+    ) {
+        let target_tick = if direction_a_to_b { tick - distance } else { tick + distance };
+        let sqrt_price = concentrated::sqrt_price_at_tick(tick).unwrap();
+        let target = concentrated::sqrt_price_at_tick(target_tick).unwrap();
+        let step = concentrated::compute_swap_step(
+            sqrt_price,
+            target,
+            liquidity,
+            amount_remaining,
+            direction_a_to_b,
+        )
+        .unwrap();
+
+        prop_assert!(step.amount_in as u128 + step.fee as u128 <= amount_remaining as u128);
+        // The price stays between start and target
+        if direction_a_to_b {
+            prop_assert!(target <= step.sqrt_price_next && step.sqrt_price_next <= sqrt_price);
+        } else {
+            prop_assert!(sqrt_price <= step.sqrt_price_next && step.sqrt_price_next <= target);
+        }
+        // The output is worth no more than the input at the starting price,
+        // which is the best price the step trades at
+        let one = fixed_point::ONE;
+        let in_value = if direction_a_to_b {
+            let scaled = fixed_point::mul_div(step.amount_in as u128, sqrt_price, one, true).unwrap();
+            fixed_point::mul_div(scaled, sqrt_price, one, true).unwrap()
+        } else {
+            let scaled = fixed_point::mul_div(step.amount_in as u128, one, sqrt_price, true).unwrap();
+            fixed_point::mul_div(scaled, one, sqrt_price, true).unwrap()
+        };
+        prop_assert!(step.amount_out as u128 <= in_value);
+    }
+
+    #[test]
+    fn concentrated_swap_round_trip_never_profits(
+        tick in -50_000i32..50_000,
+        tick_range in 1i32..200_000,
+        liquidity in 1_000_000u128..=1u128 << 50,
+        amount_in in 1u64..=1u64 << 40,
+        direction_a_to_b in any::<bool>(),
+    ) {
+        let mut pool = concentrated_pool_state(tick, tick_range, liquidity);
+        let Ok((used, amount_out, fee)) = pool.swap(amount_in, direction_a_to_b) else {
+            return Ok(());
+        };
+        prop_assert!(used <= amount_in);
+        prop_assert!(fee <= used);
+        if amount_out == 0 {
+            return Ok(());
+        }
+        let Ok((_, amount_back, _)) = pool.swap(amount_out, !direction_a_to_b) else {
+            return Ok(());
+        };
+        prop_assert!(amount_back <= used);
+    }
+}
@@ -0,0 +1,180 @@
+mod common;
+
+use borsh::BorshSerialize;
+use cargo_swap::{
+    flash_loan::flash_loan_fee,
+    CurveParams, CurveType, Pool, TestProjectInstruction, LEGACY_POOL_LEN, POOL_RESERVED_LEN,
+    POOL_VERSION,
+};
+use common::*;
+use solana_program::{program_error::ProgramError, program_pack::Pack, pubkey::Pubkey, rent::Rent};
+
+struct MigrationFixture {
+    env: TestEnv,
+    keys: PoolKeys,
+    user_a: Pubkey,
+    user_b: Pubkey,
+}
+
+async fn setup(curve: CurveParams) -> MigrationFixture {
+    let mut env = TestEnv::start().await;
+    let mint_a = env.create_mint(6).await;
+    let mint_b = env.create_mint(6).await;
+    let user_a = env.create_funded_account(&mint_a, 10_000_000).await;
+    let user_b = env.create_funded_account(&mint_b, 10_000_000).await;
+    let keys = env
+        .init_pool_with_curve(mint_a, mint_b, &user_a, &user_b, 1_000_000, 2_000_000, curve)
+        .await;
+    MigrationFixture {
+        env,
+        keys,
+        user_a,
+        user_b,
+    }
+}
+
+// Reserves and LP supply of a legacy pool, checked against its vaults and LP
+// mint, since it stores no bumps for assert_pool_consistent
+async fn legacy_pool(env: &mut TestEnv, keys: &PoolKeys) -> (u64, u64, u64) {
+    assert_eq!(env.get_account(&keys.pool).await.unwrap().data.len(), LEGACY_POOL_LEN);
+    let pool = env.pool(&keys.pool).await;
+    assert_eq!(env.token_balance(&keys.vault_a).await, pool.reserve_a);
+    assert_eq!(env.token_balance(&keys.vault_b).await, pool.reserve_b);
+    assert_eq!(env.mint_supply(&keys.lp_mint).await, pool.total_lp_supply);
+    (pool.reserve_a, pool.reserve_b, pool.total_lp_supply)
+}
+
+#[tokio::test]
+async fn legacy_pool_changes_in_its_own_layout() {
+    let MigrationFixture {
+        mut env,
+        keys,
+        user_a,
+        user_b,
+    } = setup(CurveParams::ConstantProduct).await;
+    let payer = env.payer();
+    let user_lp = associated_token_address(&payer, &keys.lp_mint);
+    env.downgrade_pool(&keys.pool).await;
+
+    // Readable in the legacy layout
+    let pool = env.pool(&keys.pool).await;
+    assert_eq!(pool.reserve_b, 2_000_000);
+    assert_eq!(pool.curve_type, CurveType::ConstantProduct);
+    let logs = env
+        .simulate_logs(&[query_ix(TestProjectInstruction::GetPoolInfo, &[keys.pool])])
+        .await;
+    assert!(logs.iter().any(|log| log.ends_with("Version: 0")));
+    let (_, _, supply) = legacy_pool(&mut env, &keys).await;
+
+    // And writable: the bumps it lacks are searched for on each instruction
+    let amount_out = expected_swap_output(100_000, 1_000_000, 2_000_000);
+    env.process(&[swap_ix(&keys, &payer, &user_a, &user_b, 100_000, true)], &[])
+        .await
+        .unwrap();
+    assert_eq!(
+        legacy_pool(&mut env, &keys).await,
+        (1_100_000, 2_000_000 - amount_out, supply)
+    );
+
+    env.process(
+        &[add_liquidity_ix(&keys, &payer, &user_a, &user_b, &user_lp, 110_000, 1_000_000)],
+        &[],
+    )
+    .await
+    .unwrap();
+    env.process(&[remove_liquidity_ix(&keys, &payer, &user_lp, &user_a, &user_b, supply / 10)], &[])
+        .await
+        .unwrap();
+    let (reserve_a, reserve_b, supply) = legacy_pool(&mut env, &keys).await;
+
+    let fee = flash_loan_fee(100_000).unwrap();
+    let loan = flash_loan_ix(&keys.pool, &keys.token_b, &keys.vault_b, &user_b, &FLASH_RECEIVER_ID, &payer, 100_000);
+    env.process(&[loan], &[]).await.unwrap();
+    assert_eq!(legacy_pool(&mut env, &keys).await, (reserve_a, reserve_b + fee, supply));
+
+    // Migrating keeps what changed
+    env.process(&[migrate_pool_ix(&keys.pool, &payer)], &[])
+        .await
+        .unwrap();
+    let pool = env.assert_pool_consistent(&keys).await;
+    assert_eq!(
+        (pool.reserve_a, pool.reserve_b, pool.total_lp_supply),
+        (reserve_a, reserve_b + fee, supply)
+    );
+}
+
+#[tokio::test]
+async fn migrate_pool_reallocs_and_keeps_state() {
+    let MigrationFixture {
+        mut env,
+        keys,
+        user_a,
+        user_b,
+    } = setup(CurveParams::ConstantProduct).await;
+    let payer = env.payer();
+    let created = env.pool(&keys.pool).await;
+    env.downgrade_pool(&keys.pool).await;
+    let before = env.pool(&keys.pool).await;
+
+    env.process(&[migrate_pool_ix(&keys.pool, &payer)], &[])
+        .await
+        .unwrap();
+    let account = env.get_account(&keys.pool).await.unwrap();
+    assert_eq!(account.data.len(), Pool::LEN);
+    assert_eq!(account.data[0], POOL_VERSION);
+    assert!(account.data[Pool::LEN - POOL_RESERVED_LEN..].iter().all(|&byte| byte == 0));
+    assert_eq!(account.lamports, Rent::default().minimum_balance(Pool::LEN));
+    // The bumps MigratePool found are the ones InitPool stored
+    let pool = env.assert_pool_consistent(&keys).await;
+    let expected = Pool {
+        vault_a_bump: created.vault_a_bump,
+        vault_b_bump: created.vault_b_bump,
+        lp_mint_bump: created.lp_mint_bump,
+        ..before
+    };
+    assert_eq!(pool.try_to_vec().unwrap(), expected.try_to_vec().unwrap());
+
+    // Migrating again changes nothing
+    env.process(&[migrate_pool_ix(&keys.pool, &payer)], &[])
+        .await
+        .unwrap();
+    assert_eq!(env.get_account(&keys.pool).await.unwrap(), account);
+
+    env.process(&[swap_ix(&keys, &payer, &user_b, &user_a, 100_000, false)], &[])
+        .await
+        .unwrap();
+    env.assert_pool_consistent(&keys).await;
+}
+
+#[tokio::test]
+async fn migrate_pool_only_accepts_the_legacy_layout() {
+    let MigrationFixture { mut env, keys, .. } = setup(CurveParams::Stable { amp: 100 }).await;
+    let payer = env.payer();
+
+    // The fields up to the curve params, without a version byte, are no
+    // layout a pool was ever created in
+    let mut account = env.get_account(&keys.pool).await.unwrap();
+    let len = LEGACY_POOL_LEN + 1 + 32 + 32;
+    account.data = env.pool(&keys.pool).await.try_to_vec().unwrap()[..len].to_vec();
+    account.lamports = Rent::default().minimum_balance(len);
+    env.set_account(&keys.pool, account);
+    assert!(Pool::unpack(&env.get_account(&keys.pool).await.unwrap().data).is_err());
+
+    let error = unwrap_program_error(
+        env.process(&[migrate_pool_ix(&keys.pool, &payer)], &[])
+            .await,
+    );
+    assert_eq!(error, instruction_error(0, ProgramError::InvalidAccountData));
+}
+
+#[tokio::test]
+async fn migrate_pool_rejects_other_accounts() {
+    let MigrationFixture { mut env, keys, .. } = setup(CurveParams::ConstantProduct).await;
+    let payer = env.payer();
+
+    let error = unwrap_program_error(
+        env.process(&[migrate_pool_ix(&keys.token_a, &payer)], &[])
+            .await,
+    );
+    assert_eq!(error, instruction_error(0, ProgramError::IncorrectProgramId));
+}
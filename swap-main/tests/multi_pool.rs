@@ -0,0 +1,554 @@
+mod common;
+
+use cargo_swap::TestProjectInstruction;
+use common::*;
+use solana_program::{program_error::ProgramError, pubkey::Pubkey};
+
+// Reserves of the four-token fixture pool, in ascending mint order
+const AMOUNTS: [u64; 4] = [1_000_000, 2_000_000, 4_000_000, 8_000_000];
+
+struct MultiPoolFixture {
+    env: TestEnv,
+    keys: MultiPoolKeys,
+    // One funded user account per pool token, in pool order
+    user_tokens: Vec<Pubkey>,
+    user_lp: Pubkey,
+}
+
+async fn setup() -> MultiPoolFixture {
+    let mut env = TestEnv::start().await;
+    let mut mints = Vec::new();
+    for _ in 0..AMOUNTS.len() {
+        mints.push(env.create_mint(6).await);
+    }
+    let keys = MultiPoolKeys::new(mints);
+    let mut user_tokens = Vec::new();
+    for token in &keys.tokens {
+        user_tokens.push(env.create_funded_account(token, 100_000_000).await);
+    }
+    let payer = env.payer();
+    env.process(
+        &[init_multi_pool_ix(&keys, &payer, &user_tokens, AMOUNTS.to_vec())],
+        &[],
+    )
+    .await
+    .unwrap();
+    let user_lp = associated_token_address(&payer, &keys.lp_mint);
+    MultiPoolFixture {
+        env,
+        keys,
+        user_tokens,
+        user_lp,
+    }
+}
+
+fn assert_logged(logs: &[String], expected: &str) {
+    assert!(
+        logs.iter().any(|log| log.contains(expected)),
+        "missing log {:?} in {:#?}",
+        expected,
+        logs
+    );
+}
+
+#[tokio::test]
+async fn init_funds_every_vault_and_mints_the_geometric_mean() {
+    let MultiPoolFixture {
+        mut env,
+        keys,
+        user_lp,
+        ..
+    } = setup().await;
+
+    let pool = env.assert_multi_pool_consistent(&keys).await;
+    assert_eq!(pool.reserves(), &AMOUNTS[..]);
+    let mean = AMOUNTS.iter().map(|&amount| amount as f64).product::<f64>().powf(0.25);
+    assert!((pool.total_lp_supply as f64 - mean).abs() <= 1.0);
+    assert_eq!(env.token_balance(&user_lp).await, pool.total_lp_supply);
+}
+
+#[tokio::test]
+async fn init_rejects_unsorted_duplicate_or_too_few_mints() {
+    let mut env = TestEnv::start().await;
+    let payer = env.payer();
+    let mut mints = Vec::new();
+    for _ in 0..3 {
+        mints.push(env.create_mint(6).await);
+    }
+    mints.sort();
+    let mut user_tokens = Vec::new();
+    for mint in &mints {
+        user_tokens.push(env.create_funded_account(mint, 1_000_000).await);
+    }
+
+    let mut unsorted = MultiPoolKeys::new(mints.clone());
+    unsorted.tokens.swap(0, 1);
+    unsorted.vaults.swap(0, 1);
+    let mut duplicate = MultiPoolKeys::new(mints.clone());
+    duplicate.tokens[1] = duplicate.tokens[0];
+    duplicate.vaults[1] = duplicate.vaults[0];
+    let mut too_few = MultiPoolKeys::new(mints.clone());
+    too_few.tokens.pop();
+    too_few.vaults.pop();
+
+    for keys in [unsorted, duplicate, too_few] {
+        let amounts = vec![100_000; keys.tokens.len()];
+        let instruction = init_multi_pool_ix(&keys, &payer, &user_tokens, amounts);
+        assert_eq!(
+            unwrap_program_error(env.process(&[instruction], &[]).await),
+            instruction_error(0, ProgramError::InvalidArgument)
+        );
+    }
+
+    // Amounts must line up with the token accounts
+    let keys = MultiPoolKeys::new(mints);
+    let instruction = init_multi_pool_ix(&keys, &payer, &user_tokens, vec![100_000; 2]);
+    assert_eq!(
+        unwrap_program_error(env.process(&[instruction], &[]).await),
+        instruction_error(0, ProgramError::NotEnoughAccountKeys)
+    );
+}
+
+#[tokio::test]
+async fn swaps_between_any_pair_only_move_that_pair() {
+    let MultiPoolFixture {
+        mut env,
+        keys,
+        user_tokens,
+        ..
+    } = setup().await;
+    let payer = env.payer();
+
+    let out_0_to_3 = expected_swap_output(10_000, AMOUNTS[0], AMOUNTS[3]);
+    let user_3_before = env.token_balance(&user_tokens[3]).await;
+    env.process(
+        &[multi_pool_swap_ix(
+            &keys,
+            &payer,
+            0,
+            3,
+            &user_tokens[0],
+            &user_tokens[3],
+            10_000,
+            out_0_to_3,
+        )],
+        &[],
+    )
+    .await
+    .unwrap();
+    assert_eq!(env.token_balance(&user_tokens[3]).await, user_3_before + out_0_to_3);
+
+    let out_2_to_1 = expected_swap_output(50_000, AMOUNTS[2], AMOUNTS[1]);
+    env.process(
+        &[multi_pool_swap_ix(
+            &keys,
+            &payer,
+            2,
+            1,
+            &user_tokens[2],
+            &user_tokens[1],
+            50_000,
+            0,
+        )],
+        &[],
+    )
+    .await
+    .unwrap();
+
+    let pool = env.assert_multi_pool_consistent(&keys).await;
+    assert_eq!(
+        pool.reserves(),
+        &[
+            AMOUNTS[0] + 10_000,
+            AMOUNTS[1] - out_2_to_1,
+            AMOUNTS[2] + 50_000,
+            AMOUNTS[3] - out_0_to_3,
+        ][..]
+    );
+}
+
+#[tokio::test]
+async fn swap_enforces_minimum_output_and_distinct_tokens() {
+    let MultiPoolFixture {
+        mut env,
+        keys,
+        user_tokens,
+        ..
+    } = setup().await;
+    let payer = env.payer();
+
+    let amount_out = expected_swap_output(10_000, AMOUNTS[1], AMOUNTS[2]);
+    let too_greedy = multi_pool_swap_ix(
+        &keys,
+        &payer,
+        1,
+        2,
+        &user_tokens[1],
+        &user_tokens[2],
+        10_000,
+        amount_out + 1,
+    );
+    assert_eq!(
+        unwrap_program_error(env.process(&[too_greedy], &[]).await),
+        instruction_error(0, ProgramError::InsufficientFunds)
+    );
+
+    let same_token = multi_pool_swap_ix(
+        &keys,
+        &payer,
+        1,
+        1,
+        &user_tokens[1],
+        &user_tokens[1],
+        10_000,
+        0,
+    );
+    assert_eq!(
+        unwrap_program_error(env.process(&[same_token], &[]).await),
+        instruction_error(0, ProgramError::InvalidArgument)
+    );
+
+    let pool = env.assert_multi_pool_consistent(&keys).await;
+    assert_eq!(pool.reserves(), &AMOUNTS[..]);
+}
+
+#[tokio::test]
+async fn proportional_liquidity_round_trip() {
+    let MultiPoolFixture {
+        mut env,
+        keys,
+        user_tokens,
+        user_lp,
+    } = setup().await;
+    let payer = env.payer();
+    let supply = env.multi_pool(&keys.pool).await.total_lp_supply;
+
+    // Token 2 is offered short, so it limits the deposit to a tenth of the pool
+    let offered = vec![1_000_000, 1_000_000, AMOUNTS[2] / 10, 1_000_000];
+    env.process(
+        &[multi_liquidity_ix(
+            TestProjectInstruction::AddMultiLiquidity { amounts: offered },
+            &keys,
+            &payer,
+            &user_lp,
+            &user_tokens,
+        )],
+        &[],
+    )
+    .await
+    .unwrap();
+
+    let pool = env.assert_multi_pool_consistent(&keys).await;
+    let minted = supply / 10;
+    assert_eq!(pool.total_lp_supply, supply + minted);
+    for (&reserve, &amount) in pool.reserves().iter().zip(&AMOUNTS) {
+        // Each deposit is rounded up in the pool's favour
+        let expected = (minted as u128 * amount as u128).div_ceil(supply as u128) as u64;
+        assert_eq!(reserve, amount + expected);
+    }
+
+    let balances_before = {
+        let mut balances = Vec::new();
+        for account in &user_tokens {
+            balances.push(env.token_balance(account).await);
+        }
+        balances
+    };
+    env.process(
+        &[multi_liquidity_ix(
+            TestProjectInstruction::RemoveMultiLiquidity { lp_amount: minted },
+            &keys,
+            &payer,
+            &user_lp,
+            &user_tokens,
+        )],
+        &[],
+    )
+    .await
+    .unwrap();
+
+    let after = env.assert_multi_pool_consistent(&keys).await;
+    assert_eq!(after.total_lp_supply, supply);
+    for index in 0..AMOUNTS.len() {
+        let returned = env.token_balance(&user_tokens[index]).await - balances_before[index];
+        let deposited = pool.reserves()[index] - AMOUNTS[index];
+        assert!(returned <= deposited);
+        assert!(deposited - returned <= 1);
+    }
+}
+
+#[tokio::test]
+async fn single_asset_deposit_and_withdrawal_cost_the_fee() {
+    let MultiPoolFixture {
+        mut env,
+        keys,
+        user_tokens,
+        user_lp,
+    } = setup().await;
+    let payer = env.payer();
+    let lp_before = env.token_balance(&user_lp).await;
+    let pool_before = env.multi_pool(&keys.pool).await;
+
+    let amount_in = 100_000;
+    let minted = pool_before.single_deposit_lp_tokens(1, amount_in).unwrap();
+    env.process(
+        &[add_multi_liquidity_single_ix(
+            &keys,
+            &payer,
+            1,
+            &user_tokens[1],
+            &user_lp,
+            amount_in,
+            minted,
+        )],
+        &[],
+    )
+    .await
+    .unwrap();
+    assert_eq!(env.token_balance(&user_lp).await, lp_before + minted);
+
+    let pool = env.assert_multi_pool_consistent(&keys).await;
+    assert_eq!(pool.reserves()[1], AMOUNTS[1] + amount_in);
+    // Close to the fee-free share of a quarter-weight token
+    let fee_free = pool_before.total_lp_supply as f64
+        * ((1.0 + amount_in as f64 / AMOUNTS[1] as f64).powf(0.25) - 1.0);
+    assert!((minted as f64) < fee_free);
+    assert!(minted as f64 > fee_free * 0.99);
+
+    // Burning the same LP for the same token gives back less than went in
+    let amount_out = pool.single_withdraw_amount(1, minted).unwrap();
+    let user_1_before = env.token_balance(&user_tokens[1]).await;
+    env.process(
+        &[remove_multi_liquidity_single_ix(
+            &keys,
+            &payer,
+            1,
+            &user_lp,
+            &user_tokens[1],
+            minted,
+            amount_out,
+        )],
+        &[],
+    )
+    .await
+    .unwrap();
+    assert_eq!(env.token_balance(&user_tokens[1]).await, user_1_before + amount_out);
+    assert!(amount_out < amount_in);
+    assert!(amount_out > amount_in * 99 / 100);
+
+    let pool = env.assert_multi_pool_consistent(&keys).await;
+    assert_eq!(pool.total_lp_supply, pool_before.total_lp_supply);
+}
+
+#[tokio::test]
+async fn single_asset_operations_enforce_limits() {
+    let MultiPoolFixture {
+        mut env,
+        keys,
+        user_tokens,
+        user_lp,
+    } = setup().await;
+    let payer = env.payer();
+    let pool = env.multi_pool(&keys.pool).await;
+
+    // At most 30% of the reserve may move in one go
+    let oversized = add_multi_liquidity_single_ix(
+        &keys,
+        &payer,
+        0,
+        &user_tokens[0],
+        &user_lp,
+        AMOUNTS[0] / 2,
+        0,
+    );
+    assert_eq!(
+        unwrap_program_error(env.process(&[oversized], &[]).await),
+        instruction_error(0, ProgramError::InvalidArgument)
+    );
+
+    let minted = pool.single_deposit_lp_tokens(0, 10_000).unwrap();
+    let too_greedy = add_multi_liquidity_single_ix(
+        &keys,
+        &payer,
+        0,
+        &user_tokens[0],
+        &user_lp,
+        10_000,
+        minted + 1,
+    );
+    assert_eq!(
+        unwrap_program_error(env.process(&[too_greedy], &[]).await),
+        instruction_error(0, ProgramError::InsufficientFunds)
+    );
+
+    let drain = remove_multi_liquidity_single_ix(
+        &keys,
+        &payer,
+        3,
+        &user_lp,
+        &user_tokens[3],
+        pool.total_lp_supply / 2,
+        0,
+    );
+    assert_eq!(
+        unwrap_program_error(env.process(&[drain], &[]).await),
+        instruction_error(0, ProgramError::InvalidArgument)
+    );
+
+    let amount_out = pool.single_withdraw_amount(3, 1_000).unwrap();
+    let too_greedy = remove_multi_liquidity_single_ix(
+        &keys,
+        &payer,
+        3,
+        &user_lp,
+        &user_tokens[3],
+        1_000,
+        amount_out + 1,
+    );
+    assert_eq!(
+        unwrap_program_error(env.process(&[too_greedy], &[]).await),
+        instruction_error(0, ProgramError::InsufficientFunds)
+    );
+
+    env.assert_multi_pool_consistent(&keys).await;
+}
+
+#[tokio::test]
+async fn multihop_passes_through_a_multi_asset_pool() {
+    let MultiPoolFixture {
+        mut env,
+        keys,
+        user_tokens,
+        ..
+    } = setup().await;
+    let payer = env.payer();
+    let mint_x = env.create_mint(6).await;
+    let user_x = env.create_funded_account(&mint_x, 10_000_000).await;
+    let pair_keys = env
+        .init_pool(mint_x, keys.tokens[0], &user_x, &user_tokens[0], 2_000_000, 1_000_000)
+        .await;
+
+    // X -(constant product)-> token 0 -(multi-asset)-> token 2
+    let out_0 = expected_swap_output(20_000, 2_000_000, 1_000_000);
+    let out_2 = expected_swap_output(out_0, AMOUNTS[0], AMOUNTS[2]);
+    let path = vec![mint_x, keys.tokens[0], keys.tokens[2]];
+    let logs = env
+        .simulate_logs(&[query_ix(
+            TestProjectInstruction::GetMultihopQuote {
+                amount_in: 20_000,
+                token_path: path.clone(),
+            },
+            &[pair_keys.pool, keys.pool],
+        )])
+        .await;
+    assert_logged(&logs, &format!("Final Amount Out: {}", out_2));
+
+    let hops = vec![
+        Hop {
+            keys: pair_keys,
+            intermediate: user_tokens[0],
+            output: user_tokens[0],
+        },
+        Hop {
+            keys: keys.hop_keys(0, 2),
+            intermediate: user_tokens[2],
+            output: user_tokens[2],
+        },
+    ];
+    let user_2_before = env.token_balance(&user_tokens[2]).await;
+    env.process(&[multihop_swap_ix(&payer, &user_x, &hops, 20_000, out_2)], &[])
+        .await
+        .unwrap();
+    assert_eq!(env.token_balance(&user_tokens[2]).await, user_2_before + out_2);
+
+    // Back again along the explicit path
+    let pool = env.assert_multi_pool_consistent(&keys).await;
+    let pair = env.assert_pool_consistent(&pair_keys).await;
+    let back_0 = expected_swap_output(out_2, pool.reserves()[2], pool.reserves()[0]);
+    let back_x = expected_swap_output(back_0, pair.reserve_b, pair.reserve_a);
+    let hops = vec![
+        Hop {
+            keys: keys.hop_keys(2, 0),
+            intermediate: user_tokens[0],
+            output: user_tokens[0],
+        },
+        Hop {
+            keys: pair_keys,
+            intermediate: user_x,
+            output: user_x,
+        },
+    ];
+    let user_x_before = env.token_balance(&user_x).await;
+    env.process(
+        &[multihop_swap_with_path_ix(
+            &payer,
+            &user_tokens[2],
+            &hops,
+            out_2,
+            back_x,
+            path.into_iter().rev().collect(),
+        )],
+        &[],
+    )
+    .await
+    .unwrap();
+    assert_eq!(env.token_balance(&user_x).await, user_x_before + back_x);
+    assert!(back_x < 20_000);
+
+    env.assert_multi_pool_consistent(&keys).await;
+    env.assert_pool_consistent(&pair_keys).await;
+}
+
+#[tokio::test]
+async fn compact_multihop_passes_through_a_multi_asset_pool() {
+    let MultiPoolFixture {
+        mut env,
+        keys,
+        user_tokens,
+        ..
+    } = setup().await;
+    let payer = env.payer();
+    let mint_x = env.create_mint(6).await;
+    let user_x = env.create_funded_account(&mint_x, 10_000_000).await;
+    let pair_keys = env
+        .init_pool(mint_x, keys.tokens[0], &user_x, &user_tokens[0], 2_000_000, 1_000_000)
+        .await;
+
+    // token 3 -(multi-asset)-> token 0 -(constant product)-> X
+    let out_0 = expected_swap_output(20_000, AMOUNTS[3], AMOUNTS[0]);
+    let out_x = expected_swap_output(out_0, 1_000_000, 2_000_000);
+    let user_x_before = env.token_balance(&user_x).await;
+    env.process(
+        &[compact_multihop_swap_ix(
+            &payer,
+            &[keys.hop_keys(3, 0), pair_keys],
+            &[keys.tokens[3], keys.tokens[0], mint_x],
+            &user_tokens[3],
+            &user_x,
+            20_000,
+            out_x,
+            Vec::new(),
+        )],
+        &[],
+    )
+    .await
+    .unwrap();
+    assert_eq!(env.token_balance(&user_x).await, user_x_before + out_x);
+
+    env.assert_multi_pool_consistent(&keys).await;
+    env.assert_pool_consistent(&pair_keys).await;
+}
+
+#[tokio::test]
+async fn get_pool_info_logs_every_token() {
+    let MultiPoolFixture { mut env, keys, .. } = setup().await;
+
+    let logs = env
+        .simulate_logs(&[query_ix(TestProjectInstruction::GetPoolInfo, &[keys.pool])])
+        .await;
+    assert_logged(&logs, "Multi-Asset Pool Info:");
+    for (index, (token, amount)) in keys.tokens.iter().zip(AMOUNTS).enumerate() {
+        assert_logged(&logs, &format!("Token {}: {}", index, token));
+        assert_logged(&logs, &format!("Reserve {}: {}", index, amount));
+    }
+}
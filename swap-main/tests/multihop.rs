@@ -0,0 +1,925 @@
+mod common;
+
+use cargo_swap::{curve::swap_fee, MultihopSwapResult, SwapResult, MAX_HOPS};
+use common::*;
+use solana_program::{program_error::ProgramError, pubkey::Pubkey};
+use solana_sdk::{packet::PACKET_DATA_SIZE, signature::Signer};
+
+// Two pools, P/Q and Q/R, so P can be routed to R through Q.
+struct MultihopFixture {
+    env: TestEnv,
+    pq: PoolKeys,
+    qr: PoolKeys,
+    user_p: Pubkey,
+    user_q: Pubkey,
+    user_r: Pubkey,
+}
+
+async fn setup() -> MultihopFixture {
+    let mut env = TestEnv::start().await;
+    let mint_p = env.create_mint(6).await;
+    let mint_q = env.create_mint(6).await;
+    let mint_r = env.create_mint(6).await;
+    let user_p = env.create_funded_account(&mint_p, 10_000_000).await;
+    let user_q = env.create_funded_account(&mint_q, 10_000_000).await;
+    let user_r = env.create_funded_account(&mint_r, 10_000_000).await;
+    let pq = env
+        .init_pool(mint_p, mint_q, &user_p, &user_q, 1_000_000, 2_000_000)
+        .await;
+    let qr = env
+        .init_pool(mint_q, mint_r, &user_q, &user_r, 3_000_000, 1_500_000)
+        .await;
+    MultihopFixture {
+        env,
+        pq,
+        qr,
+        user_p,
+        user_q,
+        user_r,
+    }
+}
+
+fn p_to_r_hops(fixture: &MultihopFixture) -> Vec<Hop> {
+    vec![
+        Hop {
+            keys: fixture.pq,
+            intermediate: fixture.user_q,
+            output: fixture.user_q,
+        },
+        Hop {
+            keys: fixture.qr,
+            intermediate: fixture.user_r,
+            output: fixture.user_r,
+        },
+    ]
+}
+
+fn p_to_r_amounts(amount_in: u64) -> (u64, u64) {
+    let q_out = expected_swap_output(amount_in, 1_000_000, 2_000_000);
+    let r_out = expected_swap_output(q_out, 3_000_000, 1_500_000);
+    (q_out, r_out)
+}
+
+#[tokio::test]
+async fn multihop_swap_routes_through_both_pools() {
+    let fixture = setup().await;
+    let hops = p_to_r_hops(&fixture);
+    let MultihopFixture {
+        mut env,
+        pq,
+        qr,
+        user_p,
+        user_q,
+        user_r,
+    } = fixture;
+    let payer = env.payer();
+    let p_before = env.token_balance(&user_p).await;
+    let q_before = env.token_balance(&user_q).await;
+    let r_before = env.token_balance(&user_r).await;
+
+    let (q_out, r_out) = p_to_r_amounts(20_000);
+    env.process(
+        &[multihop_swap_ix(&payer, &user_p, &hops, 20_000, r_out)],
+        &[],
+    )
+    .await
+    .unwrap();
+
+    let pq_pool = env.assert_pool_consistent(&pq).await;
+    assert_eq!(pq_pool.reserve_a, 1_020_000);
+    assert_eq!(pq_pool.reserve_b, 2_000_000 - q_out);
+    let qr_pool = env.assert_pool_consistent(&qr).await;
+    assert_eq!(qr_pool.reserve_a, 3_000_000 + q_out);
+    assert_eq!(qr_pool.reserve_b, 1_500_000 - r_out);
+
+    assert_eq!(env.token_balance(&user_p).await, p_before - 20_000);
+    // The intermediate Q is received and immediately spent by the second hop.
+    assert_eq!(env.token_balance(&user_q).await, q_before);
+    assert_eq!(env.token_balance(&user_r).await, r_before + r_out);
+}
+
+#[tokio::test]
+async fn multihop_swap_enforces_minimum_output() {
+    let fixture = setup().await;
+    let hops = p_to_r_hops(&fixture);
+    let MultihopFixture {
+        mut env,
+        pq,
+        qr,
+        user_p,
+        user_r,
+        ..
+    } = fixture;
+    let payer = env.payer();
+    let p_before = env.token_balance(&user_p).await;
+    let r_before = env.token_balance(&user_r).await;
+
+    let (_, r_out) = p_to_r_amounts(20_000);
+    let result = env
+        .process(
+            &[multihop_swap_ix(&payer, &user_p, &hops, 20_000, r_out + 1)],
+            &[],
+        )
+        .await;
+    assert_eq!(
+        unwrap_program_error(result),
+        instruction_error(0, ProgramError::InsufficientFunds)
+    );
+
+    // The whole route is rolled back.
+    assert_eq!(env.pool(&pq.pool).await.reserve_a, 1_000_000);
+    assert_eq!(env.pool(&qr.pool).await.reserve_a, 3_000_000);
+    assert_eq!(env.token_balance(&user_p).await, p_before);
+    assert_eq!(env.token_balance(&user_r).await, r_before);
+}
+
+#[tokio::test]
+async fn multihop_swap_rejects_partial_hop_accounts() {
+    let fixture = setup().await;
+    let hops = p_to_r_hops(&fixture);
+    let MultihopFixture {
+        mut env, user_p, ..
+    } = fixture;
+    let payer = env.payer();
+
+    let mut instruction = multihop_swap_ix(&payer, &user_p, &hops, 20_000, 0);
+    instruction.accounts.pop();
+    let result = env.process(&[instruction], &[]).await;
+    assert_eq!(
+        unwrap_program_error(result),
+        instruction_error(0, ProgramError::InvalidAccountData)
+    );
+}
+
+#[tokio::test]
+async fn multihop_swap_rejects_input_not_in_pool() {
+    let fixture = setup().await;
+    let MultihopFixture {
+        mut env,
+        qr,
+        user_p,
+        user_r,
+        ..
+    } = fixture;
+    let payer = env.payer();
+
+    // Feeding P into the Q/R pool has no valid direction.
+    let hops = vec![Hop {
+        keys: qr,
+        intermediate: user_r,
+        output: user_r,
+    }];
+    let result = env
+        .process(&[multihop_swap_ix(&payer, &user_p, &hops, 20_000, 0)], &[])
+        .await;
+    assert_eq!(
+        unwrap_program_error(result),
+        instruction_error(0, ProgramError::InvalidArgument)
+    );
+    env.assert_pool_consistent(&qr).await;
+}
+
+#[tokio::test]
+async fn multihop_swap_with_path_routes_reverse_direction() {
+    let fixture = setup().await;
+    let MultihopFixture {
+        mut env,
+        pq,
+        qr,
+        user_p,
+        user_q,
+        user_r,
+    } = fixture;
+    let payer = env.payer();
+    let p_before = env.token_balance(&user_p).await;
+    let r_before = env.token_balance(&user_r).await;
+
+    // R -> Q -> P walks both pools from their B side.
+    let hops = vec![
+        Hop {
+            keys: qr,
+            intermediate: user_q,
+            output: user_q,
+        },
+        Hop {
+            keys: pq,
+            intermediate: user_p,
+            output: user_p,
+        },
+    ];
+    let q_out = expected_swap_output(30_000, 1_500_000, 3_000_000);
+    let p_out = expected_swap_output(q_out, 2_000_000, 1_000_000);
+    env.process(
+        &[multihop_swap_with_path_ix(
+            &payer,
+            &user_r,
+            &hops,
+            30_000,
+            p_out,
+            vec![qr.token_b, qr.token_a, pq.token_a],
+        )],
+        &[],
+    )
+    .await
+    .unwrap();
+
+    let qr_pool = env.assert_pool_consistent(&qr).await;
+    assert_eq!(qr_pool.reserve_b, 1_530_000);
+    assert_eq!(qr_pool.reserve_a, 3_000_000 - q_out);
+    let pq_pool = env.assert_pool_consistent(&pq).await;
+    assert_eq!(pq_pool.reserve_b, 2_000_000 + q_out);
+    assert_eq!(pq_pool.reserve_a, 1_000_000 - p_out);
+    assert_eq!(env.token_balance(&user_r).await, r_before - 30_000);
+    assert_eq!(env.token_balance(&user_p).await, p_before + p_out);
+}
+
+#[tokio::test]
+async fn multihop_swap_with_path_rejects_pool_off_path() {
+    let fixture = setup().await;
+    let hops = p_to_r_hops(&fixture);
+    let MultihopFixture {
+        mut env,
+        pq,
+        qr,
+        user_p,
+        ..
+    } = fixture;
+    let payer = env.payer();
+
+    // Path claims P -> R directly, which the P/Q pool cannot serve.
+    let result = env
+        .process(
+            &[multihop_swap_with_path_ix(
+                &payer,
+                &user_p,
+                &hops,
+                20_000,
+                0,
+                vec![pq.token_a, qr.token_b, qr.token_a],
+            )],
+            &[],
+        )
+        .await;
+    assert_eq!(
+        unwrap_program_error(result),
+        instruction_error(0, ProgramError::InvalidArgument)
+    );
+    env.assert_pool_consistent(&pq).await;
+}
+
+#[tokio::test]
+async fn multihop_swap_with_path_requires_two_tokens() {
+    let fixture = setup().await;
+    let hops = p_to_r_hops(&fixture);
+    let MultihopFixture {
+        mut env,
+        pq,
+        user_p,
+        ..
+    } = fixture;
+    let payer = env.payer();
+
+    let result = env
+        .process(
+            &[multihop_swap_with_path_ix(
+                &payer,
+                &user_p,
+                &hops,
+                20_000,
+                0,
+                vec![pq.token_a],
+            )],
+            &[],
+        )
+        .await;
+    assert_eq!(
+        unwrap_program_error(result),
+        instruction_error(0, ProgramError::InvalidArgument)
+    );
+}
+
+#[tokio::test]
+async fn multihop_swap_with_path_enforces_minimum_output() {
+    let fixture = setup().await;
+    let hops = p_to_r_hops(&fixture);
+    let MultihopFixture {
+        mut env,
+        pq,
+        qr,
+        user_p,
+        ..
+    } = fixture;
+    let payer = env.payer();
+
+    let (_, r_out) = p_to_r_amounts(20_000);
+    let result = env
+        .process(
+            &[multihop_swap_with_path_ix(
+                &payer,
+                &user_p,
+                &hops,
+                20_000,
+                r_out + 1,
+                vec![pq.token_a, pq.token_b, qr.token_b],
+            )],
+            &[],
+        )
+        .await;
+    assert_eq!(
+        unwrap_program_error(result),
+        instruction_error(0, ProgramError::InsufficientFunds)
+    );
+    assert_eq!(env.pool(&pq.pool).await.reserve_a, 1_000_000);
+}
+
+#[tokio::test]
+async fn compact_multihop_swap_routes_both_ways() {
+    let MultihopFixture {
+        mut env,
+        pq,
+        qr,
+        user_p,
+        user_q,
+        user_r,
+    } = setup().await;
+    let payer = env.payer();
+    let p_before = env.token_balance(&user_p).await;
+    let q_before = env.token_balance(&user_q).await;
+    let r_before = env.token_balance(&user_r).await;
+
+    let (q_out, r_out) = p_to_r_amounts(20_000);
+    env.process(
+        &[compact_multihop_swap_ix(
+            &payer,
+            &[pq, qr],
+            &[pq.token_a, pq.token_b, qr.token_b],
+            &user_p,
+            &user_r,
+            20_000,
+            r_out,
+            Vec::new(),
+        )],
+        &[],
+    )
+    .await
+    .unwrap();
+    let pq_pool = env.assert_pool_consistent(&pq).await;
+    assert_eq!(pq_pool.reserve_a, 1_020_000);
+    assert_eq!(pq_pool.reserve_b, 2_000_000 - q_out);
+    let qr_pool = env.assert_pool_consistent(&qr).await;
+    assert_eq!(qr_pool.reserve_a, 3_000_000 + q_out);
+    assert_eq!(qr_pool.reserve_b, 1_500_000 - r_out);
+    assert_eq!(env.token_balance(&user_p).await, p_before - 20_000);
+    assert_eq!(env.token_balance(&user_q).await, q_before);
+    assert_eq!(env.token_balance(&user_r).await, r_before + r_out);
+
+    // R -> Q -> P walks both pools from their B side.
+    let q_back = expected_swap_output(r_out, qr_pool.reserve_b, qr_pool.reserve_a);
+    let p_back = expected_swap_output(q_back, pq_pool.reserve_b, pq_pool.reserve_a);
+    env.process(
+        &[compact_multihop_swap_ix(
+            &payer,
+            &[qr, pq],
+            &[qr.token_b, qr.token_a, pq.token_a],
+            &user_r,
+            &user_p,
+            r_out,
+            p_back,
+            Vec::new(),
+        )],
+        &[],
+    )
+    .await
+    .unwrap();
+    env.assert_pool_consistent(&pq).await;
+    env.assert_pool_consistent(&qr).await;
+    assert_eq!(env.token_balance(&user_p).await, p_before - 20_000 + p_back);
+    assert_eq!(env.token_balance(&user_r).await, r_before);
+}
+
+#[tokio::test]
+async fn compact_multihop_swap_enforces_minimum_output() {
+    let MultihopFixture {
+        mut env,
+        pq,
+        qr,
+        user_p,
+        user_r,
+        ..
+    } = setup().await;
+    let payer = env.payer();
+
+    let (_, r_out) = p_to_r_amounts(20_000);
+    let result = env
+        .process(
+            &[compact_multihop_swap_ix(
+                &payer,
+                &[pq, qr],
+                &[pq.token_a, pq.token_b, qr.token_b],
+                &user_p,
+                &user_r,
+                20_000,
+                r_out + 1,
+                Vec::new(),
+            )],
+            &[],
+        )
+        .await;
+    assert_eq!(
+        unwrap_program_error(result),
+        instruction_error(0, ProgramError::InsufficientFunds)
+    );
+    assert_eq!(env.pool(&pq.pool).await.reserve_a, 1_000_000);
+    assert_eq!(env.pool(&qr.pool).await.reserve_a, 3_000_000);
+}
+
+#[tokio::test]
+async fn compact_multihop_swap_rejects_pool_off_route() {
+    let MultihopFixture {
+        mut env,
+        pq,
+        qr,
+        user_p,
+        user_r,
+        ..
+    } = setup().await;
+    let payer = env.payer();
+
+    // The route claims P -> R directly, which the P/Q pool cannot serve.
+    let result = env
+        .process(
+            &[compact_multihop_swap_ix(
+                &payer,
+                &[pq],
+                &[pq.token_a, qr.token_b],
+                &user_p,
+                &user_r,
+                20_000,
+                0,
+                Vec::new(),
+            )],
+            &[],
+        )
+        .await;
+    assert_eq!(
+        unwrap_program_error(result),
+        instruction_error(0, ProgramError::InvalidArgument)
+    );
+    env.assert_pool_consistent(&pq).await;
+}
+
+#[tokio::test]
+async fn compact_multihop_swap_fits_five_hops_in_one_transaction() {
+    let mut env = TestEnv::start().await;
+    let mut mints = Vec::new();
+    let mut pools = Vec::new();
+    let mut previous_account = Pubkey::default();
+    for hop in 0..=5 {
+        let mint = env.create_mint(6).await;
+        let account = env.create_funded_account(&mint, 10_000_000).await;
+        if hop > 0 {
+            let keys = env
+                .init_pool(mints[hop - 1], mint, &previous_account, &account, 1_000_000, 1_000_000)
+                .await;
+            pools.push(keys);
+        }
+        mints.push(mint);
+        previous_account = account;
+    }
+
+    // The trader only holds the first and the last token of the route
+    let trader = env.create_user(1_000_000_000).await;
+    let trader_in = env.create_token_account(&mints[0], &trader.pubkey()).await;
+    let trader_out = env.create_token_account(&mints[5], &trader.pubkey()).await;
+    env.mint_to(&mints[0], &trader_in, 10_000).await;
+
+    let amount_out = (0..5).fold(10_000, |amount, _| {
+        expected_swap_output(amount, 1_000_000, 1_000_000)
+    });
+    let instruction = compact_multihop_swap_ix(
+        &trader.pubkey(),
+        &pools,
+        &mints,
+        &trader_in,
+        &trader_out,
+        10_000,
+        amount_out,
+        Vec::new(),
+    );
+    assert_eq!(instruction.accounts.len(), 2 + 5 * 3 + 6 + 2);
+    assert!(env.transaction_size(std::slice::from_ref(&instruction)) <= PACKET_DATA_SIZE);
+    env.process(&[instruction], &[&trader]).await.unwrap();
+
+    assert_eq!(env.token_balance(&trader_in).await, 0);
+    assert_eq!(env.token_balance(&trader_out).await, amount_out);
+    // Each vault holds exactly its reserve, so every hop paid the next one in full
+    for keys in &pools {
+        env.assert_pool_consistent(keys).await;
+    }
+}
+
+#[tokio::test]
+async fn multihop_swaps_check_output_account_mints() {
+    let fixture = setup().await;
+    let MultihopFixture {
+        mut env,
+        pq,
+        qr,
+        user_p,
+        user_q,
+        user_r,
+    } = fixture;
+    let payer = env.payer();
+
+    // The first hop pays Q, so its output account cannot hold R.
+    let hops = vec![
+        Hop {
+            keys: pq,
+            intermediate: user_r,
+            output: user_r,
+        },
+        Hop {
+            keys: qr,
+            intermediate: user_r,
+            output: user_r,
+        },
+    ];
+    for instruction in [
+        multihop_swap_ix(&payer, &user_p, &hops, 20_000, 0),
+        multihop_swap_with_path_ix(
+            &payer,
+            &user_p,
+            &hops,
+            20_000,
+            0,
+            vec![pq.token_a, pq.token_b, qr.token_b],
+        ),
+        compact_multihop_swap_ix(
+            &payer,
+            &[pq, qr],
+            &[pq.token_a, pq.token_b, qr.token_b],
+            &user_p,
+            &user_q,
+            20_000,
+            0,
+            Vec::new(),
+        ),
+    ] {
+        let result = env.process(&[instruction], &[]).await;
+        assert_eq!(
+            unwrap_program_error(result),
+            instruction_error(0, ProgramError::InvalidArgument)
+        );
+    }
+    env.assert_pool_consistent(&pq).await;
+    env.assert_pool_consistent(&qr).await;
+}
+
+#[tokio::test]
+async fn multihop_swaps_reject_a_pool_visited_twice() {
+    let MultihopFixture {
+        mut env,
+        pq,
+        user_p,
+        user_q,
+        ..
+    } = setup().await;
+    let payer = env.payer();
+
+    // P -> Q -> P through the same pool
+    let hops = vec![
+        Hop {
+            keys: pq,
+            intermediate: user_q,
+            output: user_q,
+        },
+        Hop {
+            keys: pq,
+            intermediate: user_p,
+            output: user_p,
+        },
+    ];
+    for instruction in [
+        multihop_swap_ix(&payer, &user_p, &hops, 20_000, 0),
+        multihop_swap_with_path_ix(
+            &payer,
+            &user_p,
+            &hops,
+            20_000,
+            0,
+            vec![pq.token_a, pq.token_b, pq.token_a],
+        ),
+        compact_multihop_swap_ix(
+            &payer,
+            &[pq, pq],
+            &[pq.token_a, pq.token_b, pq.token_a],
+            &user_p,
+            &user_p,
+            20_000,
+            0,
+            Vec::new(),
+        ),
+    ] {
+        let result = env.process(&[instruction], &[]).await;
+        assert_eq!(
+            unwrap_program_error(result),
+            instruction_error(0, ProgramError::InvalidArgument)
+        );
+    }
+    env.assert_pool_consistent(&pq).await;
+}
+
+#[tokio::test]
+async fn multihop_swaps_bound_the_hop_count() {
+    let MultihopFixture {
+        mut env,
+        pq,
+        user_p,
+        user_q,
+        ..
+    } = setup().await;
+    let payer = env.payer();
+
+    // The length is checked before any pool is looked at
+    let result = env
+        .process(
+            &[multihop_swap_with_path_ix(
+                &payer,
+                &user_p,
+                &[],
+                20_000,
+                0,
+                vec![pq.token_a; MAX_HOPS + 2],
+            )],
+            &[],
+        )
+        .await;
+    assert_eq!(
+        unwrap_program_error(result),
+        instruction_error(0, ProgramError::InvalidArgument)
+    );
+
+    let pools: Vec<PoolKeys> = (0..=MAX_HOPS)
+        .map(|_| PoolKeys::new(Pubkey::new_unique(), Pubkey::new_unique()))
+        .collect();
+    let mints = vec![pq.token_a; MAX_HOPS + 2];
+    let result = env
+        .process(
+            &[compact_multihop_swap_ix(&payer, &pools, &mints, &user_p, &user_q, 20_000, 0, Vec::new())],
+            &[],
+        )
+        .await;
+    assert_eq!(
+        unwrap_program_error(result),
+        instruction_error(0, ProgramError::InvalidArgument)
+    );
+}
+
+#[tokio::test]
+async fn multihop_swap_with_path_checks_pool_owner_and_address() {
+    let MultihopFixture {
+        mut env,
+        pq,
+        user_p,
+        user_q,
+        ..
+    } = setup().await;
+    let payer = env.payer();
+    let pool_account = env.get_account(&pq.pool).await.unwrap();
+
+    // A copy of the pool's state owned by another program
+    let mut foreign = pool_account.clone();
+    foreign.owner = TOKEN_PROGRAM_ID;
+    let foreign_pool = Pubkey::new_unique();
+    env.set_account(&foreign_pool, foreign);
+    // A copy owned by the swap program but away from the pool's PDA
+    let copied_pool = Pubkey::new_unique();
+    env.set_account(&copied_pool, pool_account);
+
+    for (pool, error) in [
+        (foreign_pool, ProgramError::IncorrectProgramId),
+        (copied_pool, ProgramError::InvalidSeeds),
+    ] {
+        let hops = vec![Hop {
+            keys: PoolKeys { pool, ..pq },
+            intermediate: user_q,
+            output: user_q,
+        }];
+        let result = env
+            .process(
+                &[multihop_swap_with_path_ix(
+                    &payer,
+                    &user_p,
+                    &hops,
+                    20_000,
+                    0,
+                    vec![pq.token_a, pq.token_b],
+                )],
+                &[],
+            )
+            .await;
+        assert_eq!(unwrap_program_error(result), instruction_error(0, error));
+    }
+    env.assert_pool_consistent(&pq).await;
+}
+
+#[tokio::test]
+async fn compact_multihop_swap_enforces_hop_minimums() {
+    let MultihopFixture {
+        mut env,
+        pq,
+        qr,
+        user_p,
+        user_r,
+        ..
+    } = setup().await;
+    let payer = env.payer();
+    let route = |minimum_hop_amounts_out: Vec<u64>| {
+        compact_multihop_swap_ix(
+            &payer,
+            &[pq, qr],
+            &[pq.token_a, pq.token_b, qr.token_b],
+            &user_p,
+            &user_r,
+            20_000,
+            0,
+            minimum_hop_amounts_out,
+        )
+    };
+
+    let (q_out, r_out) = p_to_r_amounts(20_000);
+    for (minimums, error) in [
+        (vec![q_out + 1, 0], ProgramError::InsufficientFunds),
+        (vec![q_out, r_out + 1], ProgramError::InsufficientFunds),
+        (vec![q_out], ProgramError::InvalidArgument),
+    ] {
+        let result = env.process(&[route(minimums)], &[]).await;
+        assert_eq!(unwrap_program_error(result), instruction_error(0, error));
+    }
+    assert_eq!(env.pool(&pq.pool).await.reserve_a, 1_000_000);
+
+    let r_before = env.token_balance(&user_r).await;
+    env.process(&[route(vec![q_out, r_out])], &[]).await.unwrap();
+    assert_eq!(env.token_balance(&user_r).await, r_before + r_out);
+}
+
+#[tokio::test]
+async fn multihop_swaps_enforce_hop_minimums() {
+    let fixture = setup().await;
+    let hops = p_to_r_hops(&fixture);
+    let MultihopFixture {
+        mut env,
+        pq,
+        qr,
+        user_p,
+        user_r,
+        ..
+    } = fixture;
+    let payer = env.payer();
+    let token_path = vec![pq.token_a, pq.token_b, qr.token_b];
+    let routes = |minimum_hop_amounts_out: Vec<u64>| {
+        [
+            multihop_swap_with_hop_minimums_ix(
+                &payer,
+                &user_p,
+                &hops,
+                20_000,
+                0,
+                minimum_hop_amounts_out.clone(),
+            ),
+            multihop_swap_with_path_and_hop_minimums_ix(
+                &payer,
+                &user_p,
+                &hops,
+                20_000,
+                0,
+                token_path.clone(),
+                minimum_hop_amounts_out,
+            ),
+        ]
+    };
+
+    let (q_out, r_out) = p_to_r_amounts(20_000);
+    for (minimums, error) in [
+        (vec![q_out + 1, 0], ProgramError::InsufficientFunds),
+        (vec![q_out, r_out + 1], ProgramError::InsufficientFunds),
+        (vec![q_out], ProgramError::InvalidArgument),
+    ] {
+        for instruction in routes(minimums.clone()) {
+            let result = env.process(&[instruction], &[]).await;
+            assert_eq!(unwrap_program_error(result), instruction_error(0, error.clone()));
+        }
+    }
+    assert_eq!(env.pool(&pq.pool).await.reserve_a, 1_000_000);
+
+    let r_before = env.token_balance(&user_r).await;
+    let [instruction, _] = routes(vec![q_out, r_out]);
+    env.process(&[instruction], &[]).await.unwrap();
+    assert_eq!(env.token_balance(&user_r).await, r_before + r_out);
+}
+
+#[tokio::test]
+async fn multihop_swaps_take_at_most_one_token_2022_program() {
+    let fixture = setup().await;
+    let hops = p_to_r_hops(&fixture);
+    let MultihopFixture {
+        mut env,
+        pq,
+        qr,
+        user_p,
+        user_r,
+        ..
+    } = fixture;
+    let payer = env.payer();
+
+    for instruction in [
+        multihop_swap_ix(&payer, &user_p, &hops, 20_000, 0),
+        multihop_swap_with_path_ix(
+            &payer,
+            &user_p,
+            &hops,
+            20_000,
+            0,
+            vec![pq.token_a, pq.token_b, qr.token_b],
+        ),
+        compact_multihop_swap_ix(
+            &payer,
+            &[pq, qr],
+            &[pq.token_a, pq.token_b, qr.token_b],
+            &user_p,
+            &user_r,
+            20_000,
+            0,
+            Vec::new(),
+        ),
+    ] {
+        let twice = with_token_2022(with_token_2022(instruction.clone()));
+        let result = env.process(&[twice], &[]).await;
+        assert_eq!(
+            unwrap_program_error(result),
+            instruction_error(0, ProgramError::InvalidAccountData)
+        );
+        env.process(&[with_token_2022(instruction)], &[]).await.unwrap();
+    }
+}
+
+#[tokio::test]
+async fn multihop_swaps_return_every_hop() {
+    let fixture = setup().await;
+    let hops = p_to_r_hops(&fixture);
+    let MultihopFixture {
+        mut env,
+        pq,
+        qr,
+        user_p,
+        user_r,
+        ..
+    } = fixture;
+    let payer = env.payer();
+
+    let (q_out, r_out) = p_to_r_amounts(20_000);
+    let expected = MultihopSwapResult {
+        amount_in: 20_000,
+        amount_out: r_out,
+        hops: vec![
+            SwapResult {
+                amount_in: 20_000,
+                amount_out: q_out,
+                fee: 60,
+                reserve_in: 1_020_000,
+                reserve_out: 2_000_000 - q_out,
+            },
+            SwapResult {
+                amount_in: q_out,
+                amount_out: r_out,
+                fee: swap_fee(q_out),
+                reserve_in: 3_000_000 + q_out,
+                reserve_out: 1_500_000 - r_out,
+            },
+        ],
+    };
+    for instruction in [
+        multihop_swap_ix(&payer, &user_p, &hops, 20_000, 0),
+        multihop_swap_with_path_ix(
+            &payer,
+            &user_p,
+            &hops,
+            20_000,
+            0,
+            vec![pq.token_a, pq.token_b, qr.token_b],
+        ),
+        compact_multihop_swap_ix(
+            &payer,
+            &[pq, qr],
+            &[pq.token_a, pq.token_b, qr.token_b],
+            &user_p,
+            &user_r,
+            20_000,
+            0,
+            Vec::new(),
+        ),
+    ] {
+        let result: MultihopSwapResult = env.simulate_return(&[instruction], &[]).await;
+        assert_eq!(result, expected);
+    }
+}
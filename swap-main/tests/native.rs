@@ -0,0 +1,193 @@
+mod common;
+
+use cargo_swap::SwapResult;
+use common::*;
+use solana_program::{program_error::ProgramError, pubkey::Pubkey};
+use solana_sdk::signature::{Keypair, Signer};
+
+const GOR: u64 = 1_000_000_000;
+
+struct NativeFixture {
+    env: TestEnv,
+    keys: PoolKeys,
+    user: Keypair,
+    user_token: Pubkey,
+}
+
+// A token/GOR pool seeded by the payer through WithNative, and a separate
+// user whose lamport balance is not touched by transaction fees
+async fn setup() -> NativeFixture {
+    let mut env = TestEnv::start().await;
+    let mint = env.create_mint(6).await;
+    let payer = env.payer();
+    let payer_token = env.create_funded_account(&mint, 10_000_000).await;
+    let keys = PoolKeys::new(mint, NATIVE_MINT);
+    let wrap = native_wrap_address(&payer);
+    let init = init_pool_ix(&keys, &payer, &payer_token, &wrap, 1_000_000, GOR);
+    env.process(&[with_native_ix(&payer, GOR, init)], &[])
+        .await
+        .unwrap();
+
+    let user = env.create_user(10 * GOR).await;
+    let user_token = env.create_token_account(&mint, &user.pubkey()).await;
+    env.mint_to(&mint, &user_token, 1_000_000).await;
+    NativeFixture {
+        env,
+        keys,
+        user,
+        user_token,
+    }
+}
+
+#[tokio::test]
+async fn init_pool_with_native_reserve() {
+    let NativeFixture { mut env, keys, .. } = setup().await;
+    let pool = env.assert_pool_consistent(&keys).await;
+    assert_eq!((pool.reserve_a, pool.reserve_b), (1_000_000, GOR));
+    let payer = env.payer();
+    assert!(env.get_account(&native_wrap_address(&payer)).await.is_none());
+}
+
+#[tokio::test]
+async fn swap_native_input() {
+    let NativeFixture {
+        mut env,
+        keys,
+        user,
+        user_token,
+    } = setup().await;
+    let owner = user.pubkey();
+    let wrap = native_wrap_address(&owner);
+    let lamports_before = env.lamports(&owner).await;
+
+    let amount_in = GOR / 10;
+    let expected = expected_swap_output(amount_in, GOR, 1_000_000);
+    let swap = swap_ix(&keys, &owner, &wrap, &user_token, amount_in, false);
+    env.process(&[with_native_ix(&owner, amount_in, swap)], &[&user])
+        .await
+        .unwrap();
+
+    // Exactly the input left the wallet; the rent came back on close
+    assert_eq!(env.lamports(&owner).await, lamports_before - amount_in);
+    assert_eq!(env.token_balance(&user_token).await, 1_000_000 + expected);
+    assert!(env.get_account(&wrap).await.is_none());
+    let pool = env.assert_pool_consistent(&keys).await;
+    assert_eq!(pool.reserve_b, GOR + amount_in);
+}
+
+#[tokio::test]
+async fn swap_native_output_unwraps() {
+    let NativeFixture {
+        mut env,
+        keys,
+        user,
+        user_token,
+    } = setup().await;
+    let owner = user.pubkey();
+    let wrap = native_wrap_address(&owner);
+    let lamports_before = env.lamports(&owner).await;
+
+    let expected = expected_swap_output(100_000, 1_000_000, GOR);
+    let swap = swap_ix(&keys, &owner, &user_token, &wrap, 100_000, true);
+    env.process(&[with_native_ix(&owner, 0, swap)], &[&user])
+        .await
+        .unwrap();
+
+    assert_eq!(env.lamports(&owner).await, lamports_before + expected);
+    assert_eq!(env.token_balance(&user_token).await, 900_000);
+    assert!(env.get_account(&wrap).await.is_none());
+}
+
+#[tokio::test]
+async fn with_native_keeps_the_swap_return_data() {
+    let NativeFixture {
+        mut env,
+        keys,
+        user,
+        user_token,
+    } = setup().await;
+    let owner = user.pubkey();
+    let wrap = native_wrap_address(&owner);
+
+    // Closing the wrapped account afterwards must not clobber the swap's result
+    let expected = expected_swap_output(100_000, 1_000_000, GOR);
+    let swap = swap_ix(&keys, &owner, &user_token, &wrap, 100_000, true);
+    let result: SwapResult = env
+        .simulate_return(&[with_native_ix(&owner, 0, swap)], &[&user])
+        .await;
+
+    assert_eq!(result.amount_in, 100_000);
+    assert_eq!(result.amount_out, expected);
+    assert_eq!(result.fee, 300);
+    assert_eq!(result.reserve_in, 1_100_000);
+    assert_eq!(result.reserve_out, GOR - expected);
+}
+
+#[tokio::test]
+async fn add_and_remove_liquidity_with_native() {
+    let NativeFixture {
+        mut env,
+        keys,
+        user,
+        user_token,
+    } = setup().await;
+    let owner = user.pubkey();
+    let wrap = native_wrap_address(&owner);
+    let user_lp = env.create_token_account(&keys.lp_mint, &owner).await;
+    let lamports_before = env.lamports(&owner).await;
+
+    // Offer twice the GOR the ratio needs; the excess stays in the wrap
+    // account and is unwrapped back to the user
+    let add = add_liquidity_ix(&keys, &owner, &user_token, &wrap, &user_lp, 100_000, GOR / 5);
+    env.process(&[with_native_ix(&owner, GOR / 5, add)], &[&user])
+        .await
+        .unwrap();
+    assert_eq!(env.lamports(&owner).await, lamports_before - GOR / 10);
+    assert_eq!(env.token_balance(&user_token).await, 900_000);
+    let pool = env.assert_pool_consistent(&keys).await;
+    assert_eq!(pool.reserve_b, GOR + GOR / 10);
+
+    let lp_amount = env.token_balance(&user_lp).await;
+    let remove = remove_liquidity_ix(&keys, &owner, &user_lp, &user_token, &wrap, lp_amount);
+    env.process(&[with_native_ix(&owner, 0, remove)], &[&user])
+        .await
+        .unwrap();
+    let pool = env.assert_pool_consistent(&keys).await;
+    assert_eq!(env.lamports(&owner).await, lamports_before - (pool.reserve_b - GOR));
+    assert!(env.get_account(&wrap).await.is_none());
+}
+
+#[tokio::test]
+async fn with_native_rejects_other_instructions_and_accounts() {
+    let NativeFixture {
+        mut env,
+        keys,
+        user,
+        user_token,
+    } = setup().await;
+    let owner = user.pubkey();
+    let wrap = native_wrap_address(&owner);
+
+    // Only the swap and liquidity instructions can be wrapped
+    let flash = flash_loan_ix(
+        &keys.pool,
+        &keys.token_b,
+        &keys.vault_b,
+        &wrap,
+        &FLASH_RECEIVER_ID,
+        &owner,
+        1_000,
+    );
+    let error = unwrap_program_error(env.process(&[with_native_ix(&owner, 0, flash)], &[&user]).await);
+    assert_eq!(error, instruction_error(0, ProgramError::InvalidInstructionData));
+
+    // The wrap account must be the user's own PDA
+    let mut instruction = with_native_ix(
+        &owner,
+        1_000,
+        swap_ix(&keys, &owner, &wrap, &user_token, 1_000, false),
+    );
+    instruction.accounts[1].pubkey = native_wrap_address(&env.payer());
+    let error = unwrap_program_error(env.process(&[instruction], &[&user]).await);
+    assert_eq!(error, instruction_error(0, ProgramError::InvalidSeeds));
+}
@@ -0,0 +1,459 @@
+mod common;
+
+use cargo_swap::{
+    curve::PRICE_SCALE,
+    oracle::{self, Observation, ObserveResult, WindowTwap},
+    Pool,
+};
+use common::*;
+use solana_program::{instruction::Instruction, program_error::ProgramError, pubkey::Pubkey};
+use solana_sdk::signature::{Keypair, Signer};
+
+// A pool of 1_000_000 A / 2_000_000 B with an oracle of `capacity`
+// observations, both created at `created`
+struct OracleFixture {
+    env: TestEnv,
+    keys: PoolKeys,
+    user_a: Pubkey,
+    user_b: Pubkey,
+    created: i64,
+}
+
+async fn setup(capacity: u16) -> OracleFixture {
+    let mut env = TestEnv::start().await;
+    let mint_a = env.create_mint(6).await;
+    let mint_b = env.create_mint(6).await;
+    let user_a = env.create_funded_account(&mint_a, 10_000_000).await;
+    let user_b = env.create_funded_account(&mint_b, 10_000_000).await;
+    let keys = env
+        .init_pool(mint_a, mint_b, &user_a, &user_b, 1_000_000, 2_000_000)
+        .await;
+    let created = env.pool(&keys.pool).await.last_update_timestamp;
+    let payer = env.payer();
+    env.process(&[init_oracle_ix(&keys.pool, &payer, capacity)], &[])
+        .await
+        .unwrap();
+    OracleFixture {
+        env,
+        keys,
+        user_a,
+        user_b,
+        created,
+    }
+}
+
+fn timestamps(observations: &[Observation]) -> Vec<i64> {
+    observations.iter().map(|observation| observation.timestamp).collect()
+}
+
+async fn swap_at(env: &mut TestEnv, swap: &Instruction, unix_timestamp: i64) {
+    env.set_unix_timestamp(unix_timestamp).await;
+    env.process(std::slice::from_ref(swap), &[]).await.unwrap();
+}
+
+fn price_a(pool: &Pool) -> u128 {
+    pool.reserve_b as u128 * PRICE_SCALE / pool.reserve_a as u128
+}
+
+#[tokio::test]
+async fn init_oracle_records_the_pool_now() {
+    let OracleFixture { mut env, keys, .. } = setup(4).await;
+
+    let (header, observations) = env.oracle(&keys.pool).await;
+    assert_eq!(header.pool, keys.pool);
+    assert_eq!((header.index, header.cardinality), (0, 1));
+    let pool = env.pool(&keys.pool).await;
+    assert_eq!(
+        observations,
+        vec![Observation {
+            timestamp: pool.last_update_timestamp,
+            price_a_cumulative: pool.price_a_cumulative,
+            price_b_cumulative: pool.price_b_cumulative,
+            liquidity_cumulative: 0,
+        }]
+    );
+    let account = env.get_account(&oracle_address(&keys.pool)).await.unwrap();
+    assert_eq!(account.data.len(), oracle::oracle_len(4));
+
+    // One instruction creates or adds 1 to MAX_ORACLE_GROWTH slots
+    let payer = env.payer();
+    let max = oracle::MAX_ORACLE_GROWTH as u16;
+    for instruction in [
+        init_oracle_ix(&keys.pool, &payer, 0),
+        init_oracle_ix(&keys.pool, &payer, max + 1),
+        grow_oracle_ix(&keys.pool, &payer, 4 + max + 1),
+    ] {
+        let error = unwrap_program_error(env.process(&[instruction], &[]).await);
+        assert_eq!(error, instruction_error(0, ProgramError::InvalidArgument));
+    }
+}
+
+#[tokio::test]
+async fn init_oracle_requires_the_pool_admin() {
+    let mut env = TestEnv::start().await;
+    let mint_a = env.create_mint(6).await;
+    let mint_b = env.create_mint(6).await;
+    let user_a = env.create_funded_account(&mint_a, 10_000_000).await;
+    let user_b = env.create_funded_account(&mint_b, 10_000_000).await;
+    let keys = env
+        .init_pool(mint_a, mint_b, &user_a, &user_b, 1_000_000, 2_000_000)
+        .await;
+
+    // Anyone else signing leaves the pool's instructions unchanged
+    let stranger = Keypair::new();
+    let result = env
+        .process(&[init_oracle_ix(&keys.pool, &stranger.pubkey(), 4)], &[&stranger])
+        .await;
+    assert_eq!(
+        unwrap_program_error(result),
+        instruction_error(0, ProgramError::MissingRequiredSignature)
+    );
+    assert!(!env.pool(&keys.pool).await.has_oracle);
+    assert!(env.get_account(&oracle_address(&keys.pool)).await.is_none());
+    let payer = env.payer();
+    env.process(&[swap_ix(&keys, &payer, &user_a, &user_b, 1_000, true)], &[])
+        .await
+        .unwrap();
+}
+
+#[tokio::test]
+async fn swaps_passing_the_oracle_record_once_per_second() {
+    let OracleFixture {
+        mut env,
+        keys,
+        user_a,
+        user_b,
+        created,
+    } = setup(8).await;
+    let payer = env.payer();
+    let swap = |amount_in, direction_a_to_b| {
+        let (user_in, user_out) = if direction_a_to_b {
+            (&user_a, &user_b)
+        } else {
+            (&user_b, &user_a)
+        };
+        swap_ix(&keys, &payer, user_in, user_out, amount_in, direction_a_to_b)
+    };
+
+    // Only the first of two swaps in one second records
+    env.set_unix_timestamp(created + 10).await;
+    env.process(&[with_oracle(swap(100_000, true)), with_oracle(swap(1_000, false))], &[])
+        .await
+        .unwrap();
+    let (_, observations) = env.oracle(&keys.pool).await;
+    assert_eq!(timestamps(&observations), vec![created, created + 10]);
+    assert_eq!(observations[1].price_a_cumulative, 2 * PRICE_SCALE * 10);
+    assert_eq!(
+        observations[1].liquidity_cumulative,
+        oracle::liquidity(1_000_000, 2_000_000) * 10
+    );
+
+    // The pool's swaps must pass its oracle
+    env.set_unix_timestamp(created + 20).await;
+    let error = unwrap_program_error(env.process(&[swap(50_000, false)], &[]).await);
+    assert_eq!(error, instruction_error(0, ProgramError::NotEnoughAccountKeys));
+    assert_eq!(env.oracle(&keys.pool).await.1.len(), 2);
+
+    env.set_unix_timestamp(created + 30).await;
+    let before = env.pool(&keys.pool).await;
+    env.process(&[with_oracle(swap(10_000, true))], &[]).await.unwrap();
+    let pool = env.assert_pool_consistent(&keys).await;
+    let (header, observations) = env.oracle(&keys.pool).await;
+    assert_eq!((header.index, header.cardinality), (2, 3));
+    // Prices accumulate exactly, and so does the liquidity, which held since
+    // the last record
+    assert_eq!(observations[2].timestamp, created + 30);
+    assert_eq!(observations[2].price_a_cumulative, pool.price_a_cumulative);
+    assert_eq!(observations[2].price_b_cumulative, pool.price_b_cumulative);
+    assert_eq!(
+        observations[2].liquidity_cumulative,
+        observations[1].liquidity_cumulative
+            + oracle::liquidity(before.reserve_a, before.reserve_b) * 20
+    );
+}
+
+#[tokio::test]
+async fn liquidity_changes_count_for_the_time_they_held() {
+    let OracleFixture {
+        mut env,
+        keys,
+        user_a,
+        user_b,
+        created,
+    } = setup(8).await;
+    let payer = env.payer();
+    let user_lp = associated_token_address(&payer, &keys.lp_mint);
+
+    // Deposits and withdrawals must pass the oracle too
+    let deposit = add_liquidity_ix(&keys, &payer, &user_a, &user_b, &user_lp, 500_000, 1_000_000);
+    let error = unwrap_program_error(env.process(std::slice::from_ref(&deposit), &[]).await);
+    assert_eq!(error, instruction_error(0, ProgramError::NotEnoughAccountKeys));
+
+    // Each segment holds a liquidity from its start until the next one
+    let mut segments = vec![(created, oracle::liquidity(1_000_000, 2_000_000))];
+    swap_at(&mut env, &with_oracle(deposit), created + 10).await;
+    let pool = env.pool(&keys.pool).await;
+    segments.push((created + 10, oracle::liquidity(pool.reserve_a, pool.reserve_b)));
+
+    let lp_amount = env.token_balance(&user_lp).await / 2;
+    let withdrawal = remove_liquidity_ix(&keys, &payer, &user_lp, &user_a, &user_b, lp_amount);
+    swap_at(&mut env, &with_oracle(withdrawal), created + 25).await;
+    let pool = env.pool(&keys.pool).await;
+    segments.push((created + 25, oracle::liquidity(pool.reserve_a, pool.reserve_b)));
+
+    let swap = with_oracle(swap_ix(&keys, &payer, &user_a, &user_b, 10_000, true));
+    swap_at(&mut env, &swap, created + 40).await;
+
+    let (_, observations) = env.oracle(&keys.pool).await;
+    assert_eq!(
+        timestamps(&observations),
+        vec![created, created + 10, created + 25, created + 40]
+    );
+    let mut liquidity_cumulative = 0;
+    for (index, observation) in observations.iter().enumerate().skip(1) {
+        let (from, liquidity) = segments[index - 1];
+        liquidity_cumulative += liquidity * (observation.timestamp - from) as u128;
+        assert_eq!(observation.liquidity_cumulative, liquidity_cumulative);
+    }
+    let pool = env.pool(&keys.pool).await;
+    assert_eq!(observations[3].price_a_cumulative, pool.price_a_cumulative);
+
+    // So the average over the whole life of the oracle is exact
+    let result: ObserveResult = env
+        .simulate_return(&[observe_ix(&keys.pool, vec![40])], &[])
+        .await;
+    assert_eq!(result.windows[0].liquidity, liquidity_cumulative / 40);
+}
+
+#[tokio::test]
+async fn every_reserve_change_records_through_the_oracle() {
+    let OracleFixture {
+        mut env,
+        keys,
+        user_a,
+        user_b,
+        created,
+    } = setup(16).await;
+    let payer = env.payer();
+    // A second hop through a pool without an oracle
+    let mint_c = env.create_mint(6).await;
+    let user_c = env.create_funded_account(&mint_c, 10_000_000).await;
+    let bc = env
+        .init_pool(keys.token_b, mint_c, &user_b, &user_c, 2_000_000, 2_000_000)
+        .await;
+    let (a, b) = (keys.token_a, keys.token_b);
+    let hops = [
+        Hop {
+            keys,
+            intermediate: user_b,
+            output: user_b,
+        },
+        Hop {
+            keys: bc,
+            intermediate: user_c,
+            output: user_c,
+        },
+    ];
+    let path = vec![a, b, mint_c];
+    let routes = [
+        multihop_swap_ix(&payer, &user_a, &hops, 1_000, 0),
+        multihop_swap_with_path_ix(&payer, &user_a, &hops, 1_000, 0, path.clone()),
+        multihop_swap_with_hop_minimums_ix(&payer, &user_a, &hops, 1_000, 0, vec![1, 1]),
+        multihop_swap_with_path_and_hop_minimums_ix(
+            &payer,
+            &user_a,
+            &hops,
+            1_000,
+            0,
+            path.clone(),
+            vec![1, 1],
+        ),
+        compact_multihop_swap_ix(&payer, &[keys, bc], &path, &user_a, &user_c, 1_000, 0, vec![]),
+        split_route_swap_ix(
+            &payer,
+            &user_a,
+            &user_c,
+            &[SplitLeg {
+                pools: vec![keys, bc],
+                mints: path.clone(),
+                amount_in: 1_000,
+            }],
+            0,
+        ),
+    ];
+    let loan = flash_loan_ix(&keys.pool, &b, &keys.vault_b, &user_b, &FLASH_RECEIVER_ID, &payer, 100_000);
+    let flash_swap = flash_swap_ix(&keys, &user_a, &user_b, &FLASH_RECEIVER_ID, &user_a, &payer, 0, 10_000);
+    let instructions = routes
+        .into_iter()
+        .map(|route| with_route_oracles(route, &[keys.pool]))
+        .chain([with_oracle_at(loan, 6), with_oracle_at(flash_swap, 10)]);
+
+    for (index, instruction) in instructions.enumerate() {
+        let now = created + 10 * (index as i64 + 1);
+        let before = env.pool(&keys.pool).await;
+        swap_at(&mut env, &instruction, now).await;
+        let (_, observations) = env.oracle(&keys.pool).await;
+        let newest = observations.last().unwrap();
+        assert_eq!(observations.len(), index + 2);
+        assert_eq!(newest.timestamp, now);
+        assert_eq!(
+            newest.liquidity_cumulative - observations[index].liquidity_cumulative,
+            oracle::liquidity(before.reserve_a, before.reserve_b) * 10
+        );
+        assert_eq!(newest.price_a_cumulative, env.pool(&keys.pool).await.price_a_cumulative);
+    }
+}
+
+#[tokio::test]
+async fn a_full_ring_overwrites_its_oldest_until_grown() {
+    let OracleFixture {
+        mut env,
+        keys,
+        user_a,
+        user_b,
+        created,
+    } = setup(2).await;
+    let payer = env.payer();
+    let swap = with_oracle(swap_ix(&keys, &payer, &user_a, &user_b, 1_000, true));
+
+    swap_at(&mut env, &swap, created + 10).await;
+    swap_at(&mut env, &swap, created + 20).await;
+    let (header, observations) = env.oracle(&keys.pool).await;
+    assert_eq!((header.index, header.cardinality), (0, 2));
+    assert_eq!(timestamps(&observations), vec![created + 10, created + 20]);
+
+    // Windows reach back to the oldest observation only
+    env.simulate_return::<ObserveResult>(&[observe_ix(&keys.pool, vec![10])], &[])
+        .await;
+    let error = unwrap_program_error(env.process(&[observe_ix(&keys.pool, vec![11])], &[]).await);
+    assert_eq!(error, instruction_error(0, ProgramError::InvalidArgument));
+
+    // Growing must add slots
+    let error = unwrap_program_error(
+        env.process(&[grow_oracle_ix(&keys.pool, &payer, 2)], &[]).await,
+    );
+    assert_eq!(error, instruction_error(0, ProgramError::InvalidArgument));
+    env.process(&[grow_oracle_ix(&keys.pool, &payer, 4)], &[])
+        .await
+        .unwrap();
+    let account = env.get_account(&oracle_address(&keys.pool)).await.unwrap();
+    assert_eq!(account.data.len(), oracle::oracle_len(4));
+
+    // The write after the newest still overwrites the oldest, then the new
+    // slots fill in order
+    swap_at(&mut env, &swap, created + 30).await;
+    assert_eq!(
+        timestamps(&env.oracle(&keys.pool).await.1),
+        vec![created + 20, created + 30]
+    );
+    for seconds in [40, 50, 60] {
+        swap_at(&mut env, &swap, created + seconds).await;
+    }
+    let (header, observations) = env.oracle(&keys.pool).await;
+    assert_eq!(header.cardinality, 4);
+    assert_eq!(
+        timestamps(&observations),
+        vec![created + 30, created + 40, created + 50, created + 60]
+    );
+}
+
+#[tokio::test]
+async fn observe_averages_each_window_up_to_now() {
+    let OracleFixture {
+        mut env,
+        keys,
+        user_a,
+        user_b,
+        created,
+    } = setup(8).await;
+    let payer = env.payer();
+
+    // Each segment holds a price and a liquidity for 100 seconds
+    let pool = env.pool(&keys.pool).await;
+    let mut segments = vec![(created, price_a(&pool), oracle::liquidity(1_000_000, 2_000_000))];
+    for (seconds, amount_in) in [(100, 100_000), (200, 300_000)] {
+        env.set_unix_timestamp(created + seconds).await;
+        env.process(
+            &[with_oracle(swap_ix(&keys, &payer, &user_a, &user_b, amount_in, true))],
+            &[],
+        )
+        .await
+        .unwrap();
+        let pool = env.pool(&keys.pool).await;
+        segments.push((
+            created + seconds,
+            price_a(&pool),
+            oracle::liquidity(pool.reserve_a, pool.reserve_b),
+        ));
+    }
+    let now = created + 300;
+    env.set_unix_timestamp(now).await;
+
+    // 150 and 50 seconds back fall between observations
+    let seconds_agos = vec![300, 200, 150, 50];
+    let result: ObserveResult = env
+        .simulate_return(&[observe_ix(&keys.pool, seconds_agos.clone())], &[])
+        .await;
+    assert_eq!(result.timestamp, now);
+    let expected: Vec<WindowTwap> = seconds_agos
+        .iter()
+        .map(|&seconds_ago| {
+            let start = now - seconds_ago as i64;
+            let (mut price_a, mut price_b, mut liquidity) = (0, 0, 0);
+            for (index, &(from, price, segment_liquidity)) in segments.iter().enumerate() {
+                let to = segments.get(index + 1).map_or(now, |segment| segment.0);
+                let held = (to - from.max(start)).max(0) as u128;
+                price_a += price * held;
+                price_b += oracle::inverse_price(price) * held;
+                liquidity += segment_liquidity * held;
+            }
+            let seconds = seconds_ago as u128;
+            WindowTwap {
+                seconds_ago,
+                price_a: price_a / seconds,
+                price_b: price_b / seconds,
+                liquidity: liquidity / seconds,
+            }
+        })
+        .collect();
+    assert_eq!(result.windows, expected);
+    assert_eq!(result.windows[3].price_a, segments[2].1);
+
+    // Windows must be non-empty and start within the ring
+    for seconds_agos in [vec![301], vec![0], vec![], vec![1; oracle::MAX_OBSERVE_WINDOWS + 1]] {
+        let error =
+            unwrap_program_error(env.process(&[observe_ix(&keys.pool, seconds_agos)], &[]).await);
+        assert_eq!(error, instruction_error(0, ProgramError::InvalidArgument));
+    }
+}
+
+#[tokio::test]
+async fn oracle_belongs_to_its_pool() {
+    let OracleFixture { mut env, keys, user_a, .. } = setup(4).await;
+    let payer = env.payer();
+    let mint_c = env.create_mint(6).await;
+    let user_c = env.create_funded_account(&mint_c, 10_000_000).await;
+    let other = env
+        .init_pool(keys.token_a, mint_c, &user_a, &user_c, 1_000_000, 1_000_000)
+        .await;
+    env.process(&[init_oracle_ix(&other.pool, &payer, 4)], &[])
+        .await
+        .unwrap();
+
+    // Another pool's oracle is refused by Observe and by swaps
+    let mut observe = observe_ix(&other.pool, vec![1]);
+    observe.accounts[1].pubkey = oracle_address(&keys.pool);
+    let error = unwrap_program_error(env.process(&[observe], &[]).await);
+    assert_eq!(error, instruction_error(0, ProgramError::InvalidArgument));
+
+    let mut swap = with_oracle(swap_ix(&other, &payer, &user_a, &user_c, 1_000, true));
+    swap.accounts.last_mut().unwrap().pubkey = oracle_address(&keys.pool);
+    let error = unwrap_program_error(env.process(&[swap], &[]).await);
+    assert_eq!(error, instruction_error(0, ProgramError::InvalidArgument));
+
+    // Only a pool's own oracle address can be created for it
+    let mut init = init_oracle_ix(&other.pool, &payer, 4);
+    init.accounts[1].pubkey = oracle_address(&keys.pool);
+    let error = unwrap_program_error(env.process(&[init], &[]).await);
+    assert_eq!(error, instruction_error(0, ProgramError::InvalidSeeds));
+}
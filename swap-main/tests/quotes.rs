@@ -0,0 +1,222 @@
+mod common;
+
+use cargo_swap::TestProjectInstruction;
+use common::*;
+use solana_program::program_error::ProgramError;
+
+struct QuoteFixture {
+    env: TestEnv,
+    pq: PoolKeys,
+    qr: PoolKeys,
+}
+
+async fn setup() -> QuoteFixture {
+    let mut env = TestEnv::start().await;
+    let mint_p = env.create_mint(6).await;
+    let mint_q = env.create_mint(6).await;
+    let mint_r = env.create_mint(6).await;
+    let user_p = env.create_funded_account(&mint_p, 10_000_000).await;
+    let user_q = env.create_funded_account(&mint_q, 10_000_000).await;
+    let user_r = env.create_funded_account(&mint_r, 10_000_000).await;
+    let pq = env
+        .init_pool(mint_p, mint_q, &user_p, &user_q, 1_000_000, 2_000_000)
+        .await;
+    let qr = env
+        .init_pool(mint_q, mint_r, &user_q, &user_r, 3_000_000, 1_500_000)
+        .await;
+    QuoteFixture { env, pq, qr }
+}
+
+fn assert_logged(logs: &[String], expected: &str) {
+    assert!(
+        logs.iter().any(|line| line.ends_with(expected)),
+        "missing log line {:?} in {:#?}",
+        expected,
+        logs
+    );
+}
+
+// Quotes are read-only: running one must not touch the pool.
+async fn assert_quote_left_pool_alone(env: &mut TestEnv, keys: &PoolKeys) {
+    let pool = env.assert_pool_consistent(keys).await;
+    let fresh = PoolKeys::new(pool.token_a, pool.token_b);
+    assert_eq!(fresh.pool, keys.pool);
+}
+
+#[tokio::test]
+async fn get_pool_info_logs_reserves() {
+    let QuoteFixture { mut env, pq, .. } = setup().await;
+
+    let logs = env
+        .simulate_logs(&[query_ix(TestProjectInstruction::GetPoolInfo, &[pq.pool])])
+        .await;
+    assert_logged(&logs, &format!("Token A: {}", pq.token_a));
+    assert_logged(&logs, &format!("Token B: {}", pq.token_b));
+    assert_logged(&logs, "Reserve A: 1000000");
+    assert_logged(&logs, "Reserve B: 2000000");
+    assert_logged(&logs, "Total LP Supply: 1414213");
+    assert_logged(&logs, "Ratio B/A: 2.000000");
+    assert_quote_left_pool_alone(&mut env, &pq).await;
+}
+
+#[tokio::test]
+async fn get_pool_info_rejects_non_pool_account() {
+    let QuoteFixture { mut env, pq, .. } = setup().await;
+
+    let result = env
+        .process(
+            &[query_ix(TestProjectInstruction::GetPoolInfo, &[pq.token_a])],
+            &[],
+        )
+        .await;
+    assert_eq!(
+        unwrap_program_error(result),
+        instruction_error(0, ProgramError::InvalidAccountData)
+    );
+}
+
+#[tokio::test]
+async fn get_total_pools_and_find_pools_by_token_only_log() {
+    let QuoteFixture { mut env, pq, .. } = setup().await;
+
+    let logs = env
+        .simulate_logs(&[query_ix(TestProjectInstruction::GetTotalPools, &[])])
+        .await;
+    assert_logged(&logs, "GetTotalPools called - would return total pool count");
+
+    let logs = env
+        .simulate_logs(&[query_ix(
+            TestProjectInstruction::FindPoolsByToken {
+                token_address: pq.token_a,
+            },
+            &[],
+        )])
+        .await;
+    assert_logged(
+        &logs,
+        &format!("FindPoolsByToken called for token: {}", pq.token_a),
+    );
+}
+
+#[tokio::test]
+async fn get_swap_quote_logs_output_in_both_directions() {
+    let QuoteFixture { mut env, pq, .. } = setup().await;
+
+    let logs = env
+        .simulate_logs(&[query_ix(
+            TestProjectInstruction::GetSwapQuote {
+                amount_in: 10_000,
+                token_in: pq.token_a,
+            },
+            &[pq.pool],
+        )])
+        .await;
+    assert_logged(&logs, "Direction A->B: true");
+    assert_logged(&logs, "Reserve In: 1000000");
+    assert_logged(&logs, "Reserve Out: 2000000");
+    // Quotes price the swap itself, fee included
+    assert_logged(
+        &logs,
+        &format!("Amount Out: {}", expected_swap_output(10_000, 1_000_000, 2_000_000)),
+    );
+
+    let logs = env
+        .simulate_logs(&[query_ix(
+            TestProjectInstruction::GetSwapQuote {
+                amount_in: 10_000,
+                token_in: pq.token_b,
+            },
+            &[pq.pool],
+        )])
+        .await;
+    assert_logged(&logs, "Direction A->B: false");
+    assert_logged(
+        &logs,
+        &format!("Amount Out: {}", expected_swap_output(10_000, 2_000_000, 1_000_000)),
+    );
+    assert_quote_left_pool_alone(&mut env, &pq).await;
+}
+
+#[tokio::test]
+async fn get_swap_quote_rejects_foreign_token() {
+    let QuoteFixture { mut env, pq, qr } = setup().await;
+
+    let result = env
+        .process(
+            &[query_ix(
+                TestProjectInstruction::GetSwapQuote {
+                    amount_in: 10_000,
+                    token_in: qr.token_b,
+                },
+                &[pq.pool],
+            )],
+            &[],
+        )
+        .await;
+    assert_eq!(
+        unwrap_program_error(result),
+        instruction_error(0, ProgramError::InvalidArgument)
+    );
+}
+
+#[tokio::test]
+async fn get_multihop_quote_chains_hops() {
+    let QuoteFixture { mut env, pq, qr } = setup().await;
+
+    let logs = env
+        .simulate_logs(&[query_ix(
+            TestProjectInstruction::GetMultihopQuote {
+                amount_in: 10_000,
+                token_path: vec![pq.token_a, pq.token_b, qr.token_b],
+            },
+            &[pq.pool, qr.pool],
+        )])
+        .await;
+    // Each hop is quoted exactly as MultihopSwap would execute it
+    let q_out = expected_swap_output(10_000, 1_000_000, 2_000_000);
+    let r_out = expected_swap_output(q_out, 3_000_000, 1_500_000);
+    assert_logged(&logs, &format!("Hop 1: {} -> {}", pq.token_a, pq.token_b));
+    assert_logged(&logs, &format!("Hop 2: {} -> {}", qr.token_a, qr.token_b));
+    assert_logged(&logs, &format!("Final Amount Out: {}", r_out));
+    assert_quote_left_pool_alone(&mut env, &pq).await;
+    assert_quote_left_pool_alone(&mut env, &qr).await;
+}
+
+#[tokio::test]
+async fn get_multihop_quote_validates_path() {
+    let QuoteFixture { mut env, pq, qr } = setup().await;
+
+    let result = env
+        .process(
+            &[query_ix(
+                TestProjectInstruction::GetMultihopQuote {
+                    amount_in: 10_000,
+                    token_path: vec![pq.token_a],
+                },
+                &[pq.pool],
+            )],
+            &[],
+        )
+        .await;
+    assert_eq!(
+        unwrap_program_error(result),
+        instruction_error(0, ProgramError::InvalidArgument)
+    );
+
+    let result = env
+        .process(
+            &[query_ix(
+                TestProjectInstruction::GetMultihopQuote {
+                    amount_in: 10_000,
+                    token_path: vec![pq.token_b, pq.token_a, qr.token_b],
+                },
+                &[pq.pool, qr.pool],
+            )],
+            &[],
+        )
+        .await;
+    assert_eq!(
+        unwrap_program_error(result),
+        instruction_error(0, ProgramError::InvalidArgument)
+    );
+}
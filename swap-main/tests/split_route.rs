@@ -0,0 +1,162 @@
+mod common;
+
+use cargo_swap::{SplitRouteSwapResult, MAX_SPLIT_ROUTES};
+use common::*;
+use solana_program::{program_error::ProgramError, pubkey::Pubkey};
+
+// T trades for V directly and through X, every pool 1_000_000 / 1_000_000
+struct SplitFixture {
+    env: TestEnv,
+    tv: PoolKeys,
+    tx: PoolKeys,
+    xv: PoolKeys,
+    user_t: Pubkey,
+    user_v: Pubkey,
+}
+
+async fn setup() -> SplitFixture {
+    let mut env = TestEnv::start().await;
+    let mint_t = env.create_mint(6).await;
+    let mint_v = env.create_mint(6).await;
+    let mint_x = env.create_mint(6).await;
+    let user_t = env.create_funded_account(&mint_t, 10_000_000).await;
+    let user_v = env.create_funded_account(&mint_v, 10_000_000).await;
+    let user_x = env.create_funded_account(&mint_x, 10_000_000).await;
+    let tv = env
+        .init_pool(mint_t, mint_v, &user_t, &user_v, 1_000_000, 1_000_000)
+        .await;
+    let tx = env
+        .init_pool(mint_t, mint_x, &user_t, &user_x, 1_000_000, 1_000_000)
+        .await;
+    let xv = env
+        .init_pool(mint_x, mint_v, &user_x, &user_v, 1_000_000, 1_000_000)
+        .await;
+    SplitFixture {
+        env,
+        tv,
+        tx,
+        xv,
+        user_t,
+        user_v,
+    }
+}
+
+fn direct_leg(fixture: &SplitFixture, amount_in: u64) -> SplitLeg {
+    SplitLeg {
+        pools: vec![fixture.tv],
+        mints: vec![fixture.tv.token_a, fixture.tv.token_b],
+        amount_in,
+    }
+}
+
+fn through_x_leg(fixture: &SplitFixture, amount_in: u64) -> SplitLeg {
+    SplitLeg {
+        pools: vec![fixture.tx, fixture.xv],
+        mints: vec![fixture.tx.token_a, fixture.tx.token_b, fixture.xv.token_b],
+        amount_in,
+    }
+}
+
+#[tokio::test]
+async fn split_route_executes_every_leg() {
+    let mut fixture = setup().await;
+    let legs = [direct_leg(&fixture, 100_000), through_x_leg(&fixture, 100_000)];
+    let payer = fixture.env.payer();
+    let (user_t, user_v) = (fixture.user_t, fixture.user_v);
+    let t_before = fixture.env.token_balance(&user_t).await;
+    let v_before = fixture.env.token_balance(&user_v).await;
+
+    let direct_out = expected_swap_output(100_000, 1_000_000, 1_000_000);
+    let x_out = expected_swap_output(100_000, 1_000_000, 1_000_000);
+    let through_x_out = expected_swap_output(x_out, 1_000_000, 1_000_000);
+    let total_out = direct_out + through_x_out;
+    // Half the order through each route beats all of it through T/V
+    assert!(total_out > expected_swap_output(200_000, 1_000_000, 1_000_000));
+
+    let instruction = split_route_swap_ix(&payer, &user_t, &user_v, &legs, total_out);
+    let result: SplitRouteSwapResult = fixture
+        .env
+        .simulate_return(std::slice::from_ref(&instruction), &[])
+        .await;
+    assert_eq!(result.amount_in, 200_000);
+    assert_eq!(result.amount_out, total_out);
+    assert_eq!(result.routes.len(), 2);
+    assert_eq!(result.routes[0].amount_out, direct_out);
+    assert_eq!(result.routes[1].hops.len(), 2);
+    assert_eq!(result.routes[1].amount_out, through_x_out);
+
+    fixture.env.process(&[instruction], &[]).await.unwrap();
+
+    assert_eq!(fixture.env.token_balance(&user_t).await, t_before - 200_000);
+    assert_eq!(fixture.env.token_balance(&user_v).await, v_before + total_out);
+    for keys in [fixture.tv, fixture.tx, fixture.xv] {
+        fixture.env.assert_pool_consistent(&keys).await;
+    }
+}
+
+#[tokio::test]
+async fn split_route_enforces_the_aggregate_minimum() {
+    let mut fixture = setup().await;
+    let legs = [direct_leg(&fixture, 100_000), through_x_leg(&fixture, 100_000)];
+    let payer = fixture.env.payer();
+    let (user_t, user_v) = (fixture.user_t, fixture.user_v);
+    let t_before = fixture.env.token_balance(&user_t).await;
+
+    let direct_out = expected_swap_output(100_000, 1_000_000, 1_000_000);
+    let x_out = expected_swap_output(100_000, 1_000_000, 1_000_000);
+    let total_out = direct_out + expected_swap_output(x_out, 1_000_000, 1_000_000);
+
+    // Each leg would clear on its own; only the sum falls one unit short
+    let instruction = split_route_swap_ix(&payer, &user_t, &user_v, &legs, total_out + 1);
+    let error = unwrap_program_error(fixture.env.process(&[instruction], &[]).await);
+
+    assert_eq!(error, instruction_error(0, ProgramError::InsufficientFunds));
+    assert_eq!(fixture.env.token_balance(&user_t).await, t_before);
+}
+
+#[tokio::test]
+async fn split_route_rejects_bad_routes() {
+    let mut fixture = setup().await;
+    let payer = fixture.env.payer();
+    let (user_t, user_v) = (fixture.user_t, fixture.user_v);
+    let invalid = instruction_error(0, ProgramError::InvalidArgument);
+
+    // The same pool in two routes
+    let legs = [direct_leg(&fixture, 50_000), direct_leg(&fixture, 50_000)];
+    let instruction = split_route_swap_ix(&payer, &user_t, &user_v, &legs, 0);
+    let error = unwrap_program_error(fixture.env.process(&[instruction], &[]).await);
+    assert_eq!(error, invalid);
+
+    // A route from another token than the input account's
+    let from_x = SplitLeg {
+        pools: vec![fixture.xv],
+        mints: vec![fixture.xv.token_a, fixture.xv.token_b],
+        amount_in: 50_000,
+    };
+    let legs = [direct_leg(&fixture, 50_000), from_x];
+    let instruction = split_route_swap_ix(&payer, &user_t, &user_v, &legs, 0);
+    let error = unwrap_program_error(fixture.env.process(&[instruction], &[]).await);
+    assert_eq!(error, invalid);
+
+    // A route ending in another token than the output account's
+    let to_x = SplitLeg {
+        pools: vec![fixture.tx],
+        mints: vec![fixture.tx.token_a, fixture.tx.token_b],
+        amount_in: 50_000,
+    };
+    let legs = [direct_leg(&fixture, 50_000), to_x];
+    let instruction = split_route_swap_ix(&payer, &user_t, &user_v, &legs, 0);
+    let error = unwrap_program_error(fixture.env.process(&[instruction], &[]).await);
+    assert_eq!(error, invalid);
+
+    // No routes, and more than MAX_SPLIT_ROUTES
+    let instruction = split_route_swap_ix(&payer, &user_t, &user_v, &[], 0);
+    let error = unwrap_program_error(fixture.env.process(&[instruction], &[]).await);
+    assert_eq!(error, invalid);
+    let legs: Vec<SplitLeg> = (0..=MAX_SPLIT_ROUTES)
+        .map(|_| direct_leg(&fixture, 10_000))
+        .collect();
+    let instruction = split_route_swap_ix(&payer, &user_t, &user_v, &legs, 0);
+    let error = unwrap_program_error(fixture.env.process(&[instruction], &[]).await);
+    assert_eq!(error, invalid);
+}
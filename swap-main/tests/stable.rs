@@ -0,0 +1,374 @@
+mod common;
+
+use cargo_swap::{curve::stable, CurveParams, CurveType, TestProjectInstruction};
+use common::*;
+use solana_program::{program_error::ProgramError, pubkey::Pubkey};
+use solana_sdk::signature::{Keypair, Signer};
+
+const AMP: u64 = 100;
+const DAY: i64 = 86_400;
+
+struct StableFixture {
+    env: TestEnv,
+    keys: PoolKeys,
+    user_a: Pubkey,
+    user_b: Pubkey,
+}
+
+async fn setup() -> StableFixture {
+    let mut env = TestEnv::start().await;
+    let mint_a = env.create_mint(6).await;
+    let mint_b = env.create_mint(6).await;
+    let user_a = env.create_funded_account(&mint_a, 10_000_000).await;
+    let user_b = env.create_funded_account(&mint_b, 10_000_000).await;
+    let keys = env
+        .init_pool_with_curve(
+            mint_a,
+            mint_b,
+            &user_a,
+            &user_b,
+            1_000_000,
+            1_000_000,
+            CurveParams::Stable { amp: AMP },
+        )
+        .await;
+    StableFixture {
+        env,
+        keys,
+        user_a,
+        user_b,
+    }
+}
+
+fn d(amp: u64, reserve_a: u64, reserve_b: u64) -> u128 {
+    stable::compute_d(amp, reserve_a, reserve_b).unwrap().as_u128()
+}
+
+#[tokio::test]
+async fn init_stable_pool_records_curve_and_mints_d() {
+    let StableFixture { mut env, keys, .. } = setup().await;
+    let payer = env.payer();
+    let now = env.clock().await.unix_timestamp;
+
+    let pool = env.assert_pool_consistent(&keys).await;
+    assert_eq!(pool.curve_type, CurveType::Stable);
+    assert_eq!(pool.admin, payer);
+    let params = pool.stable_params().unwrap();
+    assert_eq!((params.initial_amp, params.target_amp), (AMP, AMP));
+    assert_eq!(params.current_amp(now), AMP);
+    // A balanced stable pool is worth the sum of its reserves
+    assert_eq!(pool.total_lp_supply, 2_000_000);
+}
+
+#[tokio::test]
+async fn init_stable_pool_rejects_out_of_range_amp() {
+    let mut env = TestEnv::start().await;
+    let mint_a = env.create_mint(6).await;
+    let mint_b = env.create_mint(6).await;
+    let user_a = env.create_funded_account(&mint_a, 10_000_000).await;
+    let user_b = env.create_funded_account(&mint_b, 10_000_000).await;
+    let keys = PoolKeys::new(mint_a, mint_b);
+    let payer = env.payer();
+
+    for amp in [0, stable::MAX_AMP + 1] {
+        let result = env
+            .process(
+                &[init_pool_with_curve_ix(
+                    &keys,
+                    &payer,
+                    &user_a,
+                    &user_b,
+                    1_000_000,
+                    1_000_000,
+                    CurveParams::Stable { amp },
+                )],
+                &[],
+            )
+            .await;
+        assert_eq!(
+            unwrap_program_error(result),
+            instruction_error(0, ProgramError::InvalidArgument)
+        );
+    }
+}
+
+#[tokio::test]
+async fn stable_swap_prices_near_peg() {
+    let StableFixture {
+        mut env,
+        keys,
+        user_a,
+        user_b,
+    } = setup().await;
+    let payer = env.payer();
+    let user_b_before = env.token_balance(&user_b).await;
+
+    let expected_out = stable::calculate_swap_output(10_000, 1_000_000, 1_000_000, AMP).unwrap();
+    env.process(&[swap_ix(&keys, &payer, &user_a, &user_b, 10_000, true)], &[])
+        .await
+        .unwrap();
+
+    // Close to 1:1 after the 0.3% fee, and far better than constant product
+    assert!(expected_out > expected_swap_output(10_000, 1_000_000, 1_000_000));
+    assert!((9_960..=9_970).contains(&expected_out));
+    assert_eq!(env.token_balance(&user_b).await, user_b_before + expected_out);
+
+    let pool = env.assert_pool_consistent(&keys).await;
+    assert_eq!(pool.reserve_a, 1_010_000);
+    assert_eq!(pool.reserve_b, 1_000_000 - expected_out);
+    assert!(d(AMP, pool.reserve_a, pool.reserve_b) >= d(AMP, 1_000_000, 1_000_000));
+}
+
+#[tokio::test]
+async fn stable_liquidity_round_trip() {
+    let StableFixture {
+        mut env,
+        keys,
+        user_a,
+        user_b,
+    } = setup().await;
+    let payer = env.payer();
+    let user_lp = associated_token_address(&payer, &keys.lp_mint);
+
+    env.process(
+        &[add_liquidity_ix(&keys, &payer, &user_a, &user_b, &user_lp, 500_000, 800_000)],
+        &[],
+    )
+    .await
+    .unwrap();
+    let pool = env.assert_pool_consistent(&keys).await;
+    assert_eq!((pool.reserve_a, pool.reserve_b), (1_500_000, 1_500_000));
+    assert_eq!(pool.total_lp_supply, 3_000_000);
+    assert_eq!(env.token_balance(&user_lp).await, 3_000_000);
+
+    env.process(
+        &[remove_liquidity_ix(&keys, &payer, &user_lp, &user_a, &user_b, 1_500_000)],
+        &[],
+    )
+    .await
+    .unwrap();
+    let pool = env.assert_pool_consistent(&keys).await;
+    assert_eq!((pool.reserve_a, pool.reserve_b), (750_000, 750_000));
+    assert_eq!(pool.total_lp_supply, 1_500_000);
+}
+
+#[tokio::test]
+async fn stable_swap_quote_matches_swap() {
+    let StableFixture { mut env, keys, .. } = setup().await;
+
+    let expected_out = stable::calculate_swap_output(25_000, 1_000_000, 1_000_000, AMP).unwrap();
+    let logs = env
+        .simulate_logs(&[query_ix(
+            TestProjectInstruction::GetSwapQuote {
+                amount_in: 25_000,
+                token_in: keys.token_b,
+            },
+            &[keys.pool],
+        )])
+        .await;
+    assert!(logs
+        .iter()
+        .any(|line| line.ends_with(&format!("Amount Out: {}", expected_out))));
+
+    let logs = env
+        .simulate_logs(&[query_ix(TestProjectInstruction::GetPoolInfo, &[keys.pool])])
+        .await;
+    assert!(logs.iter().any(|line| line.ends_with("Curve: Stable")));
+    assert!(logs
+        .iter()
+        .any(|line| line.ends_with(&format!("Amplification: {}", AMP))));
+}
+
+#[tokio::test]
+async fn multihop_crosses_stable_and_constant_product_pools() {
+    let StableFixture {
+        mut env,
+        keys: stable_keys,
+        user_a,
+        user_b,
+    } = setup().await;
+    let payer = env.payer();
+    let mint_c = env.create_mint(6).await;
+    let user_c = env.create_funded_account(&mint_c, 10_000_000).await;
+    let cp_keys = env
+        .init_pool(stable_keys.token_b, mint_c, &user_b, &user_c, 1_000_000, 3_000_000)
+        .await;
+    let user_c_before = env.token_balance(&user_c).await;
+
+    let b_out = stable::calculate_swap_output(20_000, 1_000_000, 1_000_000, AMP).unwrap();
+    let c_out = expected_swap_output(b_out, 1_000_000, 3_000_000);
+    let hops = vec![
+        Hop {
+            keys: stable_keys,
+            intermediate: user_b,
+            output: user_b,
+        },
+        Hop {
+            keys: cp_keys,
+            intermediate: user_c,
+            output: user_c,
+        },
+    ];
+    env.process(&[multihop_swap_ix(&payer, &user_a, &hops, 20_000, c_out)], &[])
+        .await
+        .unwrap();
+
+    assert_eq!(env.token_balance(&user_c).await, user_c_before + c_out);
+    env.assert_pool_consistent(&stable_keys).await;
+    env.assert_pool_consistent(&cp_keys).await;
+}
+
+#[tokio::test]
+async fn ramp_amp_interpolates_and_stops() {
+    let StableFixture {
+        mut env,
+        keys,
+        user_a,
+        user_b,
+    } = setup().await;
+    let payer = env.payer();
+    let start = env.clock().await.unix_timestamp + DAY;
+    env.set_unix_timestamp(start).await;
+
+    env.process(
+        &[admin_ix(
+            TestProjectInstruction::RampAmp {
+                target_amp: 1_000,
+                ramp_stop_ts: start + 2 * DAY,
+            },
+            &keys.pool,
+            &payer,
+        )],
+        &[],
+    )
+    .await
+    .unwrap();
+    let params = env.pool(&keys.pool).await.stable_params().unwrap();
+    assert_eq!(params.ramp_start_ts, start);
+    assert_eq!(params.current_amp(start), AMP);
+    assert_eq!(params.current_amp(start + DAY), 550);
+    assert_eq!(params.current_amp(start + 3 * DAY), 1_000);
+
+    // Swaps halfway through the ramp are priced with the interpolated amplification
+    env.set_unix_timestamp(start + DAY).await;
+    let expected_out = stable::calculate_swap_output(10_000, 1_000_000, 1_000_000, 550).unwrap();
+    env.process(&[swap_ix(&keys, &payer, &user_a, &user_b, 10_000, true)], &[])
+        .await
+        .unwrap();
+    assert_eq!(env.pool(&keys.pool).await.reserve_b, 1_000_000 - expected_out);
+
+    env.process(
+        &[admin_ix(TestProjectInstruction::StopRampAmp, &keys.pool, &payer)],
+        &[],
+    )
+    .await
+    .unwrap();
+    let params = env.pool(&keys.pool).await.stable_params().unwrap();
+    assert_eq!((params.initial_amp, params.target_amp), (550, 550));
+    assert_eq!(params.current_amp(start + 10 * DAY), 550);
+}
+
+#[tokio::test]
+async fn ramp_amp_requires_admin_signature() {
+    let StableFixture { mut env, keys, .. } = setup().await;
+    let start = env.clock().await.unix_timestamp + DAY;
+    env.set_unix_timestamp(start).await;
+
+    let stranger = Keypair::new();
+    let result = env
+        .process(
+            &[admin_ix(
+                TestProjectInstruction::RampAmp {
+                    target_amp: 200,
+                    ramp_stop_ts: start + DAY,
+                },
+                &keys.pool,
+                &stranger.pubkey(),
+            )],
+            &[&stranger],
+        )
+        .await;
+    assert_eq!(
+        unwrap_program_error(result),
+        instruction_error(0, ProgramError::MissingRequiredSignature)
+    );
+    assert_eq!(env.pool(&keys.pool).await.stable_params().unwrap().target_amp, AMP);
+}
+
+#[tokio::test]
+async fn ramp_amp_enforces_limits() {
+    let StableFixture { mut env, keys, .. } = setup().await;
+    let payer = env.payer();
+    let created = env.clock().await.unix_timestamp;
+
+    let ramp = |target_amp, ramp_stop_ts| {
+        admin_ix(
+            TestProjectInstruction::RampAmp {
+                target_amp,
+                ramp_stop_ts,
+            },
+            &keys.pool,
+            &payer,
+        )
+    };
+
+    // Too soon after the pool was created
+    let result = env.process(&[ramp(200, created + 2 * DAY)], &[]).await;
+    assert_eq!(
+        unwrap_program_error(result),
+        instruction_error(0, ProgramError::InvalidArgument)
+    );
+
+    let start = created + DAY;
+    env.set_unix_timestamp(start).await;
+    for (target_amp, ramp_stop_ts) in [
+        // Ramp shorter than a day
+        (200, start + DAY - 1),
+        // More than a 10x change either way
+        (AMP * stable::MAX_AMP_CHANGE + 1, start + DAY),
+        (AMP / stable::MAX_AMP_CHANGE - 1, start + DAY),
+        // Outside the absolute bounds
+        (0, start + DAY),
+    ] {
+        let result = env.process(&[ramp(target_amp, ramp_stop_ts)], &[]).await;
+        assert_eq!(
+            unwrap_program_error(result),
+            instruction_error(0, ProgramError::InvalidArgument)
+        );
+    }
+    assert_eq!(env.pool(&keys.pool).await.stable_params().unwrap().target_amp, AMP);
+}
+
+#[tokio::test]
+async fn ramp_amp_rejects_constant_product_pool() {
+    let mut env = TestEnv::start().await;
+    let mint_a = env.create_mint(6).await;
+    let mint_b = env.create_mint(6).await;
+    let user_a = env.create_funded_account(&mint_a, 10_000_000).await;
+    let user_b = env.create_funded_account(&mint_b, 10_000_000).await;
+    let keys = env
+        .init_pool(mint_a, mint_b, &user_a, &user_b, 1_000_000, 1_000_000)
+        .await;
+    let payer = env.payer();
+    let start = env.clock().await.unix_timestamp + DAY;
+    env.set_unix_timestamp(start).await;
+
+    assert_eq!(env.pool(&keys.pool).await.curve_type, CurveType::ConstantProduct);
+    let result = env
+        .process(
+            &[admin_ix(
+                TestProjectInstruction::RampAmp {
+                    target_amp: 200,
+                    ramp_stop_ts: start + DAY,
+                },
+                &keys.pool,
+                &payer,
+            )],
+            &[],
+        )
+        .await;
+    assert_eq!(
+        unwrap_program_error(result),
+        instruction_error(0, ProgramError::InvalidArgument)
+    );
+}
@@ -0,0 +1,216 @@
+mod common;
+
+use cargo_swap::SwapResult;
+use common::*;
+use solana_program::{program_error::ProgramError, pubkey::Pubkey};
+
+struct SwapFixture {
+    env: TestEnv,
+    keys: PoolKeys,
+    user_a: Pubkey,
+    user_b: Pubkey,
+}
+
+async fn setup() -> SwapFixture {
+    let mut env = TestEnv::start().await;
+    let mint_a = env.create_mint(6).await;
+    let mint_b = env.create_mint(6).await;
+    let user_a = env.create_funded_account(&mint_a, 10_000_000).await;
+    let user_b = env.create_funded_account(&mint_b, 10_000_000).await;
+    let keys = env
+        .init_pool(mint_a, mint_b, &user_a, &user_b, 1_000_000, 2_000_000)
+        .await;
+    SwapFixture {
+        env,
+        keys,
+        user_a,
+        user_b,
+    }
+}
+
+#[tokio::test]
+async fn swap_a_to_b_moves_tokens_and_updates_reserves() {
+    let SwapFixture {
+        mut env,
+        keys,
+        user_a,
+        user_b,
+    } = setup().await;
+    let payer = env.payer();
+    let user_a_before = env.token_balance(&user_a).await;
+    let user_b_before = env.token_balance(&user_b).await;
+
+    let expected_out = expected_swap_output(10_000, 1_000_000, 2_000_000);
+    env.process(&[swap_ix(&keys, &payer, &user_a, &user_b, 10_000, true)], &[])
+        .await
+        .unwrap();
+
+    let pool = env.assert_pool_consistent(&keys).await;
+    assert_eq!(pool.reserve_a, 1_010_000);
+    assert_eq!(pool.reserve_b, 2_000_000 - expected_out);
+    assert_eq!(pool.total_lp_supply, 1_414_213);
+    assert_eq!(env.token_balance(&user_a).await, user_a_before - 10_000);
+    assert_eq!(env.token_balance(&user_b).await, user_b_before + expected_out);
+}
+
+#[tokio::test]
+async fn swap_b_to_a_moves_tokens_and_updates_reserves() {
+    let SwapFixture {
+        mut env,
+        keys,
+        user_a,
+        user_b,
+    } = setup().await;
+    let payer = env.payer();
+    let user_a_before = env.token_balance(&user_a).await;
+
+    let expected_out = expected_swap_output(50_000, 2_000_000, 1_000_000);
+    env.process(&[swap_ix(&keys, &payer, &user_b, &user_a, 50_000, false)], &[])
+        .await
+        .unwrap();
+
+    let pool = env.assert_pool_consistent(&keys).await;
+    assert_eq!(pool.reserve_b, 2_050_000);
+    assert_eq!(pool.reserve_a, 1_000_000 - expected_out);
+    assert_eq!(env.token_balance(&user_a).await, user_a_before + expected_out);
+}
+
+#[tokio::test]
+async fn swap_returns_amounts_fee_and_reserves() {
+    let SwapFixture {
+        mut env,
+        keys,
+        user_a,
+        user_b,
+    } = setup().await;
+    let payer = env.payer();
+
+    let expected_out = expected_swap_output(2_000, 2_000_000, 1_000_000);
+    let swap = swap_ix(&keys, &payer, &user_b, &user_a, 2_000, false);
+    let result: SwapResult = env.simulate_return(&[swap], &[]).await;
+    assert_eq!(
+        result,
+        SwapResult {
+            amount_in: 2_000,
+            amount_out: expected_out,
+            fee: 6,
+            reserve_in: 2_002_000,
+            reserve_out: 1_000_000 - expected_out,
+        }
+    );
+}
+
+#[tokio::test]
+async fn swap_fee_grows_constant_product() {
+    let SwapFixture {
+        mut env,
+        keys,
+        user_a,
+        user_b,
+    } = setup().await;
+    let payer = env.payer();
+
+    let k_before = 1_000_000u128 * 2_000_000u128;
+    env.process(&[swap_ix(&keys, &payer, &user_a, &user_b, 100_000, true)], &[])
+        .await
+        .unwrap();
+    env.process(&[swap_ix(&keys, &payer, &user_b, &user_a, 100_000, false)], &[])
+        .await
+        .unwrap();
+
+    let pool = env.assert_pool_consistent(&keys).await;
+    assert!(pool.reserve_a as u128 * pool.reserve_b as u128 > k_before);
+}
+
+#[tokio::test]
+async fn swap_zero_amount_fails() {
+    let SwapFixture {
+        mut env,
+        keys,
+        user_a,
+        user_b,
+    } = setup().await;
+    let payer = env.payer();
+
+    let result = env
+        .process(&[swap_ix(&keys, &payer, &user_a, &user_b, 0, true)], &[])
+        .await;
+    assert_eq!(
+        unwrap_program_error(result),
+        instruction_error(0, ProgramError::InvalidArgument)
+    );
+    let pool = env.assert_pool_consistent(&keys).await;
+    assert_eq!(pool.reserve_a, 1_000_000);
+}
+
+#[tokio::test]
+async fn swap_rejects_wrong_pool_seeds() {
+    let SwapFixture {
+        mut env,
+        keys,
+        user_a,
+        user_b,
+    } = setup().await;
+    let payer = env.payer();
+
+    let mut reversed = keys;
+    reversed.token_a = keys.token_b;
+    reversed.token_b = keys.token_a;
+    let result = env
+        .process(
+            &[swap_ix(&reversed, &payer, &user_a, &user_b, 10_000, true)],
+            &[],
+        )
+        .await;
+    assert_eq!(
+        unwrap_program_error(result),
+        instruction_error(0, ProgramError::InvalidSeeds)
+    );
+    env.assert_pool_consistent(&keys).await;
+}
+
+#[tokio::test]
+async fn swap_rejects_substituted_vault() {
+    let SwapFixture {
+        mut env,
+        keys,
+        user_a,
+        user_b,
+    } = setup().await;
+    let payer = env.payer();
+
+    // Pointing the output vault at an account the attacker controls must fail.
+    let attacker_b = env.create_funded_account(&keys.token_b, 0).await;
+    let mut wrong = keys;
+    wrong.vault_b = attacker_b;
+    let result = env
+        .process(&[swap_ix(&wrong, &payer, &user_a, &user_b, 10_000, true)], &[])
+        .await;
+    assert_eq!(
+        unwrap_program_error(result),
+        instruction_error(0, ProgramError::InvalidSeeds)
+    );
+    env.assert_pool_consistent(&keys).await;
+}
+
+#[tokio::test]
+async fn swap_more_than_balance_fails() {
+    let SwapFixture {
+        mut env,
+        keys,
+        user_a,
+        user_b,
+    } = setup().await;
+    let payer = env.payer();
+
+    let balance = env.token_balance(&user_a).await;
+    let result = env
+        .process(
+            &[swap_ix(&keys, &payer, &user_a, &user_b, balance + 1, true)],
+            &[],
+        )
+        .await;
+    assert!(result.is_err());
+    assert_eq!(env.token_balance(&user_a).await, balance);
+    env.assert_pool_consistent(&keys).await;
+}
@@ -0,0 +1,569 @@
+mod common;
+
+use cargo_swap::{
+    concentrated::{amounts_for_liquidity, sqrt_price_at_tick},
+    curve::fixed_point::ONE,
+    flash_loan::flash_loan_fee,
+    multi_pool::initial_lp_tokens,
+    TestProjectInstruction,
+};
+use common::*;
+use solana_program::{program_error::ProgramError, program_pack::Pack, pubkey::Pubkey};
+
+// 1% withheld on every transfer of the fee mint
+const FEE_BPS: u16 = 100;
+
+struct Token2022Fixture {
+    env: TestEnv,
+    keys: PoolKeys,
+    user_a: Pubkey,
+    user_b: Pubkey,
+}
+
+// An SPL Token mint A paired with a Token-2022 mint B
+async fn setup(transfer_fee: Option<u16>) -> Token2022Fixture {
+    let mut env = TestEnv::start().await;
+    let mint_a = env.create_mint(6).await;
+    let mint_b = env.create_token_2022_mint(6, transfer_fee).await;
+    let user_a = env.create_funded_account(&mint_a, 10_000_000).await;
+    let user_b = env.create_funded_account(&mint_b, 10_000_000).await;
+    let keys = PoolKeys::new(mint_a, mint_b);
+    let payer = env.payer();
+    let instruction = init_pool_ix(&keys, &payer, &user_a, &user_b, 1_000_000, 2_000_000);
+    env.process(&[with_token_2022(instruction)], &[])
+        .await
+        .unwrap();
+    Token2022Fixture {
+        env,
+        keys,
+        user_a,
+        user_b,
+    }
+}
+
+#[tokio::test]
+async fn pool_with_token_2022_mint() {
+    let Token2022Fixture {
+        mut env,
+        keys,
+        user_a,
+        user_b,
+    } = setup(None).await;
+    let payer = env.payer();
+    let vault_b = env.get_account(&keys.vault_b).await.unwrap();
+    assert_eq!(vault_b.owner, TOKEN_2022_PROGRAM_ID);
+    let pool = env.assert_pool_consistent(&keys).await;
+    assert_eq!((pool.reserve_a, pool.reserve_b), (1_000_000, 2_000_000));
+
+    let b_out = expected_swap_output(100_000, 1_000_000, 2_000_000);
+    let swap = swap_ix(&keys, &payer, &user_a, &user_b, 100_000, true);
+    env.process(&[with_token_2022(swap)], &[]).await.unwrap();
+    let a_out = expected_swap_output(50_000, 2_000_000 - b_out, 1_100_000);
+    let swap = swap_ix(&keys, &payer, &user_b, &user_a, 50_000, false);
+    env.process(&[with_token_2022(swap)], &[]).await.unwrap();
+    let pool = env.assert_pool_consistent(&keys).await;
+    assert_eq!(pool.reserve_a, 1_100_000 - a_out);
+    assert_eq!(pool.reserve_b, 2_000_000 - b_out + 50_000);
+
+    let user_lp = associated_token_address(&payer, &keys.lp_mint);
+    let lp_before = env.token_balance(&user_lp).await;
+    let add = add_liquidity_ix(&keys, &payer, &user_a, &user_b, &user_lp, 100_000, 1_000_000);
+    env.process(&[with_token_2022(add)], &[]).await.unwrap();
+    let lp_amount = env.token_balance(&user_lp).await - lp_before;
+    let remove = remove_liquidity_ix(&keys, &payer, &user_lp, &user_a, &user_b, lp_amount);
+    env.process(&[with_token_2022(remove)], &[]).await.unwrap();
+    env.assert_pool_consistent(&keys).await;
+}
+
+#[tokio::test]
+async fn transfer_fee_is_left_out_of_reserves() {
+    let Token2022Fixture {
+        mut env,
+        keys,
+        user_a,
+        user_b,
+    } = setup(Some(FEE_BPS)).await;
+    let payer = env.payer();
+
+    // The vault is sized for the fee extension and holds the deposit net of fee
+    let vault_b = env.get_account(&keys.vault_b).await.unwrap();
+    assert!(vault_b.data.len() > spl_token::state::Account::LEN);
+    let reserve_b = 2_000_000 - transfer_fee(2_000_000, FEE_BPS);
+    let pool = env.assert_pool_consistent(&keys).await;
+    assert_eq!(pool.reserve_b, reserve_b);
+
+    // Fee on input: the swap is priced on what the vault received
+    let received = 100_000 - transfer_fee(100_000, FEE_BPS);
+    let a_out = expected_swap_output(received, reserve_b, 1_000_000);
+    let a_before = env.token_balance(&user_a).await;
+    let swap = swap_ix(&keys, &payer, &user_b, &user_a, 100_000, false);
+    env.process(&[with_token_2022(swap)], &[]).await.unwrap();
+    assert_eq!(env.token_balance(&user_a).await, a_before + a_out);
+    let pool = env.assert_pool_consistent(&keys).await;
+    assert_eq!(pool.reserve_b, reserve_b + received);
+
+    // Fee on output: the whole output leaves the reserve, the user receives it net of fee
+    let b_out = expected_swap_output(50_000, pool.reserve_a, pool.reserve_b);
+    let b_before = env.token_balance(&user_b).await;
+    let swap = swap_ix(&keys, &payer, &user_a, &user_b, 50_000, true);
+    env.process(&[with_token_2022(swap)], &[]).await.unwrap();
+    assert_eq!(
+        env.token_balance(&user_b).await,
+        b_before + b_out - transfer_fee(b_out, FEE_BPS)
+    );
+    let after = env.assert_pool_consistent(&keys).await;
+    assert_eq!(after.reserve_b, pool.reserve_b - b_out);
+
+    // LP tokens are minted for the deposit net of fee
+    let user_lp = associated_token_address(&payer, &keys.lp_mint);
+    let add = add_liquidity_ix(&keys, &payer, &user_a, &user_b, &user_lp, 100_000, 10_000_000);
+    env.process(&[with_token_2022(add)], &[]).await.unwrap();
+    env.assert_pool_consistent(&keys).await;
+}
+
+#[tokio::test]
+async fn multihop_through_transfer_fee_mint() {
+    let Token2022Fixture {
+        mut env,
+        keys: ab,
+        user_a,
+        user_b,
+    } = setup(Some(FEE_BPS)).await;
+    let payer = env.payer();
+    let mint_c = env.create_mint(6).await;
+    let user_c = env.create_funded_account(&mint_c, 10_000_000).await;
+    let bc = PoolKeys::new(ab.token_b, mint_c);
+    let instruction = init_pool_ix(&bc, &payer, &user_b, &user_c, 2_000_000, 1_000_000);
+    env.process(&[with_token_2022(instruction)], &[])
+        .await
+        .unwrap();
+    let reserve_b = 2_000_000 - transfer_fee(2_000_000, FEE_BPS);
+
+    // A -> B is withheld once leaving the first pool and again entering the second
+    let b_out = expected_swap_output(100_000, 1_000_000, reserve_b);
+    let b_in = b_out - transfer_fee(b_out, FEE_BPS);
+    let b_in = b_in - transfer_fee(b_in, FEE_BPS);
+    let c_out = expected_swap_output(b_in, reserve_b, 1_000_000);
+    let hops = [
+        Hop {
+            keys: ab,
+            intermediate: user_b,
+            output: user_b,
+        },
+        Hop {
+            keys: bc,
+            intermediate: user_c,
+            output: user_c,
+        },
+    ];
+
+    let instruction = multihop_swap_ix(&payer, &user_a, &hops, 100_000, c_out + 1);
+    let error = unwrap_program_error(env.process(&[with_token_2022(instruction)], &[]).await);
+    assert_eq!(error, instruction_error(0, ProgramError::InsufficientFunds));
+
+    let c_before = env.token_balance(&user_c).await;
+    let instruction = multihop_swap_ix(&payer, &user_a, &hops, 100_000, c_out);
+    env.process(&[with_token_2022(instruction)], &[])
+        .await
+        .unwrap();
+    assert_eq!(env.token_balance(&user_c).await, c_before + c_out);
+    env.assert_pool_consistent(&ab).await;
+    let pool = env.assert_pool_consistent(&bc).await;
+    assert_eq!(pool.reserve_a, reserve_b + b_in);
+}
+
+#[tokio::test]
+async fn compact_multihop_pays_the_transfer_fee_once() {
+    let Token2022Fixture {
+        mut env,
+        keys: ab,
+        user_a,
+        user_b,
+    } = setup(Some(FEE_BPS)).await;
+    let payer = env.payer();
+    let mint_c = env.create_mint(6).await;
+    let user_c = env.create_funded_account(&mint_c, 10_000_000).await;
+    let bc = PoolKeys::new(ab.token_b, mint_c);
+    let instruction = init_pool_ix(&bc, &payer, &user_b, &user_c, 2_000_000, 1_000_000);
+    env.process(&[with_token_2022(instruction)], &[])
+        .await
+        .unwrap();
+    let reserve_b = 2_000_000 - transfer_fee(2_000_000, FEE_BPS);
+
+    // B goes from the first pool's vault straight into the second's, so it
+    // is withheld once
+    let b_out = expected_swap_output(100_000, 1_000_000, reserve_b);
+    let b_in = b_out - transfer_fee(b_out, FEE_BPS);
+    let c_out = expected_swap_output(b_in, reserve_b, 1_000_000);
+    let b_before = env.token_balance(&user_b).await;
+    let c_before = env.token_balance(&user_c).await;
+    let instruction = compact_multihop_swap_ix(
+        &payer,
+        &[ab, bc],
+        &[ab.token_a, ab.token_b, mint_c],
+        &user_a,
+        &user_c,
+        100_000,
+        c_out,
+        Vec::new(),
+    );
+    env.process(&[with_token_2022(instruction)], &[])
+        .await
+        .unwrap();
+    assert_eq!(env.token_balance(&user_b).await, b_before);
+    assert_eq!(env.token_balance(&user_c).await, c_before + c_out);
+    env.assert_pool_consistent(&ab).await;
+    let pool = env.assert_pool_consistent(&bc).await;
+    assert_eq!(pool.reserve_a, reserve_b + b_in);
+}
+
+#[tokio::test]
+async fn token_2022_pool_needs_the_token_2022_program() {
+    let Token2022Fixture {
+        mut env,
+        keys,
+        user_a,
+        user_b,
+    } = setup(None).await;
+    let payer = env.payer();
+
+    let swap = swap_ix(&keys, &payer, &user_a, &user_b, 100_000, true);
+    let error = unwrap_program_error(env.process(&[swap], &[]).await);
+    assert_eq!(error, instruction_error(0, ProgramError::NotEnoughAccountKeys));
+
+    // Mints must belong to a supported token program
+    let keys = PoolKeys::new(keys.token_a, cargo_swap::id());
+    let instruction = init_pool_ix(&keys, &payer, &user_a, &user_b, 1_000, 1_000);
+    let error = unwrap_program_error(env.process(&[instruction], &[]).await);
+    assert_eq!(error, instruction_error(0, ProgramError::IncorrectProgramId));
+}
+
+#[tokio::test]
+async fn multi_pool_with_transfer_fee_mint() {
+    let mut env = TestEnv::start().await;
+    let fee_mint = env.create_token_2022_mint(6, Some(FEE_BPS)).await;
+    let mints = vec![env.create_mint(6).await, env.create_mint(6).await, fee_mint];
+    let keys = MultiPoolKeys::new(mints);
+    let fee_index = keys.tokens.iter().position(|token| *token == fee_mint).unwrap();
+    let spl_index = (fee_index + 1) % 3;
+    let mut user_tokens = Vec::new();
+    for token in &keys.tokens {
+        user_tokens.push(env.create_funded_account(token, 100_000_000).await);
+    }
+    let payer = env.payer();
+    let user_lp = associated_token_address(&payer, &keys.lp_mint);
+
+    // The fee mint's vault lives under Token-2022 and holds the deposit net of fee
+    let amounts = vec![1_000_000, 2_000_000, 3_000_000];
+    let instruction = init_multi_pool_ix(&keys, &payer, &user_tokens, amounts.clone());
+    env.process(&[with_token_2022(instruction)], &[]).await.unwrap();
+    let vault = env.get_account(&keys.vaults[fee_index]).await.unwrap();
+    assert_eq!(vault.owner, TOKEN_2022_PROGRAM_ID);
+    let mut reserves = amounts;
+    reserves[fee_index] -= transfer_fee(reserves[fee_index], FEE_BPS);
+    let pool = env.assert_multi_pool_consistent(&keys).await;
+    assert_eq!(pool.reserves(), &reserves[..]);
+    assert_eq!(pool.total_lp_supply, initial_lp_tokens(&reserves).unwrap());
+
+    // Output arrives net of fee, input is priced net of fee
+    let out = expected_swap_output(100_000, reserves[spl_index], reserves[fee_index]);
+    let before = env.token_balance(&user_tokens[fee_index]).await;
+    let swap = multi_pool_swap_ix(
+        &keys,
+        &payer,
+        spl_index,
+        fee_index,
+        &user_tokens[spl_index],
+        &user_tokens[fee_index],
+        100_000,
+        out - transfer_fee(out, FEE_BPS),
+    );
+    env.process(&[with_token_2022(swap)], &[]).await.unwrap();
+    assert_eq!(
+        env.token_balance(&user_tokens[fee_index]).await,
+        before + out - transfer_fee(out, FEE_BPS)
+    );
+    let pool = env.assert_multi_pool_consistent(&keys).await;
+    let received = 100_000 - transfer_fee(100_000, FEE_BPS);
+    let out = expected_swap_output(received, pool.reserves[fee_index], pool.reserves[spl_index]);
+    let swap = multi_pool_swap_ix(
+        &keys,
+        &payer,
+        fee_index,
+        spl_index,
+        &user_tokens[fee_index],
+        &user_tokens[spl_index],
+        100_000,
+        out,
+    );
+    env.process(&[with_token_2022(swap)], &[]).await.unwrap();
+    let pool = env.assert_multi_pool_consistent(&keys).await;
+
+    // Proportional and single-asset deposits mint LP tokens for what arrived
+    let (deposits, _) = pool.deposit_amounts(&[1_000_000; 3]).unwrap();
+    let mut received = deposits;
+    received[fee_index] -= transfer_fee(received[fee_index], FEE_BPS);
+    let (_, liquidity) = pool.deposit_amounts(&received).unwrap();
+    let lp_before = env.token_balance(&user_lp).await;
+    let add = multi_liquidity_ix(
+        TestProjectInstruction::AddMultiLiquidity { amounts: vec![1_000_000; 3] },
+        &keys,
+        &payer,
+        &user_lp,
+        &user_tokens,
+    );
+    env.process(&[with_token_2022(add)], &[]).await.unwrap();
+    assert_eq!(env.token_balance(&user_lp).await, lp_before + liquidity);
+    let pool = env.assert_multi_pool_consistent(&keys).await;
+
+    let liquidity = pool
+        .single_deposit_lp_tokens(fee_index, 50_000 - transfer_fee(50_000, FEE_BPS))
+        .unwrap();
+    let add = add_multi_liquidity_single_ix(
+        &keys,
+        &payer,
+        fee_index,
+        &user_tokens[fee_index],
+        &user_lp,
+        50_000,
+        liquidity,
+    );
+    env.process(&[with_token_2022(add)], &[]).await.unwrap();
+    let pool = env.assert_multi_pool_consistent(&keys).await;
+
+    // The single-asset minimum bounds what the user received
+    let out = pool.single_withdraw_amount(fee_index, 10_000).unwrap();
+    let net = out - transfer_fee(out, FEE_BPS);
+    let remove = remove_multi_liquidity_single_ix(
+        &keys,
+        &payer,
+        fee_index,
+        &user_lp,
+        &user_tokens[fee_index],
+        10_000,
+        net + 1,
+    );
+    let error = unwrap_program_error(env.process(&[with_token_2022(remove)], &[]).await);
+    assert_eq!(error, instruction_error(0, ProgramError::InsufficientFunds));
+    let remove = remove_multi_liquidity_single_ix(
+        &keys,
+        &payer,
+        fee_index,
+        &user_lp,
+        &user_tokens[fee_index],
+        10_000,
+        net,
+    );
+    env.process(&[with_token_2022(remove)], &[]).await.unwrap();
+
+    let remove = multi_liquidity_ix(
+        TestProjectInstruction::RemoveMultiLiquidity { lp_amount: 100_000 },
+        &keys,
+        &payer,
+        &user_lp,
+        &user_tokens,
+    );
+    env.process(&[with_token_2022(remove)], &[]).await.unwrap();
+    env.assert_multi_pool_consistent(&keys).await;
+}
+
+#[tokio::test]
+async fn concentrated_pool_with_token_2022_mint() {
+    let mut env = TestEnv::start().await;
+    let token_a = env.create_mint(6).await;
+    let token_b = env.create_token_2022_mint(6, None).await;
+    let user_a = env.create_funded_account(&token_a, 1_000_000_000_000).await;
+    let user_b = env.create_funded_account(&token_b, 1_000_000_000_000).await;
+    let keys = concentrated_pool_keys(token_a, token_b);
+    let payer = env.payer();
+    let init = init_concentrated_pool_ix(&keys, &payer, 10, ONE);
+    env.process(&[with_token_2022(init)], &[]).await.unwrap();
+    let vault_b = env.get_account(&keys.vault_b).await.unwrap();
+    assert_eq!(vault_b.owner, TOKEN_2022_PROGRAM_ID);
+
+    let liquidity = 1_000_000_000;
+    let position = position_address(&keys.pool, &payer, -1000, 1000);
+    let increase = position_ix(
+        TestProjectInstruction::IncreaseLiquidity {
+            liquidity,
+            maximum_amount_a: u64::MAX,
+            maximum_amount_b: u64::MAX,
+        },
+        &keys,
+        &position,
+        &payer,
+        &user_a,
+        &user_b,
+    );
+    env.process(&[open_position_ix(&keys, &payer, -1000, 1000), with_token_2022(increase)], &[])
+        .await
+        .unwrap();
+    let (amount_a, amount_b) = amounts_for_liquidity(
+        ONE,
+        sqrt_price_at_tick(-1000).unwrap(),
+        sqrt_price_at_tick(1000).unwrap(),
+        liquidity,
+        true,
+    )
+    .unwrap();
+    assert_eq!(env.token_balance(&keys.vault_a).await, amount_a);
+    assert_eq!(env.token_balance(&keys.vault_b).await, amount_b);
+
+    // Both directions move the Token-2022 side through its own program
+    for direction_a_to_b in [true, false] {
+        let (user_in, user_out) = if direction_a_to_b { (user_a, user_b) } else { (user_b, user_a) };
+        let (used, out, _) = env
+            .concentrated_pool(&keys.pool)
+            .await
+            .swap(100_000, direction_a_to_b)
+            .unwrap();
+        let in_before = env.token_balance(&user_in).await;
+        let out_before = env.token_balance(&user_out).await;
+        let swap = concentrated_swap_ix(
+            &keys,
+            &payer,
+            &user_in,
+            &user_out,
+            100_000,
+            out,
+            direction_a_to_b,
+        );
+        env.process(&[with_token_2022(swap)], &[]).await.unwrap();
+        assert_eq!(env.token_balance(&user_in).await, in_before - used);
+        assert_eq!(env.token_balance(&user_out).await, out_before + out);
+    }
+
+    let decrease = position_ix(
+        TestProjectInstruction::DecreaseLiquidity {
+            liquidity,
+            minimum_amount_a: 0,
+            minimum_amount_b: 0,
+        },
+        &keys,
+        &position,
+        &payer,
+        &user_a,
+        &user_b,
+    );
+    let collect = position_ix(
+        TestProjectInstruction::CollectFees,
+        &keys,
+        &position,
+        &payer,
+        &user_a,
+        &user_b,
+    );
+    env.process(&[with_token_2022(decrease), with_token_2022(collect)], &[])
+        .await
+        .unwrap();
+    let position = env.position(&position).await;
+    assert_eq!((position.liquidity, position.tokens_owed_a, position.tokens_owed_b), (0, 0, 0));
+}
+
+#[tokio::test]
+async fn concentrated_pool_rejects_deposits_short_of_a_transfer_fee() {
+    let mut env = TestEnv::start().await;
+    let token_a = env.create_mint(6).await;
+    let token_b = env.create_token_2022_mint(6, Some(FEE_BPS)).await;
+    let user_a = env.create_funded_account(&token_a, 1_000_000_000).await;
+    let user_b = env.create_funded_account(&token_b, 1_000_000_000).await;
+    let keys = concentrated_pool_keys(token_a, token_b);
+    let payer = env.payer();
+    let init = init_concentrated_pool_ix(&keys, &payer, 10, ONE);
+    env.process(&[with_token_2022(init)], &[]).await.unwrap();
+
+    // Ranges are priced on exact amounts, which a withheld fee would leave short
+    let position = position_address(&keys.pool, &payer, -1000, 1000);
+    let increase = position_ix(
+        TestProjectInstruction::IncreaseLiquidity {
+            liquidity: 1_000_000_000,
+            maximum_amount_a: u64::MAX,
+            maximum_amount_b: u64::MAX,
+        },
+        &keys,
+        &position,
+        &payer,
+        &user_a,
+        &user_b,
+    );
+    let error = unwrap_program_error(
+        env.process(&[open_position_ix(&keys, &payer, -1000, 1000), with_token_2022(increase)], &[])
+            .await,
+    );
+    assert_eq!(error, instruction_error(1, ProgramError::InvalidArgument));
+}
+
+#[tokio::test]
+async fn flash_loan_and_flash_swap_with_token_2022_mint() {
+    let Token2022Fixture {
+        mut env,
+        keys,
+        user_a,
+        user_b,
+    } = setup(None).await;
+    let payer = env.payer();
+
+    // The token program slot must be the mint's own
+    let amount = 500_000;
+    let mut instruction = flash_loan_ix(
+        &keys.pool,
+        &keys.token_b,
+        &keys.vault_b,
+        &user_b,
+        &FLASH_RECEIVER_ID,
+        &payer,
+        amount,
+    );
+    let error = unwrap_program_error(env.process(&[instruction.clone()], &[]).await);
+    assert_eq!(error, instruction_error(0, ProgramError::IncorrectProgramId));
+    instruction.accounts[5].pubkey = TOKEN_2022_PROGRAM_ID;
+    env.process(&[instruction], &[]).await.unwrap();
+    let pool = env.assert_pool_consistent(&keys).await;
+    assert_eq!(pool.reserve_b, 2_000_000 + flash_loan_fee(amount).unwrap());
+
+    // Take A, pay in B through Token-2022
+    let mut instruction = flash_swap_ix(
+        &keys,
+        &user_a,
+        &user_b,
+        &FLASH_RECEIVER_ID,
+        &user_b,
+        &payer,
+        100_000,
+        0,
+    );
+    instruction.accounts[9].pubkey = TOKEN_2022_PROGRAM_ID;
+    let owed = pool.swap_input(100_000, false).unwrap();
+    let b_before = env.token_balance(&user_b).await;
+    env.process(&[instruction], &[]).await.unwrap();
+    assert_eq!(env.token_balance(&user_b).await, b_before - owed);
+    let after = env.assert_pool_consistent(&keys).await;
+    assert_eq!(after.reserve_a, pool.reserve_a - 100_000);
+    assert_eq!(after.reserve_b, pool.reserve_b + owed);
+}
+
+#[tokio::test]
+async fn flash_loan_repayment_counts_net_of_transfer_fee() {
+    let Token2022Fixture {
+        mut env,
+        keys,
+        user_a: _,
+        user_b,
+    } = setup(Some(FEE_BPS)).await;
+    let payer = env.payer();
+
+    // The receiver pays back amount + fee, but the vault is credited less
+    let mut instruction = flash_loan_ix(
+        &keys.pool,
+        &keys.token_b,
+        &keys.vault_b,
+        &user_b,
+        &FLASH_RECEIVER_ID,
+        &payer,
+        500_000,
+    );
+    instruction.accounts[5].pubkey = TOKEN_2022_PROGRAM_ID;
+    let error = unwrap_program_error(env.process(&[instruction], &[]).await);
+    assert_eq!(error, instruction_error(0, ProgramError::InsufficientFunds));
+    env.assert_pool_consistent(&keys).await;
+}
@@ -0,0 +1,198 @@
+mod common;
+
+use cargo_swap::{
+    curve::PRICE_SCALE,
+    oracle::{self, PriceObservation, TwapResult},
+    TestProjectInstruction,
+};
+use common::*;
+use solana_program::{instruction::Instruction, program_error::ProgramError, pubkey::Pubkey};
+
+// A pool of 1_000_000 A / 2_000_000 B, created at `created`
+struct TwapFixture {
+    env: TestEnv,
+    keys: PoolKeys,
+    user_a: Pubkey,
+    user_b: Pubkey,
+    created: i64,
+}
+
+async fn setup() -> TwapFixture {
+    let mut env = TestEnv::start().await;
+    let mint_a = env.create_mint(6).await;
+    let mint_b = env.create_mint(6).await;
+    let user_a = env.create_funded_account(&mint_a, 10_000_000).await;
+    let user_b = env.create_funded_account(&mint_b, 10_000_000).await;
+    let keys = env
+        .init_pool(mint_a, mint_b, &user_a, &user_b, 1_000_000, 2_000_000)
+        .await;
+    let created = env.pool(&keys.pool).await.last_update_timestamp;
+    TwapFixture {
+        env,
+        keys,
+        user_a,
+        user_b,
+        created,
+    }
+}
+
+fn get_twap_ix(pool: &Pubkey, since: PriceObservation) -> Instruction {
+    query_ix(TestProjectInstruction::GetTwap { since }, &[*pool])
+}
+
+#[tokio::test]
+async fn init_pool_starts_the_accumulators() {
+    let TwapFixture { mut env, keys, created, .. } = setup().await;
+
+    let pool = env.pool(&keys.pool).await;
+    assert_eq!(created, env.clock().await.unix_timestamp);
+    assert_eq!(pool.price_a_cumulative, 0);
+    assert_eq!(pool.price_b_cumulative, 0);
+}
+
+#[tokio::test]
+async fn reserve_changes_accumulate_the_price_they_replace() {
+    let TwapFixture {
+        mut env,
+        keys,
+        user_a,
+        user_b,
+        created,
+    } = setup().await;
+    let payer = env.payer();
+
+    // 100 seconds at 2 B per A, then a swap
+    env.set_unix_timestamp(created + 100).await;
+    env.process(&[swap_ix(&keys, &payer, &user_a, &user_b, 100_000, true)], &[])
+        .await
+        .unwrap();
+    let pool = env.assert_pool_consistent(&keys).await;
+    assert_eq!(pool.last_update_timestamp, created + 100);
+    assert_eq!(pool.price_a_cumulative, 2 * PRICE_SCALE * 100);
+    assert_eq!(pool.price_b_cumulative, PRICE_SCALE / 2 * 100);
+
+    // 50 seconds at the price the swap left, then a deposit
+    let price_a = pool.reserve_b as u128 * PRICE_SCALE / pool.reserve_a as u128;
+    let user_lp = associated_token_address(&payer, &keys.lp_mint);
+    env.set_unix_timestamp(created + 150).await;
+    env.process(
+        &[add_liquidity_ix(&keys, &payer, &user_a, &user_b, &user_lp, 10_000, 20_000)],
+        &[],
+    )
+    .await
+    .unwrap();
+    let pool = env.pool(&keys.pool).await;
+    assert_eq!(pool.last_update_timestamp, created + 150);
+    assert_eq!(pool.price_a_cumulative, 2 * PRICE_SCALE * 100 + price_a * 50);
+    assert_eq!(
+        pool.price_b_cumulative,
+        PRICE_SCALE / 2 * 100 + oracle::inverse_price(price_a) * 50
+    );
+}
+
+#[tokio::test]
+async fn trades_within_one_second_leave_the_average_alone() {
+    let TwapFixture {
+        mut env,
+        keys,
+        user_a,
+        user_b,
+        created,
+    } = setup().await;
+    let payer = env.payer();
+    let since = env.pool(&keys.pool).await.observe(created).unwrap();
+
+    // Push the price far up and back in one transaction
+    env.set_unix_timestamp(created + 60).await;
+    let amount_out = expected_swap_output(500_000, 1_000_000, 2_000_000);
+    env.process(
+        &[
+            swap_ix(&keys, &payer, &user_a, &user_b, 500_000, true),
+            swap_ix(&keys, &payer, &user_b, &user_a, amount_out, false),
+        ],
+        &[],
+    )
+    .await
+    .unwrap();
+    let pool = env.pool(&keys.pool).await;
+    assert_eq!(pool.price_a_cumulative, 2 * PRICE_SCALE * 60);
+
+    let result: TwapResult = env
+        .simulate_return(&[get_twap_ix(&keys.pool, since)], &[])
+        .await;
+    assert_eq!(result.price_a, 2 * PRICE_SCALE);
+    assert_eq!(result.price_b, PRICE_SCALE / 2);
+    assert_eq!(result.observation.timestamp, created + 60);
+}
+
+#[tokio::test]
+async fn get_twap_averages_up_to_now() {
+    let TwapFixture {
+        mut env,
+        keys,
+        user_a,
+        user_b,
+        created,
+    } = setup().await;
+    let payer = env.payer();
+    let since = env.pool(&keys.pool).await.observe(created).unwrap();
+
+    env.set_unix_timestamp(created + 100).await;
+    env.process(&[swap_ix(&keys, &payer, &user_a, &user_b, 100_000, true)], &[])
+        .await
+        .unwrap();
+    let pool = env.pool(&keys.pool).await;
+    let price_a = pool.reserve_b as u128 * PRICE_SCALE / pool.reserve_a as u128;
+
+    // The price since the swap counts up to now, without another update
+    env.set_unix_timestamp(created + 400).await;
+    let result: TwapResult = env
+        .simulate_return(&[get_twap_ix(&keys.pool, since)], &[])
+        .await;
+    assert_eq!(result.price_a, (2 * PRICE_SCALE * 100 + price_a * 300) / 400);
+    assert_eq!(result.observation.timestamp, created + 400);
+    assert_eq!(
+        result.observation.price_a_cumulative,
+        2 * PRICE_SCALE * 100 + price_a * 300
+    );
+    assert_eq!(
+        oracle::twap(&since, &result.observation).unwrap(),
+        (result.price_a, result.price_b)
+    );
+
+    // Averaging from the returned observation needs time to pass
+    let error = unwrap_program_error(
+        env.process(&[get_twap_ix(&keys.pool, result.observation)], &[]).await,
+    );
+    assert_eq!(error, instruction_error(0, ProgramError::InvalidArgument));
+}
+
+#[tokio::test]
+async fn strategy_reads_the_twap_through_cpi() {
+    let TwapFixture {
+        mut env,
+        keys,
+        user_a,
+        user_b,
+        created,
+    } = setup().await;
+    let payer = env.payer();
+    let since = env.pool(&keys.pool).await.observe(created).unwrap();
+
+    // A large swap just before the check barely moves the average
+    env.set_unix_timestamp(created + 1_000).await;
+    env.process(&[swap_ix(&keys, &payer, &user_b, &user_a, 2_000_000, false)], &[])
+        .await
+        .unwrap();
+    env.set_unix_timestamp(created + 1_001).await;
+
+    let guard = |maximum_price_a| {
+        through_strategy(
+            get_twap_ix(&keys.pool, since),
+            &StrategyInstruction::PriceGuard { since, maximum_price_a },
+        )
+    };
+    env.process(&[guard(2_010_000_000_000)], &[]).await.unwrap();
+    let error = unwrap_program_error(env.process(&[guard(2 * PRICE_SCALE)], &[]).await);
+    assert_eq!(error, instruction_error(0, ProgramError::InvalidArgument));
+}